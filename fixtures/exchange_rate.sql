@@ -0,0 +1,6 @@
+INSERT INTO "public"."bw_exchange_rate" ("rate_date", "symbol", "base", "rate") VALUES ('2024-06-01', 'USD', 'USD', 1);
+INSERT INTO "public"."bw_exchange_rate" ("rate_date", "symbol", "base", "rate") VALUES ('2024-06-01', 'EUR', 'USD', 0.9212);
+INSERT INTO "public"."bw_exchange_rate" ("rate_date", "symbol", "base", "rate") VALUES ('2024-06-01', 'CNY', 'USD', 7.2431);
+INSERT INTO "public"."bw_exchange_rate" ("rate_date", "symbol", "base", "rate") VALUES ('2024-06-02', 'USD', 'USD', 1);
+INSERT INTO "public"."bw_exchange_rate" ("rate_date", "symbol", "base", "rate") VALUES ('2024-06-02', 'EUR', 'USD', 0.9198);
+INSERT INTO "public"."bw_exchange_rate" ("rate_date", "symbol", "base", "rate") VALUES ('2024-06-02', 'CNY', 'USD', 7.2455);
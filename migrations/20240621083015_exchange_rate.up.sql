@@ -0,0 +1,24 @@
+-- Add up migration script here
+CREATE TABLE bw_exchange_rate (
+    rate_date DATE NOT NULL,
+    symbol VARCHAR (10) NOT NULL,
+    base VARCHAR (10) NOT NULL,
+    rate NUMERIC (30, 12) NOT NULL,
+
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP,
+
+    PRIMARY KEY (rate_date, symbol)
+);
+
+CREATE TRIGGER update_bw_exchange_rate_updated_at
+BEFORE UPDATE ON bw_exchange_rate
+FOR EACH ROW
+EXECUTE FUNCTION update_at();
+
+CREATE INDEX idx_bw_exchange_rate_symbol ON bw_exchange_rate (symbol);
+
+COMMENT ON COLUMN bw_exchange_rate.rate_date IS '汇率日期';
+COMMENT ON COLUMN bw_exchange_rate.symbol IS '货币代码';
+COMMENT ON COLUMN bw_exchange_rate.base IS '基准货币代码';
+COMMENT ON COLUMN bw_exchange_rate.rate IS '1 单位基准货币可兑换的该货币数量';
@@ -0,0 +1,23 @@
+-- Add down migration script here
+CREATE TYPE currency AS ENUM ('USD', 'EUR', 'GBP', 'CNY');
+CREATE TYPE language AS ENUM ('en-US', 'zh-CN', 'fr-FR', 'es-ES');
+
+ALTER TABLE bw_account
+    DROP CONSTRAINT IF EXISTS bw_account_local_currency_fkey,
+    DROP CONSTRAINT IF EXISTS bw_account_system_lang_fkey;
+
+UPDATE bw_account SET local_currency = 'USD'
+WHERE local_currency NOT IN ('USD', 'EUR', 'GBP', 'CNY');
+UPDATE bw_account SET system_lang = 'en-US'
+WHERE system_lang NOT IN ('en-US', 'zh-CN', 'fr-FR', 'es-ES');
+
+ALTER TABLE bw_account
+    ALTER COLUMN local_currency DROP DEFAULT,
+    ALTER COLUMN local_currency TYPE currency USING local_currency::currency,
+    ALTER COLUMN local_currency SET DEFAULT 'USD',
+    ALTER COLUMN system_lang DROP DEFAULT,
+    ALTER COLUMN system_lang TYPE language USING system_lang::language,
+    ALTER COLUMN system_lang SET DEFAULT 'en-US';
+
+DROP TABLE IF EXISTS bw_currency;
+DROP TABLE IF EXISTS bw_language;
\ No newline at end of file
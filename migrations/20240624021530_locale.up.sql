@@ -0,0 +1,68 @@
+-- Add up migration script here
+CREATE TABLE bw_currency (
+    symbol VARCHAR (10) PRIMARY KEY,
+    name VARCHAR (50) NOT NULL,
+    enabled BOOL NOT NULL DEFAULT TRUE,
+
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP
+);
+
+CREATE TRIGGER update_bw_currency_updated_at
+BEFORE UPDATE ON bw_currency
+FOR EACH ROW
+EXECUTE FUNCTION update_at();
+
+COMMENT ON COLUMN bw_currency.symbol IS '货币代码 (ISO 4217)';
+COMMENT ON COLUMN bw_currency.name IS '货币名称';
+COMMENT ON COLUMN bw_currency.enabled IS '是否可供用户选择';
+
+CREATE TABLE bw_language (
+    code VARCHAR (10) PRIMARY KEY,
+    name VARCHAR (50) NOT NULL,
+    enabled BOOL NOT NULL DEFAULT TRUE,
+
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP
+);
+
+CREATE TRIGGER update_bw_language_updated_at
+BEFORE UPDATE ON bw_language
+FOR EACH ROW
+EXECUTE FUNCTION update_at();
+
+COMMENT ON COLUMN bw_language.code IS '语言代码 (BCP 47)';
+COMMENT ON COLUMN bw_language.name IS '语言名称';
+COMMENT ON COLUMN bw_language.enabled IS '是否可供用户选择';
+
+INSERT INTO bw_currency (symbol, name) VALUES
+    ('USD', 'US Dollar'),
+    ('EUR', 'Euro'),
+    ('GBP', 'Pound Sterling'),
+    ('CNY', 'Chinese Yuan'),
+    ('KZT', 'Kazakhstani Tenge'),
+    ('RUB', 'Russian Ruble'),
+    ('BRL', 'Brazilian Real');
+
+INSERT INTO bw_language (code, name) VALUES
+    ('en-US', 'English'),
+    ('zh-CN', '简体中文'),
+    ('fr-FR', 'Français'),
+    ('es-ES', 'Español'),
+    ('ru-RU', 'Русский'),
+    ('kk-KZ', 'Қазақ тілі'),
+    ('pt-BR', 'Português (Brasil)');
+
+ALTER TABLE bw_account
+    ALTER COLUMN local_currency DROP DEFAULT,
+    ALTER COLUMN local_currency TYPE VARCHAR (10) USING local_currency::TEXT,
+    ALTER COLUMN local_currency SET DEFAULT 'USD',
+    ALTER COLUMN system_lang DROP DEFAULT,
+    ALTER COLUMN system_lang TYPE VARCHAR (10) USING system_lang::TEXT,
+    ALTER COLUMN system_lang SET DEFAULT 'en-US';
+
+ALTER TABLE bw_account ADD FOREIGN KEY (local_currency) REFERENCES bw_currency(symbol);
+ALTER TABLE bw_account ADD FOREIGN KEY (system_lang) REFERENCES bw_language(code);
+
+DROP TYPE IF EXISTS currency;
+DROP TYPE IF EXISTS language;
\ No newline at end of file
@@ -0,0 +1,4 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS bw_account_lock;
+DROP TYPE IF EXISTS lock_event;
+ALTER TABLE bw_account DROP COLUMN IF EXISTS locked_until;
\ No newline at end of file
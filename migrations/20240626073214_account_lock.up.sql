@@ -0,0 +1,30 @@
+-- Add up migration script here
+ALTER TABLE bw_account ADD COLUMN locked_until TIMESTAMP;
+
+COMMENT ON COLUMN bw_account.failed_attempt IS '连续登录失败次数';
+COMMENT ON COLUMN bw_account.locked_until IS '账户锁定截止时间';
+
+CREATE TYPE lock_event AS ENUM ('locked', 'unlocked');
+COMMENT ON TYPE lock_event IS '枚举类型，表示账户锁定事件';
+
+CREATE TABLE bw_account_lock (
+    id BIGINT PRIMARY KEY DEFAULT next_id(),
+    uid BIGINT NOT NULL REFERENCES bw_account (uid) ON DELETE CASCADE,
+    event lock_event NOT NULL,
+    ip VARCHAR (64),
+    failed_attempt INT NOT NULL,
+    locked_until TIMESTAMP,
+
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_bw_account_lock_uid ON bw_account_lock (uid);
+CREATE INDEX idx_bw_account_lock_created_at ON bw_account_lock (created_at);
+
+COMMENT ON COLUMN bw_account_lock.id IS '事件ID';
+COMMENT ON COLUMN bw_account_lock.uid IS '账户ID';
+COMMENT ON COLUMN bw_account_lock.event IS '锁定或解锁';
+COMMENT ON COLUMN bw_account_lock.ip IS '触发事件的客户端IP';
+COMMENT ON COLUMN bw_account_lock.failed_attempt IS '事件发生时的连续登录失败次数';
+COMMENT ON COLUMN bw_account_lock.locked_until IS '锁定截止时间';
+COMMENT ON COLUMN bw_account_lock.created_at IS '记录创建时间';
\ No newline at end of file
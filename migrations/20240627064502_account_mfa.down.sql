@@ -0,0 +1,3 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS bw_account_recovery_code;
+DROP TABLE IF EXISTS bw_account_mfa;
\ No newline at end of file
@@ -0,0 +1,40 @@
+-- Add up migration script here
+CREATE TABLE bw_account_mfa (
+    uid BIGINT PRIMARY KEY REFERENCES bw_account (uid) ON DELETE CASCADE,
+    secret VARCHAR (64) NOT NULL,
+    enabled_at TIMESTAMP,
+    last_used_step BIGINT NOT NULL DEFAULT 0,
+
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP
+);
+
+CREATE TRIGGER update_bw_account_mfa_updated_at
+BEFORE UPDATE ON bw_account_mfa
+FOR EACH ROW
+EXECUTE FUNCTION update_at();
+
+COMMENT ON COLUMN bw_account_mfa.uid IS '账户ID';
+COMMENT ON COLUMN bw_account_mfa.secret IS 'TOTP 密钥 (base32)';
+COMMENT ON COLUMN bw_account_mfa.enabled_at IS '启用时间，为空表示尚未确认';
+COMMENT ON COLUMN bw_account_mfa.last_used_step IS '最近一次使用的 TOTP 时间步，防止重放';
+COMMENT ON COLUMN bw_account_mfa.created_at IS '记录创建时间';
+COMMENT ON COLUMN bw_account_mfa.updated_at IS '记录更新时间';
+
+CREATE TABLE bw_account_recovery_code (
+    id BIGINT PRIMARY KEY DEFAULT next_id(),
+    uid BIGINT NOT NULL REFERENCES bw_account (uid) ON DELETE CASCADE,
+    code_hash VARCHAR (64) NOT NULL,
+    used_at TIMESTAMP,
+
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE UNIQUE INDEX idx_bw_account_recovery_code_uid_hash
+ON bw_account_recovery_code (uid, code_hash);
+
+COMMENT ON COLUMN bw_account_recovery_code.id IS '恢复码ID';
+COMMENT ON COLUMN bw_account_recovery_code.uid IS '账户ID';
+COMMENT ON COLUMN bw_account_recovery_code.code_hash IS '恢复码的 SHA-256 摘要';
+COMMENT ON COLUMN bw_account_recovery_code.used_at IS '使用时间';
+COMMENT ON COLUMN bw_account_recovery_code.created_at IS '记录创建时间';
\ No newline at end of file
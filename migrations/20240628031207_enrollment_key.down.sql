@@ -0,0 +1,16 @@
+-- Add down migration script here
+-- Only the oldest key of each account survives the downgrade.
+DELETE FROM bw_account_setting s
+USING bw_account_setting o
+WHERE s.uid = o.uid AND (s.created_at, s.key_id) > (o.created_at, o.key_id);
+
+DROP TRIGGER IF EXISTS update_bw_account_setting_updated_at ON bw_account_setting;
+DROP INDEX IF EXISTS idx_bw_account_setting_uid;
+DROP INDEX IF EXISTS idx_bw_account_setting_key;
+CREATE INDEX idx_bw_account_setting_key ON bw_account_setting (key);
+
+ALTER TABLE bw_account_setting DROP COLUMN last_used_at;
+ALTER TABLE bw_account_setting DROP COLUMN expires_at;
+ALTER TABLE bw_account_setting DROP COLUMN name;
+ALTER TABLE bw_account_setting DROP COLUMN key_id;
+ALTER TABLE bw_account_setting ADD PRIMARY KEY (uid);
\ No newline at end of file
@@ -0,0 +1,25 @@
+-- Add up migration script here
+ALTER TABLE bw_account_setting DROP CONSTRAINT bw_account_setting_pkey;
+ALTER TABLE bw_account_setting
+    ADD COLUMN key_id BIGINT NOT NULL DEFAULT next_id() PRIMARY KEY;
+ALTER TABLE bw_account_setting
+    ADD COLUMN name VARCHAR (50) NOT NULL DEFAULT 'default';
+ALTER TABLE bw_account_setting ADD COLUMN expires_at TIMESTAMP;
+ALTER TABLE bw_account_setting ADD COLUMN last_used_at TIMESTAMP;
+
+DROP INDEX IF EXISTS idx_bw_account_setting_key;
+CREATE UNIQUE INDEX idx_bw_account_setting_key ON bw_account_setting (key);
+CREATE INDEX idx_bw_account_setting_uid ON bw_account_setting (uid);
+
+CREATE TRIGGER update_bw_account_setting_updated_at
+BEFORE UPDATE ON bw_account_setting
+FOR EACH ROW
+EXECUTE FUNCTION update_at();
+
+COMMENT ON COLUMN bw_account_setting.key_id IS '设备注册密钥ID';
+COMMENT ON COLUMN bw_account_setting.uid IS '账户ID';
+COMMENT ON COLUMN bw_account_setting.name IS '密钥名称';
+COMMENT ON COLUMN bw_account_setting.key IS '设备注册密钥';
+COMMENT ON COLUMN bw_account_setting.expires_at IS '过期时间，为空表示永不过期';
+COMMENT ON COLUMN bw_account_setting.last_used_at IS '最近一次设备使用该密钥的时间';
+COMMENT ON COLUMN bw_account_setting.deleted_at IS '吊销时间';
\ No newline at end of file
@@ -0,0 +1,3 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS bw_api_token;
+DROP TYPE IF EXISTS api_scope;
\ No newline at end of file
@@ -0,0 +1,40 @@
+-- Add up migration script here
+CREATE TYPE api_scope AS ENUM ('fleet_read', 'fleet_write', 'operate');
+COMMENT ON TYPE api_scope IS '枚举类型，表示个人访问令牌的权限范围';
+
+CREATE TABLE bw_api_token (
+    token_id BIGINT PRIMARY KEY DEFAULT next_id(),
+    uid BIGINT NOT NULL REFERENCES bw_account (uid) ON DELETE CASCADE,
+    name VARCHAR (50) NOT NULL,
+    token_hash VARCHAR (64) NOT NULL,
+    prefix VARCHAR (16) NOT NULL,
+    scopes api_scope [] NOT NULL,
+    rate_limit INT NOT NULL,
+    expires_at TIMESTAMP,
+    last_used_at TIMESTAMP,
+
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP,
+    deleted_at TIMESTAMP
+);
+
+CREATE UNIQUE INDEX idx_bw_api_token_token_hash ON bw_api_token (token_hash);
+CREATE INDEX idx_bw_api_token_uid ON bw_api_token (uid);
+
+CREATE TRIGGER update_bw_api_token_updated_at
+BEFORE UPDATE ON bw_api_token
+FOR EACH ROW
+EXECUTE FUNCTION update_at();
+
+COMMENT ON COLUMN bw_api_token.token_id IS '令牌ID';
+COMMENT ON COLUMN bw_api_token.uid IS '账户ID';
+COMMENT ON COLUMN bw_api_token.name IS '令牌名称';
+COMMENT ON COLUMN bw_api_token.token_hash IS '令牌的SHA-256哈希';
+COMMENT ON COLUMN bw_api_token.prefix IS '令牌明文前缀，用于辨认令牌';
+COMMENT ON COLUMN bw_api_token.scopes IS '权限范围';
+COMMENT ON COLUMN bw_api_token.rate_limit IS '每分钟允许的请求数';
+COMMENT ON COLUMN bw_api_token.expires_at IS '过期时间，为空表示永不过期';
+COMMENT ON COLUMN bw_api_token.last_used_at IS '最近使用时间';
+COMMENT ON COLUMN bw_api_token.created_at IS '记录创建时间';
+COMMENT ON COLUMN bw_api_token.updated_at IS '记录更新时间';
+COMMENT ON COLUMN bw_api_token.deleted_at IS '吊销时间';
\ No newline at end of file
@@ -0,0 +1,4 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS bw_organization_member;
+DROP TABLE IF EXISTS bw_organization;
+DROP TYPE IF EXISTS org_role;
\ No newline at end of file
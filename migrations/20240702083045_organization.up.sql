@@ -0,0 +1,50 @@
+-- Add up migration script here
+CREATE TYPE org_role AS ENUM ('owner', 'admin', 'operator', 'viewer');
+COMMENT ON TYPE org_role IS '枚举类型，表示组织成员的角色';
+
+CREATE TABLE bw_organization (
+    org_id BIGINT PRIMARY KEY DEFAULT next_id(),
+    owner_uid BIGINT NOT NULL REFERENCES bw_account (uid) ON DELETE CASCADE,
+    name VARCHAR (50) NOT NULL,
+
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP
+);
+
+CREATE UNIQUE INDEX idx_bw_organization_owner_uid ON bw_organization (owner_uid);
+
+CREATE TRIGGER update_bw_organization_updated_at
+BEFORE UPDATE ON bw_organization
+FOR EACH ROW
+EXECUTE FUNCTION update_at();
+
+COMMENT ON COLUMN bw_organization.org_id IS '组织ID';
+COMMENT ON COLUMN bw_organization.owner_uid IS '所有者账户ID，组织共享该账户下的矿机';
+COMMENT ON COLUMN bw_organization.name IS '组织名称';
+COMMENT ON COLUMN bw_organization.created_at IS '记录创建时间';
+COMMENT ON COLUMN bw_organization.updated_at IS '记录更新时间';
+
+CREATE TABLE bw_organization_member (
+    org_id BIGINT NOT NULL REFERENCES bw_organization (org_id) ON DELETE CASCADE,
+    uid BIGINT NOT NULL REFERENCES bw_account (uid) ON DELETE CASCADE,
+    role org_role NOT NULL,
+    group_ids BIGINT [],
+
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP,
+    PRIMARY KEY (org_id, uid)
+);
+
+CREATE INDEX idx_bw_organization_member_uid ON bw_organization_member (uid);
+
+CREATE TRIGGER update_bw_organization_member_updated_at
+BEFORE UPDATE ON bw_organization_member
+FOR EACH ROW
+EXECUTE FUNCTION update_at();
+
+COMMENT ON COLUMN bw_organization_member.org_id IS '组织ID';
+COMMENT ON COLUMN bw_organization_member.uid IS '成员账户ID';
+COMMENT ON COLUMN bw_organization_member.role IS '成员角色';
+COMMENT ON COLUMN bw_organization_member.group_ids IS '可访问的分组ID，为空表示可访问全部分组';
+COMMENT ON COLUMN bw_organization_member.created_at IS '记录创建时间';
+COMMENT ON COLUMN bw_organization_member.updated_at IS '记录更新时间';
\ No newline at end of file
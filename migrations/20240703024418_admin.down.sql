@@ -0,0 +1,4 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS bw_admin_audit;
+DROP TYPE IF EXISTS admin_action;
+DROP TABLE IF EXISTS bw_admin;
\ No newline at end of file
@@ -0,0 +1,61 @@
+-- Add up migration script here
+CREATE TABLE bw_admin (
+    admin_id BIGINT PRIMARY KEY DEFAULT next_id(),
+    name VARCHAR (50) NOT NULL,
+    email VARCHAR (255) NOT NULL,
+    password VARCHAR (255) NOT NULL,
+    last_login TIMESTAMP,
+
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP,
+    deleted_at TIMESTAMP
+);
+
+CREATE UNIQUE INDEX idx_bw_admin_email ON bw_admin (email);
+
+CREATE TRIGGER update_bw_admin_updated_at
+BEFORE UPDATE ON bw_admin
+FOR EACH ROW
+EXECUTE FUNCTION update_at();
+
+COMMENT ON COLUMN bw_admin.admin_id IS '管理员ID';
+COMMENT ON COLUMN bw_admin.name IS '管理员名称';
+COMMENT ON COLUMN bw_admin.email IS '管理员邮箱';
+COMMENT ON COLUMN bw_admin.password IS '管理员密码哈希';
+COMMENT ON COLUMN bw_admin.last_login IS '最近登录时间';
+COMMENT ON COLUMN bw_admin.created_at IS '记录创建时间';
+COMMENT ON COLUMN bw_admin.updated_at IS '记录更新时间';
+COMMENT ON COLUMN bw_admin.deleted_at IS '停用时间';
+
+CREATE TYPE admin_action AS ENUM (
+    'login',
+    'create_admin',
+    'suspend_account',
+    'reactivate_account',
+    'force_password_reset',
+    'transfer_machine'
+);
+COMMENT ON TYPE admin_action IS '枚举类型，表示管理员操作';
+
+CREATE TABLE bw_admin_audit (
+    id BIGINT PRIMARY KEY DEFAULT next_id(),
+    admin_id BIGINT NOT NULL REFERENCES bw_admin (admin_id),
+    action admin_action NOT NULL,
+    target_uid BIGINT,
+    detail JSONB NOT NULL DEFAULT '{}',
+    ip VARCHAR (64),
+
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_bw_admin_audit_admin_id ON bw_admin_audit (admin_id);
+CREATE INDEX idx_bw_admin_audit_target_uid ON bw_admin_audit (target_uid);
+CREATE INDEX idx_bw_admin_audit_created_at ON bw_admin_audit (created_at);
+
+COMMENT ON COLUMN bw_admin_audit.id IS '审计记录ID';
+COMMENT ON COLUMN bw_admin_audit.admin_id IS '执行操作的管理员ID';
+COMMENT ON COLUMN bw_admin_audit.action IS '操作类型';
+COMMENT ON COLUMN bw_admin_audit.target_uid IS '被操作的账户ID';
+COMMENT ON COLUMN bw_admin_audit.detail IS '操作详情';
+COMMENT ON COLUMN bw_admin_audit.ip IS '管理员客户端IP';
+COMMENT ON COLUMN bw_admin_audit.created_at IS '记录创建时间';
\ No newline at end of file
@@ -0,0 +1,3 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS bw_account_code;
+DROP TYPE IF EXISTS code_purpose;
\ No newline at end of file
@@ -0,0 +1,26 @@
+-- Add up migration script here
+CREATE TYPE code_purpose AS ENUM ('verify_email', 'reset_password');
+COMMENT ON TYPE code_purpose IS '枚举类型，表示邮件验证码的用途';
+
+CREATE TABLE bw_account_code (
+    id BIGINT PRIMARY KEY DEFAULT next_id(),
+    uid BIGINT NOT NULL REFERENCES bw_account (uid) ON DELETE CASCADE,
+    purpose code_purpose NOT NULL,
+    code_hash VARCHAR (64) NOT NULL,
+    attempts INT NOT NULL DEFAULT 0,
+    expires_at TIMESTAMP NOT NULL,
+    used_at TIMESTAMP,
+
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_bw_account_code_uid_purpose ON bw_account_code (uid, purpose);
+
+COMMENT ON COLUMN bw_account_code.id IS '验证码ID';
+COMMENT ON COLUMN bw_account_code.uid IS '账户ID';
+COMMENT ON COLUMN bw_account_code.purpose IS '验证码用途';
+COMMENT ON COLUMN bw_account_code.code_hash IS '验证码的SHA-256哈希';
+COMMENT ON COLUMN bw_account_code.attempts IS '已失败的校验次数';
+COMMENT ON COLUMN bw_account_code.expires_at IS '过期时间';
+COMMENT ON COLUMN bw_account_code.used_at IS '使用时间，为空表示未使用';
+COMMENT ON COLUMN bw_account_code.created_at IS '记录创建时间';
\ No newline at end of file
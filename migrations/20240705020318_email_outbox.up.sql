@@ -0,0 +1,44 @@
+-- Add up migration script here
+CREATE TYPE email_status AS ENUM ('pending', 'queued', 'retrying', 'sent', 'failed');
+COMMENT ON TYPE email_status IS '枚举类型，表示邮件的投递状态';
+
+CREATE TABLE bw_email_outbox (
+    email_id BIGINT PRIMARY KEY DEFAULT next_id(),
+    uid BIGINT REFERENCES bw_account (uid) ON DELETE SET NULL,
+    recipient VARCHAR (255) NOT NULL,
+    subject VARCHAR (255) NOT NULL,
+    template VARCHAR (50) NOT NULL,
+    params JSONB NOT NULL,
+    lang VARCHAR (10) NOT NULL,
+    status email_status NOT NULL DEFAULT 'pending',
+    attempts INT NOT NULL DEFAULT 0,
+    last_error TEXT,
+    next_attempt_at TIMESTAMP,
+    sent_at TIMESTAMP,
+
+    created_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMP
+);
+
+CREATE INDEX idx_bw_email_outbox_status ON bw_email_outbox (status, created_at);
+CREATE INDEX idx_bw_email_outbox_uid ON bw_email_outbox (uid);
+
+CREATE TRIGGER update_bw_email_outbox_updated_at
+BEFORE UPDATE ON bw_email_outbox
+FOR EACH ROW
+EXECUTE FUNCTION update_at();
+
+COMMENT ON COLUMN bw_email_outbox.email_id IS '邮件ID';
+COMMENT ON COLUMN bw_email_outbox.uid IS '收件账户ID，系统邮件为空';
+COMMENT ON COLUMN bw_email_outbox.recipient IS '收件地址';
+COMMENT ON COLUMN bw_email_outbox.subject IS '邮件主题';
+COMMENT ON COLUMN bw_email_outbox.template IS '邮件模板';
+COMMENT ON COLUMN bw_email_outbox.params IS '模板参数';
+COMMENT ON COLUMN bw_email_outbox.lang IS '邮件语言';
+COMMENT ON COLUMN bw_email_outbox.status IS '投递状态';
+COMMENT ON COLUMN bw_email_outbox.attempts IS '已尝试发送的次数';
+COMMENT ON COLUMN bw_email_outbox.last_error IS '最近一次发送失败的原因';
+COMMENT ON COLUMN bw_email_outbox.next_attempt_at IS '下次重试时间';
+COMMENT ON COLUMN bw_email_outbox.sent_at IS '发送成功时间';
+COMMENT ON COLUMN bw_email_outbox.created_at IS '记录创建时间';
+COMMENT ON COLUMN bw_email_outbox.updated_at IS '记录更新时间';
\ No newline at end of file
@@ -0,0 +1,4 @@
+-- Add up migration script here
+ALTER TABLE bw_account ADD COLUMN last_failed_at TIMESTAMP;
+
+COMMENT ON COLUMN bw_account.last_failed_at IS '最近一次登录失败时间';
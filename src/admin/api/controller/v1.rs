@@ -0,0 +1,6 @@
+pub mod account;
+pub mod audit;
+pub mod auth;
+pub mod email;
+pub mod machine;
+pub mod stat;
@@ -0,0 +1,109 @@
+use std::sync::Arc;
+
+use axum::{extract::State, response::IntoResponse, Json};
+use serde_json::json;
+
+use crate::{
+    admin::{
+        entity::{
+            account::{
+                AccountActionRequest, AccountSummary, SearchAccountRequest,
+            },
+            PagedResponse,
+        },
+        service::{
+            account_service, audit_service::Entry, jwt_service::AdminClaims,
+        },
+    },
+    library::error::AppResult,
+    miner::{
+        bootstrap::AppState, entity::common::SuccessResponse,
+        service::session_service::ClientInfo,
+    },
+    models::{
+        account::{BwAccount, SearchBwAccountSchema},
+        types::AdminAction,
+    },
+};
+
+pub async fn search_accounts_handler(
+    State(state): State<Arc<AppState>>,
+    _claims: AdminClaims,
+    Json(body): Json<SearchAccountRequest>,
+) -> AppResult<impl IntoResponse> {
+    let item = SearchBwAccountSchema {
+        keyword: body.keyword.filter(|k| !k.trim().is_empty()),
+        status: body.status,
+        offset: body.page.offset(),
+        limit: body.page.limit(),
+    };
+    let (accounts, total) =
+        BwAccount::search_accounts(state.get_db(), &item).await?;
+    Ok(SuccessResponse {
+        msg: "success",
+        data: Some(Json(PagedResponse {
+            total,
+            items: accounts
+                .into_iter()
+                .map(AccountSummary::from)
+                .collect::<Vec<_>>(),
+        })),
+    })
+}
+
+fn entry<'a>(
+    claims: &AdminClaims,
+    client: &'a ClientInfo,
+    action: AdminAction,
+    body: &AccountActionRequest,
+) -> Entry<'a> {
+    Entry {
+        admin_id: claims.admin_id,
+        client,
+        action,
+        target_uid: Some(body.uid),
+        detail: json!({ "reason": body.reason }),
+    }
+}
+
+pub async fn suspend_account_handler(
+    State(state): State<Arc<AppState>>,
+    claims: AdminClaims,
+    client: ClientInfo,
+    Json(body): Json<AccountActionRequest>,
+) -> AppResult<impl IntoResponse> {
+    let entry = entry(&claims, &client, AdminAction::SuspendAccount, &body);
+    account_service::suspend(&state, body.uid, entry).await?;
+    Ok(SuccessResponse {
+        msg: "success",
+        data: None::<()>,
+    })
+}
+
+pub async fn reactivate_account_handler(
+    State(state): State<Arc<AppState>>,
+    claims: AdminClaims,
+    client: ClientInfo,
+    Json(body): Json<AccountActionRequest>,
+) -> AppResult<impl IntoResponse> {
+    let entry = entry(&claims, &client, AdminAction::ReactivateAccount, &body);
+    account_service::reactivate(&state, body.uid, entry).await?;
+    Ok(SuccessResponse {
+        msg: "success",
+        data: None::<()>,
+    })
+}
+
+pub async fn force_password_reset_handler(
+    State(state): State<Arc<AppState>>,
+    claims: AdminClaims,
+    client: ClientInfo,
+    Json(body): Json<AccountActionRequest>,
+) -> AppResult<impl IntoResponse> {
+    let entry = entry(&claims, &client, AdminAction::ForcePasswordReset, &body);
+    account_service::force_password_reset(&state, body.uid, entry).await?;
+    Ok(SuccessResponse {
+        msg: "success",
+        data: None::<()>,
+    })
+}
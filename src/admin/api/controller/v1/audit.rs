@@ -0,0 +1,32 @@
+use std::sync::Arc;
+
+use axum::{extract::State, response::IntoResponse, Json};
+
+use crate::{
+    admin::{
+        entity::{audit::ReadAuditRequest, PagedResponse},
+        service::jwt_service::AdminClaims,
+    },
+    library::error::AppResult,
+    miner::{bootstrap::AppState, entity::common::SuccessResponse},
+    models::admin::{BwAdminAudit, ReadBwAdminAuditSchema},
+};
+
+pub async fn get_audits_handler(
+    State(state): State<Arc<AppState>>,
+    _claims: AdminClaims,
+    Json(body): Json<ReadAuditRequest>,
+) -> AppResult<impl IntoResponse> {
+    let item = ReadBwAdminAuditSchema {
+        admin_id: body.admin_id,
+        target_uid: body.target_uid,
+        offset: body.page.offset(),
+        limit: body.page.limit(),
+    };
+    let (items, total) =
+        BwAdminAudit::fetch_audits(state.get_db(), &item).await?;
+    Ok(SuccessResponse {
+        msg: "success",
+        data: Some(Json(PagedResponse { total, items })),
+    })
+}
@@ -0,0 +1,85 @@
+use std::sync::Arc;
+
+use axum::{extract::State, response::IntoResponse, Json};
+use serde_json::json;
+
+use crate::{
+    admin::{
+        entity::auth::{
+            AdminLoginRequest, AdminLoginResponse, CreateAdminRequest,
+        },
+        service::{
+            admin_service,
+            audit_service::{self, Entry},
+            jwt_service::AdminClaims,
+        },
+    },
+    library::{
+        crypto,
+        error::{ApiInnerError, AppError::ApiError, AppInnerError, AppResult},
+    },
+    miner::{
+        bootstrap::AppState, entity::common::SuccessResponse,
+        service::session_service::ClientInfo,
+    },
+    models::{
+        admin::{BwAdmin, CreateBwAdminSchema},
+        types::AdminAction,
+    },
+};
+
+pub async fn login_handler(
+    State(state): State<Arc<AppState>>,
+    client: ClientInfo,
+    Json(body): Json<AdminLoginRequest>,
+) -> AppResult<impl IntoResponse> {
+    let admin =
+        admin_service::login(&state, &body.email, &body.password, &client)
+            .await?;
+    let access_token = AdminClaims::generate_token(&admin)?;
+    let entry = Entry {
+        admin_id: admin.admin_id,
+        client: &client,
+        action: AdminAction::Login,
+        target_uid: None,
+        detail: json!({ "device": client.device }),
+    };
+    audit_service::record(state.get_db(), entry).await?;
+    Ok(SuccessResponse {
+        msg: "success",
+        data: Some(Json(AdminLoginResponse {
+            access_token,
+            admin,
+        })),
+    })
+}
+
+pub async fn create_admin_handler(
+    State(state): State<Arc<AppState>>,
+    claims: AdminClaims,
+    client: ClientInfo,
+    Json(body): Json<CreateAdminRequest>,
+) -> AppResult<impl IntoResponse> {
+    let item = CreateBwAdminSchema {
+        name: body.name,
+        email: body.email,
+        password: crypto::hash_password(body.password.as_bytes())?,
+    };
+    let mut tx = state.get_db().begin().await.map_err(AppInnerError::from)?;
+    let admin = BwAdmin::create_bw_admin(&mut *tx, &item)
+        .await?
+        .ok_or(ApiError(ApiInnerError::AdminAlreadyExists))?;
+    let entry = Entry {
+        admin_id: claims.admin_id,
+        client: &client,
+        action: AdminAction::CreateAdmin,
+        target_uid: None,
+        detail: json!({ "admin_id": admin.admin_id, "email": admin.email }),
+    };
+    audit_service::record(&mut *tx, entry).await?;
+    tx.commit().await.map_err(AppInnerError::from)?;
+    Ok(SuccessResponse {
+        msg: "success",
+        data: Some(Json(admin)),
+    })
+}
@@ -0,0 +1,65 @@
+use std::sync::Arc;
+
+use axum::{extract::State, response::IntoResponse, Json};
+
+use crate::{
+    admin::{
+        entity::{
+            email::{EmailIdRequest, PreviewEmailRequest, ReadEmailRequest},
+            PagedResponse,
+        },
+        service::jwt_service::AdminClaims,
+    },
+    library::{
+        error::{ApiInnerError, AppError::ApiError, AppResult},
+        templator::MailTemplate,
+    },
+    miner::{bootstrap::AppState, entity::common::SuccessResponse},
+    models::email_outbox::{BwEmailOutbox, ReadBwEmailOutboxSchema},
+};
+
+/// Renders a template with sample values.
+pub async fn preview_email_handler(
+    _claims: AdminClaims,
+    Json(body): Json<PreviewEmailRequest>,
+) -> AppResult<impl IntoResponse> {
+    let rendered = MailTemplate::sample(body.template).render(&body.lang)?;
+    Ok(SuccessResponse {
+        msg: "success",
+        data: Some(Json(rendered)),
+    })
+}
+
+pub async fn get_emails_handler(
+    State(state): State<Arc<AppState>>,
+    _claims: AdminClaims,
+    Json(body): Json<ReadEmailRequest>,
+) -> AppResult<impl IntoResponse> {
+    let item = ReadBwEmailOutboxSchema {
+        uid: body.uid,
+        status: body.status,
+        recipient: body.recipient,
+        offset: body.page.offset(),
+        limit: body.page.limit(),
+    };
+    let (items, total) =
+        BwEmailOutbox::fetch_emails(state.get_db(), &item).await?;
+    Ok(SuccessResponse {
+        msg: "success",
+        data: Some(Json(PagedResponse { total, items })),
+    })
+}
+
+pub async fn get_email_handler(
+    State(state): State<Arc<AppState>>,
+    _claims: AdminClaims,
+    Json(body): Json<EmailIdRequest>,
+) -> AppResult<impl IntoResponse> {
+    let email = BwEmailOutbox::fetch_email_by_id(state.get_db(), body.email_id)
+        .await?
+        .ok_or(ApiError(ApiInnerError::EmailNotFound))?;
+    Ok(SuccessResponse {
+        msg: "success",
+        data: Some(Json(email)),
+    })
+}
@@ -0,0 +1,59 @@
+use std::sync::Arc;
+
+use axum::{extract::State, response::IntoResponse, Json};
+use serde_json::json;
+
+use crate::{
+    admin::{
+        entity::machine::TransferMachineRequest,
+        service::{
+            audit_service::{self, Entry},
+            jwt_service::AdminClaims,
+        },
+    },
+    library::error::{
+        ApiInnerError, AppError::ApiError, AppInnerError, AppResult,
+    },
+    miner::{
+        bootstrap::AppState,
+        entity::common::SuccessResponse,
+        service::{machine_service, session_service::ClientInfo},
+    },
+    models::{account::BwAccount, machine::BwMachine, types::AdminAction},
+};
+
+pub async fn transfer_machine_handler(
+    State(state): State<Arc<AppState>>,
+    claims: AdminClaims,
+    client: ClientInfo,
+    Json(body): Json<TransferMachineRequest>,
+) -> AppResult<impl IntoResponse> {
+    if BwAccount::check_user_exists_by_uid(state.get_db(), &body.to_uid).await?
+        != Some(true)
+    {
+        return Err(ApiError(ApiInnerError::AccountNotFound));
+    }
+    let mut tx = state.get_db().begin().await.map_err(AppInnerError::from)?;
+    let (from_uid, machine) =
+        BwMachine::transfer_bw_machine(&mut tx, &body.mac, body.to_uid)
+            .await?
+            .ok_or(ApiError(ApiInnerError::MachineNotFound))?;
+    let entry = Entry {
+        admin_id: claims.admin_id,
+        client: &client,
+        action: AdminAction::TransferMachine,
+        target_uid: Some(body.to_uid),
+        detail: json!({
+            "mac": machine.mac,
+            "from_uid": from_uid,
+            "reason": body.reason,
+        }),
+    };
+    audit_service::record(&mut *tx, entry).await?;
+    tx.commit().await.map_err(AppInnerError::from)?;
+    machine_service::invalidate(&state, &machine.mac).await?;
+    Ok(SuccessResponse {
+        msg: "success",
+        data: Some(Json(machine)),
+    })
+}
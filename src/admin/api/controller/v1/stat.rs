@@ -0,0 +1,21 @@
+use std::sync::Arc;
+
+use axum::{extract::State, response::IntoResponse, Json};
+
+use crate::{
+    admin::service::jwt_service::AdminClaims,
+    library::error::AppResult,
+    miner::{bootstrap::AppState, entity::common::SuccessResponse},
+    models::admin::PlatformStats,
+};
+
+pub async fn get_platform_stats_handler(
+    State(state): State<Arc<AppState>>,
+    _claims: AdminClaims,
+) -> AppResult<impl IntoResponse> {
+    let stats = PlatformStats::fetch(state.get_db()).await?;
+    Ok(SuccessResponse {
+        msg: "success",
+        data: Some(Json(stats)),
+    })
+}
@@ -0,0 +1,40 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Request, State},
+    http::header::AUTHORIZATION,
+    middleware::Next,
+    response::Response,
+};
+
+use crate::{
+    admin::service::jwt_service::AdminClaims,
+    library::error::{AppError::AuthError, AppResult, AuthInnerError},
+    miner::bootstrap::AppState,
+    models::admin::BwAdmin,
+};
+
+/// Accepts admin access tokens of admins that have not been removed since.
+pub async fn handle(
+    State(state): State<Arc<AppState>>,
+    mut request: Request,
+    next: Next,
+) -> AppResult<Response> {
+    let token = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|auth_header| auth_header.to_str().ok())
+        .and_then(|auth_value| auth_value.strip_prefix("Bearer "))
+        .ok_or(AuthError(AuthInnerError::InvalidToken))?;
+
+    let claims = AdminClaims::parse_token(token)?;
+    if BwAdmin::check_admin_exists_by_id(state.get_db(), claims.admin_id)
+        .await?
+        != Some(true)
+    {
+        return Err(AuthError(AuthInnerError::InvalidToken));
+    }
+    request.extensions_mut().insert(claims);
+
+    Ok(next.run(request).await)
+}
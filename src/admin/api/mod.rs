@@ -0,0 +1,63 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use tokio::net::TcpListener;
+
+use crate::{
+    admin::service::admin_service,
+    library::cfg,
+    miner::bootstrap::{shutdown_signal, AppState},
+};
+
+pub mod controller;
+pub mod middleware;
+pub mod route;
+
+pub struct Server {
+    pub host: &'static str,
+    pub port: usize,
+    pub app_state: Arc<AppState>,
+}
+
+impl Server {
+    pub fn init(app_state: Arc<AppState>) -> Self {
+        let config = cfg::config();
+        let host = &config.admin.api_host;
+        let port = config.admin.api_port;
+        Self {
+            host,
+            port,
+            app_state,
+        }
+    }
+
+    pub async fn serve(self) {
+        admin_service::bootstrap(&self.app_state)
+            .await
+            .unwrap_or_else(|e| {
+                panic!("💥 Failed to create bootstrap admin: {e:?}")
+            });
+
+        let app = route::init(self.app_state.clone());
+        let listener =
+            TcpListener::bind(format!("{}:{}", self.host, self.port))
+                .await
+                .unwrap_or_else(|e| {
+                    panic!("💥 Failed to connect bind TcpListener: {e:?}")
+                });
+
+        tracing::info!(
+            "✨ admin listening on {}",
+            listener.local_addr().unwrap_or_else(|e| panic!(
+                "💥 Failed to connect bind TcpListener: {e:?}"
+            ))
+        );
+
+        axum::serve(
+            listener,
+            app.into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .with_graceful_shutdown(shutdown_signal())
+        .await
+        .unwrap_or_else(|e| panic!("💥 Failed to start admin server: {e:?}"));
+    }
+}
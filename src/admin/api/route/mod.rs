@@ -0,0 +1,61 @@
+use std::{sync::Arc, time::Duration};
+
+use axum::{
+    middleware::{from_fn, from_fn_with_state},
+    routing::post,
+    Router,
+};
+use tower_http::timeout::TimeoutLayer;
+
+use super::{
+    controller::v1::{
+        account::{
+            force_password_reset_handler, reactivate_account_handler,
+            search_accounts_handler, suspend_account_handler,
+        },
+        audit::get_audits_handler,
+        auth::{create_admin_handler, login_handler},
+        email::{get_email_handler, get_emails_handler, preview_email_handler},
+        machine::transfer_machine_handler,
+        stat::get_platform_stats_handler,
+    },
+    middleware::auth,
+};
+use crate::miner::{
+    api::{
+        controller::handler_404,
+        middleware::{cors, log, req_id},
+    },
+    bootstrap::AppState,
+};
+
+pub fn init(state: Arc<AppState>) -> Router {
+    let open = Router::new().route("/auth/login", post(login_handler));
+
+    let auth = Router::new()
+        .route("/admins/create", post(create_admin_handler))
+        .route("/accounts/search", post(search_accounts_handler))
+        .route("/accounts/suspend", post(suspend_account_handler))
+        .route("/accounts/reactivate", post(reactivate_account_handler))
+        .route(
+            "/accounts/reset_password",
+            post(force_password_reset_handler),
+        )
+        .route("/machines/transfer", post(transfer_machine_handler))
+        .route("/stats", post(get_platform_stats_handler))
+        .route("/audits/list", post(get_audits_handler))
+        .route("/emails/list", post(get_emails_handler))
+        .route("/emails/get", post(get_email_handler))
+        .route("/emails/preview", post(preview_email_handler))
+        .route_layer(from_fn_with_state(state.clone(), auth::handle))
+        .with_state(state.clone());
+
+    Router::new()
+        .nest("/admin/v1", open.merge(auth))
+        .fallback(handler_404)
+        .with_state(state)
+        .layer(TimeoutLayer::new(Duration::from_secs(30)))
+        .layer(from_fn(log::handle))
+        .layer(from_fn(cors::handle))
+        .layer(from_fn(req_id::handle))
+}
@@ -0,0 +1,57 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+use super::Page;
+use crate::models::{
+    account::BwAccount,
+    types::{AccountStatus, Currency, Language},
+};
+
+#[derive(Debug, Deserialize)]
+pub struct SearchAccountRequest {
+    pub keyword: Option<String>,
+    pub status: Option<AccountStatus>,
+    #[serde(flatten)]
+    pub page: Page,
+}
+
+/// An account as shown to admins, without its password hash.
+#[derive(Debug, Serialize)]
+pub struct AccountSummary {
+    pub uid: i64,
+    pub name: String,
+    pub email: String,
+    pub email_verified_at: Option<NaiveDateTime>,
+    pub status: AccountStatus,
+    pub failed_attempt: i32,
+    pub locked_until: Option<NaiveDateTime>,
+    pub last_login: Option<NaiveDateTime>,
+    pub local_currency: Currency,
+    pub system_lang: Language,
+    pub created_at: NaiveDateTime,
+}
+
+impl From<BwAccount> for AccountSummary {
+    fn from(account: BwAccount) -> Self {
+        Self {
+            uid: account.uid,
+            name: account.name,
+            email: account.email,
+            email_verified_at: account.email_verified_at,
+            status: account.status,
+            failed_attempt: account.failed_attempt,
+            locked_until: account.locked_until,
+            last_login: account.last_login,
+            local_currency: account.local_currency,
+            system_lang: account.system_lang,
+            created_at: account.created_at,
+        }
+    }
+}
+
+/// Target of an account action, with the reason kept in the audit log.
+#[derive(Debug, Deserialize)]
+pub struct AccountActionRequest {
+    pub uid: i64,
+    pub reason: Option<String>,
+}
@@ -0,0 +1,11 @@
+use serde::Deserialize;
+
+use super::Page;
+
+#[derive(Debug, Deserialize)]
+pub struct ReadAuditRequest {
+    pub admin_id: Option<i64>,
+    pub target_uid: Option<i64>,
+    #[serde(flatten)]
+    pub page: Page,
+}
@@ -0,0 +1,22 @@
+use serde::{Deserialize, Serialize};
+
+use crate::models::admin::BwAdmin;
+
+#[derive(Debug, Deserialize)]
+pub struct AdminLoginRequest {
+    pub email: String,
+    pub password: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AdminLoginResponse {
+    pub access_token: String,
+    pub admin: BwAdmin,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateAdminRequest {
+    pub name: String,
+    pub email: String,
+    pub password: String,
+}
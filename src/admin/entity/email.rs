@@ -0,0 +1,28 @@
+use serde::Deserialize;
+
+use super::Page;
+use crate::{
+    library::templator::TemplateKind,
+    models::types::{EmailStatus, Language},
+};
+
+#[derive(Debug, Deserialize)]
+pub struct PreviewEmailRequest {
+    pub template: TemplateKind,
+    #[serde(default)]
+    pub lang: Language,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReadEmailRequest {
+    pub uid: Option<i64>,
+    pub status: Option<EmailStatus>,
+    pub recipient: Option<String>,
+    #[serde(flatten)]
+    pub page: Page,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EmailIdRequest {
+    pub email_id: i64,
+}
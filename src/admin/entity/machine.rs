@@ -0,0 +1,8 @@
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub struct TransferMachineRequest {
+    pub mac: String,
+    pub to_uid: i64,
+    pub reason: Option<String>,
+}
@@ -0,0 +1,42 @@
+pub mod account;
+pub mod audit;
+pub mod auth;
+pub mod email;
+pub mod machine;
+
+use serde::{Deserialize, Serialize};
+
+const MAX_PAGE_SIZE: u32 = 100;
+
+/// Paging of list requests, 1-based.
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct Page {
+    #[serde(default = "Page::default_page")]
+    pub page: u32,
+    #[serde(default = "Page::default_page_size")]
+    pub page_size: u32,
+}
+
+impl Page {
+    const fn default_page() -> u32 {
+        1
+    }
+
+    const fn default_page_size() -> u32 {
+        20
+    }
+
+    pub fn offset(&self) -> i64 {
+        i64::from(self.page.max(1) - 1) * self.limit()
+    }
+
+    pub fn limit(&self) -> i64 {
+        i64::from(self.page_size.clamp(1, MAX_PAGE_SIZE))
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct PagedResponse<T> {
+    pub total: i64,
+    pub items: Vec<T>,
+}
@@ -1 +1,9 @@
-// TODO: this is for admin only also needs to be struct as a web server.
+//! Admin console for platform operators.
+//!
+//! Served on its own port, with its own accounts and tokens, on top of the
+//! state of the miner server. Every action changing the platform is
+//! recorded in `bw_admin_audit`.
+
+pub mod api;
+pub mod entity;
+pub mod service;
@@ -0,0 +1,110 @@
+//! Account actions of the admin console. Each is recorded in the audit log
+//! in the transaction making it, unless it changed nothing, and returns
+//! whether it did. Caches and sessions are dealt with once it is committed.
+
+use crate::{
+    admin::service::audit_service::{self, Entry},
+    library::{
+        crypto,
+        error::{ApiInnerError, AppError::ApiError, AppInnerError, AppResult},
+        templator::{MailTemplate, NoticeParams},
+    },
+    miner::{
+        bootstrap::AppState,
+        service::{account_service, message_queue, session_service},
+    },
+    models::{
+        account::{BwAccount, ResetPasswordSchema},
+        api_token::BwApiToken,
+    },
+};
+
+async fn fetch_account(state: &AppState, uid: i64) -> AppResult<BwAccount> {
+    BwAccount::fetch_user_by_uid(state.get_db(), uid)
+        .await?
+        .ok_or(ApiError(ApiInnerError::AccountNotFound))
+}
+
+/// Signs the account out everywhere: sessions and personal API tokens.
+async fn revoke_access(state: &AppState, uid: i64) -> AppResult<()> {
+    session_service::revoke_all(state, uid).await?;
+    BwApiToken::revoke_tokens_by_uid(state.get_db(), uid).await?;
+    Ok(())
+}
+
+async fn notify(state: &AppState, account: &BwAccount, template: MailTemplate) {
+    if let Err(e) = message_queue::queue_email(
+        state,
+        Some(account.uid),
+        &account.email,
+        &template,
+        &account.system_lang,
+    )
+    .await
+    {
+        tracing::error!("Failed to queue email to {}: {}", account.email, e);
+    }
+}
+
+pub async fn suspend(
+    state: &AppState,
+    uid: i64,
+    entry: Entry<'_>,
+) -> AppResult<bool> {
+    fetch_account(state, uid).await?;
+    let mut tx = state.get_db().begin().await.map_err(AppInnerError::from)?;
+    let changed = BwAccount::suspend(&mut *tx, uid).await? == 1;
+    if changed {
+        audit_service::record(&mut *tx, entry).await?;
+    }
+    tx.commit().await.map_err(AppInnerError::from)?;
+    if changed {
+        account_service::invalidate(state, uid).await?;
+        revoke_access(state, uid).await?;
+    }
+    Ok(changed)
+}
+
+pub async fn reactivate(
+    state: &AppState,
+    uid: i64,
+    entry: Entry<'_>,
+) -> AppResult<bool> {
+    fetch_account(state, uid).await?;
+    let mut tx = state.get_db().begin().await.map_err(AppInnerError::from)?;
+    let changed = BwAccount::reactivate(&mut *tx, uid).await? == 1;
+    if changed {
+        audit_service::record(&mut *tx, entry).await?;
+    }
+    tx.commit().await.map_err(AppInnerError::from)?;
+    if changed {
+        account_service::invalidate(state, uid).await?;
+    }
+    Ok(changed)
+}
+
+/// Replaces the password with a random one nobody knows and signs the
+/// account out, leaving the user to choose a new password through the
+/// forgotten password flow.
+pub async fn force_password_reset(
+    state: &AppState,
+    uid: i64,
+    entry: Entry<'_>,
+) -> AppResult<bool> {
+    let account = fetch_account(state, uid).await?;
+    let item = ResetPasswordSchema {
+        uid,
+        password: crypto::hash_password(crypto::random_words(32).as_bytes())?,
+    };
+    let mut tx = state.get_db().begin().await.map_err(AppInnerError::from)?;
+    BwAccount::update_password_by_uid(&mut *tx, &item).await?;
+    audit_service::record(&mut *tx, entry).await?;
+    tx.commit().await.map_err(AppInnerError::from)?;
+    account_service::invalidate(state, uid).await?;
+    revoke_access(state, uid).await?;
+    let template = MailTemplate::PasswordResetNotice(NoticeParams {
+        name: account.name.clone(),
+    });
+    notify(state, &account, template).await;
+    Ok(true)
+}
@@ -0,0 +1,51 @@
+use crate::{
+    library::{
+        cfg, crypto,
+        error::{AppError::AuthError, AppResult, AuthInnerError},
+    },
+    miner::{
+        bootstrap::AppState,
+        service::{login_guard, session_service::ClientInfo},
+    },
+    models::admin::{BwAdmin, CreateBwAdminSchema},
+};
+
+/// Creates the admin configured under `admin.bootstrap`, if any, unless an
+/// admin with its email exists.
+pub async fn bootstrap(state: &AppState) -> AppResult<()> {
+    let Some(admin) = &cfg::config().admin.bootstrap else {
+        return Ok(());
+    };
+    let item = CreateBwAdminSchema {
+        name: admin.name.clone(),
+        email: admin.email.clone(),
+        password: admin.password_hash.clone(),
+    };
+    if BwAdmin::create_bw_admin(state.get_db(), &item)
+        .await?
+        .is_some()
+    {
+        tracing::info!("Created bootstrap admin {}", admin.email);
+    }
+    Ok(())
+}
+
+/// Checks the credentials of an admin. Failures count against the client
+/// IP like failed user logins do.
+pub async fn login(
+    state: &AppState,
+    email: &str,
+    password: &str,
+    client: &ClientInfo,
+) -> AppResult<BwAdmin> {
+    login_guard::check_ip(state, client).await?;
+    let admin = BwAdmin::fetch_admin_by_email(state.get_db(), email).await?;
+    let Some(admin) = admin.filter(|admin| {
+        crypto::verify_password(&admin.password, password).unwrap_or(false)
+    }) else {
+        login_guard::record_failure(state, &[], client).await?;
+        return Err(AuthError(AuthInnerError::WrongCredentials));
+    };
+    BwAdmin::update_last_login(state.get_db(), admin.admin_id).await?;
+    Ok(admin)
+}
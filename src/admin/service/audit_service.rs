@@ -0,0 +1,43 @@
+use serde_json::Value;
+use sqlx::PgExecutor;
+
+use crate::{
+    library::error::AppResult,
+    miner::service::session_service::ClientInfo,
+    models::{
+        admin::{BwAdminAudit, CreateBwAdminAuditSchema},
+        types::AdminAction,
+    },
+};
+
+/// An action of `admin_id`, as it goes in the audit log.
+pub struct Entry<'a> {
+    pub admin_id: i64,
+    pub client: &'a ClientInfo,
+    pub action: AdminAction,
+    pub target_uid: Option<i64>,
+    pub detail: Value,
+}
+
+/// Records `entry` in the audit log. Actions changing anything pass the
+/// transaction they are made in, so none is left without its entry.
+pub async fn record(
+    db: impl PgExecutor<'_>,
+    entry: Entry<'_>,
+) -> AppResult<()> {
+    let item = CreateBwAdminAuditSchema {
+        admin_id: entry.admin_id,
+        action: entry.action,
+        target_uid: entry.target_uid,
+        detail: entry.detail,
+        ip: entry.client.ip.clone(),
+    };
+    BwAdminAudit::insert_audit(db, &item).await?;
+    tracing::info!(
+        admin_id = entry.admin_id,
+        action = ?entry.action,
+        target_uid = ?entry.target_uid,
+        "admin action"
+    );
+    Ok(())
+}
@@ -0,0 +1,103 @@
+use axum::{async_trait, extract::FromRequestParts, http::request::Parts};
+use jsonwebtoken::{
+    decode, encode, DecodingKey, EncodingKey, Header, Validation,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    library::{
+        cfg,
+        error::{AppError, AppError::AuthError, AppResult, AuthInnerError},
+    },
+    models::admin::BwAdmin,
+};
+
+/// Claims of an admin access token. Signed with their own secret so user
+/// tokens are never accepted by the admin console and the other way round.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AdminClaims {
+    pub admin_id: i64,
+    pub email: String,
+    pub iat: usize,
+    pub exp: usize,
+}
+
+impl AdminClaims {
+    pub fn generate_token(admin: &BwAdmin) -> AppResult<String> {
+        let config = &cfg::config().admin.access_token;
+        let now = chrono::Utc::now();
+        let claims = Self {
+            admin_id: admin.admin_id,
+            email: admin.email.clone(),
+            iat: now.timestamp() as usize,
+            exp: (now
+                + chrono::Duration::seconds(config.secret_expiration.into()))
+            .timestamp() as usize,
+        };
+        encode(
+            &Header::default(),
+            &claims,
+            &EncodingKey::from_secret(config.secret.as_ref()),
+        )
+        .map_err(|_| AuthError(AuthInnerError::TokenCreation))
+    }
+
+    pub fn parse_token(token: &str) -> AppResult<Self> {
+        let config = &cfg::config().admin.access_token;
+        decode::<Self>(
+            token,
+            &DecodingKey::from_secret(config.secret.as_ref()),
+            &Validation::default(),
+        )
+        .map(|data| data.claims)
+        .map_err(|_| AuthError(AuthInnerError::InvalidToken))
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for AdminClaims
+where
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    /// Set by the admin `auth` middleware.
+    async fn from_request_parts(
+        parts: &mut Parts,
+        _state: &S,
+    ) -> AppResult<Self> {
+        parts
+            .extensions
+            .get::<AdminClaims>()
+            .cloned()
+            .ok_or(AuthError(AuthInnerError::InvalidToken))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        miner::service::jwt_service::{Claims, TokenType},
+        models::admin::BwAdmin,
+    };
+
+    #[test]
+    fn admin_token_is_not_a_user_token() {
+        cfg::init(&"./fixtures/config.toml".to_string());
+        let admin = BwAdmin {
+            admin_id: 1,
+            name: "root".to_string(),
+            email: "root@example.com".to_string(),
+            password: String::new(),
+            last_login: None,
+            created_at: chrono::Utc::now().naive_utc(),
+            updated_at: None,
+            deleted_at: None,
+        };
+        let token = AdminClaims::generate_token(&admin).unwrap();
+        let claims = AdminClaims::parse_token(&token).unwrap();
+        assert_eq!(claims.admin_id, 1);
+        assert!(Claims::parse_token(&token, TokenType::ACCESS, false).is_err());
+    }
+}
@@ -0,0 +1,4 @@
+pub mod account_service;
+pub mod admin_service;
+pub mod audit_service;
+pub mod jwt_service;
@@ -1,5 +1,5 @@
 use crate::{
-    library::{cfg, logger},
+    library::{cfg, logger, tracer},
     miner,
 };
 
@@ -9,4 +9,5 @@ pub async fn web_serve() {
     tracing::info!("Application started");
     miner::serve().await;
     tracing::info!("Application stopped");
+    tracer::shutdown();
 }
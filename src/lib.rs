@@ -1,3 +1,4 @@
+pub mod admin;
 pub mod cmd;
 pub mod cron;
 pub mod library;
@@ -0,0 +1,743 @@
+//! Queues and topic exchanges behind the [`MessageBus`] trait, implemented
+//! by [`Mqer`] over RabbitMQ and by [`MemoryBus`] within the process.
+//!
+//! The in-memory bus follows the broker closely enough to run the server
+//! and its consumers without one: queues buffer until consumed, requeued
+//! messages are delivered again, and topic routing keys are matched with
+//! the same `*` and `#` wildcards. Nothing survives a restart.
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering::SeqCst},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use uuid::Uuid;
+
+use crate::library::{
+    cfg::{self, MessageBusKind},
+    error::{AppResult, InnerResult, MqerError},
+    Mqer,
+};
+
+#[async_trait]
+pub trait MessageBus: Send + Sync {
+    async fn basic_send(
+        &self,
+        queue_name: &str,
+        payload: &str,
+    ) -> InnerResult<()>;
+
+    /// Publishes to `queue_name`, a queue holding each message for `delay`
+    /// before moving it to `target_queue`.
+    async fn delay_send(
+        &self,
+        queue_name: &str,
+        target_queue: &str,
+        delay: Duration,
+        payload: &str,
+    ) -> InnerResult<()>;
+
+    async fn basic_receive(
+        &self,
+        queue_name: &str,
+        tag: &str,
+        subscriber: Subscriber,
+    ) -> InnerResult<()>;
+
+    /// Publishes `envelope` to the topic exchange `exchange`, reaching every
+    /// queue bound to a pattern matching `routing_key`. See
+    /// [`MessageBus::publish`] for typed envelopes.
+    async fn topic_send(
+        &self,
+        exchange: &str,
+        routing_key: &str,
+        envelope: &Envelope<serde_json::Value>,
+    ) -> InnerResult<()>;
+
+    /// Consumes the durable queue `queue_name`, bound to `exchange` with
+    /// each of `binding_keys`. Every consumer wanting its own copy of the
+    /// messages uses its own queue; consumers sharing a queue share the
+    /// work. Dead-lettered messages go to `<queue_name>.dead`.
+    async fn topic_receive(
+        &self,
+        exchange: &str,
+        queue_name: &str,
+        binding_keys: &[&str],
+        tag: &str,
+        subscriber: Subscriber,
+    ) -> InnerResult<()>;
+
+    /// Like [`MessageBus::topic_receive`], through a queue of this process
+    /// alone that goes away with it, for messages only worth having while
+    /// it runs. Nothing is dead-lettered.
+    async fn topic_subscribe(
+        &self,
+        exchange: &str,
+        binding_keys: &[&str],
+        tag: &str,
+        subscriber: Subscriber,
+    ) -> InnerResult<()>;
+
+    /// Fails unless the broker is reachable and the bus is not shutting
+    /// down.
+    async fn ping(&self) -> InnerResult<()>;
+
+    /// Operations currently holding a broker connection.
+    fn in_flight(&self) -> usize;
+
+    fn graceful_shutdown(&self) -> AppResult<()>;
+}
+
+impl dyn MessageBus {
+    pub async fn publish<T: Event>(
+        &self,
+        exchange: &str,
+        routing_key: &str,
+        envelope: &Envelope<T>,
+    ) -> InnerResult<()> {
+        self.topic_send(exchange, routing_key, &envelope.to_value()?)
+            .await
+    }
+}
+
+/// The bus selected by `miner.message_bus`.
+pub fn init() -> Arc<dyn MessageBus> {
+    match cfg::config().miner.message_bus {
+        MessageBusKind::Amqp => Arc::new(Mqer::init()),
+        MessageBusKind::Memory => {
+            tracing::info!("🚀 Using the in-memory message bus");
+            Arc::new(MemoryBus::new())
+        }
+    }
+}
+
+/// What becomes of a delivery once its handler is done with it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Disposition {
+    Ack,
+    /// Put back on the queue to be delivered again.
+    Requeue,
+    /// Rejected, ending up in the dead letter queue if the queue has one.
+    DeadLetter,
+}
+
+/// `true` acknowledges, `false` requeues.
+impl From<bool> for Disposition {
+    fn from(done: bool) -> Self {
+        if done {
+            Self::Ack
+        } else {
+            Self::Requeue
+        }
+    }
+}
+
+/// A message published to a topic exchange inside an [`Envelope`].
+pub trait Event: Serialize + DeserializeOwned + Send + 'static {
+    /// Names the type in the envelope, e.g. `machine.online`.
+    const TYPE: &'static str;
+    /// Bumped whenever the payload changes in a way older consumers cannot
+    /// read.
+    const VERSION: u32;
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Envelope<T> {
+    pub message_id: Uuid,
+    pub message_type: String,
+    pub version: u32,
+    /// Ties together the messages caused by one request.
+    pub correlation_id: Option<String>,
+    pub timestamp: DateTime<Utc>,
+    pub payload: T,
+}
+
+impl<T: Event> Envelope<T> {
+    pub fn new(payload: T) -> Self {
+        Self {
+            message_id: Uuid::new_v4(),
+            message_type: T::TYPE.to_string(),
+            version: T::VERSION,
+            correlation_id: None,
+            timestamp: Utc::now(),
+            payload,
+        }
+    }
+
+    #[must_use]
+    pub fn with_correlation_id(mut self, correlation_id: &str) -> Self {
+        self.correlation_id = Some(correlation_id.to_string());
+        self
+    }
+
+    /// Parses an envelope holding a `T`. Envelopes of another type, or of a
+    /// newer version than this build knows, are refused.
+    pub fn decode(message: &str) -> InnerResult<Self> {
+        let header: Envelope<serde::de::IgnoredAny> =
+            serde_json::from_str(message)?;
+        if header.message_type != T::TYPE {
+            return Err(MqerError::InvalidMessage(format!(
+                "expected {}, got {}",
+                T::TYPE,
+                header.message_type
+            ))
+            .into());
+        }
+        if header.version > T::VERSION {
+            return Err(MqerError::InvalidMessage(format!(
+                "{} version {} is newer than {}",
+                T::TYPE,
+                header.version,
+                T::VERSION
+            ))
+            .into());
+        }
+        Ok(serde_json::from_str(message)?)
+    }
+
+    fn to_value(&self) -> InnerResult<Envelope<serde_json::Value>> {
+        Ok(Envelope {
+            message_id: self.message_id,
+            message_type: self.message_type.clone(),
+            version: self.version,
+            correlation_id: self.correlation_id.clone(),
+            timestamp: self.timestamp,
+            payload: serde_json::to_value(&self.payload)?,
+        })
+    }
+}
+
+type Handler = dyn Fn(String) -> Pin<Box<dyn Future<Output = Disposition> + Send>>
+    + Send
+    + Sync;
+
+/// Handles the messages of a queue, deciding the [`Disposition`] of each.
+/// Plain `bool` handlers acknowledge on `true` and requeue on `false`.
+#[derive(Clone)]
+pub struct Subscriber {
+    func: Arc<Handler>,
+}
+
+impl Subscriber {
+    pub fn new<F, Fut, D>(func: F) -> Self
+    where
+        F: Fn(String) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = D> + Send + 'static,
+        D: Into<Disposition>,
+    {
+        Self {
+            func: Arc::new(move |message| {
+                let fut = func(message);
+                Box::pin(async move { fut.await.into() })
+            }),
+        }
+    }
+
+    /// Consumes [`Envelope`]s of `T`, dead-lettering anything else.
+    pub fn typed<T, F, Fut>(func: F) -> Self
+    where
+        T: Event,
+        F: Fn(Envelope<T>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Disposition> + Send + 'static,
+    {
+        let func = Arc::new(func);
+        Self::new(move |message: String| {
+            let func = func.clone();
+            async move {
+                match Envelope::<T>::decode(&message) {
+                    Ok(envelope) => func(envelope).await,
+                    Err(e) => {
+                        tracing::error!(
+                            "Dead-lettering unreadable message: {}",
+                            e
+                        );
+                        Disposition::DeadLetter
+                    }
+                }
+            }
+        })
+    }
+
+    pub async fn handle(&self, message: String) -> Disposition {
+        (self.func)(message).await
+    }
+}
+
+/// Whether `routing_key` matches the binding `pattern`, where `*` stands
+/// for exactly one dot-separated word and `#` for any number of them.
+pub fn topic_matches(pattern: &str, routing_key: &str) -> bool {
+    fn matches(pattern: &[&str], key: &[&str]) -> bool {
+        match (pattern.first(), key.first()) {
+            (None, None) => true,
+            (Some(&"#"), _) => {
+                matches(&pattern[1..], key)
+                    || (!key.is_empty() && matches(pattern, &key[1..]))
+            }
+            (Some(&"*"), Some(_)) => matches(&pattern[1..], &key[1..]),
+            (Some(p), Some(k)) => p == k && matches(&pattern[1..], &key[1..]),
+            _ => false,
+        }
+    }
+    let pattern = pattern.split('.').collect::<Vec<_>>();
+    let key = routing_key.split('.').collect::<Vec<_>>();
+    matches(&pattern, &key)
+}
+
+struct MemoryQueue {
+    tx: UnboundedSender<String>,
+    rx: Arc<tokio::sync::Mutex<UnboundedReceiver<String>>>,
+    dead_letter: Option<String>,
+}
+
+impl MemoryQueue {
+    fn new() -> Self {
+        let (tx, rx) = mpsc::unbounded_channel();
+        Self {
+            tx,
+            rx: Arc::new(tokio::sync::Mutex::new(rx)),
+            dead_letter: None,
+        }
+    }
+}
+
+#[derive(Default)]
+struct MemoryState {
+    queues: HashMap<String, MemoryQueue>,
+    /// Exchange name to its `(pattern, queue)` bindings.
+    bindings: HashMap<String, Vec<(String, String)>>,
+}
+
+/// A [`MessageBus`] living in the process, for running without a broker.
+#[derive(Clone)]
+pub struct MemoryBus {
+    state: Arc<Mutex<MemoryState>>,
+    running: Arc<AtomicBool>,
+}
+
+impl Default for MemoryBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MemoryBus {
+    pub fn new() -> Self {
+        Self {
+            state: Arc::default(),
+            running: Arc::new(AtomicBool::new(true)),
+        }
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, MemoryState> {
+        self.state
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    fn push(&self, queue_name: &str, payload: String) {
+        let mut state = self.state();
+        let queue = state
+            .queues
+            .entry(queue_name.to_string())
+            .or_insert_with(MemoryQueue::new);
+        // The receiver lives as long as the queue, so this cannot fail.
+        let _ = queue.tx.send(payload);
+    }
+
+    fn receiver(
+        &self,
+        queue_name: &str,
+    ) -> Arc<tokio::sync::Mutex<UnboundedReceiver<String>>> {
+        let mut state = self.state();
+        let queue = state
+            .queues
+            .entry(queue_name.to_string())
+            .or_insert_with(MemoryQueue::new);
+        queue.rx.clone()
+    }
+
+    fn bind(&self, exchange: &str, queue_name: &str, binding_keys: &[&str]) {
+        let mut state = self.state();
+        let bindings = state.bindings.entry(exchange.to_string()).or_default();
+        for key in binding_keys {
+            let binding = (key.to_string(), queue_name.to_string());
+            if !bindings.contains(&binding) {
+                bindings.push(binding);
+            }
+        }
+    }
+
+    async fn consume(&self, queue_name: String, subscriber: Subscriber) {
+        let rx = self.receiver(&queue_name);
+        loop {
+            let Some(message) = rx.lock().await.recv().await else {
+                break;
+            };
+            if !self.running.load(SeqCst) {
+                break;
+            }
+            match subscriber.handle(message.clone()).await {
+                Disposition::Ack => {}
+                Disposition::Requeue => self.push(&queue_name, message),
+                Disposition::DeadLetter => {
+                    let dead_letter = self
+                        .state()
+                        .queues
+                        .get(&queue_name)
+                        .and_then(|queue| queue.dead_letter.clone());
+                    match dead_letter {
+                        Some(dead_letter) => self.push(&dead_letter, message),
+                        None => tracing::warn!(
+                            "Dropping rejected message from {}",
+                            queue_name
+                        ),
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl MessageBus for MemoryBus {
+    async fn basic_send(
+        &self,
+        queue_name: &str,
+        payload: &str,
+    ) -> InnerResult<()> {
+        self.push(queue_name, payload.to_string());
+        Ok(())
+    }
+
+    async fn delay_send(
+        &self,
+        _queue_name: &str,
+        target_queue: &str,
+        delay: Duration,
+        payload: &str,
+    ) -> InnerResult<()> {
+        let bus = self.clone();
+        let target_queue = target_queue.to_string();
+        let payload = payload.to_string();
+        tokio::spawn(async move {
+            tokio::time::sleep(delay).await;
+            bus.push(&target_queue, payload);
+        });
+        Ok(())
+    }
+
+    async fn basic_receive(
+        &self,
+        queue_name: &str,
+        _tag: &str,
+        subscriber: Subscriber,
+    ) -> InnerResult<()> {
+        let bus = self.clone();
+        let queue_name = queue_name.to_string();
+        tokio::spawn(async move { bus.consume(queue_name, subscriber).await });
+        Ok(())
+    }
+
+    async fn topic_send(
+        &self,
+        exchange: &str,
+        routing_key: &str,
+        envelope: &Envelope<serde_json::Value>,
+    ) -> InnerResult<()> {
+        let payload = serde_json::to_string(envelope)?;
+        let mut queues = self
+            .state()
+            .bindings
+            .get(exchange)
+            .into_iter()
+            .flatten()
+            .filter(|(pattern, _)| topic_matches(pattern, routing_key))
+            .map(|(_, queue)| queue.clone())
+            .collect::<Vec<_>>();
+        queues.sort();
+        queues.dedup();
+        for queue in queues {
+            self.push(&queue, payload.clone());
+        }
+        Ok(())
+    }
+
+    async fn topic_receive(
+        &self,
+        exchange: &str,
+        queue_name: &str,
+        binding_keys: &[&str],
+        tag: &str,
+        subscriber: Subscriber,
+    ) -> InnerResult<()> {
+        {
+            let mut state = self.state();
+            state
+                .queues
+                .entry(queue_name.to_string())
+                .or_insert_with(MemoryQueue::new)
+                .dead_letter = Some(format!("{queue_name}.dead"));
+        }
+        self.bind(exchange, queue_name, binding_keys);
+        self.basic_receive(queue_name, tag, subscriber).await
+    }
+
+    async fn topic_subscribe(
+        &self,
+        exchange: &str,
+        binding_keys: &[&str],
+        tag: &str,
+        subscriber: Subscriber,
+    ) -> InnerResult<()> {
+        let queue_name = format!("{exchange}.{}", Uuid::new_v4());
+        self.bind(exchange, &queue_name, binding_keys);
+        self.basic_receive(&queue_name, tag, subscriber).await
+    }
+
+    async fn ping(&self) -> InnerResult<()> {
+        if !self.running.load(SeqCst) {
+            return Err(anyhow::anyhow!("Message bus is stopped").into());
+        }
+        Ok(())
+    }
+
+    fn in_flight(&self) -> usize {
+        0
+    }
+
+    fn graceful_shutdown(&self) -> AppResult<()> {
+        self.running.store(false, SeqCst);
+        tracing::info!("MQ Stopped");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc::unbounded_channel;
+
+    use super::*;
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct MachineOnline {
+        mac: String,
+    }
+
+    impl Event for MachineOnline {
+        const TYPE: &'static str = "machine.online";
+        const VERSION: u32 = 2;
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct CommandAcked {
+        command_id: i64,
+    }
+
+    impl Event for CommandAcked {
+        const TYPE: &'static str = "command.acked";
+        const VERSION: u32 = 1;
+    }
+
+    fn online() -> MachineOnline {
+        MachineOnline {
+            mac: "00:1A:2B:3C:4D:5E".to_string(),
+        }
+    }
+
+    /// A subscriber forwarding what it receives, answering with `reply`.
+    fn forward(reply: Disposition) -> (Subscriber, UnboundedReceiver<String>) {
+        let (tx, rx) = unbounded_channel();
+        let subscriber = Subscriber::new(move |message: String| {
+            let _ = tx.send(message);
+            async move { reply }
+        });
+        (subscriber, rx)
+    }
+
+    async fn recv(rx: &mut UnboundedReceiver<String>) -> String {
+        tokio::time::timeout(Duration::from_secs(1), rx.recv())
+            .await
+            .unwrap()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_envelope_round_trip() {
+        let envelope = Envelope::new(online()).with_correlation_id("req-1");
+        let json = serde_json::to_string(&envelope).unwrap();
+        let decoded = Envelope::<MachineOnline>::decode(&json).unwrap();
+        assert_eq!(decoded.message_id, envelope.message_id);
+        assert_eq!(decoded.message_type, "machine.online");
+        assert_eq!(decoded.version, 2);
+        assert_eq!(decoded.correlation_id.as_deref(), Some("req-1"));
+        assert_eq!(decoded.timestamp, envelope.timestamp);
+        assert_eq!(decoded.payload, online());
+    }
+
+    #[test]
+    fn test_envelope_decode_refuses_other_messages() {
+        let other = Envelope::new(CommandAcked { command_id: 1 });
+        let json = serde_json::to_string(&other).unwrap();
+        assert!(Envelope::<MachineOnline>::decode(&json).is_err());
+
+        let mut newer = Envelope::new(online());
+        newer.version = 3;
+        let json = serde_json::to_string(&newer).unwrap();
+        assert!(Envelope::<MachineOnline>::decode(&json).is_err());
+
+        let mut older = Envelope::new(online());
+        older.version = 1;
+        let json = serde_json::to_string(&older).unwrap();
+        assert!(Envelope::<MachineOnline>::decode(&json).is_ok());
+
+        assert!(Envelope::<MachineOnline>::decode("not json").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_typed_subscriber_dispositions() {
+        let subscriber =
+            Subscriber::typed(|envelope: Envelope<MachineOnline>| async move {
+                if envelope.payload.mac.is_empty() {
+                    Disposition::Requeue
+                } else {
+                    Disposition::Ack
+                }
+            });
+        let json = serde_json::to_string(&Envelope::new(online())).unwrap();
+        assert_eq!(subscriber.handle(json).await, Disposition::Ack);
+        let json = serde_json::to_string(&Envelope::new(MachineOnline {
+            mac: String::new(),
+        }))
+        .unwrap();
+        assert_eq!(subscriber.handle(json).await, Disposition::Requeue);
+        let json = serde_json::to_string(&Envelope::new(CommandAcked {
+            command_id: 1,
+        }))
+        .unwrap();
+        assert_eq!(subscriber.handle(json).await, Disposition::DeadLetter);
+    }
+
+    #[test]
+    fn test_topic_matches() {
+        assert!(topic_matches("machine.online", "machine.online"));
+        assert!(topic_matches("machine.*", "machine.online"));
+        assert!(!topic_matches("machine.*", "machine.rig.online"));
+        assert!(topic_matches("machine.#", "machine.rig.online"));
+        assert!(topic_matches("machine.#", "machine"));
+        assert!(topic_matches("#", "command.acked"));
+        assert!(topic_matches("#.acked", "command.acked"));
+        assert!(!topic_matches("command.*", "machine.online"));
+    }
+
+    #[tokio::test]
+    async fn test_memory_queue_buffers_until_consumed() {
+        let bus = MemoryBus::new();
+        bus.basic_send("queue", "first").await.unwrap();
+        let (subscriber, mut rx) = forward(Disposition::Ack);
+        bus.basic_receive("queue", "tag", subscriber).await.unwrap();
+        bus.basic_send("queue", "second").await.unwrap();
+        assert_eq!(recv(&mut rx).await, "first");
+        assert_eq!(recv(&mut rx).await, "second");
+    }
+
+    #[tokio::test]
+    async fn test_memory_delay_send() {
+        let bus = MemoryBus::new();
+        let (subscriber, mut rx) = forward(Disposition::Ack);
+        bus.basic_receive("target", "tag", subscriber)
+            .await
+            .unwrap();
+        bus.delay_send("delay", "target", Duration::from_millis(10), "later")
+            .await
+            .unwrap();
+        assert_eq!(recv(&mut rx).await, "later");
+    }
+
+    #[tokio::test]
+    async fn test_memory_topic_fan_out() {
+        let bus: Arc<dyn MessageBus> = Arc::new(MemoryBus::new());
+        let (first, mut first_rx) = forward(Disposition::Ack);
+        let (second, mut second_rx) = forward(Disposition::Ack);
+        bus.topic_receive("events", "first", &["machine.*"], "tag", first)
+            .await
+            .unwrap();
+        bus.topic_receive("events", "second", &["#"], "tag", second)
+            .await
+            .unwrap();
+
+        let envelope = Envelope::new(online());
+        bus.publish("events", "machine.online", &envelope)
+            .await
+            .unwrap();
+        bus.publish("events", "command.acked", &Envelope::new(online()))
+            .await
+            .unwrap();
+
+        let received = recv(&mut first_rx).await;
+        let decoded = Envelope::<MachineOnline>::decode(&received).unwrap();
+        assert_eq!(decoded.message_id, envelope.message_id);
+        assert!(first_rx.try_recv().is_err());
+        recv(&mut second_rx).await;
+        recv(&mut second_rx).await;
+    }
+
+    #[tokio::test]
+    async fn test_memory_subscriptions_each_get_a_copy() {
+        let bus: Arc<dyn MessageBus> = Arc::new(MemoryBus::new());
+        let (first, mut first_rx) = forward(Disposition::Ack);
+        let (second, mut second_rx) = forward(Disposition::Ack);
+        for subscriber in [first, second] {
+            bus.topic_subscribe("events", &["machine.*"], "tag", subscriber)
+                .await
+                .unwrap();
+        }
+
+        bus.publish("events", "machine.online", &Envelope::new(online()))
+            .await
+            .unwrap();
+        recv(&mut first_rx).await;
+        recv(&mut second_rx).await;
+    }
+
+    #[tokio::test]
+    async fn test_memory_dispositions() {
+        let bus = MemoryBus::new();
+        let (tx, mut rx) = unbounded_channel();
+        // Requeues every message once, then dead-letters it.
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let subscriber = Subscriber::new(move |message: String| {
+            let mut seen = seen.lock().unwrap();
+            let reply = if seen.contains(&message) {
+                Disposition::DeadLetter
+            } else {
+                seen.push(message.clone());
+                Disposition::Requeue
+            };
+            let _ = tx.send(message);
+            async move { reply }
+        });
+        bus.topic_receive("events", "queue", &["#"], "tag", subscriber)
+            .await
+            .unwrap();
+        let (dead, mut dead_rx) = forward(Disposition::Ack);
+        bus.basic_receive("queue.dead", "tag", dead).await.unwrap();
+
+        let envelope = Envelope::new(online()).to_value().unwrap();
+        bus.topic_send("events", "machine.online", &envelope)
+            .await
+            .unwrap();
+        let first = recv(&mut rx).await;
+        assert_eq!(recv(&mut rx).await, first);
+        assert_eq!(recv(&mut dead_rx).await, first);
+    }
+}
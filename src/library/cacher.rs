@@ -1 +1,327 @@
+//! Read-through cache over [`Storage`].
+//!
+//! Values are kept as JSON under `cache:{key}`. A load that finds nothing
+//! is cached too, for a shorter time, so lookups of unknown keys do not
+//! all reach the database. Concurrent misses of one key within the process
+//! wait for a single load. Entries may carry tags, each tag remembering its
+//! keys in the hash `cache_tag:{tag}` so they can be dropped together.
+//!
+//! A load racing an invalidation may still write what it read before the
+//! change; such an entry lives until its TTL runs out.
 
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::library::{
+    cfg::{self, CacheConfig},
+    error::{AppInnerError, InnerResult},
+    storage::{Pipeline, Storage},
+};
+
+fn entry_key(key: &str) -> String {
+    format!("cache:{key}")
+}
+
+fn tag_key(tag: &str) -> String {
+    format!("cache_tag:{tag}")
+}
+
+pub struct Cacher {
+    storage: Arc<dyn Storage>,
+    ttl: u64,
+    negative_ttl: u64,
+    /// One lock per key being loaded.
+    loading: Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+}
+
+impl Cacher {
+    /// A cache over `storage` with the TTLs of `miner.cache`.
+    pub fn init(storage: Arc<dyn Storage>) -> Self {
+        Self::new(storage, &cfg::config().miner.cache)
+    }
+
+    pub fn new(storage: Arc<dyn Storage>, config: &CacheConfig) -> Self {
+        Self {
+            storage,
+            ttl: config.ttl,
+            negative_ttl: config.negative_ttl,
+            loading: Mutex::default(),
+        }
+    }
+
+    /// The entry at `key`, to be read with [`Entry::get_or_load`].
+    pub fn entry(&self, key: &str) -> Entry<'_> {
+        Entry {
+            cacher: self,
+            key: key.to_string(),
+            ttl: self.ttl,
+            negative_ttl: self.negative_ttl,
+            tags: Vec::new(),
+        }
+    }
+
+    pub async fn invalidate(&self, key: &str) -> InnerResult<()> {
+        self.storage.del(&entry_key(key)).await
+    }
+
+    /// Drops every entry tagged with `tag`.
+    pub async fn invalidate_tag(&self, tag: &str) -> InnerResult<()> {
+        let tag = tag_key(tag);
+        let keys = self.storage.hkeys(&tag).await?;
+        let mut pipeline = Pipeline::new();
+        for key in &keys {
+            pipeline.del(key);
+        }
+        pipeline.del(&tag);
+        self.storage.exec(&pipeline).await
+    }
+
+    fn loading(
+        &self,
+    ) -> MutexGuard<'_, HashMap<String, Arc<tokio::sync::Mutex<()>>>> {
+        self.loading.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    async fn read<T: DeserializeOwned>(
+        &self,
+        key: &str,
+    ) -> InnerResult<Option<Option<T>>> {
+        let Some(raw) = self.storage.get(key).await? else {
+            return Ok(None);
+        };
+        match serde_json::from_str(&raw) {
+            Ok(value) => Ok(Some(value)),
+            Err(e) => {
+                // Most likely written before the type changed, load afresh.
+                tracing::warn!("Dropping unreadable cache entry {key}: {e}");
+                Ok(None)
+            }
+        }
+    }
+}
+
+/// A cache entry being looked up, with the TTLs and tags it is written
+/// with on a miss.
+pub struct Entry<'a> {
+    cacher: &'a Cacher,
+    key: String,
+    ttl: u64,
+    negative_ttl: u64,
+    tags: Vec<String>,
+}
+
+impl Entry<'_> {
+    /// Seconds a loaded value is kept, `miner.cache.ttl` by default.
+    pub fn ttl(mut self, ttl: u64) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Seconds a load that found nothing is remembered, zero to not cache
+    /// it at all.
+    pub fn negative_ttl(mut self, ttl: u64) -> Self {
+        self.negative_ttl = ttl;
+        self
+    }
+
+    pub fn tag(mut self, tag: &str) -> Self {
+        self.tags.push(tag.to_string());
+        self
+    }
+
+    /// The cached value, or else the result of `load`, which is cached.
+    pub async fn get_or_load<T, E, F, Fut>(
+        self,
+        load: F,
+    ) -> Result<Option<T>, E>
+    where
+        T: Serialize + DeserializeOwned,
+        E: From<AppInnerError>,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<Option<T>, E>>,
+    {
+        let key = entry_key(&self.key);
+        if let Some(value) = self.cacher.read(&key).await? {
+            return Ok(value);
+        }
+
+        let lock = self
+            .cacher
+            .loading()
+            .entry(key.clone())
+            .or_default()
+            .clone();
+        let loaded = {
+            let _guard = lock.lock().await;
+            // Someone else may have loaded it while we waited.
+            match self.cacher.read(&key).await? {
+                Some(value) => Ok(value),
+                None => self.load(&key, load).await,
+            }
+        };
+        let mut loading = self.cacher.loading();
+        // Only the map and this call hold it, nobody is waiting.
+        if Arc::strong_count(&lock) == 2 {
+            loading.remove(&key);
+        }
+        loaded
+    }
+
+    async fn load<T, E, F, Fut>(
+        &self,
+        key: &str,
+        load: F,
+    ) -> Result<Option<T>, E>
+    where
+        T: Serialize,
+        E: From<AppInnerError>,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<Option<T>, E>>,
+    {
+        let value = load().await?;
+        let ttl = if value.is_some() {
+            self.ttl
+        } else {
+            self.negative_ttl
+        };
+        if ttl == 0 {
+            return Ok(value);
+        }
+        let raw = serde_json::to_string(&value).map_err(AppInnerError::from)?;
+        let mut pipeline = Pipeline::new();
+        pipeline.set_ex(key, &raw, ttl);
+        for tag in &self.tags {
+            let tag = tag_key(tag);
+            // A tag must outlive every entry it lists.
+            let keep = self.cacher.storage.ttl(&tag).await?.unwrap_or(0);
+            pipeline
+                .hset(&tag, key, "")
+                .expire(&tag, keep.max(ttl as i64));
+        }
+        self.cacher.storage.exec(&pipeline).await?;
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::library::storage::MemoryStorage;
+
+    fn cacher() -> Cacher {
+        let config = CacheConfig {
+            ttl: 60,
+            negative_ttl: 10,
+            ..CacheConfig::default()
+        };
+        Cacher::new(Arc::new(MemoryStorage::new()), &config)
+    }
+
+    async fn load(
+        cacher: &Cacher,
+        key: &str,
+        loads: &AtomicUsize,
+        value: Option<i64>,
+    ) -> Option<i64> {
+        cacher
+            .entry(key)
+            .tag("numbers")
+            .get_or_load(|| async {
+                loads.fetch_add(1, Ordering::SeqCst);
+                Ok::<_, AppInnerError>(value)
+            })
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_read_through() {
+        let cacher = cacher();
+        let loads = AtomicUsize::new(0);
+        assert_eq!(load(&cacher, "one", &loads, Some(1)).await, Some(1));
+        assert_eq!(load(&cacher, "one", &loads, Some(2)).await, Some(1));
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+        assert_eq!(cacher.storage.ttl("cache:one").await.unwrap(), Some(60));
+
+        cacher.invalidate("one").await.unwrap();
+        assert_eq!(load(&cacher, "one", &loads, Some(2)).await, Some(2));
+        assert_eq!(loads.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_negative_caching() {
+        let cacher = cacher();
+        let loads = AtomicUsize::new(0);
+        assert_eq!(load(&cacher, "none", &loads, None).await, None);
+        assert_eq!(load(&cacher, "none", &loads, Some(1)).await, None);
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+        assert_eq!(cacher.storage.ttl("cache:none").await.unwrap(), Some(10));
+
+        let value = cacher
+            .entry("uncached")
+            .negative_ttl(0)
+            .get_or_load(|| async { Ok::<Option<i64>, AppInnerError>(None) })
+            .await
+            .unwrap();
+        assert_eq!(value, None);
+        assert_eq!(cacher.storage.get("cache:uncached").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_invalidate_tag() {
+        let cacher = cacher();
+        let loads = AtomicUsize::new(0);
+        load(&cacher, "one", &loads, Some(1)).await;
+        load(&cacher, "two", &loads, Some(2)).await;
+        cacher.invalidate_tag("numbers").await.unwrap();
+        assert_eq!(cacher.storage.get("cache:one").await.unwrap(), None);
+        assert_eq!(cacher.storage.get("cache:two").await.unwrap(), None);
+        assert!(cacher
+            .storage
+            .hkeys("cache_tag:numbers")
+            .await
+            .unwrap()
+            .is_empty());
+
+        assert_eq!(load(&cacher, "one", &loads, Some(3)).await, Some(3));
+        assert_eq!(loads.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_misses_load_once() {
+        let cacher = cacher();
+        let loads = AtomicUsize::new(0);
+        let lookup = || {
+            cacher.entry("slow").get_or_load(|| async {
+                loads.fetch_add(1, Ordering::SeqCst);
+                tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+                Ok::<_, AppInnerError>(Some(7))
+            })
+        };
+        let values = tokio::join!(lookup(), lookup(), lookup(), lookup());
+        assert_eq!(values.0.unwrap(), Some(7));
+        assert_eq!(values.3.unwrap(), Some(7));
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+        assert!(cacher.loading().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_load_error_is_not_cached() {
+        let cacher = cacher();
+        let failed = cacher
+            .entry("flaky")
+            .get_or_load(|| async {
+                Err::<Option<i64>, _>(AppInnerError::Unknown("down".into()))
+            })
+            .await;
+        assert!(failed.is_err());
+        assert_eq!(cacher.storage.get("cache:flaky").await.unwrap(), None);
+    }
+}
@@ -1,8 +1,10 @@
-use std::{fmt::Debug, fs, sync::OnceLock};
+use std::{collections::HashMap, fmt::Debug, fs, sync::OnceLock};
 
 use config::Config;
 use serde::{Deserialize, Serialize};
 
+use crate::library::templator::TemplateKind;
+
 // Create a static lock for the configuration, ensuring
 // that it's only initialized once across the entire application.
 static CFG: OnceLock<AppConfig> = OnceLock::new();
@@ -11,7 +13,67 @@ static CFG: OnceLock<AppConfig> = OnceLock::new();
 pub struct AppConfig {
     pub log: LogConfig,
     pub miner: MinerConfig,
+    pub admin: AdminConfig,
     pub mail: MailConfig,
+    /// Shared by the miner and admin APIs.
+    #[serde(default)]
+    pub cors: CorsConfig,
+}
+
+/// Which browser origins may call the APIs, and with what.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CorsConfig {
+    /// Exact origins such as `https://app.example.com`, or patterns where
+    /// `*` stands for any run of characters, e.g. `https://*.example.com`.
+    /// A lone `*` allows every origin, but never with credentials.
+    pub allowed_origins: Vec<String>,
+    pub allowed_methods: Vec<String>,
+    pub allowed_headers: Vec<String>,
+    /// Response headers scripts of an allowed origin may read.
+    pub expose_headers: Vec<String>,
+    /// Seconds browsers may cache the answer to a preflight.
+    pub max_age_secs: u64,
+    pub allow_credentials: bool,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        let strings = |values: &[&str]| {
+            values.iter().map(ToString::to_string).collect::<Vec<_>>()
+        };
+        Self {
+            allowed_origins: Vec::new(),
+            allowed_methods: strings(&["GET", "POST", "PUT", "DELETE"]),
+            allowed_headers: strings(&[
+                "content-type",
+                "authorization",
+                "x-org-id",
+                "traceparent",
+            ]),
+            expose_headers: strings(&["x-request-id", "retry-after"]),
+            max_age_secs: 600,
+            allow_credentials: false,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AdminConfig {
+    pub api_host: String,
+    pub api_port: usize,
+    pub access_token: JWTConfig,
+    /// Admin created on startup unless its email is taken, so the first
+    /// operator can sign in.
+    pub bootstrap: Option<BootstrapAdminConfig>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BootstrapAdminConfig {
+    pub name: String,
+    pub email: String,
+    /// Argon2 PHC string, never the password itself.
+    pub password_hash: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -31,19 +93,33 @@ pub struct LogConfig {
     pub database_target: String,
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MailConfig {
+    /// Profile emails are sent through unless `routes` says otherwise.
+    pub default_profile: String,
+    pub profiles: HashMap<String, SmtpProfile>,
+    /// Profile to send each template through, e.g. alerts through a
+    /// separate account so a flood of them cannot hold up password resets.
+    #[serde(default)]
+    pub routes: HashMap<TemplateKind, String>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SmtpProfile {
     pub username: String,
     pub password: String,
     pub host: String,
+    /// Sender address, the username if not set.
+    pub from: Option<String>,
 }
 
-impl Debug for MailConfig {
+impl Debug for SmtpProfile {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        f.debug_struct("Point")
+        f.debug_struct("SmtpProfile")
             .field("username", &self.username)
-            .field("password", &"&self.password")
+            .field("password", &"********")
             .field("host", &self.host)
+            .field("from", &self.from)
             .finish()
     }
 }
@@ -56,15 +132,309 @@ pub struct JWTConfig {
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct ExchangeRateConfig {
-    pub host: String,
-    pub key: String,
     pub frequency: u64,
+    pub max_age: Option<u64>,
+    /// Providers in priority order, the first healthy one wins.
+    pub providers: Vec<ExchangeRateProviderConfig>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ExchangeRateProviderConfig {
+    ExchangerateApi { host: String, key: String },
+    OpenErApi { host: String },
+    Fixture { path: String },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct CoinStatConfig {
-    pub host: String,
     pub frequency: u64,
+    pub max_age: Option<u64>,
+    /// Providers in priority order, the first healthy one wins.
+    pub providers: Vec<CoinStatProviderConfig>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum CoinStatProviderConfig {
+    Minerstat { host: String },
+    Fixture { path: String },
+}
+
+impl ExchangeRateConfig {
+    /// Seconds after which cached rates are reported as stale.
+    pub fn max_age(&self) -> u64 {
+        self.max_age.unwrap_or(self.frequency * 2)
+    }
+}
+
+impl CoinStatConfig {
+    /// Seconds after which cached coin stats are reported as stale.
+    pub fn max_age(&self) -> u64 {
+        self.max_age.unwrap_or(self.frequency * 2)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LoginGuardConfig {
+    /// Consecutive failed logins before an account is locked.
+    pub max_attempts: i32,
+    /// Failures further apart than this are no longer consecutive, so the
+    /// count starts over.
+    pub failure_window_secs: u64,
+    /// First lock duration, doubled on every further failure.
+    pub lock_secs: u64,
+    pub max_lock_secs: u64,
+    /// Failed logins accepted from one IP within `ip_window_secs`.
+    pub ip_max_attempts: i64,
+    pub ip_window_secs: u64,
+}
+
+impl Default for LoginGuardConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            failure_window_secs: 86400,
+            lock_secs: 60,
+            max_lock_secs: 3600,
+            ip_max_attempts: 20,
+            ip_window_secs: 900,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MfaConfig {
+    /// Shown next to the account in authenticator apps.
+    pub issuer: String,
+    /// Lifetime of the token handed out between the two login steps.
+    pub token_expiration: u32,
+    /// Codes that may be tried with one such token.
+    pub max_attempts: i64,
+    pub recovery_codes: usize,
+}
+
+impl Default for MfaConfig {
+    fn default() -> Self {
+        Self {
+            issuer: "Miner".to_string(),
+            token_expiration: 300,
+            max_attempts: 5,
+            recovery_codes: 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ApiTokenConfig {
+    /// Requests per minute a token gets unless created with its own limit.
+    pub rate_limit: i32,
+    /// Upper bound for the limit a token may be created with.
+    pub max_rate_limit: i32,
+    /// Active tokens an account may hold.
+    pub max_per_account: i64,
+}
+
+impl Default for ApiTokenConfig {
+    fn default() -> Self {
+        Self {
+            rate_limit: 60,
+            max_rate_limit: 600,
+            max_per_account: 20,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VerificationConfig {
+    /// Characters in an emailed code.
+    pub code_length: usize,
+    /// Seconds an email verification code stays valid.
+    pub verify_email_ttl: i64,
+    /// Seconds a password reset code stays valid.
+    pub reset_password_ttl: i64,
+    /// Seconds to wait before another code may be sent.
+    pub resend_interval: i64,
+    /// Wrong guesses after which a code is no longer accepted.
+    pub max_attempts: i32,
+}
+
+impl Default for VerificationConfig {
+    fn default() -> Self {
+        Self {
+            code_length: 6,
+            verify_email_ttl: 60 * 30,
+            reset_password_ttl: 60 * 15,
+            resend_interval: 60,
+            max_attempts: 5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MailOutboxConfig {
+    /// Seconds to wait before each retry. An email is given up on once
+    /// they are used up.
+    pub retry_backoff: Vec<u64>,
+    /// Seconds after which an email that never reached the queue is
+    /// published again.
+    pub stale_after: i64,
+    /// Seconds between looking for such emails.
+    pub sweep_interval: u64,
+    /// Seconds sent and failed emails are kept, deleted by the sweep
+    /// afterwards.
+    pub retention: i64,
+}
+
+impl Default for MailOutboxConfig {
+    fn default() -> Self {
+        Self {
+            retry_backoff: vec![30, 120, 600, 1800],
+            stale_after: 60,
+            sweep_interval: 60,
+            retention: 2592000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CacheConfig {
+    /// Seconds a cached lookup is kept unless its caller says otherwise.
+    pub ttl: u64,
+    /// Seconds a lookup that found nothing is remembered.
+    pub negative_ttl: u64,
+    /// Seconds the account an enrollment key belongs to is kept, never
+    /// past the expiry of the key itself.
+    pub enrollment_key_ttl: u64,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            ttl: 600,
+            negative_ttl: 60,
+            enrollment_key_ttl: 259200,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HealthConfig {
+    /// Milliseconds each readiness check may take before it counts as
+    /// failed.
+    pub check_timeout_ms: u64,
+    /// Seconds to keep serving, reporting not ready, after a shutdown
+    /// signal, for load balancers to stop sending traffic.
+    pub drain_secs: u64,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self {
+            check_timeout_ms: 2000,
+            drain_secs: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MetricsConfig {
+    /// Seconds since its last MQTT message a machine counts as online.
+    pub online_window: u64,
+    /// Accounts with the most machines online that get a gauge of their
+    /// own, bounding the series by account. None when 0.
+    pub top_accounts: usize,
+    /// Bearer token a scraper presents for `/metrics`, which is not served
+    /// without one, and for why `/readyz` fails.
+    pub token: Option<String>,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            online_window: 300,
+            top_accounts: 20,
+            token: None,
+        }
+    }
+}
+
+/// At most `limit` requests within a window of `window_secs`, the window
+/// starting with the first of them.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct RateLimitPolicy {
+    pub limit: i64,
+    pub window_secs: u64,
+}
+
+impl RateLimitPolicy {
+    const fn new(limit: i64, window_secs: u64) -> Self {
+        Self { limit, window_secs }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    pub enabled: bool,
+    /// Password and MFA logins, by IP.
+    pub login: RateLimitPolicy,
+    /// Sign ups, by IP.
+    pub register: RateLimitPolicy,
+    /// Requests sending an email, by account or else by IP.
+    pub email: RateLimitPolicy,
+    /// Attempts at an emailed code, by account or else by IP.
+    pub code: RateLimitPolicy,
+    /// Every authenticated request, by account. API tokens are limited on
+    /// top of that by their own `rate_limit`.
+    pub api: RateLimitPolicy,
+    /// Device sign-ins over gRPC, by enrollment key.
+    pub sign: RateLimitPolicy,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            login: RateLimitPolicy::new(10, 60),
+            register: RateLimitPolicy::new(5, 3600),
+            email: RateLimitPolicy::new(5, 600),
+            code: RateLimitPolicy::new(10, 600),
+            api: RateLimitPolicy::new(600, 60),
+            sign: RateLimitPolicy::new(30, 60),
+        }
+    }
+}
+
+/// Export of spans to an OpenTelemetry collector over OTLP/gRPC.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TracingConfig {
+    pub enabled: bool,
+    pub endpoint: String,
+    pub service_name: String,
+    /// Share of the traces started here that are sampled, between 0 and 1.
+    /// Traces started upstream follow the sampling decision of the caller.
+    pub sample_ratio: f64,
+}
+
+impl Default for TracingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            endpoint: "http://localhost:4317".to_string(),
+            service_name: "miner-server".to_string(),
+            sample_ratio: 1.0,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -84,6 +454,30 @@ pub struct TopicConfig {
     pub qos: u8,
 }
 
+/// Where keys are kept: Redis at `redis_url`, or a map within the process
+/// when running without Redis, e.g. in tests.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum StorageKind {
+    #[default]
+    Redis,
+    Memory,
+}
+
+/// Where queued messages go: RabbitMQ at `mq_url`, or channels within the
+/// process when running without a broker, e.g. in tests.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum MessageBusKind {
+    #[default]
+    Amqp,
+    Memory,
+}
+
 #[derive(Default, Debug, Serialize, Deserialize)]
 pub struct MinerConfig {
     pub env: String,
@@ -94,9 +488,42 @@ pub struct MinerConfig {
     pub db_url: String,
     pub redis_url: String,
     pub redis_prefix: String,
+    /// Reverse proxies, as addresses or CIDR blocks, whose
+    /// `X-Forwarded-For` and `X-Real-IP` headers name the client. Those
+    /// headers are ignored when coming from any other peer.
+    #[serde(default)]
+    pub trusted_proxies: Vec<String>,
+    /// Serves an interactive viewer of the OpenAPI document at
+    /// `/api/v1/docs`.
+    #[serde(default)]
+    pub api_docs: bool,
+    #[serde(default)]
+    pub storage: StorageKind,
     pub mq_url: String,
+    #[serde(default)]
+    pub message_bus: MessageBusKind,
     pub access_token: JWTConfig,
     pub refresh_token: JWTConfig,
+    #[serde(default)]
+    pub login_guard: LoginGuardConfig,
+    #[serde(default)]
+    pub mfa: MfaConfig,
+    #[serde(default)]
+    pub api_token: ApiTokenConfig,
+    #[serde(default)]
+    pub verification: VerificationConfig,
+    #[serde(default)]
+    pub mail_outbox: MailOutboxConfig,
+    #[serde(default)]
+    pub cache: CacheConfig,
+    #[serde(default)]
+    pub health: HealthConfig,
+    #[serde(default)]
+    pub metrics: MetricsConfig,
+    #[serde(default)]
+    pub tracing: TracingConfig,
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
     pub exchange_rate: ExchangeRateConfig,
     pub coin_stat: CoinStatConfig,
     pub coins: Vec<String>,
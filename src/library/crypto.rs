@@ -5,6 +5,7 @@ use argon2::{
 };
 use rand::{distributions::Alphanumeric, Rng};
 use rand_core::OsRng;
+use sha2::{Digest, Sha256};
 
 use crate::library::error::{AppError, AppResult};
 
@@ -22,7 +23,7 @@ pub fn verify_password(input: &str, hashed: &str) -> AppResult<bool> {
     Ok(match PasswordHash::new(input) {
         Ok(parsed_hash) => Argon2::default()
             .verify_password(hashed.as_bytes(), &parsed_hash)
-            .map_or(false, |()| true),
+            .is_ok(),
         Err(_) => false,
     })
 }
@@ -34,3 +35,12 @@ pub fn random_words(length: usize) -> String {
         .map(char::from)
         .collect()
 }
+
+/// Hex encoded SHA-256 of a high-entropy secret such as a one-time code or
+/// an API token. Not suitable for passwords, use [`hash_password`].
+pub fn hash_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
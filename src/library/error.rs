@@ -1,5 +1,5 @@
 use axum::{
-    http::StatusCode,
+    http::{header::RETRY_AFTER, HeaderValue, StatusCode},
     response::{IntoResponse, Response},
 };
 use thiserror::Error;
@@ -19,6 +19,8 @@ pub enum AppInnerError {
     JsonError(#[from] serde_json::Error),
     #[error("Email error: `{0}`")]
     EmailError(#[from] lettre::transport::smtp::Error),
+    #[error("Template error: `{0}`")]
+    TemplateError(#[from] tera::Error),
     #[error("Internal server error")]
     Unknown(String),
     #[error(transparent)]
@@ -31,6 +33,8 @@ pub enum RedisorError {
     PoolError(#[from] deadpool_redis::PoolError),
     #[error("Redis execution error: `{0}`")]
     ExeError(#[from] redis::RedisError),
+    #[error("Wrong kind of value at `{0}`")]
+    WrongType(String),
 }
 
 #[derive(Error, Debug)]
@@ -39,6 +43,8 @@ pub enum MqerError {
     PoolError(#[from] deadpool_lapin::PoolError),
     #[error("Mq execution error: `{0}`")]
     ExeError(#[from] deadpool_lapin::lapin::Error),
+    #[error("Invalid message: {0}")]
+    InvalidMessage(String),
 }
 
 #[derive(Error, Debug)]
@@ -60,6 +66,40 @@ pub enum ApiInnerError {
     DeleteGroupError,
     #[error("Error occurred when Update group")]
     UpdateGroupError,
+    #[error("Exchange rate not found")]
+    ExchangeRateNotFound,
+    #[error("Unsupported currency")]
+    UnsupportedCurrency,
+    #[error("Unsupported language")]
+    UnsupportedLanguage,
+    #[error("Coin stats not found")]
+    CoinStatNotFound,
+    #[error("Enrollment key not found")]
+    EnrollmentKeyNotFound,
+    #[error("API token not found")]
+    ApiTokenNotFound,
+    #[error("Too many API tokens")]
+    ApiTokenLimitExceeded,
+    #[error("Invalid API token: {0}")]
+    InvalidApiToken(String),
+    #[error("Organization already exists")]
+    OrganizationAlreadyExists,
+    #[error("Account not found")]
+    AccountNotFound,
+    #[error("Member not found")]
+    MemberNotFound,
+    #[error("Member already exists")]
+    MemberAlreadyExists,
+    #[error("Groups outside of the fleet or of your own access")]
+    InvalidGroupScope,
+    #[error("Admin already exists")]
+    AdminAlreadyExists,
+    #[error("Machine not found")]
+    MachineNotFound,
+    #[error("Email not found")]
+    EmailNotFound,
+    #[error("Unknown fleet event kind")]
+    InvalidFleetEventKind,
 }
 
 #[derive(Error, Debug)]
@@ -103,6 +143,30 @@ pub enum AuthInnerError {
     InvalidTokenType,
     #[error("UserAlreadyActivated")]
     UserAlreadyActivated,
+    #[error("RefreshTokenReused")]
+    RefreshTokenReused,
+    #[error("AccountLocked, retry after {0} seconds")]
+    AccountLocked(i64),
+    #[error("TooManyLoginAttempts, retry after {0} seconds")]
+    TooManyLoginAttempts(i64),
+    #[error("MfaAlreadyEnabled")]
+    MfaAlreadyEnabled,
+    #[error("MfaNotEnabled")]
+    MfaNotEnabled,
+    #[error("InsufficientScope")]
+    InsufficientScope,
+    #[error("ApiTokenRateLimited, retry after {0} seconds")]
+    ApiTokenRateLimited(i64),
+    #[error("NotOrganizationMember")]
+    NotOrganizationMember,
+    #[error("PermissionDenied")]
+    PermissionDenied,
+    #[error("CodeExpired")]
+    CodeExpired,
+    #[error("TooManyCodeAttempts")]
+    TooManyCodeAttempts,
+    #[error("TooManyRequests, retry after {0} seconds")]
+    TooManyRequests(i64),
 }
 
 impl AppError {
@@ -132,6 +196,40 @@ impl AppError {
                 AuthInnerError::UserAlreadyActivated => {
                     (StatusCode::CONFLICT, 10009)
                 }
+                AuthInnerError::RefreshTokenReused => {
+                    (StatusCode::UNAUTHORIZED, 10010)
+                }
+                AuthInnerError::AccountLocked(_) => (StatusCode::LOCKED, 10011),
+                AuthInnerError::TooManyLoginAttempts(_) => {
+                    (StatusCode::TOO_MANY_REQUESTS, 10012)
+                }
+                AuthInnerError::MfaAlreadyEnabled => {
+                    (StatusCode::CONFLICT, 10013)
+                }
+                AuthInnerError::MfaNotEnabled => {
+                    (StatusCode::BAD_REQUEST, 10014)
+                }
+                AuthInnerError::InsufficientScope => {
+                    (StatusCode::FORBIDDEN, 10015)
+                }
+                AuthInnerError::ApiTokenRateLimited(_) => {
+                    (StatusCode::TOO_MANY_REQUESTS, 10016)
+                }
+                AuthInnerError::NotOrganizationMember => {
+                    (StatusCode::FORBIDDEN, 10017)
+                }
+                AuthInnerError::PermissionDenied => {
+                    (StatusCode::FORBIDDEN, 10018)
+                }
+                AuthInnerError::CodeExpired => {
+                    (StatusCode::UNAUTHORIZED, 10019)
+                }
+                AuthInnerError::TooManyCodeAttempts => {
+                    (StatusCode::TOO_MANY_REQUESTS, 10020)
+                }
+                AuthInnerError::TooManyRequests(_) => {
+                    (StatusCode::TOO_MANY_REQUESTS, 10021)
+                }
             },
             Self::ApiError(e) => match e {
                 ApiInnerError::ValidationError(_) => {
@@ -145,10 +243,41 @@ impl AppError {
                 ApiInnerError::GetGroupError => (StatusCode::OK, 30003),
                 ApiInnerError::DeleteGroupError => (StatusCode::OK, 30004),
                 ApiInnerError::UpdateGroupError => (StatusCode::OK, 30004),
+                ApiInnerError::ExchangeRateNotFound => (StatusCode::OK, 30005),
+                ApiInnerError::UnsupportedCurrency => (StatusCode::OK, 30006),
+                ApiInnerError::UnsupportedLanguage => (StatusCode::OK, 30007),
+                ApiInnerError::CoinStatNotFound => (StatusCode::OK, 30008),
+                ApiInnerError::EnrollmentKeyNotFound => (StatusCode::OK, 30009),
+                ApiInnerError::ApiTokenNotFound => (StatusCode::OK, 30010),
+                ApiInnerError::ApiTokenLimitExceeded => (StatusCode::OK, 30011),
+                ApiInnerError::InvalidApiToken(_) => (StatusCode::OK, 30012),
+                ApiInnerError::OrganizationAlreadyExists => {
+                    (StatusCode::OK, 30013)
+                }
+                ApiInnerError::AccountNotFound => (StatusCode::OK, 30014),
+                ApiInnerError::MemberNotFound => (StatusCode::OK, 30015),
+                ApiInnerError::MemberAlreadyExists => (StatusCode::OK, 30016),
+                ApiInnerError::InvalidGroupScope => (StatusCode::OK, 30017),
+                ApiInnerError::AdminAlreadyExists => (StatusCode::OK, 30018),
+                ApiInnerError::MachineNotFound => (StatusCode::OK, 30019),
+                ApiInnerError::EmailNotFound => (StatusCode::OK, 30020),
+                ApiInnerError::InvalidFleetEventKind => (StatusCode::OK, 30021),
             },
             _ => (StatusCode::BAD_REQUEST, 99999),
         }
     }
+
+    /// Seconds a rate limited client should wait before trying again.
+    pub fn retry_after(&self) -> Option<i64> {
+        match self {
+            Self::AuthError(
+                AuthInnerError::TooManyLoginAttempts(secs)
+                | AuthInnerError::ApiTokenRateLimited(secs)
+                | AuthInnerError::TooManyRequests(secs),
+            ) => Some(*secs),
+            _ => None,
+        }
+    }
 }
 
 pub type AppResult<T> = Result<T, AppError>;
@@ -160,6 +289,12 @@ impl IntoResponse for AppError {
             "code": code,
             "msg": format!("{self}")
         }));
-        (status, body).into_response()
+        let mut response = (status, body).into_response();
+        if let Some(secs) = self.retry_after() {
+            response
+                .headers_mut()
+                .insert(RETRY_AFTER, HeaderValue::from(secs));
+        }
+        response
     }
 }
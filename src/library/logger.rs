@@ -12,7 +12,7 @@ use tracing_subscriber::{
     Layer, Registry,
 };
 
-use crate::library::cfg::AppConfig;
+use crate::library::{cfg::AppConfig, tracer};
 
 struct LocalTimer;
 
@@ -129,6 +129,8 @@ pub fn init(
         LevelFilter::from_str(&cfg.log.file_level).unwrap_or(LevelFilter::INFO),
     );
 
+    let tracer = tracer::init(&cfg.miner.tracing);
+
     if stdout {
         let mine_target = Arc::new(cfg.log.mine_target.clone());
 
@@ -156,14 +158,24 @@ pub fn init(
         let registry = Registry::default()
             .with(router_file_layer.with_filter(level_file))
             .with(mine_log.with_filter(mine_level_formatting))
-            .with(other_log.with_filter(other_level_formatting));
+            .with(other_log.with_filter(other_level_formatting))
+            .with(tracer.map(|tracer| {
+                tracing_opentelemetry::layer()
+                    .with_tracer(tracer)
+                    .with_filter(LevelFilter::INFO)
+            }));
 
         set_global_default(registry).unwrap_or_else(|e| {
             panic!("💥 Failed to setting tracing subscriber: {e:?}");
         });
     } else {
-        let registry =
-            Registry::default().with(router_file_layer.with_filter(level_file));
+        let registry = Registry::default()
+            .with(router_file_layer.with_filter(level_file))
+            .with(tracer.map(|tracer| {
+                tracing_opentelemetry::layer()
+                    .with_tracer(tracer)
+                    .with_filter(LevelFilter::INFO)
+            }));
 
         set_global_default(registry).unwrap_or_else(|e| {
             panic!("💥 Failed to setting tracing subscriber: {e:?}");
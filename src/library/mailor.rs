@@ -1,95 +1,208 @@
-use std::fmt::Debug;
+use std::collections::HashMap;
 
 use lettre::{
-    message::header::ContentType,
+    message::{Mailbox, MultiPart},
     transport::smtp::{authentication::Credentials, response::Response},
-    AsyncSmtpTransport, AsyncTransport, Message, SmtpTransport, Tokio1Executor,
-    Transport,
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
 };
-use serde::{Deserialize, Serialize};
 
 use crate::library::{
-    cfg,
-    cfg::MailConfig,
+    cfg::{self, MailConfig, SmtpProfile},
     error::{AppInnerError, InnerResult},
+    templator::{RenderedEmail, TemplateKind},
 };
 
-// TODO: masking the password in the log using macro
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Email<'a> {
-    pub to: &'a str,
-    pub subject: &'a str,
-    pub body: &'a str,
-    pub config: MailConfig,
+/// An email ready to be sent. It holds no transport details, those are
+/// looked up by the [`Mailer`] sending it.
+#[derive(Debug, Clone)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub text: String,
+    pub html: String,
 }
 
-impl<'a> Email<'a> {
-    pub fn new(to: &'a str, subject: &'a str, body: &'a str) -> Self {
-        let config = cfg::config().mail.clone();
+impl Email {
+    pub fn new(to: &str, rendered: RenderedEmail) -> Self {
         Self {
-            to,
-            subject,
-            body,
-            config,
+            to: to.to_string(),
+            subject: rendered.subject,
+            text: rendered.text,
+            html: rendered.html,
         }
     }
 
-    pub fn sync_send_text(&self) -> InnerResult<Response> {
-        let message = Message::builder()
-            .from(self.config.username.parse().map_err(|e| {
-                anyhow::anyhow!("Error occurred while sending message: {}", e)
-            })?)
+    fn message(&self, from: &Mailbox) -> InnerResult<Message> {
+        Ok(Message::builder()
+            .from(from.clone())
             .to(self.to.parse().map_err(|e| {
                 anyhow::anyhow!("Error occurred while sending message: {}", e)
             })?)
-            .subject(self.subject)
-            .header(ContentType::TEXT_PLAIN) // ContentType::TEXT_HTML
-            .body(self.body.to_string())
+            .subject(&self.subject)
+            .multipart(MultiPart::alternative_plain_html(
+                self.text.clone(),
+                self.html.clone(),
+            ))
             .map_err(|e| {
                 anyhow::anyhow!("Error occurred while sending message: {}", e)
-            })?;
+            })?)
+    }
+}
+
+struct Transport {
+    from: Mailbox,
+    smtp: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl Transport {
+    fn new(profile: &SmtpProfile) -> InnerResult<Self> {
+        let from = profile
+            .from
+            .as_deref()
+            .unwrap_or(&profile.username)
+            .parse()
+            .map_err(|e| anyhow::anyhow!("Invalid sender address: {}", e))?;
         let creds = Credentials::new(
-            self.config.username.clone(),
-            self.config.password.clone(),
+            profile.username.clone(),
+            profile.password.clone(),
         );
-        let mailer = SmtpTransport::relay(&self.config.host)
-            .map_err(|e| {
-                tracing::error!("📧 Failed to send email: {e}");
-                AppInnerError::EmailError(e)
-            })?
+        let smtp = AsyncSmtpTransport::<Tokio1Executor>::relay(&profile.host)
+            .map_err(AppInnerError::EmailError)?
             .credentials(creds)
             .build();
-        Ok(mailer.send(&message)?)
+        Ok(Self { from, smtp })
     }
+}
 
-    pub async fn async_send_text(&self) -> InnerResult<Response> {
-        let message = Message::builder()
-            .from(self.config.username.parse().map_err(|e| {
-                anyhow::anyhow!("Error occurred while sending message: {}", e)
-            })?)
-            .to(self.to.parse().map_err(|e| {
-                anyhow::anyhow!("Error occurred while sending message: {}", e)
-            })?)
-            .subject(self.subject)
-            .header(ContentType::TEXT_PLAIN) // ContentType::TEXT_HTML
-            .body(self.body.to_string())
-            .map_err(|e| {
-                anyhow::anyhow!("Error occurred while sending message: {}", e)
-            })?;
-        let creds = Credentials::new(
-            self.config.username.clone(),
-            self.config.password.clone(),
+/// Sends emails through the SMTP profiles in the mail config, picking one
+/// per template.
+pub struct Mailer {
+    default_profile: String,
+    routes: HashMap<TemplateKind, String>,
+    transports: HashMap<String, Transport>,
+}
+
+impl Mailer {
+    pub fn init() -> Self {
+        match Self::new(&cfg::config().mail) {
+            Ok(mailer) => mailer,
+            Err(e) => panic!("💥 Invalid mail configuration: {e}"),
+        }
+    }
+
+    /// Fails if a profile cannot be set up or the default profile or a
+    /// route names one that does not exist.
+    pub fn new(config: &MailConfig) -> InnerResult<Self> {
+        let transports = config
+            .profiles
+            .iter()
+            .map(|(name, profile)| {
+                let transport = Transport::new(profile).map_err(|e| {
+                    anyhow::anyhow!("Mail profile `{}`: {}", name, e)
+                })?;
+                Ok((name.clone(), transport))
+            })
+            .collect::<InnerResult<HashMap<_, _>>>()?;
+        let mut names = std::iter::once(&config.default_profile)
+            .chain(config.routes.values());
+        if let Some(name) = names.find(|name| !transports.contains_key(*name)) {
+            return Err(
+                anyhow::anyhow!("Unknown mail profile `{}`", name).into()
+            );
+        }
+        Ok(Self {
+            default_profile: config.default_profile.clone(),
+            routes: config.routes.clone(),
+            transports,
+        })
+    }
+
+    /// Name of the profile `kind` is sent through.
+    pub fn profile(&self, kind: TemplateKind) -> &str {
+        self.routes.get(&kind).unwrap_or(&self.default_profile)
+    }
+
+    pub async fn send(
+        &self,
+        kind: TemplateKind,
+        email: &Email,
+    ) -> InnerResult<Response> {
+        let profile = self.profile(kind);
+        let transport = &self.transports[profile];
+        let message = email.message(&transport.from)?;
+        transport.smtp.send(message).await.map_err(|e| {
+            tracing::error!("📧 Failed to send email via `{profile}`: {e}");
+            AppInnerError::EmailError(e)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> MailConfig {
+        let profile = |from: Option<&str>| SmtpProfile {
+            username: "noreply@example.com".to_string(),
+            password: "secret".to_string(),
+            host: "smtp.example.com".to_string(),
+            from: from.map(str::to_string),
+        };
+        MailConfig {
+            default_profile: "transactional".to_string(),
+            profiles: HashMap::from([
+                ("transactional".to_string(), profile(None)),
+                (
+                    "alerts".to_string(),
+                    profile(Some("Alerts <alerts@example.com>")),
+                ),
+            ]),
+            routes: HashMap::from([(
+                TemplateKind::Alert,
+                "alerts".to_string(),
+            )]),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_profile_routing() {
+        let mailer = Mailer::new(&config()).unwrap();
+        assert_eq!(mailer.profile(TemplateKind::Alert), "alerts");
+        assert_eq!(mailer.profile(TemplateKind::Activation), "transactional");
+        assert_eq!(
+            mailer.transports["alerts"].from.to_string(),
+            "Alerts <alerts@example.com>"
         );
+    }
 
-        let mailer =
-            AsyncSmtpTransport::<Tokio1Executor>::relay(&self.config.host)
-                .map_err(|e| {
-                    tracing::error!("📧 Failed to send email: {e}");
-                    AppInnerError::EmailError(e)
-                })?
-                .credentials(creds)
-                .build();
+    #[tokio::test]
+    async fn test_unknown_profile_is_rejected() {
+        let mut config = config();
+        config
+            .routes
+            .insert(TemplateKind::Report, "reports".to_string());
+        assert!(Mailer::new(&config).is_err());
+
+        let mut config = self::config();
+        config.default_profile = "missing".to_string();
+        assert!(Mailer::new(&config).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_config_routes() {
+        cfg::init(&"./fixtures/config.toml".to_string());
+        let mailer = Mailer::init();
+        assert_eq!(mailer.profile(TemplateKind::Report), "alerts");
+        assert_eq!(
+            mailer.profile(TemplateKind::ResetPassword),
+            "transactional"
+        );
+    }
 
-        Ok(mailer.send(message).await?)
+    #[test]
+    fn test_profile_password_is_not_logged() {
+        let config = config();
+        let debug = format!("{:?}", config.profiles["transactional"]);
+        assert!(!debug.contains("secret"));
     }
 }
@@ -0,0 +1,195 @@
+//! Prometheus metrics of the process.
+//!
+//! Counters and histograms are updated where things happen. Gauges of
+//! state held elsewhere, pools and caches, are set right before each
+//! scrape by whoever renders [`render`].
+
+use std::sync::OnceLock;
+
+use prometheus::{
+    core::Collector, HistogramOpts, HistogramVec, IntCounterVec, IntGauge,
+    IntGaugeVec, Opts, Registry, TextEncoder,
+};
+
+pub struct Metrics {
+    registry: Registry,
+    /// By `method`, matched `route` and `status`.
+    pub http_requests: IntCounterVec,
+    pub http_duration: HistogramVec,
+    /// Device sign-ins over gRPC, by `result`.
+    pub grpc_signs: IntCounterVec,
+    /// By subscribed `topic` filter.
+    pub mqtt_messages: IntCounterVec,
+    pub mqtt_parse_failures: IntCounterVec,
+    /// Connections of each `pool` by `state`.
+    pub pool_connections: IntGaugeVec,
+    /// Message bus operations holding a broker connection.
+    pub mq_in_flight: IntGauge,
+    /// Market data fetches, by `source` and `result`.
+    pub market_fetches: IntCounterVec,
+    /// Age of the cached market data by `source`, -1 when nothing is cached.
+    pub market_age: IntGaugeVec,
+    /// Machines owned by an account heard from recently, all accounts
+    /// together.
+    pub miners_online: IntGauge,
+    /// The same by owning account `uid`, for the accounts with the most.
+    pub miners_online_by_account: IntGaugeVec,
+}
+
+fn register<T: Collector + Clone + 'static>(
+    registry: &Registry,
+    metric: T,
+) -> T {
+    registry
+        .register(Box::new(metric.clone()))
+        .unwrap_or_else(|e| panic!("💥 Failed to register metric: {e}"));
+    metric
+}
+
+fn counter(
+    registry: &Registry,
+    name: &str,
+    help: &str,
+    labels: &[&str],
+) -> IntCounterVec {
+    let metric = IntCounterVec::new(Opts::new(name, help), labels)
+        .unwrap_or_else(|e| panic!("💥 Invalid metric {name}: {e}"));
+    register(registry, metric)
+}
+
+fn gauge(
+    registry: &Registry,
+    name: &str,
+    help: &str,
+    labels: &[&str],
+) -> IntGaugeVec {
+    let metric = IntGaugeVec::new(Opts::new(name, help), labels)
+        .unwrap_or_else(|e| panic!("💥 Invalid metric {name}: {e}"));
+    register(registry, metric)
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new();
+        let http_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "Time taken to answer HTTP requests",
+            ),
+            &["method", "route", "status"],
+        )
+        .unwrap_or_else(|e| panic!("💥 Invalid metric: {e}"));
+        let mq_in_flight = IntGauge::new(
+            "mq_in_flight",
+            "Message bus operations holding a broker connection",
+        )
+        .unwrap_or_else(|e| panic!("💥 Invalid metric: {e}"));
+        let miners_online =
+            IntGauge::new("miners_online", "Machines heard from recently")
+                .unwrap_or_else(|e| panic!("💥 Invalid metric: {e}"));
+        Self {
+            http_requests: counter(
+                &registry,
+                "http_requests_total",
+                "HTTP requests answered",
+                &["method", "route", "status"],
+            ),
+            http_duration: register(&registry, http_duration),
+            grpc_signs: counter(
+                &registry,
+                "grpc_sign_total",
+                "Device sign-ins over gRPC",
+                &["result"],
+            ),
+            mqtt_messages: counter(
+                &registry,
+                "mqtt_messages_total",
+                "MQTT messages received",
+                &["topic"],
+            ),
+            mqtt_parse_failures: counter(
+                &registry,
+                "mqtt_parse_failures_total",
+                "MQTT messages whose payload could not be parsed",
+                &["topic"],
+            ),
+            pool_connections: gauge(
+                &registry,
+                "pool_connections",
+                "Connections of the Postgres and Redis pools",
+                &["pool", "state"],
+            ),
+            mq_in_flight: register(&registry, mq_in_flight),
+            market_fetches: counter(
+                &registry,
+                "market_fetch_total",
+                "Exchange rate and coin stat fetches",
+                &["source", "result"],
+            ),
+            market_age: gauge(
+                &registry,
+                "market_data_age_seconds",
+                "Age of the cached market data, -1 when there is none",
+                &["source"],
+            ),
+            miners_online: register(&registry, miners_online),
+            miners_online_by_account: gauge(
+                &registry,
+                "miners_online_by_account",
+                "Machines heard from recently, of the busiest accounts",
+                &["uid"],
+            ),
+            registry,
+        }
+    }
+}
+
+pub fn metrics() -> &'static Metrics {
+    static METRICS: OnceLock<Metrics> = OnceLock::new();
+    METRICS.get_or_init(Metrics::new)
+}
+
+/// Every metric in the Prometheus text format.
+pub fn render() -> String {
+    TextEncoder::new()
+        .encode_to_string(&metrics().registry.gather())
+        .unwrap_or_else(|e| {
+            tracing::error!("Failed to encode metrics: {e}");
+            String::new()
+        })
+}
+
+/// `"ok"` or `"error"`, the `result` label of an outcome.
+pub fn result_label<T, E>(result: &Result<T, E>) -> &'static str {
+    if result.is_ok() {
+        "ok"
+    } else {
+        "error"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let metrics = metrics();
+        metrics
+            .http_requests
+            .with_label_values(&["POST", "/api/v1/auth/login", "200"])
+            .inc();
+        metrics
+            .http_duration
+            .with_label_values(&["POST", "/api/v1/auth/login", "200"])
+            .observe(0.02);
+        metrics.mq_in_flight.set(3);
+        let text = render();
+        assert!(text.contains(
+            "http_requests_total{method=\"POST\",\
+             route=\"/api/v1/auth/login\",status=\"200\"} 1"
+        ));
+        assert!(text.contains("mq_in_flight 3"));
+        assert!(text.contains("# TYPE http_request_duration_seconds histogram"));
+    }
+}
@@ -1,3 +1,4 @@
+pub mod bus;
 pub mod cacher;
 pub mod cfg;
 pub mod crypto;
@@ -5,10 +6,15 @@ pub mod dber;
 pub mod error;
 pub mod logger;
 pub mod mailor;
+pub mod metricer;
 pub mod mqer;
 pub mod redisor;
+pub mod storage;
+pub mod templator;
+pub mod totp;
+pub mod tracer;
 
 pub use dber::{Dber, DB};
 pub use mqer::{Mqer, MQ};
 pub use redis::AsyncCommands;
-pub use redisor::{Redis, Redisor};
+pub use redisor::Redisor;
@@ -1,4 +1,5 @@
 use std::{
+    collections::HashMap,
     future::Future,
     pin::Pin,
     sync::{
@@ -8,23 +9,28 @@ use std::{
     time::{Duration, Instant},
 };
 
+use axum::async_trait;
 use deadpool_lapin::{
     lapin::{
         message::DeliveryResult,
         options::{
-            BasicAckOptions, BasicConsumeOptions, BasicPublishOptions,
+            BasicAckOptions, BasicConsumeOptions, BasicNackOptions,
+            BasicPublishOptions, ExchangeDeclareOptions, QueueBindOptions,
             QueueDeclareOptions,
         },
-        types::FieldTable,
-        BasicProperties, ConsumerDelegate,
+        types::{AMQPValue, FieldTable, ShortString},
+        BasicProperties, Channel, ConsumerDelegate, ExchangeKind,
     },
     Object, Runtime,
 };
+use tracing::Instrument;
 
 use super::error::AppResult;
 use crate::library::{
+    bus::{Disposition, Envelope, MessageBus, Subscriber},
     cfg,
     error::{InnerResult, MqerError},
+    tracer,
 };
 
 pub type MQ = Object;
@@ -37,41 +43,110 @@ pub struct Mqer {
     pub count: Arc<AtomicUsize>,
 }
 
-#[derive(Clone)]
-pub struct Subscriber {
-    pub func: Arc<Box<dyn Fn(String) + Send + Sync>>,
-    pub mqer: Arc<Mqer>,
+/// Hands the deliveries of a consumer to its [`Subscriber`].
+struct Consumer {
+    subscriber: Subscriber,
+    mqer: Mqer,
 }
 
-impl Subscriber {
-    pub fn new<F>(func: F, mqer: Arc<Mqer>) -> Self
-    where
-        F: Fn(String) + Send + Sync + 'static,
-    {
-        Self {
-            func: Arc::new(Box::new(func)),
-            mqer,
-        }
+/// Carries the trace context of the publishing span, if any.
+fn traced(properties: BasicProperties) -> BasicProperties {
+    let headers = tracer::current_headers();
+    if headers.is_empty() {
+        return properties;
+    }
+    let mut table = FieldTable::default();
+    for (key, value) in headers {
+        table.insert(key.into(), AMQPValue::LongString(value.into()));
     }
+    properties.with_headers(table)
+}
+
+/// The string headers of a delivery, where its trace context is.
+fn headers(properties: &BasicProperties) -> HashMap<String, String> {
+    let Some(table) = properties.headers() else {
+        return HashMap::new();
+    };
+    table
+        .inner()
+        .iter()
+        .filter_map(|(key, value)| match value {
+            AMQPValue::LongString(value) => Some((
+                key.as_str().to_string(),
+                String::from_utf8_lossy(value.as_bytes()).into_owned(),
+            )),
+            _ => None,
+        })
+        .collect()
 }
 
-impl ConsumerDelegate for Subscriber {
+/// Persistent, so it survives a broker restart with the queue.
+fn properties(envelope: &Envelope<serde_json::Value>) -> BasicProperties {
+    let properties = traced(BasicProperties::default())
+        .with_content_type("application/json".into())
+        .with_delivery_mode(2)
+        .with_message_id(envelope.message_id.to_string().into())
+        .with_type(envelope.message_type.as_str().into())
+        .with_timestamp(envelope.timestamp.timestamp().unsigned_abs());
+    match &envelope.correlation_id {
+        Some(id) => properties.with_correlation_id(id.as_str().into()),
+        None => properties,
+    }
+}
+
+impl ConsumerDelegate for Consumer {
     fn on_new_delivery(
         &self,
         delivery: DeliveryResult,
     ) -> Pin<Box<dyn Future<Output = ()> + Send>> {
-        let func_cloned = Arc::clone(&self.func);
-        let mqer_cloned = Arc::clone(&self.mqer);
+        let subscriber = self.subscriber.clone();
+        let mqer_cloned = self.mqer.clone();
         Box::pin(async move {
             if let Ok(Some(delivery)) = delivery {
                 mqer_cloned.increase_count();
                 if !mqer_cloned.running.load(SeqCst) {
+                    mqer_cloned.decrease_count();
                     return;
                 }
 
+                let span = tracing::info_span!(
+                    "amqp_deliver",
+                    otel.kind = "consumer",
+                    messaging.destination.name = delivery.routing_key.as_str(),
+                    messaging.message.id = delivery
+                        .properties
+                        .message_id()
+                        .as_ref()
+                        .map(ShortString::as_str),
+                );
+                tracer::continue_trace(&span, &headers(&delivery.properties));
                 let message = String::from_utf8_lossy(&delivery.data);
-                (func_cloned)(message.to_string());
-                if let Err(e) = delivery.ack(BasicAckOptions::default()).await {
+                let disposition = subscriber
+                    .handle(message.to_string())
+                    .instrument(span)
+                    .await;
+                let result = match disposition {
+                    Disposition::Ack => {
+                        delivery.ack(BasicAckOptions::default()).await
+                    }
+                    Disposition::Requeue => {
+                        delivery
+                            .nack(BasicNackOptions {
+                                requeue: true,
+                                ..Default::default()
+                            })
+                            .await
+                    }
+                    Disposition::DeadLetter => {
+                        delivery
+                            .nack(BasicNackOptions {
+                                requeue: false,
+                                ..Default::default()
+                            })
+                            .await
+                    }
+                };
+                if let Err(e) = result {
                     tracing::error!("Failed to acknowledge message: {:?}", e);
                 }
                 mqer_cloned.decrease_count();
@@ -125,24 +200,74 @@ impl Mqer {
         self.count.fetch_add(1, SeqCst);
     }
 
-    pub fn graceful_shutdown(&self) -> AppResult<()> {
-        self.running.store(false, SeqCst);
+    async fn channel(&self) -> InnerResult<Channel> {
+        Ok(self
+            .get_conn()
+            .await?
+            .ok_or(anyhow::anyhow!("Channel is going to be closed"))?
+            .create_channel()
+            .await
+            .map_err(MqerError::ExeError)?)
+    }
 
-        let start = Instant::now();
+    async fn declare_topic(chan: &Channel, exchange: &str) -> InnerResult<()> {
+        chan.exchange_declare(
+            exchange,
+            ExchangeKind::Topic,
+            ExchangeDeclareOptions {
+                durable: true,
+                ..Default::default()
+            },
+            FieldTable::default(),
+        )
+        .await
+        .map_err(MqerError::ExeError)?;
+        Ok(())
+    }
 
-        while self.count.load(SeqCst) > 0 {
-            if start.elapsed() > Duration::from_secs(TIMEOUT) {
-                tracing::warn!("Graceful shutdown timed out, exiting.");
-                break;
-            }
-            std::thread::sleep(Duration::from_secs(1));
+    /// Binds `queue_name` to `exchange` and hands its deliveries to
+    /// `subscriber`.
+    async fn consume_bound(
+        &self,
+        chan: &Channel,
+        exchange: &str,
+        queue_name: &str,
+        binding_keys: &[&str],
+        tag: &str,
+        subscriber: Subscriber,
+    ) -> InnerResult<()> {
+        for key in binding_keys {
+            chan.queue_bind(
+                queue_name,
+                exchange,
+                key,
+                QueueBindOptions::default(),
+                FieldTable::default(),
+            )
+            .await
+            .map_err(MqerError::ExeError)?;
         }
 
-        tracing::info!("MQ Stopped");
+        chan.basic_consume(
+            queue_name,
+            tag,
+            BasicConsumeOptions::default(),
+            FieldTable::default(),
+        )
+        .await
+        .map_err(MqerError::ExeError)?
+        .set_delegate(Consumer {
+            subscriber,
+            mqer: self.clone(),
+        });
+        self.decrease_count();
         Ok(())
     }
+}
 
-    pub async fn basic_send(
+#[async_trait]
+impl MessageBus for Mqer {
+    async fn basic_send(
         &self,
         queue_name: &str,
         payload: &str,
@@ -171,7 +296,59 @@ impl Mqer {
             queue.name().as_str(),
             BasicPublishOptions::default(),
             payload,
-            BasicProperties::default(),
+            traced(BasicProperties::default()),
+        )
+        .await
+        .map_err(MqerError::ExeError)?
+        .await
+        .map_err(MqerError::ExeError)?;
+        self.decrease_count();
+        Ok(())
+    }
+
+    /// Messages with the same delay share a queue, so they expire in the
+    /// order they were sent.
+    async fn delay_send(
+        &self,
+        queue_name: &str,
+        target_queue: &str,
+        delay: Duration,
+        payload: &str,
+    ) -> InnerResult<()> {
+        let chan = self
+            .get_conn()
+            .await?
+            .ok_or(anyhow::anyhow!("Channel is going to be closed"))?
+            .create_channel()
+            .await
+            .map_err(MqerError::ExeError)?;
+
+        let mut args = FieldTable::default();
+        args.insert(
+            "x-message-ttl".into(),
+            AMQPValue::LongLongInt(
+                i64::try_from(delay.as_millis()).unwrap_or(i64::MAX),
+            ),
+        );
+        args.insert(
+            "x-dead-letter-exchange".into(),
+            AMQPValue::LongString("".into()),
+        );
+        args.insert(
+            "x-dead-letter-routing-key".into(),
+            AMQPValue::LongString(target_queue.into()),
+        );
+        let queue = chan
+            .queue_declare(queue_name, QueueDeclareOptions::default(), args)
+            .await
+            .map_err(MqerError::ExeError)?;
+
+        chan.basic_publish(
+            "",
+            queue.name().as_str(),
+            BasicPublishOptions::default(),
+            payload.as_bytes(),
+            traced(BasicProperties::default()),
         )
         .await
         .map_err(MqerError::ExeError)?
@@ -181,11 +358,11 @@ impl Mqer {
         Ok(())
     }
 
-    pub async fn basic_receive(
+    async fn basic_receive(
         &self,
         queue_name: &str,
         tag: &str,
-        delegate: impl ConsumerDelegate + 'static,
+        subscriber: Subscriber,
     ) -> InnerResult<()> {
         let chan = self
             .get_conn()
@@ -212,146 +389,171 @@ impl Mqer {
         )
         .await
         .map_err(MqerError::ExeError)?
-        .set_delegate(delegate);
+        .set_delegate(Consumer {
+            subscriber,
+            mqer: self.clone(),
+        });
         self.decrease_count();
         Ok(())
     }
-}
 
-// pub async fn topic_send(
-//     &self,
-//     exchange: &str,
-//     queue_name: &str,
-//     routing_key: &str,
-//     payload: &str,
-// ) -> InnerResult<()> {
-//     let chan = self
-//         .get_conn()
-//         .await?
-//         .create_channel()
-//         .await
-//         .map_err(MqerError::ExeError)?;
-//
-//     let () = chan
-//         .exchange_declare(
-//             exchange,
-//             ExchangeKind::Topic,
-//             ExchangeDeclareOptions::default(),
-//             FieldTable::default(),
-//         )
-//         .await
-//         .map_err(MqerError::ExeError)?;
-//
-//     let queue = chan
-//         .queue_declare(
-//             queue_name,
-//             QueueDeclareOptions::default(),
-//             FieldTable::default(),
-//         )
-//         .await
-//         .map_err(MqerError::ExeError)?;
-//
-//     chan.queue_bind(
-//         queue.name().as_str(),
-//         exchange,
-//         routing_key,
-//         QueueBindOptions::default(),
-//         FieldTable::default(),
-//     )
-//     .await
-//     .map_err(MqerError::ExeError)?;
-//
-//     let payload = payload.as_bytes();
-//
-//     chan.basic_publish(
-//         exchange,
-//         routing_key,
-//         BasicPublishOptions::default(),
-//         payload,
-//         BasicProperties::default(),
-//     )
-//     .await
-//     .map_err(MqerError::ExeError)?
-//     .await
-//     .map_err(MqerError::ExeError)?;
-//     Ok(())
-// }
-// pub async fn topic_receive<D: ConsumerDelegate + 'static>(
-//     &self,
-//     exchange: &str,
-//     queue_name: &str,
-//     routing_key: &str,
-//     tag: &str,
-//     delegate: D,
-// ) -> InnerResult<()> {
-//     let chan = self
-//         .get_conn()
-//         .await?
-//         .create_channel()
-//         .await
-//         .map_err(MqerError::ExeError)?;
-//
-//     chan.exchange_declare(
-//         exchange,
-//         ExchangeKind::Topic,
-//         ExchangeDeclareOptions::default(),
-//         FieldTable::default(),
-//     )
-//     .await
-//     .map_err(MqerError::ExeError)?;
-//
-//     let queue = chan
-//         .queue_declare(
-//             queue_name,
-//             QueueDeclareOptions::default(),
-//             FieldTable::default(),
-//         )
-//         .await
-//         .map_err(MqerError::ExeError)?;
-//
-//     chan.queue_bind(
-//         queue.name().as_str(),
-//         exchange,
-//         routing_key,
-//         QueueBindOptions::default(),
-//         FieldTable::default(),
-//     )
-//     .await
-//     .map_err(MqerError::ExeError)?;
-//
-//     let consumer = chan
-//         .basic_consume(
-//             queue.name().as_str(),
-//             tag,
-//             BasicConsumeOptions::default(),
-//             FieldTable::default(),
-//         )
-//         .await
-//         .map_err(MqerError::ExeError)?;
-//
-//     consumer.set_delegate(delegate);
-//
-//     loop {
-//         tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-//     }
-// }
+    async fn topic_send(
+        &self,
+        exchange: &str,
+        routing_key: &str,
+        envelope: &Envelope<serde_json::Value>,
+    ) -> InnerResult<()> {
+        let chan = self.channel().await?;
+        Self::declare_topic(&chan, exchange).await?;
+        let payload = serde_json::to_vec(envelope)?;
+
+        chan.basic_publish(
+            exchange,
+            routing_key,
+            BasicPublishOptions::default(),
+            &payload,
+            properties(envelope),
+        )
+        .await
+        .map_err(MqerError::ExeError)?
+        .await
+        .map_err(MqerError::ExeError)?;
+        self.decrease_count();
+        Ok(())
+    }
+
+    async fn topic_receive(
+        &self,
+        exchange: &str,
+        queue_name: &str,
+        binding_keys: &[&str],
+        tag: &str,
+        subscriber: Subscriber,
+    ) -> InnerResult<()> {
+        let chan = self.channel().await?;
+        Self::declare_topic(&chan, exchange).await?;
+
+        let durable = QueueDeclareOptions {
+            durable: true,
+            ..Default::default()
+        };
+        let dead_queue = format!("{queue_name}.dead");
+        chan.queue_declare(&dead_queue, durable, FieldTable::default())
+            .await
+            .map_err(MqerError::ExeError)?;
+        let mut args = FieldTable::default();
+        args.insert(
+            "x-dead-letter-exchange".into(),
+            AMQPValue::LongString("".into()),
+        );
+        args.insert(
+            "x-dead-letter-routing-key".into(),
+            AMQPValue::LongString(dead_queue.into()),
+        );
+        chan.queue_declare(queue_name, durable, args)
+            .await
+            .map_err(MqerError::ExeError)?;
+        self.consume_bound(
+            &chan,
+            exchange,
+            queue_name,
+            binding_keys,
+            tag,
+            subscriber,
+        )
+        .await
+    }
+
+    /// The queue is named by the broker and deleted once this connection
+    /// closes.
+    async fn topic_subscribe(
+        &self,
+        exchange: &str,
+        binding_keys: &[&str],
+        tag: &str,
+        subscriber: Subscriber,
+    ) -> InnerResult<()> {
+        let chan = self.channel().await?;
+        Self::declare_topic(&chan, exchange).await?;
+        let queue = chan
+            .queue_declare(
+                "",
+                QueueDeclareOptions {
+                    exclusive: true,
+                    auto_delete: true,
+                    ..Default::default()
+                },
+                FieldTable::default(),
+            )
+            .await
+            .map_err(MqerError::ExeError)?;
+        self.consume_bound(
+            &chan,
+            exchange,
+            queue.name().as_str(),
+            binding_keys,
+            tag,
+            subscriber,
+        )
+        .await
+    }
+
+    async fn ping(&self) -> InnerResult<()> {
+        if !self.running.load(SeqCst) {
+            return Err(anyhow::anyhow!("Message bus is stopped").into());
+        }
+        let conn = self.pool.get().await.map_err(MqerError::PoolError)?;
+        if !conn.status().connected() {
+            return Err(anyhow::anyhow!("Broker connection is closed").into());
+        }
+        Ok(())
+    }
+
+    fn in_flight(&self) -> usize {
+        self.count.load(SeqCst)
+    }
+
+    fn graceful_shutdown(&self) -> AppResult<()> {
+        self.running.store(false, SeqCst);
+
+        let start = Instant::now();
+
+        while self.count.load(SeqCst) > 0 {
+            if start.elapsed() > Duration::from_secs(TIMEOUT) {
+                tracing::warn!("Graceful shutdown timed out, exiting.");
+                break;
+            }
+            std::thread::sleep(Duration::from_secs(1));
+        }
+
+        tracing::info!("MQ Stopped");
+        Ok(())
+    }
+}
 
 #[cfg(test)]
 mod tests {
-    // use deadpool_lapin::lapin::{
-    //     message::DeliveryResult, options::BasicAckOptions,
-    // };
+    use serde::{Deserialize, Serialize};
 
-    use std::sync::Arc;
+    use crate::library::{
+        bus::{Disposition, Envelope, Event, MessageBus, Subscriber},
+        cfg, Mqer,
+    };
 
-    use crate::library::{cfg, mqer::Subscriber, Mqer};
+    #[derive(Debug, Serialize, Deserialize)]
+    struct MachineOnline {
+        mac: String,
+    }
+
+    impl Event for MachineOnline {
+        const TYPE: &'static str = "machine.online";
+        const VERSION: u32 = 1;
+    }
 
     #[tokio::test]
     #[ignore]
     async fn test_basic_send() {
         cfg::init(&"./fixtures/config.toml".to_string());
-        // let mqer = init("miner.dev.queue", Some("miner.dev.exchange"),
-        // Some("miner.dev.routine")).await;
         let mqer = Mqer::init();
 
         for i in 0..10 {
@@ -371,77 +573,53 @@ mod tests {
     #[ignore]
     async fn test_basic_receive() {
         cfg::init(&"./fixtures/config.toml".to_string());
-        let mqer = Arc::new(Mqer::init());
-        let func = |message: String| {
+        let mqer = Mqer::init();
+        let func = |message: String| async move {
             eprintln!("{message}");
+            true
         };
-        let delegate = Subscriber::new(func, mqer.clone());
-        // tokio::spawn(async move {
-        mqer.basic_receive("miner.dev.queue", "miner.dev.tag", delegate)
-            .await
-            .unwrap();
-        // });
-        // loop{}
+        mqer.basic_receive(
+            "miner.dev.queue",
+            "miner.dev.tag",
+            Subscriber::new(func),
+        )
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore]
+    async fn test_topic_send() {
+        cfg::init(&"./fixtures/config.toml".to_string());
+        let mqer: Box<dyn MessageBus> = Box::new(Mqer::init());
+        for _ in 0..10 {
+            let envelope = Envelope::new(MachineOnline {
+                mac: "00:1A:2B:3C:4D:5E".to_string(),
+            });
+            mqer.publish("miner.dev.events", "machine.online", &envelope)
+                .await
+                .unwrap();
+        }
     }
 
-    // #[tokio::test]
-    // #[ignore]
-    // async fn test_topic_send() {
-    //     cfg::init(&"../fixtures/config.toml".to_string());
-    //     let mqer = Mqer::init();
-    //     for i in 0..10 {
-    //         let msg = format!("#{i} Testtest");
-    //         let confirm = mqer
-    //             .topic_send(
-    //                 "miner.dev.exchange",
-    //                 "miner.dev.queue",
-    //                 "miner.dev.routine",
-    //                 &msg,
-    //             )
-    //             .await;
-    //         match confirm {
-    //             Ok(()) => tracing::info!("[x] 消息已发送成功！{}", msg),
-    //             Err(e) => tracing::error!("{:?}", e),
-    //         };
-    //
-    //         tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-    //     }
-    // }
-    //
-    // #[tokio::test]
-    // #[ignore]
-    // async fn test_topic_receive() {
-    //     cfg::init(&"../fixtures/config.toml".to_string());
-    //     let mqer = Mqer::init();
-    //     mqer.topic_receive(
-    //         "miner.dev.queue",
-    //         "miner.dev.exchange",
-    //         "miner.dev.routine",
-    //         "miner.dev.tag",
-    //         move |delivery: DeliveryResult| async move {
-    //             tracing::debug!("aaa");
-    //             let delivery = match delivery {
-    //                 Ok(Some(delivery)) => delivery,
-    //                 Ok(None) => {
-    //                     tracing::error!("None ");
-    //                     return;
-    //                 }
-    //                 Err(err) => {
-    //                     tracing::error!(
-    //                         "Failed to consume queue message {}",
-    //                         err
-    //                     );
-    //                     return;
-    //                 }
-    //             };
-    //
-    //             let message = String::from_utf8_lossy(&delivery.data);
-    //             tracing::info!("Received a message: {}", message);
-    //
-    //             delivery.ack(BasicAckOptions::default()).await.unwrap();
-    //         },
-    //     )
-    //     .await
-    //     .unwrap();
-    // }
+    #[tokio::test]
+    #[ignore]
+    async fn test_topic_receive() {
+        cfg::init(&"./fixtures/config.toml".to_string());
+        let mqer = Mqer::init();
+        let subscriber =
+            Subscriber::typed(|envelope: Envelope<MachineOnline>| async move {
+                eprintln!("{envelope:?}");
+                Disposition::Ack
+            });
+        mqer.topic_receive(
+            "miner.dev.events",
+            "miner.dev.events.test",
+            &["machine.*"],
+            "miner.dev.events.tag",
+            subscriber,
+        )
+        .await
+        .unwrap();
+    }
 }
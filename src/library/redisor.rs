@@ -1,9 +1,13 @@
+use std::collections::HashMap;
+
+use axum::async_trait;
 use deadpool_redis::{Connection, Pool, Runtime};
-use redis::{AsyncCommands, FromRedisValue, ToRedisArgs};
+use redis::AsyncCommands;
 
 use crate::library::{
     cfg,
     error::{InnerResult, RedisorError},
+    storage::{Command, Pipeline, PoolState, Storage},
 };
 
 pub struct Redisor {
@@ -11,11 +15,6 @@ pub struct Redisor {
     pub prefix: &'static str,
 }
 
-pub struct Redis {
-    pub connection: Connection,
-    pub prefix: &'static str,
-}
-
 impl Redisor {
     pub fn init() -> Self {
         let cfg = cfg::config();
@@ -33,148 +32,259 @@ impl Redisor {
         }
     }
 
-    pub async fn get_redis(&self) -> InnerResult<Redis> {
-        Ok(Redis {
-            prefix: self.prefix,
-            connection: self
-                .pool
-                .get()
-                .await
-                .map_err(RedisorError::PoolError)?,
-        })
+    pub async fn connection(&self) -> InnerResult<Connection> {
+        Ok(self.pool.get().await.map_err(RedisorError::PoolError)?)
     }
-}
 
-impl Redis {
-    pub fn key(&mut self, key: &str) -> String {
+    pub fn key(&self, key: &str) -> String {
         format!("{}{}", self.prefix, key)
     }
+}
 
-    pub async fn get<T: FromRedisValue + Send + Sync>(
-        &mut self,
-        key: &str,
-    ) -> InnerResult<Option<T>> {
-        let key = self.key(key);
-        let result: Option<T> = self
-            .connection
-            .get(key)
+#[async_trait]
+impl Storage for Redisor {
+    async fn get(&self, key: &str) -> InnerResult<Option<String>> {
+        let result: Option<String> = self
+            .connection()
+            .await?
+            .get(self.key(key))
             .await
             .map_err(RedisorError::ExeError)?;
         Ok(result)
     }
 
-    pub async fn set<T: ToRedisArgs + Send + Sync>(
-        &mut self,
+    async fn mget(&self, keys: &[&str]) -> InnerResult<Vec<Option<String>>> {
+        if keys.is_empty() {
+            return Ok(Vec::new());
+        }
+        let keys = keys.iter().map(|key| self.key(key)).collect::<Vec<_>>();
+        let result: Vec<Option<String>> = self
+            .connection()
+            .await?
+            .mget(keys)
+            .await
+            .map_err(RedisorError::ExeError)?;
+        Ok(result)
+    }
+
+    async fn set(&self, key: &str, value: &str) -> InnerResult<()> {
+        self.connection()
+            .await?
+            .set::<_, _, ()>(self.key(key), value)
+            .await
+            .map_err(RedisorError::ExeError)?;
+        Ok(())
+    }
+
+    async fn set_ex(
+        &self,
         key: &str,
-        value: T,
+        value: &str,
+        ttl: u64,
     ) -> InnerResult<()> {
-        let key = self.key(key);
-        self.connection
-            .set::<_, _, ()>(key, value)
+        self.connection()
+            .await?
+            .set_ex::<_, _, ()>(self.key(key), value, ttl)
+            .await
+            .map_err(RedisorError::ExeError)?;
+        Ok(())
+    }
+
+    async fn del(&self, key: &str) -> InnerResult<()> {
+        self.connection()
+            .await?
+            .del::<_, ()>(self.key(key))
             .await
             .map_err(RedisorError::ExeError)?;
         Ok(())
     }
 
-    pub async fn hkeys<T: FromRedisValue + Send + Sync>(
-        &mut self,
+    async fn expire(&self, key: &str, ttl: i64) -> InnerResult<()> {
+        self.connection()
+            .await?
+            .expire::<_, ()>(self.key(key), ttl)
+            .await
+            .map_err(RedisorError::ExeError)?;
+        Ok(())
+    }
+
+    async fn ttl(&self, key: &str) -> InnerResult<Option<i64>> {
+        let ttl: i64 = self
+            .connection()
+            .await?
+            .ttl(self.key(key))
+            .await
+            .map_err(RedisorError::ExeError)?;
+        Ok((ttl >= 0).then_some(ttl))
+    }
+
+    async fn incr_ex(&self, key: &str, ttl: i64) -> InnerResult<i64> {
+        // One script, so the counter cannot be left without its expiry; a
+        // counter that somehow has none gets one on the next increment.
+        let script = redis::Script::new(
+            r"
+            local count = redis.call('INCR', KEYS[1])
+            if redis.call('TTL', KEYS[1]) < 0 then
+                redis.call('EXPIRE', KEYS[1], ARGV[1])
+            end
+            return count
+            ",
+        );
+        let count: i64 = script
+            .key(self.key(key))
+            .arg(ttl)
+            .invoke_async(&mut self.connection().await?)
+            .await
+            .map_err(RedisorError::ExeError)?;
+        Ok(count)
+    }
+
+    async fn compare_and_swap(
+        &self,
         key: &str,
-    ) -> InnerResult<Option<Vec<T>>> {
-        let key = self.key(key);
-        let result: Option<Vec<T>> = self
-            .connection
-            .hkeys(key)
+        current: &str,
+        value: &str,
+        ttl: u64,
+    ) -> InnerResult<bool> {
+        let script = redis::Script::new(
+            r"
+            if redis.call('GET', KEYS[1]) == ARGV[1] then
+                redis.call('SET', KEYS[1], ARGV[2], 'EX', ARGV[3])
+                return 1
+            end
+            return 0
+            ",
+        );
+        let swapped: i32 = script
+            .key(self.key(key))
+            .arg(current)
+            .arg(value)
+            .arg(ttl)
+            .invoke_async(&mut self.connection().await?)
             .await
             .map_err(RedisorError::ExeError)?;
-        Ok(result)
+        Ok(swapped == 1)
     }
 
-    pub async fn hset<T: ToRedisArgs + Send + Sync>(
-        &mut self,
+    async fn hset(
+        &self,
         key: &str,
         field: &str,
-        value: T,
+        value: &str,
     ) -> InnerResult<()> {
-        let key = self.key(key);
-        self.connection
-            .hset::<_, _, _, ()>(key, field, value)
+        self.connection()
+            .await?
+            .hset::<_, _, _, ()>(self.key(key), field, value)
             .await
             .map_err(RedisorError::ExeError)?;
         Ok(())
     }
 
-    pub async fn del(&mut self, key: &str) -> InnerResult<()> {
-        let key = self.key(key);
-        self.connection
-            .del::<_, ()>(key)
+    async fn hdel(&self, key: &str, field: &str) -> InnerResult<()> {
+        self.connection()
+            .await?
+            .hdel::<_, _, ()>(self.key(key), field)
             .await
             .map_err(RedisorError::ExeError)?;
         Ok(())
     }
 
-    pub async fn set_ex<T: ToRedisArgs + Send + Sync>(
-        &mut self,
+    async fn hkeys(&self, key: &str) -> InnerResult<Vec<String>> {
+        let result: Vec<String> = self
+            .connection()
+            .await?
+            .hkeys(self.key(key))
+            .await
+            .map_err(RedisorError::ExeError)?;
+        Ok(result)
+    }
+
+    async fn hgets(
+        &self,
         key: &str,
-        value: T,
-        ttl: u64,
-    ) -> InnerResult<()> {
-        let key = self.key(key);
-        self.connection
-            .set_ex::<_, _, ()>(key, value, ttl)
+        fields: &[&str],
+    ) -> InnerResult<Vec<Option<String>>> {
+        if fields.is_empty() {
+            return Ok(Vec::new());
+        }
+        // `hget` would send a lone field as HGET, answered by a bare value.
+        let result: Vec<Option<String>> = redis::cmd("HMGET")
+            .arg(self.key(key))
+            .arg(fields)
+            .query_async(&mut self.connection().await?)
+            .await
+            .map_err(RedisorError::ExeError)?;
+        Ok(result)
+    }
+
+    async fn hgetall(&self, key: &str) -> InnerResult<HashMap<String, String>> {
+        let result: HashMap<String, String> = self
+            .connection()
+            .await?
+            .hgetall(self.key(key))
+            .await
+            .map_err(RedisorError::ExeError)?;
+        Ok(result)
+    }
+
+    async fn hgetalls(
+        &self,
+        keys: &[&str],
+    ) -> InnerResult<Vec<HashMap<String, String>>> {
+        let mut pipe = redis::pipe();
+        for key in keys {
+            pipe.hgetall(self.key(key));
+        }
+        let result: Vec<HashMap<String, String>> = pipe
+            .query_async(&mut self.connection().await?)
+            .await
+            .map_err(RedisorError::ExeError)?;
+        Ok(result)
+    }
+
+    async fn exec(&self, pipeline: &Pipeline) -> InnerResult<()> {
+        let mut pipe = redis::pipe();
+        pipe.atomic();
+        for command in &pipeline.commands {
+            match command {
+                Command::Set { key, value, ttl } => match ttl {
+                    Some(ttl) => pipe.set_ex(self.key(key), value, *ttl),
+                    None => pipe.set(self.key(key), value),
+                },
+                Command::Del { key } => pipe.del(self.key(key)),
+                Command::Expire { key, ttl } => {
+                    pipe.expire(self.key(key), *ttl)
+                }
+                Command::Hset { key, field, value } => {
+                    pipe.hset(self.key(key), field, value)
+                }
+                Command::Hdel { key, field } => pipe.hdel(self.key(key), field),
+            }
+            .ignore();
+        }
+        pipe.query_async::<_, ()>(&mut self.connection().await?)
             .await
             .map_err(RedisorError::ExeError)?;
         Ok(())
     }
 
-    pub async fn expire(&mut self, key: &str, ttl: i64) -> InnerResult<()> {
-        let key = self.key(key);
-        self.connection
-            .expire::<_, ()>(key, ttl)
+    async fn ping(&self) -> InnerResult<()> {
+        redis::cmd("PING")
+            .query_async::<_, String>(&mut self.connection().await?)
             .await
             .map_err(RedisorError::ExeError)?;
         Ok(())
     }
 
-    // pub async fn mget(
-    //     &mut self,
-    //     keys: &[&str],
-    // ) -> InnerResult<Vec<Option<String>>> {
-    //     // let key = self.key(key);
-    //     let result: Vec<Option<String>> = self
-    //         .connection
-    //         .mget(keys)
-    //         .await
-    //         .map_err(RedisorError::ExeError)?;
-    //     Ok(result)
-    // }
-
-    // pub async fn hgetalls(
-    //     &mut self,
-    //     keys: &[&str],
-    // ) -> InnerResult<Vec<HashMap<String, String>>> {
-    //     let mut pipe = redis::pipe();
-    //     keys.into_iter().for_each(|key| {
-    //         pipe.hgetall(key);
-    //     });
-    //     let result = pipe
-    //         .query_async(&mut self.connection)
-    //         .await
-    //         .map_err(RedisorError::ExeError)?;
-    //     Ok(result)
-    // }
-
-    // pub async fn hgets(
-    //     &mut self,
-    //     key: &str,
-    //     fields: &[&str],
-    // ) -> InnerResult<Vec<Option<String>>> {
-    //     let result: Vec<Option<String>> = self
-    //         .connection
-    //         .hget(key, fields)
-    //         .await
-    //         .map_err(RedisorError::ExeError)?;
-    //     Ok(result)
-    // }
+    fn pool_state(&self) -> Option<PoolState> {
+        let status = self.pool.status();
+        Some(PoolState {
+            max_size: status.max_size,
+            size: status.size,
+            available: status.available,
+            waiting: status.waiting,
+        })
+    }
 }
 
 #[cfg(test)]
@@ -186,26 +296,21 @@ mod tests {
     #[tokio::test]
     async fn test_redisor_init() {
         cfg::init(&"./fixtures/config.toml".to_string());
-        let redisor = Redisor::init();
-        let mut redis = redisor.get_redis().await.unwrap();
+        let redis = Redisor::init();
 
         redis.set("ping", "pong").await.unwrap();
-        assert_eq!(redis.get::<String>("ping").await.unwrap().unwrap(), "pong");
+        assert_eq!(redis.get("ping").await.unwrap().unwrap(), "pong");
     }
 
     #[tokio::test]
     async fn test_redisor_del() {
         cfg::init(&"./fixtures/config.toml".to_string());
-        let redisor = Redisor::init();
-        let mut redis = redisor.get_redis().await.unwrap();
+        let redis = Redisor::init();
 
         redis.set("key2", "value").await.unwrap();
-        assert_eq!(
-            redis.get::<String>("key2").await.unwrap(),
-            Some("value".to_string())
-        );
+        assert_eq!(redis.get("key2").await.unwrap(), Some("value".to_string()));
         redis.del("key2").await.unwrap();
-        assert_eq!(redis.get::<String>("key2").await.unwrap(), None);
+        assert_eq!(redis.get("key2").await.unwrap(), None);
         redis.del("key2").await.unwrap();
     }
 
@@ -213,30 +318,25 @@ mod tests {
     #[ignore]
     async fn test_redisor_set_ex() {
         cfg::init(&"./fixtures/config.toml".to_string());
-        let redisor = Redisor::init();
-        let mut redis = redisor.get_redis().await.unwrap();
+        let redis = Redisor::init();
         redis.del("key3").await.unwrap();
         redis.set_ex("key3", "value", 10).await.unwrap();
-        assert_eq!(
-            redis.get::<String>("key3").await.unwrap(),
-            Some("value".to_string())
-        );
+        assert_eq!(redis.get("key3").await.unwrap(), Some("value".to_string()));
         tokio::time::sleep(time::Duration::from_millis(10000)).await;
-        assert_eq!(redis.get::<String>("key3").await.unwrap(), None);
+        assert_eq!(redis.get("key3").await.unwrap(), None);
         redis.del("key3").await.unwrap();
     }
 
     #[tokio::test]
     async fn test_redisor_hset() {
         cfg::init(&"./fixtures/config.toml".to_string());
-        let redisor = Redisor::init();
-        let mut redis = redisor.get_redis().await.unwrap();
+        let redis = Redisor::init();
         redis.del("key4").await.unwrap();
         redis.hset("key4", "field1", "value1").await.unwrap();
         redis.hset("key4", "field2", "value2").await.unwrap();
         assert_eq!(
             redis.hkeys("key4").await.unwrap(),
-            Some(vec!["field1".to_string(), "field2".to_string()])
+            vec!["field1".to_string(), "field2".to_string()]
         );
         redis.del("key4").await.unwrap();
     }
@@ -244,15 +344,14 @@ mod tests {
     #[tokio::test]
     async fn test_redisor_hkeys() {
         cfg::init(&"./fixtures/config.toml".to_string());
-        let redisor = Redisor::init();
-        let mut redis = redisor.get_redis().await.unwrap();
+        let redis = Redisor::init();
         redis.del("key5").await.unwrap();
-        assert_eq!(redis.hkeys::<String>("key5").await.unwrap(), Some(vec![]));
+        assert!(redis.hkeys("key5").await.unwrap().is_empty());
         redis.hset("key5", "field1", "value1").await.unwrap();
         redis.hset("key5", "field2", "value2").await.unwrap();
         assert_eq!(
             redis.hkeys("key5").await.unwrap(),
-            Some(vec!["field1".to_string(), "field2".to_string()])
+            vec!["field1".to_string(), "field2".to_string()]
         );
         redis.del("key5").await.unwrap();
     }
@@ -260,75 +359,82 @@ mod tests {
     #[tokio::test]
     async fn test_redisor_expire() {
         cfg::init(&"./fixtures/config.toml".to_string());
-        let redisor = Redisor::init();
-        let mut redis = redisor.get_redis().await.unwrap();
+        let redis = Redisor::init();
         redis.del("key6").await.unwrap();
         redis.set_ex("key6", "value", 10).await.unwrap();
-        assert_eq!(
-            redis.get::<String>("key6").await.unwrap(),
-            Some("value".to_string())
-        );
+        assert_eq!(redis.get("key6").await.unwrap(), Some("value".to_string()));
         tokio::time::sleep(time::Duration::from_millis(10000)).await;
-        assert_eq!(redis.get::<String>("key6").await.unwrap(), None);
+        assert_eq!(redis.get("key6").await.unwrap(), None);
         redis.del("key6").await.unwrap();
     }
 
-    // #[tokio::test]
-    // async fn test_redisor_mget() {
-    //     cfg::init(&"./fixtures/config.toml".to_string());
-    //     let redisor = Redisor::init();
-    //     let mut redis = redisor.get_redis().await.unwrap();
-    //     redis.set("key7", "value1").await.unwrap();
-    //     redis.set("key8", "value2").await.unwrap();
-    //     assert_eq!(
-    //         redis.mget(&["key7", "key8"].to_vec()).await.unwrap(),
-    //         vec![Some("value1".to_string()), Some("value2".to_string())]
-    //     );
-    //     redis.del("key7").await.unwrap();
-    //     redis.del("key8").await.unwrap();
-    // }
-
-    // #[tokio::test]
-    // async fn test_redisor_hget() {
-    //     cfg::init(&"./fixtures/config.toml".to_string());
-    //     let redisor = Redisor::init();
-    //     let mut redis = redisor.get_redis().await.unwrap();
-    //     redis.del("key9").await.unwrap();
-    //     redis.hset("key9", "field1", "value1").await.unwrap();
-    //     redis.hset("key9", "field2", "value2").await.unwrap();
-    //     assert_eq!(
-    //         redis.hgets("key9", &["field1", "field2"]).await.unwrap(),
-    //         vec![Some("value1".to_string()), Some("value2".to_string())]
-    //     );
-    //     redis.del("key9").await.unwrap();
-    // }
-
-    // #[tokio::test]
-    // async fn test_redisor_hgetalls() {
-    //     cfg::init(&"./fixtures/config.toml".to_string());
-    //     let redisor = Redisor::init();
-    //     let mut redis = redisor.get_redis().await.unwrap();
-    //     redis.del("key10").await.unwrap();
-    //     redis.hset("key10", "field1", "value1").await.unwrap();
-    //     redis.hset("key10", "field2", "value2").await.unwrap();
-    //     redis.del("key11").await.unwrap();
-    //     redis.hset("key11", "field1", "value1").await.unwrap();
-    //     redis.hset("key11", "field2", "value2").await.unwrap();
-    //     eprintln!(
-    //         "{:#?}",
-    //         redis.hgetalls(&["key10", "key11", "key12"]).await.unwrap()
-    //     );
-    //     let mut hm1 = HashMap::new();
-    //     hm1.insert("field1".to_string(), "value1".to_string());
-    //     hm1.insert("field2".to_string(), "value2".to_string());
-    //     let mut hm2 = HashMap::new();
-    //     hm2.insert("field1".to_string(), "value1".to_string());
-    //     hm2.insert("field2".to_string(), "value2".to_string());
-    //     assert_eq!(
-    //         redis.hgetalls(&["key10", "key11"]).await.unwrap(),
-    //         vec![hm1, hm2]
-    //     );
-    //     redis.del("key10").await.unwrap();
-    //     redis.del("key11").await.unwrap();
-    // }
+    #[tokio::test]
+    async fn test_redisor_mget() {
+        cfg::init(&"./fixtures/config.toml".to_string());
+        let redis = Redisor::init();
+        redis.set("key7", "value1").await.unwrap();
+        redis.set("key8", "value2").await.unwrap();
+        assert_eq!(
+            redis.mget(&["key7", "key8", "key7a"]).await.unwrap(),
+            vec![Some("value1".to_string()), Some("value2".to_string()), None]
+        );
+        assert_eq!(
+            redis.mget(&["key7"]).await.unwrap(),
+            vec![Some("value1".to_string())]
+        );
+        redis.del("key7").await.unwrap();
+        redis.del("key8").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_redisor_hgets() {
+        cfg::init(&"./fixtures/config.toml".to_string());
+        let redis = Redisor::init();
+        redis.del("key9").await.unwrap();
+        redis.hset("key9", "field1", "value1").await.unwrap();
+        redis.hset("key9", "field2", "value2").await.unwrap();
+        assert_eq!(
+            redis.hgets("key9", &["field1", "field2"]).await.unwrap(),
+            vec![Some("value1".to_string()), Some("value2".to_string())]
+        );
+        assert_eq!(redis.hgets("key9", &["field3"]).await.unwrap(), vec![None]);
+        redis.del("key9").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_redisor_hgetalls() {
+        cfg::init(&"./fixtures/config.toml".to_string());
+        let redis = Redisor::init();
+        redis.del("key10").await.unwrap();
+        redis.hset("key10", "field1", "value1").await.unwrap();
+        redis.hset("key10", "field2", "value2").await.unwrap();
+        redis.del("key11").await.unwrap();
+        redis.hset("key11", "field1", "value1").await.unwrap();
+        redis.hset("key11", "field2", "value2").await.unwrap();
+        let mut hm = HashMap::new();
+        hm.insert("field1".to_string(), "value1".to_string());
+        hm.insert("field2".to_string(), "value2".to_string());
+        assert_eq!(
+            redis.hgetalls(&["key10", "key11", "key12"]).await.unwrap(),
+            vec![hm.clone(), hm, HashMap::new()]
+        );
+        redis.del("key10").await.unwrap();
+        redis.del("key11").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_redisor_exec() {
+        cfg::init(&"./fixtures/config.toml".to_string());
+        let redis = Redisor::init();
+        let mut pipeline = Pipeline::new();
+        pipeline
+            .set_ex("key13", "value", 60)
+            .hset("key14", "field", "value")
+            .expire("key14", 60);
+        redis.exec(&pipeline).await.unwrap();
+        assert_eq!(redis.ttl("key13").await.unwrap(), Some(60));
+        assert_eq!(redis.ttl("key14").await.unwrap(), Some(60));
+        redis.del("key13").await.unwrap();
+        redis.del("key14").await.unwrap();
+    }
 }
@@ -0,0 +1,714 @@
+//! Key-value and hash storage behind the [`Storage`] trait, implemented by
+//! [`Redisor`] and by [`MemoryStorage`] within the process.
+//!
+//! Values are strings, as they are in Redis; callers serialize anything
+//! else themselves. The in-memory storage keeps Redis semantics where the
+//! server relies on them: expiring keys, `set` clearing a TTL while `hset`
+//! and `incr` keep it, and type errors on using a hash as a string. Expired
+//! entries are dropped when next touched and by a periodic sweep, so keys
+//! never read again do not pile up.
+
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    sync::{Arc, Mutex, MutexGuard, PoisonError, Weak},
+    time::{Duration, Instant},
+};
+
+use axum::async_trait;
+
+use crate::library::{
+    cfg::{self, StorageKind},
+    error::{InnerResult, RedisorError},
+    Redisor,
+};
+
+#[async_trait]
+pub trait Storage: Send + Sync {
+    async fn get(&self, key: &str) -> InnerResult<Option<String>>;
+
+    /// Values of `keys`, in order, `None` for the missing ones.
+    async fn mget(&self, keys: &[&str]) -> InnerResult<Vec<Option<String>>>;
+
+    /// Sets `key`, dropping any expiry it had.
+    async fn set(&self, key: &str, value: &str) -> InnerResult<()>;
+
+    async fn set_ex(&self, key: &str, value: &str, ttl: u64)
+        -> InnerResult<()>;
+
+    async fn del(&self, key: &str) -> InnerResult<()>;
+
+    async fn expire(&self, key: &str, ttl: i64) -> InnerResult<()>;
+
+    /// Remaining seconds to live of `key`, `None` if it has no expiry.
+    async fn ttl(&self, key: &str) -> InnerResult<Option<i64>>;
+
+    /// Increments a counter, starting its `ttl` when it is created.
+    async fn incr_ex(&self, key: &str, ttl: i64) -> InnerResult<i64>;
+
+    /// Atomically replaces `key` with `value` only if it still holds
+    /// `current`, returning whether the swap happened.
+    async fn compare_and_swap(
+        &self,
+        key: &str,
+        current: &str,
+        value: &str,
+        ttl: u64,
+    ) -> InnerResult<bool>;
+
+    async fn hset(
+        &self,
+        key: &str,
+        field: &str,
+        value: &str,
+    ) -> InnerResult<()>;
+
+    async fn hdel(&self, key: &str, field: &str) -> InnerResult<()>;
+
+    async fn hkeys(&self, key: &str) -> InnerResult<Vec<String>>;
+
+    /// Values of `fields` in the hash `key`, in order.
+    async fn hgets(
+        &self,
+        key: &str,
+        fields: &[&str],
+    ) -> InnerResult<Vec<Option<String>>>;
+
+    async fn hgetall(&self, key: &str) -> InnerResult<HashMap<String, String>>;
+
+    /// [`Storage::hgetall`] of each of `keys` in one round trip, empty for
+    /// the missing ones.
+    async fn hgetalls(
+        &self,
+        keys: &[&str],
+    ) -> InnerResult<Vec<HashMap<String, String>>>;
+
+    /// Runs the writes queued in `pipeline` in one round trip, with no other
+    /// command in between. As in a Redis transaction, a failing command
+    /// does not undo the others.
+    async fn exec(&self, pipeline: &Pipeline) -> InnerResult<()>;
+
+    /// Fails unless the backend answers.
+    async fn ping(&self) -> InnerResult<()>;
+
+    /// Connections of the pool behind the storage, if there is one.
+    fn pool_state(&self) -> Option<PoolState>;
+}
+
+/// Connections of a pool, as reported by deadpool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolState {
+    pub max_size: usize,
+    pub size: usize,
+    pub available: usize,
+    pub waiting: usize,
+}
+
+impl dyn Storage + '_ {
+    /// Like [`Storage::get`], parsing the value.
+    pub async fn get_as<T: FromStr>(&self, key: &str) -> InnerResult<Option<T>>
+    where
+        T::Err: std::fmt::Display,
+    {
+        self.get(key)
+            .await?
+            .map(|value| {
+                value.parse().map_err(|e| {
+                    anyhow::anyhow!("Unexpected value at {}: {}", key, e).into()
+                })
+            })
+            .transpose()
+    }
+}
+
+/// How often [`MemoryStorage`] drops expired entries no one read since.
+const MEMORY_SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The storage selected by `miner.storage`.
+pub fn init() -> Arc<dyn Storage> {
+    match cfg::config().miner.storage {
+        StorageKind::Redis => Arc::new(Redisor::init()),
+        StorageKind::Memory => {
+            tracing::info!("🚀 Using the in-memory storage");
+            let storage = MemoryStorage::new();
+            storage.sweep_every(MEMORY_SWEEP_INTERVAL);
+            Arc::new(storage)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Set {
+        key: String,
+        value: String,
+        ttl: Option<u64>,
+    },
+    Del {
+        key: String,
+    },
+    Expire {
+        key: String,
+        ttl: i64,
+    },
+    Hset {
+        key: String,
+        field: String,
+        value: String,
+    },
+    Hdel {
+        key: String,
+        field: String,
+    },
+}
+
+/// Writes queued to be run together by [`Storage::exec`].
+#[derive(Debug, Clone, Default)]
+pub struct Pipeline {
+    pub commands: Vec<Command>,
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) -> &mut Self {
+        self.commands.push(Command::Set {
+            key: key.to_string(),
+            value: value.to_string(),
+            ttl: None,
+        });
+        self
+    }
+
+    pub fn set_ex(&mut self, key: &str, value: &str, ttl: u64) -> &mut Self {
+        self.commands.push(Command::Set {
+            key: key.to_string(),
+            value: value.to_string(),
+            ttl: Some(ttl),
+        });
+        self
+    }
+
+    pub fn del(&mut self, key: &str) -> &mut Self {
+        self.commands.push(Command::Del {
+            key: key.to_string(),
+        });
+        self
+    }
+
+    pub fn expire(&mut self, key: &str, ttl: i64) -> &mut Self {
+        self.commands.push(Command::Expire {
+            key: key.to_string(),
+            ttl,
+        });
+        self
+    }
+
+    pub fn hset(&mut self, key: &str, field: &str, value: &str) -> &mut Self {
+        self.commands.push(Command::Hset {
+            key: key.to_string(),
+            field: field.to_string(),
+            value: value.to_string(),
+        });
+        self
+    }
+
+    pub fn hdel(&mut self, key: &str, field: &str) -> &mut Self {
+        self.commands.push(Command::Hdel {
+            key: key.to_string(),
+            field: field.to_string(),
+        });
+        self
+    }
+}
+
+enum Value {
+    String(String),
+    Hash(HashMap<String, String>),
+}
+
+struct Entry {
+    value: Value,
+    expires_at: Option<Instant>,
+}
+
+impl Entry {
+    fn is_live(&self, now: Instant) -> bool {
+        self.expires_at.is_none_or(|at| at > now)
+    }
+}
+
+#[derive(Default)]
+struct Entries(HashMap<String, Entry>);
+
+impl Entries {
+    /// The entry at `key` unless it has expired, which removes it.
+    fn live(&mut self, key: &str) -> Option<&mut Entry> {
+        let now = Instant::now();
+        if self.0.get(key).is_some_and(|entry| !entry.is_live(now)) {
+            self.0.remove(key);
+        }
+        self.0.get_mut(key)
+    }
+
+    fn string(&mut self, key: &str) -> InnerResult<Option<&mut String>> {
+        match self.live(key) {
+            None => Ok(None),
+            Some(Entry {
+                value: Value::String(value),
+                ..
+            }) => Ok(Some(value)),
+            Some(_) => Err(RedisorError::WrongType(key.to_string()).into()),
+        }
+    }
+
+    fn hash(
+        &mut self,
+        key: &str,
+    ) -> InnerResult<Option<&mut HashMap<String, String>>> {
+        match self.live(key) {
+            None => Ok(None),
+            Some(Entry {
+                value: Value::Hash(hash),
+                ..
+            }) => Ok(Some(hash)),
+            Some(_) => Err(RedisorError::WrongType(key.to_string()).into()),
+        }
+    }
+
+    fn set(&mut self, key: &str, value: &str, ttl: Option<u64>) {
+        let entry = Entry {
+            value: Value::String(value.to_string()),
+            expires_at: ttl
+                .map(|ttl| Instant::now() + Duration::from_secs(ttl)),
+        };
+        self.0.insert(key.to_string(), entry);
+    }
+
+    fn expire(&mut self, key: &str, ttl: i64) {
+        if ttl <= 0 {
+            self.0.remove(key);
+        } else if let Some(entry) = self.live(key) {
+            entry.expires_at =
+                Some(Instant::now() + Duration::from_secs(ttl.unsigned_abs()));
+        }
+    }
+
+    /// Drops every expired entry, returning how many there were.
+    fn purge_expired(&mut self) -> usize {
+        let now = Instant::now();
+        let before = self.0.len();
+        self.0.retain(|_, entry| entry.is_live(now));
+        before - self.0.len()
+    }
+
+    fn hset(&mut self, key: &str, field: &str, value: &str) -> InnerResult<()> {
+        match self.hash(key)? {
+            Some(hash) => {
+                hash.insert(field.to_string(), value.to_string());
+            }
+            None => {
+                let hash =
+                    HashMap::from([(field.to_string(), value.to_string())]);
+                self.0.insert(
+                    key.to_string(),
+                    Entry {
+                        value: Value::Hash(hash),
+                        expires_at: None,
+                    },
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn hdel(&mut self, key: &str, field: &str) -> InnerResult<()> {
+        if let Some(hash) = self.hash(key)? {
+            hash.remove(field);
+            if hash.is_empty() {
+                self.0.remove(key);
+            }
+        }
+        Ok(())
+    }
+
+    fn hgetall(&mut self, key: &str) -> InnerResult<HashMap<String, String>> {
+        Ok(self.hash(key)?.cloned().unwrap_or_default())
+    }
+
+    fn run(&mut self, command: &Command) -> InnerResult<()> {
+        match command {
+            Command::Set { key, value, ttl } => self.set(key, value, *ttl),
+            Command::Del { key } => {
+                self.0.remove(key);
+            }
+            Command::Expire { key, ttl } => self.expire(key, *ttl),
+            Command::Hset { key, field, value } => {
+                self.hset(key, field, value)?
+            }
+            Command::Hdel { key, field } => self.hdel(key, field)?,
+        }
+        Ok(())
+    }
+}
+
+/// A [`Storage`] living in the process, for running without Redis.
+#[derive(Clone, Default)]
+pub struct MemoryStorage {
+    entries: Arc<Mutex<Entries>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn entries(&self) -> MutexGuard<'_, Entries> {
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Drops expired entries every `period` for as long as the storage
+    /// lives. Must be called within a Tokio runtime.
+    pub fn sweep_every(&self, period: Duration) {
+        let entries = Arc::downgrade(&self.entries);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                let Some(entries) = Weak::upgrade(&entries) else {
+                    return;
+                };
+                let purged = entries
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .purge_expired();
+                if purged > 0 {
+                    tracing::debug!("Purged {purged} expired entries");
+                }
+            }
+        });
+    }
+}
+
+#[async_trait]
+impl Storage for MemoryStorage {
+    async fn get(&self, key: &str) -> InnerResult<Option<String>> {
+        Ok(self.entries().string(key)?.cloned())
+    }
+
+    async fn mget(&self, keys: &[&str]) -> InnerResult<Vec<Option<String>>> {
+        let mut entries = self.entries();
+        // Like Redis, anything but a string reads as missing here.
+        Ok(keys
+            .iter()
+            .map(|key| entries.string(key).ok().flatten().cloned())
+            .collect())
+    }
+
+    async fn set(&self, key: &str, value: &str) -> InnerResult<()> {
+        self.entries().set(key, value, None);
+        Ok(())
+    }
+
+    async fn set_ex(
+        &self,
+        key: &str,
+        value: &str,
+        ttl: u64,
+    ) -> InnerResult<()> {
+        self.entries().set(key, value, Some(ttl));
+        Ok(())
+    }
+
+    async fn del(&self, key: &str) -> InnerResult<()> {
+        self.entries().0.remove(key);
+        Ok(())
+    }
+
+    async fn expire(&self, key: &str, ttl: i64) -> InnerResult<()> {
+        self.entries().expire(key, ttl);
+        Ok(())
+    }
+
+    async fn ttl(&self, key: &str) -> InnerResult<Option<i64>> {
+        let mut entries = self.entries();
+        Ok(entries
+            .live(key)
+            .and_then(|entry| entry.expires_at)
+            .map(|at| {
+                let left = at.saturating_duration_since(Instant::now());
+                // Rounded like Redis, so a fresh `set_ex(.., 10)` reads 10.
+                (left.as_millis() as i64 + 500) / 1000
+            }))
+    }
+
+    async fn incr_ex(&self, key: &str, ttl: i64) -> InnerResult<i64> {
+        let mut entries = self.entries();
+        let count = match entries.string(key)? {
+            Some(value) => {
+                let count = value
+                    .parse::<i64>()
+                    .map_err(|_| RedisorError::WrongType(key.to_string()))?
+                    + 1;
+                *value = count.to_string();
+                count
+            }
+            None => {
+                entries.set(key, "1", None);
+                1
+            }
+        };
+        if entries
+            .live(key)
+            .is_some_and(|entry| entry.expires_at.is_none())
+        {
+            entries.expire(key, ttl);
+        }
+        Ok(count)
+    }
+
+    async fn compare_and_swap(
+        &self,
+        key: &str,
+        current: &str,
+        value: &str,
+        ttl: u64,
+    ) -> InnerResult<bool> {
+        let mut entries = self.entries();
+        if entries.string(key)?.is_some_and(|held| held == current) {
+            entries.set(key, value, Some(ttl));
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    async fn hset(
+        &self,
+        key: &str,
+        field: &str,
+        value: &str,
+    ) -> InnerResult<()> {
+        self.entries().hset(key, field, value)
+    }
+
+    async fn hdel(&self, key: &str, field: &str) -> InnerResult<()> {
+        self.entries().hdel(key, field)
+    }
+
+    async fn hkeys(&self, key: &str) -> InnerResult<Vec<String>> {
+        Ok(self.entries().hgetall(key)?.into_keys().collect())
+    }
+
+    async fn hgets(
+        &self,
+        key: &str,
+        fields: &[&str],
+    ) -> InnerResult<Vec<Option<String>>> {
+        let hash = self.entries().hgetall(key)?;
+        Ok(fields
+            .iter()
+            .map(|field| hash.get(*field).cloned())
+            .collect())
+    }
+
+    async fn hgetall(&self, key: &str) -> InnerResult<HashMap<String, String>> {
+        self.entries().hgetall(key)
+    }
+
+    async fn hgetalls(
+        &self,
+        keys: &[&str],
+    ) -> InnerResult<Vec<HashMap<String, String>>> {
+        let mut entries = self.entries();
+        keys.iter().map(|key| entries.hgetall(key)).collect()
+    }
+
+    async fn exec(&self, pipeline: &Pipeline) -> InnerResult<()> {
+        let mut entries = self.entries();
+        let mut result = Ok(());
+        for command in &pipeline.commands {
+            if let Err(e) = entries.run(command) {
+                if result.is_ok() {
+                    result = Err(e);
+                }
+            }
+        }
+        result
+    }
+
+    async fn ping(&self) -> InnerResult<()> {
+        Ok(())
+    }
+
+    fn pool_state(&self) -> Option<PoolState> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn storage() -> Arc<dyn Storage> {
+        Arc::new(MemoryStorage::new())
+    }
+
+    #[tokio::test]
+    async fn test_get_set() {
+        let storage = storage();
+        assert_eq!(storage.get("key").await.unwrap(), None);
+        storage.set("key", "42").await.unwrap();
+        assert_eq!(storage.get("key").await.unwrap().as_deref(), Some("42"));
+        assert_eq!(storage.get_as::<i64>("key").await.unwrap(), Some(42));
+        storage.set("key", "forty-two").await.unwrap();
+        assert!(storage.get_as::<i64>("key").await.is_err());
+        storage.del("key").await.unwrap();
+        assert_eq!(storage.get("key").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_expiry() {
+        let storage = storage();
+        storage.set_ex("short", "value", 1).await.unwrap();
+        storage.set_ex("long", "value", 10).await.unwrap();
+        storage.set("forever", "value").await.unwrap();
+        assert_eq!(storage.ttl("long").await.unwrap(), Some(10));
+        assert_eq!(storage.ttl("forever").await.unwrap(), None);
+        assert_eq!(storage.ttl("missing").await.unwrap(), None);
+
+        // Setting again drops the expiry.
+        storage.set("long", "value").await.unwrap();
+        assert_eq!(storage.ttl("long").await.unwrap(), None);
+        storage.expire("long", 0).await.unwrap();
+        assert_eq!(storage.get("long").await.unwrap(), None);
+
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+        assert_eq!(storage.get("short").await.unwrap(), None);
+        assert!(storage.get("forever").await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_incr_ex() {
+        let storage = storage();
+        assert_eq!(storage.incr_ex("count", 60).await.unwrap(), 1);
+        assert_eq!(storage.incr_ex("count", 5).await.unwrap(), 2);
+        // The window starts with the first increment.
+        assert_eq!(storage.ttl("count").await.unwrap(), Some(60));
+        assert_eq!(storage.get_as::<i64>("count").await.unwrap(), Some(2));
+    }
+
+    #[tokio::test]
+    async fn test_sweep_every() {
+        let storage = MemoryStorage::new();
+        storage.set_ex("short", "1", 1).await.unwrap();
+        storage.set("long", "1").await.unwrap();
+        storage.sweep_every(Duration::from_millis(10));
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+        assert_eq!(storage.entries().0.len(), 1);
+        assert!(storage.entries().0.contains_key("long"));
+    }
+
+    #[tokio::test]
+    async fn test_compare_and_swap() {
+        let storage = storage();
+        storage.set("key", "old").await.unwrap();
+        assert!(!storage
+            .compare_and_swap("key", "other", "new", 60)
+            .await
+            .unwrap());
+        assert!(storage
+            .compare_and_swap("key", "old", "new", 60)
+            .await
+            .unwrap());
+        assert_eq!(storage.get("key").await.unwrap().as_deref(), Some("new"));
+        assert_eq!(storage.ttl("key").await.unwrap(), Some(60));
+        assert!(!storage
+            .compare_and_swap("missing", "old", "new", 60)
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_mget() {
+        let storage = storage();
+        storage.set("a", "1").await.unwrap();
+        storage.set("b", "2").await.unwrap();
+        storage.hset("h", "field", "value").await.unwrap();
+        assert_eq!(
+            storage.mget(&["a", "missing", "b", "h"]).await.unwrap(),
+            vec![Some("1".to_string()), None, Some("2".to_string()), None]
+        );
+        assert!(storage.mget(&[]).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_hashes() {
+        let storage = storage();
+        storage.hset("h", "f1", "v1").await.unwrap();
+        storage.hset("h", "f2", "v2").await.unwrap();
+        storage.expire("h", 30).await.unwrap();
+        storage.hset("h", "f3", "v3").await.unwrap();
+        // Adding fields keeps the expiry.
+        assert_eq!(storage.ttl("h").await.unwrap(), Some(30));
+
+        let mut keys = storage.hkeys("h").await.unwrap();
+        keys.sort();
+        assert_eq!(keys, ["f1", "f2", "f3"]);
+        assert_eq!(
+            storage.hgets("h", &["f2", "nope", "f1"]).await.unwrap(),
+            vec![Some("v2".to_string()), None, Some("v1".to_string())]
+        );
+
+        storage.set("s", "value").await.unwrap();
+        assert!(storage.hset("s", "f", "v").await.is_err());
+        assert!(storage.get("h").await.is_err());
+
+        for field in ["f1", "f2", "f3"] {
+            storage.hdel("h", field).await.unwrap();
+        }
+        assert!(storage.hkeys("h").await.unwrap().is_empty());
+        assert_eq!(storage.ttl("h").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_hgetalls() {
+        let storage = storage();
+        storage.hset("h1", "f", "1").await.unwrap();
+        storage.hset("h2", "f", "2").await.unwrap();
+        let all = storage.hgetalls(&["h1", "missing", "h2"]).await.unwrap();
+        assert_eq!(all.len(), 3);
+        assert_eq!(all[0]["f"], "1");
+        assert!(all[1].is_empty());
+        assert_eq!(all[2]["f"], "2");
+        assert_eq!(storage.hgetall("h2").await.unwrap(), all[2]);
+    }
+
+    #[tokio::test]
+    async fn test_pipeline() {
+        let storage = storage();
+        storage.set("gone", "value").await.unwrap();
+        let mut pipeline = Pipeline::new();
+        pipeline
+            .set_ex("session", "{}", 60)
+            .hset("index", "sid", "1")
+            .expire("index", 60)
+            .del("gone");
+        storage.exec(&pipeline).await.unwrap();
+        assert_eq!(storage.ttl("session").await.unwrap(), Some(60));
+        assert_eq!(storage.ttl("index").await.unwrap(), Some(60));
+        assert_eq!(storage.get("gone").await.unwrap(), None);
+
+        // A failing command leaves the others in place.
+        let mut pipeline = Pipeline::new();
+        pipeline
+            .set("before", "value")
+            .hset("session", "field", "value")
+            .set("after", "value");
+        assert!(storage.exec(&pipeline).await.is_err());
+        assert!(storage.get("before").await.unwrap().is_some());
+        assert!(storage.get("after").await.unwrap().is_some());
+    }
+}
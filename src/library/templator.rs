@@ -0,0 +1,327 @@
+//! Email templates, one set per supported language, embedded into the
+//! binary from `templates/email`.
+//!
+//! Every template comes as a subject, an HTML part and a plain text part.
+//! The HTML parts extend `<lang>/layout.html`, which holds the translated
+//! footer and extends the shared `base.html`.
+
+use std::sync::OnceLock;
+
+use serde::{Deserialize, Serialize};
+use tera::{Context, Tera};
+
+use crate::{library::error::InnerResult, models::types::Language};
+
+/// Languages templates exist for. Anything else falls back to the first.
+pub const LANGUAGES: [&str; 4] = ["en-US", "zh-CN", "fr-FR", "es-ES"];
+
+static TEMPLATES: OnceLock<Tera> = OnceLock::new();
+
+macro_rules! embed {
+    ($lang:literal: $($name:literal),+) => {
+        [
+            embed!(@file $lang, "layout.html"),
+            embed!(@file $lang, "layout.txt"),
+            $(
+                embed!(@file $lang, concat!($name, ".subject.txt")),
+                embed!(@file $lang, concat!($name, ".html")),
+                embed!(@file $lang, concat!($name, ".txt")),
+            )+
+        ]
+    };
+    (@file $lang:literal, $file:expr) => {
+        (
+            concat!($lang, "/", $file),
+            include_str!(concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/templates/email/",
+                $lang,
+                "/",
+                $file
+            )),
+        )
+    };
+}
+
+macro_rules! embed_all {
+    ($($lang:literal),+) => {
+        [$(
+            embed!($lang:
+                "activation",
+                "reset_password",
+                "password_reset_notice",
+                "alert",
+                "report"
+            ),
+        )+]
+    };
+}
+
+fn templates() -> &'static Tera {
+    TEMPLATES.get_or_init(|| {
+        let mut tera = Tera::default();
+        tera.add_raw_template(
+            "base.html",
+            include_str!(concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/templates/email/base.html"
+            )),
+        )
+        .expect("Failed to parse email template base.html");
+        for files in embed_all!("en-US", "zh-CN", "fr-FR", "es-ES") {
+            tera.add_raw_templates(files)
+                .expect("Failed to parse email templates");
+        }
+        tera
+    })
+}
+
+/// Picks the template language for `lang`, falling back to English.
+pub fn resolve_language(lang: &Language) -> &'static str {
+    LANGUAGES
+        .into_iter()
+        .find(|l| l.eq_ignore_ascii_case(lang.as_str()))
+        .unwrap_or(LANGUAGES[0])
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TemplateKind {
+    Activation,
+    ResetPassword,
+    PasswordResetNotice,
+    Alert,
+    Report,
+}
+
+impl TemplateKind {
+    pub const ALL: [Self; 5] = [
+        Self::Activation,
+        Self::ResetPassword,
+        Self::PasswordResetNotice,
+        Self::Alert,
+        Self::Report,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Activation => "activation",
+            Self::ResetPassword => "reset_password",
+            Self::PasswordResetNotice => "password_reset_notice",
+            Self::Alert => "alert",
+            Self::Report => "report",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CodeParams {
+    pub name: String,
+    pub code: String,
+    /// Minutes until the code expires.
+    pub expires_in: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NoticeParams {
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertParams {
+    pub name: String,
+    pub machine: String,
+    pub title: String,
+    pub severity: String,
+    pub triggered_at: String,
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReportParams {
+    pub name: String,
+    pub period: String,
+    pub machines: i64,
+    pub online: i64,
+    pub offline: i64,
+    pub hashrate: String,
+}
+
+/// A template together with the values it is rendered with.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "template", content = "params", rename_all = "snake_case")]
+pub enum MailTemplate {
+    Activation(CodeParams),
+    ResetPassword(CodeParams),
+    PasswordResetNotice(NoticeParams),
+    Alert(AlertParams),
+    Report(ReportParams),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RenderedEmail {
+    pub subject: String,
+    pub html: String,
+    pub text: String,
+}
+
+impl MailTemplate {
+    pub fn kind(&self) -> TemplateKind {
+        match self {
+            Self::Activation(_) => TemplateKind::Activation,
+            Self::ResetPassword(_) => TemplateKind::ResetPassword,
+            Self::PasswordResetNotice(_) => TemplateKind::PasswordResetNotice,
+            Self::Alert(_) => TemplateKind::Alert,
+            Self::Report(_) => TemplateKind::Report,
+        }
+    }
+
+    /// The values the template is rendered with, as stored in the outbox.
+    pub fn params(&self) -> InnerResult<serde_json::Value> {
+        let mut value = serde_json::to_value(self)?;
+        Ok(value["params"].take())
+    }
+
+    /// Inverse of [`MailTemplate::kind`] and [`MailTemplate::params`].
+    pub fn from_parts(
+        template: &str,
+        params: serde_json::Value,
+    ) -> InnerResult<Self> {
+        Ok(serde_json::from_value(serde_json::json!({
+            "template": template,
+            "params": params,
+        }))?)
+    }
+
+    /// Made-up values for previewing `kind`.
+    pub fn sample(kind: TemplateKind) -> Self {
+        let name = "Satoshi".to_string();
+        match kind {
+            TemplateKind::Activation => Self::Activation(CodeParams {
+                name,
+                code: "X7K2QD".to_string(),
+                expires_in: 30,
+            }),
+            TemplateKind::ResetPassword => Self::ResetPassword(CodeParams {
+                name,
+                code: "M4P9WZ".to_string(),
+                expires_in: 15,
+            }),
+            TemplateKind::PasswordResetNotice => {
+                Self::PasswordResetNotice(NoticeParams { name })
+            }
+            TemplateKind::Alert => Self::Alert(AlertParams {
+                name,
+                machine: "rig-07 (00:1A:2B:3C:4D:5E)".to_string(),
+                title: "Temperature above 85°C".to_string(),
+                severity: "critical".to_string(),
+                triggered_at: "2024-07-04 03:15:22 UTC".to_string(),
+                message: Some("Fan 2 reports 0 RPM.".to_string()),
+            }),
+            TemplateKind::Report => Self::Report(ReportParams {
+                name,
+                period: "2024-06".to_string(),
+                machines: 42,
+                online: 40,
+                offline: 2,
+                hashrate: "4.21 PH/s".to_string(),
+            }),
+        }
+    }
+
+    fn context(&self) -> InnerResult<Context> {
+        Ok(match self {
+            Self::Activation(p) | Self::ResetPassword(p) => {
+                Context::from_serialize(p)?
+            }
+            Self::PasswordResetNotice(p) => Context::from_serialize(p)?,
+            Self::Alert(p) => Context::from_serialize(p)?,
+            Self::Report(p) => Context::from_serialize(p)?,
+        })
+    }
+
+    /// Renders the template in `lang`, or in English if there is no
+    /// translation for it.
+    pub fn render(&self, lang: &Language) -> InnerResult<RenderedEmail> {
+        let tera = templates();
+        let lang = resolve_language(lang);
+        let name = self.kind().name();
+        let mut context = self.context()?;
+        context.insert("lang", lang);
+
+        let subject =
+            tera.render(&format!("{lang}/{name}.subject.txt"), &context)?;
+        let subject = subject.trim().to_string();
+        context.insert("subject", &subject);
+        Ok(RenderedEmail {
+            html: tera.render(&format!("{lang}/{name}.html"), &context)?,
+            text: tera.render(&format!("{lang}/{name}.txt"), &context)?,
+            subject,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_snapshots() {
+        for lang in LANGUAGES {
+            for kind in TemplateKind::ALL {
+                let rendered = MailTemplate::sample(kind)
+                    .render(&Language(lang.to_string()))
+                    .unwrap();
+                insta::with_settings!({
+                    snapshot_suffix => format!("{lang}_{}", kind.name()),
+                    prepend_module_to_snapshot => false,
+                }, {
+                    insta::assert_snapshot!(format!(
+                        "Subject: {}\n\n{}\n{}",
+                        rendered.subject, rendered.text, rendered.html
+                    ));
+                });
+            }
+        }
+    }
+
+    #[test]
+    fn test_render_escapes_html_only() {
+        let template = MailTemplate::PasswordResetNotice(NoticeParams {
+            name: "<b>Eve</b>".to_string(),
+        });
+        let rendered = template.render(&Language::default()).unwrap();
+        assert!(rendered.html.contains("&lt;b&gt;Eve&lt;&#x2F;b&gt;"));
+        assert!(rendered.text.contains("Hi <b>Eve</b>,"));
+    }
+
+    #[test]
+    fn test_from_parts() {
+        for kind in TemplateKind::ALL {
+            let template = MailTemplate::sample(kind);
+            let parsed = MailTemplate::from_parts(
+                kind.name(),
+                template.params().unwrap(),
+            )
+            .unwrap();
+            assert_eq!(parsed.kind(), kind);
+            assert_eq!(
+                parsed.render(&Language::default()).unwrap(),
+                template.render(&Language::default()).unwrap()
+            );
+        }
+        assert!(
+            MailTemplate::from_parts("unknown", serde_json::json!({})).is_err()
+        );
+    }
+
+    #[test]
+    fn test_unknown_language_falls_back() {
+        let template = MailTemplate::sample(TemplateKind::Activation);
+        assert_eq!(
+            template.render(&Language("de-DE".to_string())).unwrap(),
+            template.render(&Language::default()).unwrap()
+        );
+        assert_eq!(resolve_language(&Language("zh-cn".to_string())), "zh-CN");
+    }
+}
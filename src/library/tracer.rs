@@ -0,0 +1,214 @@
+//! Export of spans to an OpenTelemetry collector, and propagation of the
+//! W3C trace context across HTTP, gRPC, MQTT and the message bus.
+//!
+//! Spans are the `tracing` spans of the process, turned into OpenTelemetry
+//! spans by a layer of the logger. With `miner.tracing.enabled` off there is
+//! no such layer, so nothing is exported or propagated.
+
+use std::collections::HashMap;
+
+use axum::http::HeaderMap;
+use opentelemetry::{
+    global, propagation::Extractor, trace::TracerProvider as _, KeyValue,
+};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{
+    propagation::TraceContextPropagator,
+    runtime,
+    trace::{self, Sampler, Tracer, TracerProvider},
+    Resource,
+};
+use tonic::metadata::MetadataMap;
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+use crate::library::cfg::TracingConfig;
+
+/// Trace context of an incoming HTTP request.
+pub struct HeaderCarrier<'a>(pub &'a HeaderMap);
+
+impl Extractor for HeaderCarrier<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|key| key.as_str()).collect()
+    }
+}
+
+/// Trace context of an incoming gRPC call.
+pub struct MetadataCarrier<'a>(pub &'a MetadataMap);
+
+impl Extractor for MetadataCarrier<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0
+            .keys()
+            .filter_map(|key| match key {
+                tonic::metadata::KeyRef::Ascii(key) => Some(key.as_str()),
+                tonic::metadata::KeyRef::Binary(_) => None,
+            })
+            .collect()
+    }
+}
+
+fn provider(config: &TracingConfig) -> TracerProvider {
+    let exporter = opentelemetry_otlp::new_exporter()
+        .tonic()
+        .with_endpoint(&config.endpoint)
+        .build_span_exporter()
+        .unwrap_or_else(|e| {
+            panic!("💥 Failed to create the OTLP exporter: {e}");
+        });
+    let sampler = Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(
+        config.sample_ratio,
+    )));
+    TracerProvider::builder()
+        .with_batch_exporter(exporter, runtime::Tokio)
+        .with_config(trace::config().with_sampler(sampler).with_resource(
+            Resource::new([KeyValue::new(
+                "service.name",
+                config.service_name.clone(),
+            )]),
+        ))
+        .build()
+}
+
+/// Installs the exporting provider and the propagator, returning the tracer
+/// the logger exports through. `None` when tracing is off.
+pub fn init(config: &TracingConfig) -> Option<Tracer> {
+    if !config.enabled {
+        return None;
+    }
+    global::set_text_map_propagator(TraceContextPropagator::new());
+    let provider = provider(config);
+    let tracer = provider.tracer(env!("CARGO_PKG_NAME"));
+    global::set_tracer_provider(provider);
+    Some(tracer)
+}
+
+/// Flushes the spans not exported yet.
+pub fn shutdown() {
+    global::shutdown_tracer_provider();
+}
+
+/// Makes `span` part of the trace `carrier` comes with, if any.
+pub fn continue_trace(span: &Span, carrier: &dyn Extractor) {
+    let context = global::get_text_map_propagator(|propagator| {
+        propagator.extract(carrier)
+    });
+    span.set_parent(context);
+}
+
+/// Trace context of the current span, as headers of an outgoing message.
+pub fn current_headers() -> HashMap<String, String> {
+    let mut headers = HashMap::new();
+    let context = Span::current().context();
+    global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&context, &mut headers);
+    });
+    headers
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use axum::http::HeaderValue;
+    use opentelemetry_proto::tonic::{
+        collector::trace::v1::{
+            trace_service_server::{TraceService, TraceServiceServer},
+            ExportTraceServiceRequest, ExportTraceServiceResponse,
+        },
+        common::v1::any_value::Value,
+    };
+    use tokio::{net::TcpListener, sync::mpsc};
+    use tonic::{Request, Response, Status};
+    use tracing_subscriber::{layer::SubscriberExt, Registry};
+
+    use super::*;
+
+    /// Stands in for an OpenTelemetry collector, handing over what it gets.
+    struct Collector(mpsc::UnboundedSender<ExportTraceServiceRequest>);
+
+    #[tonic::async_trait]
+    impl TraceService for Collector {
+        async fn export(
+            &self,
+            request: Request<ExportTraceServiceRequest>,
+        ) -> Result<Response<ExportTraceServiceResponse>, Status> {
+            let _ = self.0.send(request.into_inner());
+            Ok(Response::new(ExportTraceServiceResponse {
+                partial_success: None,
+            }))
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_export() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let incoming =
+            futures_util::stream::unfold(listener, |listener| async move {
+                let stream = listener.accept().await.map(|(stream, _)| stream);
+                Some((stream, listener))
+            });
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        tokio::spawn(
+            tonic::transport::Server::builder()
+                .add_service(TraceServiceServer::new(Collector(sender)))
+                .serve_with_incoming(incoming),
+        );
+
+        global::set_text_map_propagator(TraceContextPropagator::new());
+        let provider = provider(&TracingConfig {
+            enabled: true,
+            endpoint: format!("http://{addr}"),
+            ..Default::default()
+        });
+        let subscriber = Registry::default().with(
+            tracing_opentelemetry::layer().with_tracer(provider.tracer("test")),
+        );
+
+        let trace_id = "4bf92f3577b34da6a3ce929d0e0e4736";
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "traceparent",
+            HeaderValue::from_str(&format!(
+                "00-{trace_id}-00f067aa0ba902b7-01"
+            ))
+            .unwrap(),
+        );
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("http_request", request_id = "01J");
+            continue_trace(&span, &HeaderCarrier(&headers));
+            let outgoing = span.in_scope(current_headers);
+            assert!(outgoing["traceparent"].contains(trace_id));
+        });
+        provider.force_flush();
+
+        let request =
+            tokio::time::timeout(Duration::from_secs(5), receiver.recv())
+                .await
+                .expect("Nothing exported")
+                .unwrap();
+        let span = request
+            .resource_spans
+            .iter()
+            .flat_map(|resource| &resource.scope_spans)
+            .flat_map(|scope| &scope.spans)
+            .find(|span| span.name == "http_request")
+            .expect("Span not exported");
+        assert_eq!(data_encoding::HEXLOWER.encode(&span.trace_id), trace_id);
+        let request_id = span
+            .attributes
+            .iter()
+            .find(|attribute| attribute.key == "request_id")
+            .and_then(|attribute| attribute.value.as_ref()?.value.as_ref());
+        assert_eq!(request_id, Some(&Value::StringValue("01J".to_string())));
+    }
+}
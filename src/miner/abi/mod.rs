@@ -2,19 +2,31 @@ use std::sync::Arc;
 
 use rand::{distributions::Alphanumeric, Rng};
 use sqlx::types::{chrono, Json};
-use tonic::{Request, Response, Status};
+use tonic::{
+    metadata::{MetadataMap, MetadataValue},
+    Code, Request, Response, Status,
+};
 
-use super::bootstrap::{shutdown_signal, AppState};
+use super::{
+    bootstrap::{shutdown_signal, AppState},
+    service::{
+        enrollment_service, machine_service,
+        rate_limit_service::{self, Policy},
+    },
+};
 use crate::{
-    library::{cfg, error::AppResult},
-    models::{
-        account_setting::BwAccountSetting,
-        machine::{BwMachine, CreateBwMachineSchema, Setting},
+    library::{
+        cfg, crypto,
+        error::AppResult,
+        metricer,
+        tracer::{self, MetadataCarrier},
     },
+    models::machine::{BwMachine, CreateBwMachineSchema, Setting},
     pb::{
         self,
         miner_sign::{
             miner_sign_server::{MinerSign, MinerSignServer},
+            sign_request::Capability,
             SignRequest, SignResponse,
         },
     },
@@ -33,7 +45,67 @@ impl MinerSign for Server {
         request: Request<SignRequest>,
     ) -> Result<Response<SignResponse>, Status> {
         let inner = request.into_inner();
-        self.store(inner).await.expect("Failed");
+        let reply = match self.limited(&inner.key).await {
+            Some(status) => Err(status),
+            None => self.sign_machine(inner).await,
+        };
+        let result = match &reply {
+            Err(status) if status.code() == Code::PermissionDenied => "denied",
+            Err(status) if status.code() == Code::InvalidArgument => "invalid",
+            Err(status) if status.code() == Code::ResourceExhausted => {
+                "limited"
+            }
+            reply => metricer::result_label(reply),
+        };
+        metricer::metrics()
+            .grpc_signs
+            .with_label_values(&[result])
+            .inc();
+        reply
+    }
+}
+
+impl Server {
+    /// Refuses enrollment keys signing in too often, telling the device in
+    /// the `retry-after` metadata how many seconds to wait.
+    async fn limited(&self, key: &str) -> Option<Status> {
+        let subject = format!("key:{}", crypto::hash_token(key));
+        match rate_limit_service::hit(&self.app_state, Policy::Sign, &subject)
+            .await
+        {
+            Ok(Some(retry)) => {
+                let mut status =
+                    Status::resource_exhausted("Too many sign requests");
+                status
+                    .metadata_mut()
+                    .insert("retry-after", MetadataValue::from(retry));
+                Some(status)
+            }
+            Ok(None) => None,
+            Err(e) => {
+                tracing::warn!("Failed to count sign request: {e}");
+                None
+            }
+        }
+    }
+
+    async fn sign_machine(
+        &self,
+        mut inner: SignRequest,
+    ) -> Result<Response<SignResponse>, Status> {
+        let Some(capability) = inner.capability.take() else {
+            return Err(Status::invalid_argument("Missing capability"));
+        };
+        let Some(uid) =
+            enrollment_service::resolve(&self.app_state, &inner.key)
+                .await
+                .map_err(|e| Status::internal(e.to_string()))?
+        else {
+            return Err(Status::permission_denied("Invalid enrollment key"));
+        };
+        self.store(uid, &inner, capability)
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?;
         let mqtt_config = cfg::config().miner.mqtt.clone();
         let emqx_user = self.rand_emqx_user();
         let reply = SignResponse {
@@ -46,9 +118,7 @@ impl MinerSign for Server {
         };
         Ok(Response::new(reply))
     }
-}
 
-impl Server {
     pub fn init(app_state: Arc<AppState>) -> Self {
         let config = cfg::config();
         let host = &config.miner.grpc_host;
@@ -70,31 +140,32 @@ impl Server {
         tracing::info!("✨ listening on {}", addr);
 
         tonic::transport::Server::builder()
-            .trace_fn(|_| tracing::info_span!("grpc_server"))
+            .trace_fn(|request| {
+                let metadata =
+                    MetadataMap::from_headers(request.headers().clone());
+                let span = tracing::info_span!(
+                    "grpc_server",
+                    otel.kind = "server",
+                    rpc.method = request.uri().path(),
+                    request_id = metadata
+                        .get("x-request-id")
+                        .and_then(|value| value.to_str().ok()),
+                );
+                tracer::continue_trace(&span, &MetadataCarrier(&metadata));
+                span
+            })
             .add_service(signer)
             .serve_with_shutdown(addr, shutdown_signal())
             .await
             .unwrap_or_else(|e| panic!("💥 Failed to start ABI server: {e:?}"));
     }
 
-    pub async fn store(&self, sign: SignRequest) -> AppResult<()> {
-        let mut redis = self.app_state.get_redis().await?;
-
-        let r_user_key = format!("m_user:{}", sign.key);
-        let uid = match redis.get(&r_user_key).await? {
-            Some(i) => i,
-            None => {
-                let i = BwAccountSetting::fetch_uid_by_key(
-                    self.app_state.get_db(),
-                    &sign.key,
-                )
-                .await?;
-                redis.set_ex(&r_user_key, i, 259200).await?;
-                i
-            }
-        };
-
-        let cap = sign.capability.expect("Failed");
+    pub async fn store(
+        &self,
+        uid: i64,
+        sign: &SignRequest,
+        cap: Capability,
+    ) -> AppResult<()> {
         let power_modes = pb::get_energy_modes(cap.powermode);
         let crypto_coin = pb::get_coins(cap.algoset);
 
@@ -116,10 +187,8 @@ impl Server {
             hardware_version: &sign.hv,
             software_version: &sign.sv,
         };
-        BwMachine::create_bw_machine(self.app_state.get_db(), &item)
-            .await
-            .expect("Failed to add machine");
-        Ok(())
+        BwMachine::create_bw_machine(self.app_state.get_db(), &item).await?;
+        machine_service::invalidate(&self.app_state, &sign.mac).await
     }
 
     fn rand_emqx_user(&self) -> (String, String) {
@@ -0,0 +1,59 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::State,
+    http::{header::CONTENT_TYPE, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::json;
+
+use crate::{
+    library::cfg,
+    miner::{
+        bootstrap::AppState,
+        service::{health_service, metrics_service},
+    },
+};
+
+/// Liveness, answering as long as the process serves requests.
+#[allow(clippy::unused_async)]
+pub async fn healthz_handler() -> impl IntoResponse {
+    Json(json!({ "status": "ok" }))
+}
+
+/// Readiness, `503` when a dependency is down or the server is draining.
+/// Why a dependency is down is only told to holders of the metrics token.
+pub async fn readyz_handler(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let mut readiness = health_service::readiness(&state).await;
+    let token = cfg::config().miner.metrics.token.as_deref();
+    if !metrics_service::authorized(token, &headers) {
+        readiness.redact();
+    }
+    let status = if readiness.is_ready() {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(readiness))
+}
+
+/// Prometheus metrics, for scrapers presenting the configured token. Not
+/// found otherwise, so the route tells nothing to anyone else.
+pub async fn metrics_handler(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Response {
+    let token = cfg::config().miner.metrics.token.as_deref();
+    if !metrics_service::authorized(token, &headers) {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    (
+        [(CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metrics_service::render(&state).await,
+    )
+        .into_response()
+}
@@ -1,5 +1,6 @@
 use axum::{http::StatusCode, response::IntoResponse};
 
+pub mod health;
 pub mod v1;
 
 #[allow(clippy::unused_async)]
@@ -1,9 +1,17 @@
 pub mod account;
+pub mod api_token;
+pub mod coin_stat;
+pub mod enrollment_key;
+pub mod exchange_rate;
+pub mod fleet_event;
 pub mod group;
 pub mod machine;
 pub mod news;
+pub mod openapi;
 pub mod operate;
+pub mod organization;
 pub mod policy;
 pub mod product;
+pub mod setting;
 pub mod template;
 pub mod third;
@@ -10,28 +10,48 @@ use crate::{
             AppError::{ApiError, AuthError},
             AppResult, AuthInnerError,
         },
-        mailor::Email,
     },
     miner::{
-        bootstrap::{
-            constants::{self, MQ_SEND_EMAIL_QUEUE},
-            AppState,
-        },
+        api::openapi::{Envelope, Nothing},
+        bootstrap::AppState,
         entity::{
             account::{
-                ActiveAccountRequest, LoginResponse, LoginUserRequest,
-                RegisterUserRequest, ResetPasswordRequest,
+                ActiveAccountRequest, ForgotPasswordRequest, LoginMfaRequest,
+                LoginResponse, LoginResult, LoginUserRequest, MfaCodeRequest,
+                MfaEnrollResponse, MfaRequiredResponse, PreferencesResponse,
+                RecoveryCodesResponse, RegisterUserRequest,
+                ResetPasswordRequest, RevokeSessionRequest, SessionResponse,
+                UpdatePreferencesRequest,
             },
             common::SuccessResponse,
         },
-        service::jwt_service::{Claims, RefreshTokenRequest},
+        service::{
+            account_service, enrollment_service,
+            jwt_service::{Claims, RefreshTokenRequest, TokenSchema},
+            login_guard, mfa_service,
+            session_service::{self, ClientInfo},
+            verification_service,
+        },
     },
     models::{
-        account::{BwAccount, CreateBwAccountSchema, ResetPasswordSchema},
-        types::AccountStatus,
+        account::{BwAccount, CreateBwAccountSchema, UpdatePreferencesSchema},
+        api_token::BwApiToken,
+        currency::BwCurrency,
+        language::BwLanguage,
+        types::{AccountStatus, CodePurpose},
     },
 };
 
+/// Registers an account.
+#[utoipa::path(
+    post,
+    path = "/auth/register",
+    tag = "auth",
+    request_body = RegisterUserRequest,
+    responses(
+        (status = 200, description = "Success", body = Envelope<BwAccount>)
+    )
+)]
 pub async fn register_user_handler(
     State(state): State<Arc<AppState>>,
     Json(body): Json<RegisterUserRequest>,
@@ -58,50 +78,145 @@ pub async fn register_user_handler(
     })
 }
 
+/// Logs in.
+///
+/// Answers with tokens, or with an `mfa_token` for `/auth/login/mfa` when
+/// the account has a second factor.
+#[utoipa::path(
+    post,
+    path = "/auth/login",
+    tag = "auth",
+    request_body = LoginUserRequest,
+    responses(
+        (status = 200, description = "Success", body = Envelope<LoginResult>)
+    )
+)]
 pub async fn login_user_handler(
     State(state): State<Arc<AppState>>,
+    client: ClientInfo,
     Json(body): Json<LoginUserRequest>,
 ) -> AppResult<impl IntoResponse> {
+    login_guard::check_ip(&state, &client).await?;
+
     let users = BwAccount::fetch_user_by_email_or_name(
         state.get_db(),
         &body.email_or_name,
     )
     .await?;
-    if users.is_empty() {
-        return Err(AuthError(AuthInnerError::WrongCredentials));
+
+    let mut locked_for = None;
+    let mut unlocked = Vec::with_capacity(users.len());
+    for user in &users {
+        if let Some(secs) = login_guard::check_lock(&state, user).await? {
+            locked_for = locked_for.max(Some(secs));
+            continue;
+        }
+        unlocked.push(user);
     }
-    for user in users {
+
+    for user in &unlocked {
         if crypto::verify_password(&user.password, &body.password)? {
-            let tokens = Claims::generate_tokens_for_user(&user).await?;
-            let affected =
-                BwAccount::update_last_login(state.get_db(), user.uid).await?;
-            if affected != 1 {
-                tracing::error!(
-                    "Failed to update last login time for user: {}",
-                    user.uid
-                );
+            if mfa_service::is_enabled(&state, user.uid).await? {
+                return Ok(SuccessResponse {
+                    msg: "Second factor required",
+                    data: Some(Json(LoginResult::MfaRequired(
+                        MfaRequiredResponse {
+                            mfa_required: true,
+                            mfa_token: Claims::generate_mfa_token(user)?,
+                        },
+                    ))),
+                });
             }
-            // TODO: generate  key
+            login_guard::record_success(&state, user).await?;
+            let tokens = session_service::issue(&state, user, &client).await?;
+            update_last_login(&state, user.uid).await?;
+            enrollment_service::ensure_default(&state, user).await?;
             return Ok(SuccessResponse {
                 msg: "Tokens generated successfully",
-                data: Some(Json(LoginResponse::new(tokens, user))),
+                data: Some(Json(LoginResult::Success(LoginResponse::new(
+                    tokens,
+                    (*user).clone(),
+                )))),
             });
         }
     }
-    Err(AuthError(AuthInnerError::WrongCredentials))
+
+    let just_locked =
+        login_guard::record_failure(&state, &unlocked, &client).await?;
+    match just_locked.max(locked_for) {
+        Some(secs) if unlocked.is_empty() || just_locked.is_some() => {
+            Err(AuthError(AuthInnerError::AccountLocked(secs)))
+        }
+        _ => Err(AuthError(AuthInnerError::WrongCredentials)),
+    }
+}
+
+/// Finishes a login with a second factor.
+#[utoipa::path(
+    post,
+    path = "/auth/login/mfa",
+    tag = "auth",
+    request_body = LoginMfaRequest,
+    responses(
+        (status = 200, description = "Success", body = Envelope<LoginResponse>)
+    )
+)]
+pub async fn login_mfa_handler(
+    State(state): State<Arc<AppState>>,
+    client: ClientInfo,
+    Json(body): Json<LoginMfaRequest>,
+) -> AppResult<impl IntoResponse> {
+    let (tokens, user) =
+        mfa_service::login(&state, &body.mfa_token, &body.code, &client)
+            .await?;
+    update_last_login(&state, user.uid).await?;
+    enrollment_service::ensure_default(&state, &user).await?;
+    Ok(SuccessResponse {
+        msg: "Tokens generated successfully",
+        data: Some(Json(LoginResponse::new(tokens, user))),
+    })
+}
+
+async fn update_last_login(state: &AppState, uid: i64) -> AppResult<()> {
+    let affected = BwAccount::update_last_login(state.get_db(), uid).await?;
+    if affected != 1 {
+        tracing::error!("Failed to update last login time for user: {}", uid);
+    }
+    account_service::invalidate(state, uid).await
 }
 
+/// Trades a refresh token for new tokens.
+#[utoipa::path(
+    post,
+    path = "/users/refresh_token",
+    tag = "auth",
+    request_body = RefreshTokenRequest,
+    responses(
+        (status = 200, description = "Success", body = Envelope<TokenSchema>)
+    )
+)]
 pub async fn refresh_token_handler(
     State(state): State<Arc<AppState>>,
+    client: ClientInfo,
     Json(body): Json<RefreshTokenRequest>,
 ) -> AppResult<impl IntoResponse> {
-    let token = Claims::refresh_token(&body.refresh_token, state).await?;
+    let token =
+        Claims::refresh_token(&body.refresh_token, state, &client).await?;
     Ok(SuccessResponse {
         msg: "Tokens refreshed successfully",
         data: Some(Json(token)),
     })
 }
 
+/// Returns the account.
+#[utoipa::path(
+    post,
+    path = "/users/get_me",
+    tag = "users",
+    responses(
+        (status = 200, description = "Success", body = Envelope<BwAccount>)
+    )
+)]
 pub async fn get_me_handler(
     State(state): State<Arc<AppState>>,
     claims: Claims,
@@ -114,37 +229,38 @@ pub async fn get_me_handler(
     })
 }
 
+/// Emails an activation code.
+#[utoipa::path(
+    post,
+    path = "/users/send_active",
+    tag = "users",
+    responses(
+        (status = 200, description = "Success", body = Envelope<Nothing>)
+    )
+)]
 pub async fn send_active_account_email_handler(
     State(state): State<Arc<AppState>>,
     claims: Claims,
 ) -> AppResult<impl IntoResponse> {
-    let mut redis = state.get_redis().await?;
-    let key = format!("{}_{}", claims.uid, constants::REDIS_ACTIVE_ACCOUNT_KEY);
-    if redis.get::<String>(&key).await?.is_some() {
-        return Err(ApiError(ApiInnerError::CodeIntervalRejection));
-    }
     if claims.status != AccountStatus::Inactive {
         return Err(AuthError(AuthInnerError::UserAlreadyActivated));
     }
-    let active_code = crypto::random_words(6);
-    let body = format!("Active Code: {}", active_code);
-
-    redis
-        .set_ex(
-            &format!("{}_{}", claims.uid, constants::REDIS_ACTIVE_ACCOUNT_KEY),
-            &active_code,
-            60 * 5,
-        )
-        .await?;
-
-    let email = Email::new(&claims.email, "Active your account", &body);
-    let email_json = serde_json::to_string(&email).map_err(|e| {
-        anyhow::anyhow!("Error occurred while sending email: {}", e)
-    })?;
-    state
-        .get_mq()?
-        .basic_send(MQ_SEND_EMAIL_QUEUE, &email_json)
-        .await?;
+    let user = account_service::fetch(&state, claims.uid)
+        .await?
+        .ok_or(AuthError(AuthInnerError::WrongCredentials))?;
+    let code = verification_service::issue(
+        state.get_db(),
+        user.uid,
+        CodePurpose::VerifyEmail,
+    )
+    .await?;
+    verification_service::send_code(
+        &state,
+        &user,
+        CodePurpose::VerifyEmail,
+        &code,
+    )
+    .await?;
 
     Ok(SuccessResponse {
         msg: "success",
@@ -152,35 +268,75 @@ pub async fn send_active_account_email_handler(
     })
 }
 
-pub async fn send_reset_password_email_handler(
+/// Activates the account with an emailed code.
+#[utoipa::path(
+    post,
+    path = "/users/verify_active",
+    tag = "users",
+    request_body = ActiveAccountRequest,
+    responses(
+        (status = 200, description = "Success", body = Envelope<TokenSchema>)
+    )
+)]
+pub async fn verify_active_account_code_handler(
     State(state): State<Arc<AppState>>,
     claims: Claims,
+    client: ClientInfo,
+    Json(body): Json<ActiveAccountRequest>,
 ) -> AppResult<impl IntoResponse> {
-    let mut redis = state.get_redis().await?;
-    let key = format!("{}_{}", claims.uid, constants::REDIS_RESET_PASSWORD_KEY);
-    if redis.get::<String>(&key).await?.is_some() {
-        return Err(ApiError(ApiInnerError::CodeIntervalRejection));
+    if claims.status != AccountStatus::Inactive {
+        return Err(AuthError(AuthInnerError::UserAlreadyActivated));
     }
+    verification_service::verify_email(state.get_db(), claims.uid, &body.code)
+        .await?;
+    account_service::invalidate(&state, claims.uid).await?;
 
-    let reset_password_code = crypto::random_words(6);
-    let body = format!("ResetPassword Code: {}", reset_password_code);
+    let user = account_service::fetch(&state, claims.uid)
+        .await?
+        .ok_or(AuthError(AuthInnerError::WrongCredentials))?;
 
-    redis
-        .set_ex(
-            &format!("{}_{}", claims.uid, constants::REDIS_RESET_PASSWORD_KEY),
-            &reset_password_code,
-            60,
-        )
-        .await?;
+    let tokens =
+        session_service::reissue(&state, &user, claims.sid.as_deref(), &client)
+            .await?;
 
-    let email = Email::new(&claims.email, "Reset Password", &body);
-    let email_json = serde_json::to_string(&email).map_err(|e| {
-        anyhow::anyhow!("Error occurred while sending email: {}", e)
-    })?;
-    state
-        .get_mq()?
-        .basic_send(MQ_SEND_EMAIL_QUEUE, &email_json)
-        .await?;
+    Ok(SuccessResponse {
+        msg: "success",
+        data: Some(Json(tokens)),
+    })
+}
+
+/// Emails a password reset code.
+///
+/// Succeeds whether or not the email is registered.
+#[utoipa::path(
+    post,
+    path = "/auth/forgot_password",
+    tag = "auth",
+    request_body = ForgotPasswordRequest,
+    responses(
+        (status = 200, description = "Success", body = Envelope<Nothing>)
+    )
+)]
+pub async fn forgot_password_handler(
+    State(state): State<Arc<AppState>>,
+    Json(body): Json<ForgotPasswordRequest>,
+) -> AppResult<impl IntoResponse> {
+    match verification_service::forgot_password(state.get_db(), &body.email)
+        .await
+    {
+        Ok(Some((user, code))) => {
+            verification_service::send_code(
+                &state,
+                &user,
+                CodePurpose::ResetPassword,
+                &code,
+            )
+            .await?;
+        }
+        // Answering too soon would tell the email is registered.
+        Ok(None) | Err(ApiError(ApiInnerError::CodeIntervalRejection)) => {}
+        Err(e) => return Err(e),
+    }
 
     Ok(SuccessResponse {
         msg: "success",
@@ -188,64 +344,280 @@ pub async fn send_reset_password_email_handler(
     })
 }
 
-pub async fn verify_active_account_code_handler(
+/// Resets the password with an emailed code.
+#[utoipa::path(
+    post,
+    path = "/auth/reset_password",
+    tag = "auth",
+    request_body = ResetPasswordRequest,
+    responses(
+        (status = 200, description = "Success", body = Envelope<Nothing>)
+    )
+)]
+pub async fn reset_password_handler(
+    State(state): State<Arc<AppState>>,
+    Json(body): Json<ResetPasswordRequest>,
+) -> AppResult<impl IntoResponse> {
+    let uid = verification_service::reset_password(
+        state.get_db(),
+        &body.email,
+        &body.code,
+        &body.password,
+    )
+    .await?;
+    account_service::invalidate(&state, uid).await?;
+    // Whoever knew the old password may have made tokens with it.
+    session_service::revoke_all(&state, uid).await?;
+    BwApiToken::revoke_tokens_by_uid(state.get_db(), uid).await?;
+
+    Ok(SuccessResponse {
+        msg: "success",
+        data: None::<()>,
+    })
+}
+
+/// Changes the currency and language.
+#[utoipa::path(
+    post,
+    path = "/users/update_preferences",
+    tag = "users",
+    request_body = UpdatePreferencesRequest,
+    responses((
+        status = 200,
+        description = "Success",
+        body = Envelope<PreferencesResponse>
+    ))
+)]
+pub async fn update_preferences_handler(
     State(state): State<Arc<AppState>>,
     claims: Claims,
-    Json(body): Json<ActiveAccountRequest>,
+    Json(body): Json<UpdatePreferencesRequest>,
 ) -> AppResult<impl IntoResponse> {
-    let mut redis = state.get_redis().await?;
-    if claims.status != AccountStatus::Inactive {
-        return Err(AuthError(AuthInnerError::UserAlreadyActivated));
+    if let Some(currency) = &body.local_currency {
+        if !BwCurrency::check_currency_supported(
+            state.get_db(),
+            currency.as_str(),
+        )
+        .await?
+        .unwrap_or(false)
+        {
+            return Err(ApiError(ApiInnerError::UnsupportedCurrency));
+        }
     }
-
-    let key = format!("{}_{}", claims.uid, constants::REDIS_ACTIVE_ACCOUNT_KEY);
-    if let Some(active_code_stored) = redis.get::<String>(&key).await? {
-        if active_code_stored == body.code {
-            BwAccount::update_email_verified_at(state.get_db(), claims.uid)
-                .await?;
-            redis.del(&key).await?;
-        } else {
-            return Err(AuthError(AuthInnerError::WrongCode));
+    if let Some(language) = &body.system_lang {
+        if !BwLanguage::check_language_supported(
+            state.get_db(),
+            language.as_str(),
+        )
+        .await?
+        .unwrap_or(false)
+        {
+            return Err(ApiError(ApiInnerError::UnsupportedLanguage));
         }
     }
 
-    let user = BwAccount::fetch_user_by_uid(state.get_db(), claims.uid)
+    let item = UpdatePreferencesSchema {
+        uid: claims.uid,
+        local_currency: body.local_currency,
+        system_lang: body.system_lang,
+    };
+    BwAccount::update_preferences(state.get_db(), &item).await?;
+    account_service::invalidate(&state, claims.uid).await?;
+
+    let user = account_service::fetch(&state, claims.uid)
         .await?
         .ok_or(AuthError(AuthInnerError::WrongCredentials))?;
 
-    let tokens = Claims::generate_tokens_for_user(&user).await?;
+    Ok(SuccessResponse {
+        msg: "success",
+        data: Some(Json(PreferencesResponse::from(user))),
+    })
+}
 
-    redis.del(&key).await?;
+/// Ends this session.
+#[utoipa::path(
+    post,
+    path = "/auth/logout",
+    tag = "auth",
+    responses(
+        (status = 200, description = "Success", body = Envelope<Nothing>)
+    )
+)]
+pub async fn logout_handler(
+    State(state): State<Arc<AppState>>,
+    claims: Claims,
+) -> AppResult<impl IntoResponse> {
+    if let Some(sid) = &claims.sid {
+        session_service::revoke(&state, claims.uid, sid).await?;
+    }
+    Ok(SuccessResponse {
+        msg: "success",
+        data: None::<()>,
+    })
+}
 
+/// Ends every session.
+#[utoipa::path(
+    post,
+    path = "/auth/logout_all",
+    tag = "auth",
+    responses(
+        (status = 200, description = "Success", body = Envelope<Nothing>)
+    )
+)]
+pub async fn logout_all_handler(
+    State(state): State<Arc<AppState>>,
+    claims: Claims,
+) -> AppResult<impl IntoResponse> {
+    session_service::revoke_all(&state, claims.uid).await?;
     Ok(SuccessResponse {
         msg: "success",
-        data: Some(Json(tokens)),
+        data: None::<()>,
     })
 }
 
-pub async fn change_password_handler(
+/// Lists the sessions.
+#[utoipa::path(
+    post,
+    path = "/auth/sessions",
+    tag = "auth",
+    responses((
+        status = 200,
+        description = "Success",
+        body = Envelope<Vec<SessionResponse>>
+    ))
+)]
+pub async fn get_sessions_handler(
     State(state): State<Arc<AppState>>,
     claims: Claims,
-    Json(body): Json<ResetPasswordRequest>,
 ) -> AppResult<impl IntoResponse> {
-    let mut redis = state.get_redis().await?;
-    let key = format!("{}_{}", claims.uid, constants::REDIS_RESET_PASSWORD_KEY);
-
-    if let Some(active_code_stored) = redis.get::<String>(&key).await? {
-        if active_code_stored == body.code {
-            let item = ResetPasswordSchema {
-                uid: claims.uid,
-                password: crypto::hash_password(body.password.as_bytes())?,
-            };
-            BwAccount::update_password_by_uid(state.get_db(), &item).await?;
-            redis.del(&key).await?;
-        } else {
-            return Err(AuthError(AuthInnerError::WrongCode));
-        }
-    }
+    let sessions = session_service::list(&state, claims.uid)
+        .await?
+        .into_iter()
+        .map(|s| SessionResponse::new(s, claims.sid.as_deref()))
+        .collect::<Vec<_>>();
+    Ok(SuccessResponse {
+        msg: "success",
+        data: Some(Json(sessions)),
+    })
+}
 
+/// Ends a session.
+#[utoipa::path(
+    post,
+    path = "/auth/sessions/revoke",
+    tag = "auth",
+    request_body = RevokeSessionRequest,
+    responses(
+        (status = 200, description = "Success", body = Envelope<Nothing>)
+    )
+)]
+pub async fn revoke_session_handler(
+    State(state): State<Arc<AppState>>,
+    claims: Claims,
+    Json(body): Json<RevokeSessionRequest>,
+) -> AppResult<impl IntoResponse> {
+    session_service::revoke(&state, claims.uid, &body.sid).await?;
     Ok(SuccessResponse {
         msg: "success",
         data: None::<()>,
     })
 }
+
+/// Starts enrolling an authenticator.
+#[utoipa::path(
+    post,
+    path = "/users/mfa/enroll",
+    tag = "mfa",
+    responses((
+        status = 200,
+        description = "Success",
+        body = Envelope<MfaEnrollResponse>
+    ))
+)]
+pub async fn enroll_mfa_handler(
+    State(state): State<Arc<AppState>>,
+    claims: Claims,
+) -> AppResult<impl IntoResponse> {
+    let enrollment = mfa_service::enroll(&state, &claims).await?;
+    Ok(SuccessResponse {
+        msg: "success",
+        data: Some(Json(MfaEnrollResponse {
+            secret: enrollment.secret,
+            otpauth_uri: enrollment.otpauth_uri,
+        })),
+    })
+}
+
+/// Confirms the authenticator.
+#[utoipa::path(
+    post,
+    path = "/users/mfa/confirm",
+    tag = "mfa",
+    request_body = MfaCodeRequest,
+    responses((
+        status = 200,
+        description = "Success",
+        body = Envelope<RecoveryCodesResponse>
+    ))
+)]
+pub async fn confirm_mfa_handler(
+    State(state): State<Arc<AppState>>,
+    claims: Claims,
+    Json(body): Json<MfaCodeRequest>,
+) -> AppResult<impl IntoResponse> {
+    let recovery_codes =
+        mfa_service::confirm(&state, claims.uid, &body.code).await?;
+    Ok(SuccessResponse {
+        msg: "success",
+        data: Some(Json(RecoveryCodesResponse { recovery_codes })),
+    })
+}
+
+/// Turns the second factor off.
+#[utoipa::path(
+    post,
+    path = "/users/mfa/disable",
+    tag = "mfa",
+    request_body = MfaCodeRequest,
+    responses(
+        (status = 200, description = "Success", body = Envelope<Nothing>)
+    )
+)]
+pub async fn disable_mfa_handler(
+    State(state): State<Arc<AppState>>,
+    claims: Claims,
+    Json(body): Json<MfaCodeRequest>,
+) -> AppResult<impl IntoResponse> {
+    mfa_service::disable(&state, claims.uid, &body.code).await?;
+    Ok(SuccessResponse {
+        msg: "success",
+        data: None::<()>,
+    })
+}
+
+/// Replaces the recovery codes.
+#[utoipa::path(
+    post,
+    path = "/users/mfa/recovery_codes",
+    tag = "mfa",
+    request_body = MfaCodeRequest,
+    responses((
+        status = 200,
+        description = "Success",
+        body = Envelope<RecoveryCodesResponse>
+    ))
+)]
+pub async fn regenerate_recovery_codes_handler(
+    State(state): State<Arc<AppState>>,
+    claims: Claims,
+    Json(body): Json<MfaCodeRequest>,
+) -> AppResult<impl IntoResponse> {
+    let recovery_codes =
+        mfa_service::regenerate_recovery_codes(&state, claims.uid, &body.code)
+            .await?;
+    Ok(SuccessResponse {
+        msg: "success",
+        data: Some(Json(RecoveryCodesResponse { recovery_codes })),
+    })
+}
@@ -0,0 +1,97 @@
+use std::sync::Arc;
+
+use axum::{extract::State, response::IntoResponse, Json};
+
+use crate::{
+    library::error::AppResult,
+    miner::{
+        api::openapi::{Envelope, Nothing},
+        bootstrap::AppState,
+        entity::{
+            api_token::{
+                CreateApiTokenRequest, CreateApiTokenResponse,
+                RevokeApiTokenRequest,
+            },
+            common::SuccessResponse,
+        },
+        service::{api_token_service, jwt_service::Claims},
+    },
+    models::api_token::BwApiToken,
+};
+
+/// Lists the API tokens.
+#[utoipa::path(
+    post,
+    path = "/api_tokens/list",
+    tag = "api_tokens",
+    responses((
+        status = 200,
+        description = "Success",
+        body = Envelope<Vec<BwApiToken>>
+    ))
+)]
+pub async fn get_api_tokens_handler(
+    State(state): State<Arc<AppState>>,
+    claims: Claims,
+) -> AppResult<impl IntoResponse> {
+    let tokens =
+        BwApiToken::fetch_tokens_by_uid(state.get_db(), claims.uid).await?;
+    Ok(SuccessResponse {
+        msg: "success",
+        data: Some(Json(tokens)),
+    })
+}
+
+/// Creates an API token.
+#[utoipa::path(
+    post,
+    path = "/api_tokens/create",
+    tag = "api_tokens",
+    request_body = CreateApiTokenRequest,
+    responses((
+        status = 200,
+        description = "Success",
+        body = Envelope<CreateApiTokenResponse>
+    ))
+)]
+pub async fn create_api_token_handler(
+    State(state): State<Arc<AppState>>,
+    claims: Claims,
+    Json(body): Json<CreateApiTokenRequest>,
+) -> AppResult<impl IntoResponse> {
+    let (info, token) = api_token_service::create(
+        &state,
+        claims.uid,
+        body.name,
+        body.scopes,
+        body.rate_limit,
+        body.expires_at,
+    )
+    .await?;
+    Ok(SuccessResponse {
+        msg: "success",
+        data: Some(Json(CreateApiTokenResponse { token, info })),
+    })
+}
+
+/// Revokes an API token.
+#[utoipa::path(
+    post,
+    path = "/api_tokens/revoke",
+    tag = "api_tokens",
+    request_body = RevokeApiTokenRequest,
+    responses(
+        (status = 200, description = "Success", body = Envelope<Nothing>)
+    )
+)]
+pub async fn revoke_api_token_handler(
+    State(state): State<Arc<AppState>>,
+    claims: Claims,
+    Json(body): Json<RevokeApiTokenRequest>,
+) -> AppResult<impl IntoResponse> {
+    api_token_service::revoke(&state, body.token_id, claims.uid).await?;
+    Ok(SuccessResponse {
+        msg: "success",
+        data: None::<()>,
+    })
+}
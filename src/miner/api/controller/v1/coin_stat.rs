@@ -0,0 +1,39 @@
+use std::sync::Arc;
+
+use axum::{extract::State, response::IntoResponse, Json};
+
+use crate::{
+    library::error::AppResult,
+    miner::{
+        api::openapi::Envelope,
+        bootstrap::AppState,
+        entity::common::SuccessResponse,
+        service::{
+            jwt_service::Claims,
+            miner_stat::{CoinData, Server},
+            provider::MarketData,
+        },
+    },
+};
+
+/// Returns the current coin statistics.
+#[utoipa::path(
+    post,
+    path = "/coin_stat/current",
+    tag = "market",
+    responses((
+        status = 200,
+        description = "Success",
+        body = Envelope<MarketData<Vec<CoinData>>>
+    ))
+)]
+pub async fn get_current_coin_stats_handler(
+    State(state): State<Arc<AppState>>,
+    _claims: Claims,
+) -> AppResult<impl IntoResponse> {
+    let stats = Server::current_stats(&state).await?;
+    Ok(SuccessResponse {
+        msg: "success",
+        data: Some(Json(stats)),
+    })
+}
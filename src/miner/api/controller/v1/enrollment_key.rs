@@ -0,0 +1,126 @@
+use std::sync::Arc;
+
+use axum::{extract::State, response::IntoResponse, Json};
+
+use crate::{
+    library::error::AppResult,
+    miner::{
+        api::openapi::{Envelope, Nothing},
+        bootstrap::AppState,
+        entity::{
+            common::SuccessResponse,
+            enrollment_key::{
+                CreateEnrollmentKeyRequest, EnrollmentKeyRequest,
+            },
+        },
+        service::{enrollment_service, jwt_service::Claims},
+    },
+    models::account_setting::{BwAccountSetting, RevokeBwAccountSettingSchema},
+};
+
+/// Lists the enrollment keys.
+#[utoipa::path(
+    post,
+    path = "/enrollment_keys/list",
+    tag = "enrollment_keys",
+    responses((
+        status = 200,
+        description = "Success",
+        body = Envelope<Vec<BwAccountSetting>>
+    ))
+)]
+pub async fn get_enrollment_keys_handler(
+    State(state): State<Arc<AppState>>,
+    claims: Claims,
+) -> AppResult<impl IntoResponse> {
+    let keys =
+        BwAccountSetting::fetch_keys_by_uid(state.get_db(), claims.uid).await?;
+    Ok(SuccessResponse {
+        msg: "success",
+        data: Some(Json(keys)),
+    })
+}
+
+/// Creates an enrollment key.
+#[utoipa::path(
+    post,
+    path = "/enrollment_keys/create",
+    tag = "enrollment_keys",
+    request_body = CreateEnrollmentKeyRequest,
+    responses((
+        status = 200,
+        description = "Success",
+        body = Envelope<BwAccountSetting>
+    ))
+)]
+pub async fn create_enrollment_key_handler(
+    State(state): State<Arc<AppState>>,
+    claims: Claims,
+    Json(body): Json<CreateEnrollmentKeyRequest>,
+) -> AppResult<impl IntoResponse> {
+    let key = enrollment_service::create(
+        &state,
+        claims.uid,
+        body.name,
+        body.expires_at,
+    )
+    .await?;
+    Ok(SuccessResponse {
+        msg: "success",
+        data: Some(Json(key)),
+    })
+}
+
+/// Revokes an enrollment key.
+#[utoipa::path(
+    post,
+    path = "/enrollment_keys/revoke",
+    tag = "enrollment_keys",
+    request_body = EnrollmentKeyRequest,
+    responses(
+        (status = 200, description = "Success", body = Envelope<Nothing>)
+    )
+)]
+pub async fn revoke_enrollment_key_handler(
+    State(state): State<Arc<AppState>>,
+    claims: Claims,
+    Json(body): Json<EnrollmentKeyRequest>,
+) -> AppResult<impl IntoResponse> {
+    let item = RevokeBwAccountSettingSchema {
+        key_id: body.key_id,
+        uid: claims.uid,
+    };
+    enrollment_service::revoke(&state, &item).await?;
+    Ok(SuccessResponse {
+        msg: "success",
+        data: None::<()>,
+    })
+}
+
+/// Replaces the secret of an enrollment key.
+#[utoipa::path(
+    post,
+    path = "/enrollment_keys/rotate",
+    tag = "enrollment_keys",
+    request_body = EnrollmentKeyRequest,
+    responses((
+        status = 200,
+        description = "Success",
+        body = Envelope<BwAccountSetting>
+    ))
+)]
+pub async fn rotate_enrollment_key_handler(
+    State(state): State<Arc<AppState>>,
+    claims: Claims,
+    Json(body): Json<EnrollmentKeyRequest>,
+) -> AppResult<impl IntoResponse> {
+    let item = RevokeBwAccountSettingSchema {
+        key_id: body.key_id,
+        uid: claims.uid,
+    };
+    let key = enrollment_service::rotate(&state, &item).await?;
+    Ok(SuccessResponse {
+        msg: "success",
+        data: Some(Json(key)),
+    })
+}
@@ -0,0 +1,103 @@
+use std::sync::Arc;
+
+use axum::{extract::State, response::IntoResponse, Json};
+
+use crate::{
+    library::error::AppResult,
+    miner::{
+        api::openapi::Envelope,
+        bootstrap::AppState,
+        entity::{
+            common::SuccessResponse,
+            exchange_rate::{
+                ConvertRequest, ConvertResponse, HistoryExchangeRateRequest,
+            },
+        },
+        service::{exchange_rate::Server, jwt_service::Claims},
+    },
+    models::exchange_rate::BwExchangeRate,
+};
+
+/// Returns today's exchange rates.
+#[utoipa::path(
+    post,
+    path = "/exchange_rate/current",
+    tag = "market",
+    responses((
+        status = 200,
+        description = "Success",
+        body = Envelope<Vec<BwExchangeRate>>
+    ))
+)]
+pub async fn get_current_rates_handler(
+    State(state): State<Arc<AppState>>,
+    _claims: Claims,
+) -> AppResult<impl IntoResponse> {
+    let rates = Server::current_snapshot(&state).await?;
+    Ok(SuccessResponse {
+        msg: "success",
+        data: Some(Json(rates)),
+    })
+}
+
+/// Returns the exchange rates of a day.
+#[utoipa::path(
+    post,
+    path = "/exchange_rate/history",
+    tag = "market",
+    request_body = HistoryExchangeRateRequest,
+    responses((
+        status = 200,
+        description = "Success",
+        body = Envelope<Vec<BwExchangeRate>>
+    ))
+)]
+pub async fn get_history_rates_handler(
+    State(state): State<Arc<AppState>>,
+    _claims: Claims,
+    Json(body): Json<HistoryExchangeRateRequest>,
+) -> AppResult<impl IntoResponse> {
+    let rates = Server::rates_on(&state, body.date).await?;
+    Ok(SuccessResponse {
+        msg: "success",
+        data: Some(Json(rates)),
+    })
+}
+
+/// Converts between currencies.
+#[utoipa::path(
+    post,
+    path = "/exchange_rate/convert",
+    tag = "market",
+    request_body = ConvertRequest,
+    responses((
+        status = 200,
+        description = "Success",
+        body = Envelope<ConvertResponse>
+    ))
+)]
+pub async fn convert_handler(
+    State(state): State<Arc<AppState>>,
+    _claims: Claims,
+    Json(body): Json<ConvertRequest>,
+) -> AppResult<impl IntoResponse> {
+    let result = match body.date {
+        Some(date) => {
+            Server::convert_on(&state, body.amount, &body.from, &body.to, date)
+                .await?
+        }
+        None => {
+            Server::convert(&state, body.amount, &body.from, &body.to).await?
+        }
+    };
+    Ok(SuccessResponse {
+        msg: "success",
+        data: Some(Json(ConvertResponse {
+            amount: body.amount,
+            from: body.from,
+            to: body.to,
+            result,
+            date: body.date,
+        })),
+    })
+}
@@ -0,0 +1,62 @@
+use std::{convert::Infallible, sync::Arc};
+
+use axum::{
+    extract::{Query, State},
+    response::sse::{Event, KeepAlive, Sse},
+};
+use futures_util::{Stream, StreamExt};
+
+use crate::{
+    library::error::AppResult,
+    miner::{
+        bootstrap::AppState,
+        entity::fleet_event::{FleetEvent, FleetEventQuery},
+        service::{
+            fleet_event_service::{self, Delivery, FleetFilter},
+            jwt_service::Claims,
+            org_service::FleetAccess,
+        },
+    },
+};
+
+/// Streams events of the fleet as server-sent events.
+///
+/// Events are named after their kind, plus `missed` when the client fell
+/// behind and should reload. The stream closes when the token expires or
+/// access is lost, after which the client reconnects with a fresh token.
+#[utoipa::path(
+    get,
+    path = "/fleet/events",
+    tag = "fleet",
+    params(FleetEventQuery),
+    responses((
+        status = 200,
+        description = "A `FleetEvent` per event, the number of events \
+            dropped for `missed`.",
+        content_type = "text/event-stream",
+        body = FleetEvent
+    ))
+)]
+pub async fn fleet_events_handler(
+    State(state): State<Arc<AppState>>,
+    claims: Claims,
+    access: FleetAccess,
+    Query(query): Query<FleetEventQuery>,
+) -> AppResult<Sse<impl Stream<Item = Result<Event, Infallible>>>> {
+    let filter = FleetFilter::new(access, &query)?;
+    let events =
+        fleet_event_service::subscribe(state, claims, filter).map(|delivery| {
+            Ok(match delivery {
+                Delivery::Event(event) => Event::default()
+                    .event(event.kind.as_str())
+                    .json_data(event.as_ref())
+                    .unwrap_or_else(|e| {
+                        Event::default().comment(e.to_string())
+                    }),
+                Delivery::Missed(missed) => {
+                    Event::default().event("missed").data(missed.to_string())
+                }
+            })
+        });
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
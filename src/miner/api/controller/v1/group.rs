@@ -3,8 +3,13 @@ use std::sync::Arc;
 use axum::{extract::State, response::IntoResponse, Json};
 
 use crate::{
-    library::error::{ApiInnerError, AppError::ApiError, AppResult},
+    library::error::{
+        ApiInnerError,
+        AppError::{ApiError, AuthError},
+        AppResult, AuthInnerError,
+    },
     miner::{
+        api::openapi::{Envelope, Nothing},
         bootstrap::AppState,
         entity::{
             common::SuccessResponse,
@@ -13,7 +18,7 @@ use crate::{
                 UpdateBwGroupRequest,
             },
         },
-        service::jwt_service::Claims,
+        service::{group_service, org_service::FleetAccess},
     },
     models::group::{
         BwGroup, CreateBwGroupSchema, DeleteBwGroupSchema, ReadBwGroupSchema,
@@ -21,19 +26,35 @@ use crate::{
     },
 };
 
+/// Creates a group.
+#[utoipa::path(
+    post,
+    path = "/groups/create",
+    tag = "groups",
+    request_body = CreateGroupRequest,
+    responses(
+        (status = 200, description = "Success", body = Envelope<BwGroup>)
+    )
+)]
 pub async fn create_group_handler(
     State(state): State<Arc<AppState>>,
-    claims: Claims,
+    access: FleetAccess,
     Json(body): Json<CreateGroupRequest>,
 ) -> AppResult<impl IntoResponse> {
+    // A group created by a member limited to some groups would be out of
+    // their own reach.
+    if access.is_scoped() {
+        return Err(AuthError(AuthInnerError::PermissionDenied));
+    }
     let item = CreateBwGroupSchema {
-        uid: claims.uid,
+        uid: access.uid,
         name: body.name,
         remark: body.remark,
     };
     let group = BwGroup::create_bw_group(state.get_db(), &item)
         .await
         .map_err(|_| ApiError(ApiInnerError::CreateGroupError))?;
+    group_service::invalidate(&state, access.uid).await?;
 
     Ok(SuccessResponse {
         msg: "success",
@@ -41,30 +62,54 @@ pub async fn create_group_handler(
     })
 }
 
+/// Lists the groups.
+#[utoipa::path(
+    post,
+    path = "/groups/list",
+    tag = "groups",
+    responses(
+        (status = 200, description = "Success", body = Envelope<Vec<BwGroup>>)
+    )
+)]
 pub async fn get_groups_handler(
     State(state): State<Arc<AppState>>,
-    claims: Claims,
+    access: FleetAccess,
 ) -> AppResult<impl IntoResponse> {
-    let group = BwGroup::fetch_group_by_uid(state.get_db(), claims.uid)
+    let mut group = group_service::fetch_by_uid(&state, access.uid)
         .await
         .map_err(|_| ApiError(ApiInnerError::GetGroupError))?;
+    group.retain(|g| access.can_access_group(g.group_id));
     Ok(SuccessResponse {
         msg: "success",
         data: Some(Json(group)),
     })
 }
 
+/// Returns groups by id.
+#[utoipa::path(
+    post,
+    path = "/groups/ids",
+    tag = "groups",
+    request_body = ReadBwGroupRequest,
+    responses(
+        (status = 200, description = "Success", body = Envelope<Vec<BwGroup>>)
+    )
+)]
 pub async fn get_groups_by_ids_handler(
     State(state): State<Arc<AppState>>,
-    claims: Claims,
+    access: FleetAccess,
     Json(body): Json<ReadBwGroupRequest>,
 ) -> AppResult<impl IntoResponse> {
     let item = ReadBwGroupSchema {
-        uid: claims.uid,
-        group_ids: body.group_ids,
+        uid: access.uid,
+        group_ids: body
+            .group_ids
+            .into_iter()
+            .filter(|id| access.can_access_group(*id))
+            .collect(),
     };
 
-    let groups = BwGroup::fetch_group_info_by_ids(state.get_db(), &item)
+    let groups = group_service::fetch_by_ids(&state, &item)
         .await
         .map_err(|_| ApiError(ApiInnerError::GetGroupError))?;
     Ok(SuccessResponse {
@@ -73,19 +118,31 @@ pub async fn get_groups_by_ids_handler(
     })
 }
 
+/// Deletes a group.
+#[utoipa::path(
+    post,
+    path = "/groups/delete",
+    tag = "groups",
+    request_body = DeleteBwGroupRequest,
+    responses(
+        (status = 200, description = "Success", body = Envelope<Nothing>)
+    )
+)]
 pub async fn delete_group_handler(
     State(state): State<Arc<AppState>>,
-    claims: Claims,
+    access: FleetAccess,
     Json(body): Json<DeleteBwGroupRequest>,
 ) -> AppResult<impl IntoResponse> {
+    access.ensure_group(body.group_id)?;
     let item = DeleteBwGroupSchema {
         group_id: body.group_id,
-        uid: claims.uid,
+        uid: access.uid,
     };
     let rows_affected =
         BwGroup::delete_group_by_group_id(state.get_db(), &item)
             .await
             .map_err(|_| ApiError(ApiInnerError::DeleteGroupError))?;
+    group_service::invalidate(&state, access.uid).await?;
     if rows_affected != 0 {
         return Err(ApiError(ApiInnerError::DeleteGroupError));
     }
@@ -95,14 +152,25 @@ pub async fn delete_group_handler(
     })
 }
 
+/// Updates a group.
+#[utoipa::path(
+    post,
+    path = "/groups/update",
+    tag = "groups",
+    request_body = UpdateBwGroupRequest,
+    responses(
+        (status = 200, description = "Success", body = Envelope<Nothing>)
+    )
+)]
 pub async fn update_group_handler(
     State(state): State<Arc<AppState>>,
-    claims: Claims,
+    access: FleetAccess,
     Json(body): Json<UpdateBwGroupRequest>,
 ) -> AppResult<impl IntoResponse> {
+    access.ensure_group(body.group_id)?;
     let item = UpdateBwGroupSchema {
         group_id: body.group_id,
-        uid: claims.uid,
+        uid: access.uid,
         name: body.name,
         remark: body.remark,
     };
@@ -110,6 +178,7 @@ pub async fn update_group_handler(
         BwGroup::update_group_by_group_id(state.get_db(), &item)
             .await
             .map_err(|_| ApiError(ApiInnerError::UpdateGroupError))?;
+    group_service::invalidate(&state, access.uid).await?;
     if rows_affected != 0 {
         return Err(ApiError(ApiInnerError::UpdateGroupError));
     }
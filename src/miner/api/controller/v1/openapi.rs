@@ -0,0 +1,40 @@
+use std::sync::Arc;
+
+use axum::{response::Html, Extension, Json};
+use serde_json::Value;
+
+/// Returns this document.
+#[utoipa::path(
+    get,
+    path = "/openapi.json",
+    tag = "meta",
+    responses((status = 200, description = "OpenAPI document", body = Value))
+)]
+#[allow(clippy::unused_async)]
+pub async fn openapi_handler(
+    Extension(document): Extension<Arc<Value>>,
+) -> Json<Value> {
+    Json(document.as_ref().clone())
+}
+
+/// Swagger UI over [`openapi_handler`], loaded from a CDN.
+#[allow(clippy::unused_async)]
+pub async fn docs_handler() -> Html<&'static str> {
+    Html(
+        r##"<!DOCTYPE html>
+<html>
+<head>
+  <meta charset="utf-8">
+  <title>Miner API</title>
+  <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css">
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js"></script>
+  <script>
+    SwaggerUIBundle({ url: "openapi.json", dom_id: "#swagger-ui" });
+  </script>
+</body>
+</html>"##,
+    )
+}
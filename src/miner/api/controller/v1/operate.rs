@@ -3,19 +3,43 @@ use std::sync::Arc;
 use axum::{extract::State, response::IntoResponse, Json};
 
 use crate::{
-    library::error::AppResult,
+    library::error::{AppError::AuthError, AppResult, AuthInnerError},
     miner::{
+        api::openapi::Envelope,
         bootstrap::AppState,
         entity::{common::SuccessResponse, operate::OperateRequest},
-        service::jwt_service::Claims,
+        service::org_service::FleetAccess,
     },
+    models::machine::BwMachine,
 };
 
+/// Sends a command to machines.
+#[utoipa::path(
+    post,
+    path = "/operate/do",
+    tag = "operate",
+    request_body = OperateRequest,
+    responses(
+        (status = 200, description = "Success", body = Envelope<OperateRequest>)
+    )
+)]
 pub async fn operate_handler(
-    State(_state): State<Arc<AppState>>,
-    _claims: Claims,
+    State(state): State<Arc<AppState>>,
+    access: FleetAccess,
     Json(body): Json<OperateRequest>,
 ) -> AppResult<impl IntoResponse> {
+    for mac in body
+        .macs
+        .split(',')
+        .map(str::trim)
+        .filter(|m| !m.is_empty())
+    {
+        let machines =
+            BwMachine::fetch_machine_by_mac(state.get_db(), mac).await?;
+        if !machines.iter().any(|m| access.can_access_machine(m)) {
+            return Err(AuthError(AuthInnerError::PermissionDenied));
+        }
+    }
     Ok(SuccessResponse {
         msg: "success",
         data: Some(Json(body)),
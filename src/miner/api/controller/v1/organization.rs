@@ -0,0 +1,174 @@
+use std::sync::Arc;
+
+use axum::{extract::State, response::IntoResponse, Json};
+
+use crate::{
+    library::error::AppResult,
+    miner::{
+        api::openapi::{Envelope, Nothing},
+        bootstrap::AppState,
+        entity::{
+            common::SuccessResponse,
+            organization::{
+                AddMemberRequest, CreateOrganizationRequest,
+                OrganizationRequest, RemoveMemberRequest, UpdateMemberRequest,
+            },
+        },
+        service::{jwt_service::Claims, org_service},
+    },
+    models::organization::{
+        BwOrganization, BwOrganizationMember, Membership,
+        SaveBwOrganizationMemberSchema,
+    },
+};
+
+/// Creates an organization.
+#[utoipa::path(
+    post,
+    path = "/orgs/create",
+    tag = "orgs",
+    request_body = CreateOrganizationRequest,
+    responses(
+        (status = 200, description = "Success", body = Envelope<BwOrganization>)
+    )
+)]
+pub async fn create_organization_handler(
+    State(state): State<Arc<AppState>>,
+    claims: Claims,
+    Json(body): Json<CreateOrganizationRequest>,
+) -> AppResult<impl IntoResponse> {
+    let org = org_service::create(&state, claims.uid, body.name).await?;
+    Ok(SuccessResponse {
+        msg: "success",
+        data: Some(Json(org)),
+    })
+}
+
+/// Lists the organizations the account belongs to.
+#[utoipa::path(
+    post,
+    path = "/orgs/list",
+    tag = "orgs",
+    responses((
+        status = 200,
+        description = "Success",
+        body = Envelope<Vec<Membership>>
+    ))
+)]
+pub async fn get_organizations_handler(
+    State(state): State<Arc<AppState>>,
+    claims: Claims,
+) -> AppResult<impl IntoResponse> {
+    let memberships =
+        BwOrganization::fetch_memberships_by_uid(state.get_db(), claims.uid)
+            .await?;
+    Ok(SuccessResponse {
+        msg: "success",
+        data: Some(Json(memberships)),
+    })
+}
+
+/// Lists the members.
+#[utoipa::path(
+    post,
+    path = "/orgs/members/list",
+    tag = "orgs",
+    request_body = OrganizationRequest,
+    responses((
+        status = 200,
+        description = "Success",
+        body = Envelope<Vec<BwOrganizationMember>>
+    ))
+)]
+pub async fn get_members_handler(
+    State(state): State<Arc<AppState>>,
+    claims: Claims,
+    Json(body): Json<OrganizationRequest>,
+) -> AppResult<impl IntoResponse> {
+    let members = org_service::members(&state, claims.uid, body.org_id).await?;
+    Ok(SuccessResponse {
+        msg: "success",
+        data: Some(Json(members)),
+    })
+}
+
+/// Adds a member.
+#[utoipa::path(
+    post,
+    path = "/orgs/members/add",
+    tag = "orgs",
+    request_body = AddMemberRequest,
+    responses(
+        (status = 200, description = "Success", body = Envelope<Nothing>)
+    )
+)]
+pub async fn add_member_handler(
+    State(state): State<Arc<AppState>>,
+    claims: Claims,
+    Json(body): Json<AddMemberRequest>,
+) -> AppResult<impl IntoResponse> {
+    org_service::add_member(
+        &state,
+        claims.uid,
+        body.org_id,
+        &body.email,
+        body.role,
+        body.group_ids,
+    )
+    .await?;
+    Ok(SuccessResponse {
+        msg: "success",
+        data: None::<()>,
+    })
+}
+
+/// Changes a member's role or groups.
+#[utoipa::path(
+    post,
+    path = "/orgs/members/update",
+    tag = "orgs",
+    request_body = UpdateMemberRequest,
+    responses(
+        (status = 200, description = "Success", body = Envelope<Nothing>)
+    )
+)]
+pub async fn update_member_handler(
+    State(state): State<Arc<AppState>>,
+    claims: Claims,
+    Json(body): Json<UpdateMemberRequest>,
+) -> AppResult<impl IntoResponse> {
+    let item = SaveBwOrganizationMemberSchema {
+        org_id: body.org_id,
+        uid: body.uid,
+        role: body.role,
+        group_ids: body.group_ids,
+    };
+    org_service::update_member(&state, claims.uid, &item).await?;
+    Ok(SuccessResponse {
+        msg: "success",
+        data: None::<()>,
+    })
+}
+
+/// Removes a member.
+#[utoipa::path(
+    post,
+    path = "/orgs/members/remove",
+    tag = "orgs",
+    request_body = RemoveMemberRequest,
+    responses(
+        (status = 200, description = "Success", body = Envelope<Nothing>)
+    )
+)]
+pub async fn remove_member_handler(
+    State(state): State<Arc<AppState>>,
+    claims: Claims,
+    Json(body): Json<RemoveMemberRequest>,
+) -> AppResult<impl IntoResponse> {
+    org_service::remove_member(&state, claims.uid, body.org_id, body.uid)
+        .await?;
+    Ok(SuccessResponse {
+        msg: "success",
+        data: None::<()>,
+    })
+}
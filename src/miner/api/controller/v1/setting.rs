@@ -0,0 +1,57 @@
+use std::sync::Arc;
+
+use axum::{extract::State, response::IntoResponse, Json};
+
+use crate::{
+    library::error::AppResult,
+    miner::{
+        api::openapi::Envelope, bootstrap::AppState,
+        entity::common::SuccessResponse, service::jwt_service::Claims,
+    },
+    models::{currency::BwCurrency, language::BwLanguage},
+};
+
+/// Lists the supported currencies.
+#[utoipa::path(
+    post,
+    path = "/settings/currencies",
+    tag = "settings",
+    responses((
+        status = 200,
+        description = "Success",
+        body = Envelope<Vec<BwCurrency>>
+    ))
+)]
+pub async fn get_currencies_handler(
+    State(state): State<Arc<AppState>>,
+    _claims: Claims,
+) -> AppResult<impl IntoResponse> {
+    let currencies =
+        BwCurrency::fetch_supported_currencies(state.get_db()).await?;
+    Ok(SuccessResponse {
+        msg: "success",
+        data: Some(Json(currencies)),
+    })
+}
+
+/// Lists the supported languages.
+#[utoipa::path(
+    post,
+    path = "/settings/languages",
+    tag = "settings",
+    responses((
+        status = 200,
+        description = "Success",
+        body = Envelope<Vec<BwLanguage>>
+    ))
+)]
+pub async fn get_languages_handler(
+    State(state): State<Arc<AppState>>,
+    _claims: Claims,
+) -> AppResult<impl IntoResponse> {
+    let languages = BwLanguage::fetch_enabled_languages(state.get_db()).await?;
+    Ok(SuccessResponse {
+        msg: "success",
+        data: Some(Json(languages)),
+    })
+}
@@ -1,14 +1,29 @@
+use std::sync::Arc;
+
 use axum::{
-    extract::Request, http::header::AUTHORIZATION, middleware::Next,
+    extract::{Request, State},
+    http::header::AUTHORIZATION,
+    middleware::Next,
     response::Response,
 };
 
 use crate::{
     library::error::{AppError::AuthError, AppResult, AuthInnerError},
-    miner::service::jwt_service::{Claims, TokenType},
+    miner::{
+        bootstrap::AppState,
+        service::{
+            api_token_service,
+            jwt_service::{Claims, TokenType},
+            org_service,
+        },
+    },
 };
 
-pub async fn handle(request: Request, next: Next) -> AppResult<Response> {
+pub async fn handle(
+    State(state): State<Arc<AppState>>,
+    mut request: Request,
+    next: Next,
+) -> AppResult<Response> {
     let token = request
         .headers()
         .get(AUTHORIZATION)
@@ -16,7 +31,30 @@ pub async fn handle(request: Request, next: Next) -> AppResult<Response> {
         .and_then(|auth_value| auth_value.strip_prefix("Bearer "))
         .ok_or(AuthError(AuthInnerError::InvalidToken))?;
 
-    Claims::parse_token(token, TokenType::ACCESS, true)?;
+    let claims = if api_token_service::is_api_token(token) {
+        let (claims, api_token) =
+            api_token_service::authenticate(&state, token).await?;
+        request.extensions_mut().insert(api_token);
+        claims
+    } else {
+        let claims = Claims::parse_token(token, TokenType::ACCESS, true)?;
+        claims.ensure_session(&state).await?;
+        claims
+    };
+    let org_id = request
+        .headers()
+        .get(org_service::ORG_HEADER)
+        .map(|value| {
+            value
+                .to_str()
+                .ok()
+                .and_then(|value| value.parse::<i64>().ok())
+                .ok_or(AuthError(AuthInnerError::NotOrganizationMember))
+        })
+        .transpose()?;
+    let access = org_service::resolve(&state, claims.uid, org_id).await?;
+    request.extensions_mut().insert(access);
+    request.extensions_mut().insert(claims);
 
     Ok(next.run(request).await)
 }
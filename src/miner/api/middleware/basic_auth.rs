@@ -1,14 +1,25 @@
+use std::sync::Arc;
+
 use axum::{
-    extract::Request, http::header::AUTHORIZATION, middleware::Next,
+    extract::{Request, State},
+    http::header::AUTHORIZATION,
+    middleware::Next,
     response::Response,
 };
 
 use crate::{
     library::error::{AppError::AuthError, AppResult, AuthInnerError},
-    miner::service::jwt_service::{Claims, TokenType},
+    miner::{
+        bootstrap::AppState,
+        service::jwt_service::{Claims, TokenType},
+    },
 };
 
-pub async fn handle(request: Request, next: Next) -> AppResult<Response> {
+pub async fn handle(
+    State(state): State<Arc<AppState>>,
+    mut request: Request,
+    next: Next,
+) -> AppResult<Response> {
     let token = request
         .headers()
         .get(AUTHORIZATION)
@@ -16,7 +27,9 @@ pub async fn handle(request: Request, next: Next) -> AppResult<Response> {
         .and_then(|auth_value| auth_value.strip_prefix("Bearer "))
         .ok_or(AuthError(AuthInnerError::InvalidToken))?;
 
-    Claims::parse_token(token, TokenType::ACCESS, false)?;
+    let claims = Claims::parse_token(token, TokenType::ACCESS, false)?;
+    claims.ensure_session(&state).await?;
+    request.extensions_mut().insert(claims);
 
     Ok(next.run(request).await)
 }
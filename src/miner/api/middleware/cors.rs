@@ -1,3 +1,5 @@
+use std::sync::OnceLock;
+
 use axum::{
     extract::Request,
     http::{self, HeaderMap, HeaderValue, Method, StatusCode},
@@ -7,33 +9,217 @@ use axum::{
 use http::header::{
     ACCESS_CONTROL_ALLOW_CREDENTIALS, ACCESS_CONTROL_ALLOW_HEADERS,
     ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN,
+    ACCESS_CONTROL_EXPOSE_HEADERS, ACCESS_CONTROL_MAX_AGE, ORIGIN, UPGRADE,
+    VARY,
 };
 
+use crate::library::cfg::{self, CorsConfig};
+
+/// `cors` of the configuration, with its header values built once.
+struct Policy {
+    /// Lowercased origin patterns.
+    origins: Vec<String>,
+    /// Every origin is allowed, answered with `*` and no credentials.
+    any: bool,
+    credentials: bool,
+    methods: HeaderValue,
+    headers: HeaderValue,
+    expose: HeaderValue,
+    max_age: HeaderValue,
+}
+
+fn header_list(values: &[String]) -> HeaderValue {
+    HeaderValue::from_str(&values.join(", ")).unwrap_or_else(|e| {
+        panic!("💥 Invalid CORS header list {values:?}: {e}");
+    })
+}
+
+/// Whether `origin` matches `pattern`, where `*` stands for any run of
+/// characters.
+fn matches(pattern: &str, origin: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let Some(mut rest) = origin.strip_prefix(parts.next().unwrap_or_default())
+    else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+    for part in middle {
+        let Some(at) = rest.find(part) else {
+            return false;
+        };
+        rest = &rest[at + part.len()..];
+    }
+    rest.ends_with(last)
+}
+
+impl Policy {
+    fn new(config: &CorsConfig) -> Self {
+        let any = config.allowed_origins.iter().any(|origin| origin == "*");
+        Self {
+            origins: config
+                .allowed_origins
+                .iter()
+                .map(|origin| origin.to_ascii_lowercase())
+                .collect(),
+            any,
+            credentials: config.allow_credentials && !any,
+            methods: header_list(&config.allowed_methods),
+            headers: header_list(&config.allowed_headers),
+            expose: header_list(&config.expose_headers),
+            max_age: HeaderValue::from(config.max_age_secs),
+        }
+    }
+
+    /// The `Origin` of `headers` if it is allowed.
+    fn allowed<'a>(&self, headers: &'a HeaderMap) -> Option<&'a HeaderValue> {
+        let origin = headers.get(ORIGIN)?;
+        let lowercase = origin.to_str().ok()?.to_ascii_lowercase();
+        let allowed = self.any
+            || self
+                .origins
+                .iter()
+                .any(|pattern| matches(pattern, &lowercase));
+        allowed.then_some(origin)
+    }
+
+    /// Headers answering an allowed `origin`, with those of a preflight if
+    /// `preflight`.
+    fn headers(&self, origin: &HeaderValue, preflight: bool) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        let allow_origin = if self.any {
+            HeaderValue::from_static("*")
+        } else {
+            origin.clone()
+        };
+        headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, allow_origin);
+        if self.credentials {
+            headers.insert(
+                ACCESS_CONTROL_ALLOW_CREDENTIALS,
+                HeaderValue::from_static("true"),
+            );
+        }
+        if preflight {
+            headers.insert(ACCESS_CONTROL_ALLOW_METHODS, self.methods.clone());
+            headers.insert(ACCESS_CONTROL_ALLOW_HEADERS, self.headers.clone());
+            headers.insert(ACCESS_CONTROL_MAX_AGE, self.max_age.clone());
+        } else if !self.expose.is_empty() {
+            headers.insert(ACCESS_CONTROL_EXPOSE_HEADERS, self.expose.clone());
+        }
+        headers
+    }
+}
+
+fn policy() -> &'static Policy {
+    static POLICY: OnceLock<Policy> = OnceLock::new();
+    POLICY.get_or_init(|| Policy::new(&cfg::config().cors))
+}
+
+fn is_websocket(headers: &HeaderMap) -> bool {
+    headers
+        .get(UPGRADE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.eq_ignore_ascii_case("websocket"))
+}
+
+/// Answers preflights and tags responses for the origins allowed by the
+/// `cors` configuration, leaving requests from other origins without any
+/// CORS header so browsers keep their scripts out. Browsers do not apply
+/// CORS to WebSocket handshakes, so those are refused here instead.
 pub async fn handle(request: Request, next: Next) -> Response {
-    let mut cors_headers = HeaderMap::new();
-
-    cors_headers
-        .insert(ACCESS_CONTROL_ALLOW_ORIGIN, HeaderValue::from_static("*"));
-    cors_headers.insert(
-        ACCESS_CONTROL_ALLOW_CREDENTIALS,
-        HeaderValue::from_static("true"),
-    );
-    cors_headers.insert(
-        ACCESS_CONTROL_ALLOW_METHODS,
-        HeaderValue::from_static("GET, POST, PUT, DELETE, OPTIONS"),
-    );
-    cors_headers.insert(
-        ACCESS_CONTROL_ALLOW_HEADERS,
-        HeaderValue::from_static(
-            "content-type, authorization, withCredentials",
-        ),
-    );
-
-    if request.method() == Method::OPTIONS {
-        return (StatusCode::NO_CONTENT, cors_headers).into_response();
+    let policy = policy();
+    let origin = policy.allowed(request.headers()).cloned();
+    if origin.is_none()
+        && request.headers().contains_key(ORIGIN)
+        && is_websocket(request.headers())
+    {
+        return StatusCode::FORBIDDEN.into_response();
+    }
+
+    let preflight = request.method() == Method::OPTIONS;
+    let mut headers = origin
+        .map(|origin| policy.headers(&origin, preflight))
+        .unwrap_or_default();
+    headers.insert(VARY, HeaderValue::from_static("origin"));
+
+    if preflight {
+        return (StatusCode::NO_CONTENT, headers).into_response();
     }
 
     let response = next.run(request).await;
 
-    (cors_headers, response).into_response()
+    (headers, response).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(origins: &[&str], credentials: bool) -> CorsConfig {
+        CorsConfig {
+            allowed_origins: origins.iter().map(ToString::to_string).collect(),
+            allow_credentials: credentials,
+            ..Default::default()
+        }
+    }
+
+    fn origin(origin: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(ORIGIN, HeaderValue::from_static(origin));
+        headers
+    }
+
+    #[test]
+    fn test_matches() {
+        assert!(matches(
+            "https://app.example.com",
+            "https://app.example.com"
+        ));
+        assert!(!matches(
+            "https://app.example.com",
+            "https://app.example.co"
+        ));
+        assert!(matches("https://*.example.com", "https://a.b.example.com"));
+        assert!(!matches("https://*.example.com", "https://example.com"));
+        assert!(!matches("https://*.example.com", "https://evil.com"));
+        assert!(matches("http://localhost:*", "http://localhost:3000"));
+        assert!(matches("*", "https://anything"));
+    }
+
+    #[test]
+    fn test_policy() {
+        let policy = Policy::new(&config(&["https://*.Example.com"], true));
+        let allowed = origin("https://App.example.com");
+        let origin_value = policy.allowed(&allowed).unwrap();
+        let headers = policy.headers(origin_value, true);
+        assert_eq!(
+            headers[ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://App.example.com"
+        );
+        assert_eq!(headers[ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+        assert_eq!(
+            headers[ACCESS_CONTROL_ALLOW_METHODS],
+            "GET, POST, PUT, DELETE"
+        );
+        assert_eq!(headers[ACCESS_CONTROL_MAX_AGE], "600");
+        assert!(!headers.contains_key(ACCESS_CONTROL_EXPOSE_HEADERS));
+        let headers = policy.headers(origin_value, false);
+        assert_eq!(
+            headers[ACCESS_CONTROL_EXPOSE_HEADERS],
+            "x-request-id, retry-after"
+        );
+        assert!(!headers.contains_key(ACCESS_CONTROL_MAX_AGE));
+
+        assert!(policy.allowed(&origin("https://evil.com")).is_none());
+        assert!(policy.allowed(&HeaderMap::new()).is_none());
+
+        // A wildcard never goes together with credentials.
+        let policy = Policy::new(&config(&["*"], true));
+        let headers =
+            policy.headers(&HeaderValue::from_static("https://a"), false);
+        assert_eq!(headers[ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+        assert!(!headers.contains_key(ACCESS_CONTROL_ALLOW_CREDENTIALS));
+    }
 }
@@ -2,4 +2,8 @@ pub mod auth;
 pub mod basic_auth;
 pub mod cors;
 pub mod log;
+pub mod metrics;
+pub mod rate_limit;
 pub mod req_id;
+pub mod scope;
+pub mod trace;
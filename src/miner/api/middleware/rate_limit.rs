@@ -0,0 +1,68 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    middleware::Next,
+    response::Response,
+};
+
+use crate::{
+    library::{
+        cfg,
+        error::{AppError::AuthError, AppResult, AuthInnerError},
+    },
+    miner::{
+        bootstrap::AppState,
+        service::{
+            jwt_service::Claims,
+            rate_limit_service::{self, Policy},
+            session_service::ClientInfo,
+        },
+    },
+};
+
+/// Who a request counts against: the client IP for policies of anonymous
+/// routes, the account for the others, falling back to the IP when the
+/// request carries no claims.
+fn subject(policy: Policy, request: &Request) -> Option<String> {
+    let account = match policy {
+        Policy::Login | Policy::Register => None,
+        Policy::Email | Policy::Code | Policy::Api | Policy::Sign => request
+            .extensions()
+            .get::<Claims>()
+            .map(|claims| format!("uid:{}", claims.uid)),
+    };
+    account.or_else(|| {
+        let peer = request
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| *addr);
+        ClientInfo::from_parts(
+            request.headers(),
+            peer,
+            &cfg::config().miner.trusted_proxies,
+        )
+        .ip
+        .map(|ip| format!("ip:{ip}"))
+    })
+}
+
+/// Refuses requests over `policy` with `429 Too Many Requests` and a
+/// `Retry-After` header. Lets requests through when the storage cannot
+/// count them, rather than refusing everything while it is down.
+pub async fn handle(
+    State((state, policy)): State<(Arc<AppState>, Policy)>,
+    request: Request,
+    next: Next,
+) -> AppResult<Response> {
+    if let Some(subject) = subject(policy, &request) {
+        match rate_limit_service::hit(&state, policy, &subject).await {
+            Ok(Some(retry)) => {
+                return Err(AuthError(AuthInnerError::TooManyRequests(retry)));
+            }
+            Ok(None) => {}
+            Err(e) => tracing::warn!("Failed to count {policy:?} hit: {e}"),
+        }
+    }
+    Ok(next.run(request).await)
+}
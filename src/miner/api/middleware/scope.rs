@@ -0,0 +1,42 @@
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+
+use crate::{
+    library::error::{AppError::AuthError, AppResult, AuthInnerError},
+    miner::service::org_service::FleetAccess,
+    models::{api_token::BwApiToken, types::ApiScope},
+};
+
+/// Lets a request through only if the caller's role in the fleet it acts
+/// on allows `scope` and, for requests made with a personal API token, the
+/// token holds `scope`. Must run after the `auth` middleware.
+pub async fn require(
+    State(scope): State<ApiScope>,
+    request: Request,
+    next: Next,
+) -> AppResult<Response> {
+    if let Some(token) = request.extensions().get::<BwApiToken>() {
+        if !token.has_scope(scope) {
+            return Err(AuthError(AuthInnerError::InsufficientScope));
+        }
+    }
+    let access = request
+        .extensions()
+        .get::<FleetAccess>()
+        .ok_or(AuthError(AuthInnerError::InvalidToken))?;
+    if !access.role.grants(scope) {
+        return Err(AuthError(AuthInnerError::PermissionDenied));
+    }
+    Ok(next.run(request).await)
+}
+
+/// Rejects personal API tokens, for routes managing the account itself.
+pub async fn session_only(request: Request, next: Next) -> AppResult<Response> {
+    if request.extensions().get::<BwApiToken>().is_some() {
+        return Err(AuthError(AuthInnerError::InsufficientScope));
+    }
+    Ok(next.run(request).await)
+}
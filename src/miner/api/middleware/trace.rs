@@ -0,0 +1,27 @@
+use axum::{extract::Request, middleware::Next, response::Response};
+use tracing::{field, Instrument};
+
+use crate::library::tracer::{self, HeaderCarrier};
+
+/// Runs the request in a span continuing the trace of the caller, tagged
+/// with the id given to it by [`super::req_id`].
+pub async fn handle(request: Request, next: Next) -> Response {
+    let request_id = request
+        .headers()
+        .get("x-request-id")
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    let span = tracing::info_span!(
+        "http_request",
+        otel.kind = "server",
+        http.request.method = %request.method(),
+        url.path = request.uri().path(),
+        request_id,
+        http.response.status_code = field::Empty,
+    );
+    tracer::continue_trace(&span, &HeaderCarrier(request.headers()));
+
+    let response = next.run(request).instrument(span.clone()).await;
+    span.record("http.response.status_code", response.status().as_u16());
+    response
+}
@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::{net::SocketAddr, sync::Arc};
 
 use tokio::net::TcpListener;
 
@@ -9,6 +9,7 @@ use crate::{
 
 pub mod controller;
 pub mod middleware;
+pub mod openapi;
 pub mod route;
 
 pub struct Server {
@@ -46,9 +47,12 @@ impl Server {
         );
 
         // Run the server with graceful shutdown
-        axum::serve(listener, app)
-            .with_graceful_shutdown(shutdown_signal())
-            .await
-            .unwrap_or_else(|e| panic!("💥 Failed to start API server: {e:?}"));
+        axum::serve(
+            listener,
+            app.into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .with_graceful_shutdown(shutdown_signal())
+        .await
+        .unwrap_or_else(|e| panic!("💥 Failed to start API server: {e:?}"));
     }
 }
@@ -0,0 +1,521 @@
+//! OpenAPI 3 document of the v1 API, generated from the handlers.
+//!
+//! Handlers describe themselves with `#[utoipa::path]` and the types they
+//! take and return derive [`ToSchema`]. [`route::init`](super::route::init)
+//! registers every handler through an [`ApiRouter`], which serves it at the
+//! path and method it documents and adds what only the router knows: who
+//! may call it and the rate limits it is under.
+
+use std::convert::Infallible;
+
+use axum::{
+    extract::Request,
+    handler::Handler,
+    response::IntoResponse,
+    routing::{MethodFilter, MethodRouter, Route},
+    Router,
+};
+use tower::{Layer, Service};
+use utoipa::{
+    __dev::{SchemaReferences, Tags},
+    openapi::{
+        path::{HttpMethod, Operation, Paths},
+        schema::{ObjectBuilder, Schema, Type},
+        security::{
+            HttpAuthScheme, HttpBuilder, SecurityRequirement, SecurityScheme,
+        },
+        ComponentsBuilder, ContentBuilder, HeaderBuilder, InfoBuilder, OpenApi,
+        OpenApiBuilder, Ref, RefOr, Response, ResponseBuilder, Server,
+    },
+    PartialSchema, Path, ToSchema,
+};
+
+use crate::models::types::ApiScope;
+
+/// The body every JSON route answers with, `data` being what it returns.
+#[derive(ToSchema)]
+pub struct Envelope<T> {
+    /// 0 on success.
+    pub code: u16,
+    pub msg: String,
+    pub data: T,
+}
+
+/// What routes returning nothing put in `data`.
+pub struct Nothing;
+
+impl PartialSchema for Nothing {
+    fn schema() -> RefOr<Schema> {
+        ObjectBuilder::new().schema_type(Type::Null).into()
+    }
+}
+
+impl ToSchema for Nothing {}
+
+/// The body of a failed request.
+#[derive(ToSchema)]
+pub struct Failure {
+    /// Tells which error it is.
+    pub code: u16,
+    pub msg: String,
+}
+
+/// Who may call a route, beyond holding an access token.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    /// Anyone, no token needed.
+    Open,
+    /// A login session, personal API tokens are refused.
+    Session,
+    /// A login session or an API token holding the scope.
+    Scope(ApiScope),
+}
+
+impl Access {
+    fn describe(self, operation: &mut Operation) {
+        match self {
+            Self::Open => operation.security = Some(Vec::new()),
+            Self::Session => {
+                let note = "Not available to personal API tokens.";
+                operation.description =
+                    Some(match operation.description.take() {
+                        Some(description) => format!("{description}\n\n{note}"),
+                        None => note.to_string(),
+                    });
+            }
+            Self::Scope(scope) => {
+                operation
+                    .extensions
+                    .get_or_insert_with(Default::default)
+                    .insert(
+                        "x-required-scope".to_string(),
+                        serde_json::json!(scope),
+                    );
+            }
+        }
+    }
+}
+
+fn failure(description: &str) -> ResponseBuilder {
+    ResponseBuilder::new().description(description).content(
+        "application/json",
+        ContentBuilder::new()
+            .schema(Some(Ref::from_schema_name(Failure::name())))
+            .build(),
+    )
+}
+
+/// Documents the `429` answered once over a rate limit.
+fn limited(operation: &mut Operation) {
+    let response: Response = failure("Rate limited")
+        .header(
+            "Retry-After",
+            HeaderBuilder::new()
+                .schema(ObjectBuilder::new().schema_type(Type::Integer))
+                .description(Some("Seconds until the limit resets"))
+                .build(),
+        )
+        .build();
+    operation
+        .responses
+        .responses
+        .insert("429".to_string(), response.into());
+}
+
+const fn method_filter(method: &HttpMethod) -> MethodFilter {
+    match method {
+        HttpMethod::Get => MethodFilter::GET,
+        HttpMethod::Post => MethodFilter::POST,
+        HttpMethod::Put => MethodFilter::PUT,
+        HttpMethod::Delete => MethodFilter::DELETE,
+        HttpMethod::Options => MethodFilter::OPTIONS,
+        HttpMethod::Head => MethodFilter::HEAD,
+        HttpMethod::Patch => MethodFilter::PATCH,
+        HttpMethod::Trace => MethodFilter::TRACE,
+    }
+}
+
+/// A handler along with the operation documenting it, see [`api_route`].
+pub struct ApiRoute<S> {
+    path: String,
+    methods: Vec<HttpMethod>,
+    operation: Operation,
+    schemas: Vec<(String, RefOr<Schema>)>,
+    router: MethodRouter<S>,
+}
+
+impl<S> ApiRoute<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    /// Serves `handler` as `P`, its `#[utoipa::path]`, documents it.
+    pub fn new<P, H, T>(handler: H) -> Self
+    where
+        P: Path + SchemaReferences + for<'t> Tags<'t>,
+        H: Handler<T, S>,
+        T: 'static,
+    {
+        let methods = P::methods();
+        let router =
+            methods.iter().fold(MethodRouter::new(), |router, method| {
+                router.on(method_filter(method), handler.clone())
+            });
+        let mut operation = P::operation();
+        operation
+            .tags
+            .get_or_insert_with(Vec::new)
+            .extend(P::tags().into_iter().map(String::from));
+        operation.responses.responses.insert(
+            "default".to_string(),
+            failure("Failure, `code` tells which").build().into(),
+        );
+        let mut schemas = Vec::new();
+        P::schemas(&mut schemas);
+        Self {
+            path: P::path(),
+            methods,
+            operation,
+            schemas,
+            router,
+        }
+    }
+
+    /// Puts the route under the rate limit `layer` applies.
+    #[must_use]
+    pub fn limit<L>(mut self, layer: L) -> Self
+    where
+        L: Layer<Route> + Clone + Send + 'static,
+        L::Service: Service<Request> + Clone + Send + 'static,
+        <L::Service as Service<Request>>::Response: IntoResponse + 'static,
+        <L::Service as Service<Request>>::Error: Into<Infallible> + 'static,
+        <L::Service as Service<Request>>::Future: Send + 'static,
+    {
+        self.router = self.router.layer(layer);
+        limited(&mut self.operation);
+        self
+    }
+}
+
+/// An [`ApiRoute`] of `module::handler`, which `#[utoipa::path]` documents.
+macro_rules! api_route {
+    ($module:ident :: $handler:ident) => {
+        ::paste::paste! {
+            $crate::miner::api::openapi::ApiRoute::new::<
+                $module::[<__path_ $handler>],
+                _,
+                _,
+            >($module::$handler)
+        }
+    };
+}
+
+pub(crate) use api_route;
+
+/// A [`Router`] documenting the routes it serves.
+pub struct ApiRouter<S = ()> {
+    router: Router<S>,
+    paths: Paths,
+    schemas: Vec<(String, RefOr<Schema>)>,
+}
+
+impl<S> Default for ApiRouter<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    fn default() -> Self {
+        Self {
+            router: Router::new(),
+            paths: Paths::new(),
+            schemas: Vec::new(),
+        }
+    }
+}
+
+impl<S> ApiRouter<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn route(mut self, route: ApiRoute<S>) -> Self {
+        self.router = self.router.route(&route.path, route.router);
+        self.paths.add_path_operation(
+            route.path,
+            route.methods,
+            route.operation,
+        );
+        self.schemas.extend(route.schemas);
+        self
+    }
+
+    #[must_use]
+    pub fn merge(mut self, other: Self) -> Self {
+        self.router = self.router.merge(other.router);
+        self.paths.merge(other.paths);
+        self.schemas.extend(other.schemas);
+        self
+    }
+
+    #[must_use]
+    pub fn layer<L>(mut self, layer: L) -> Self
+    where
+        L: Layer<Route> + Clone + Send + 'static,
+        L::Service: Service<Request> + Clone + Send + 'static,
+        <L::Service as Service<Request>>::Response: IntoResponse + 'static,
+        <L::Service as Service<Request>>::Error: Into<Infallible> + 'static,
+        <L::Service as Service<Request>>::Future: Send + 'static,
+    {
+        self.router = self.router.layer(layer);
+        self
+    }
+
+    #[must_use]
+    pub fn route_layer<L>(mut self, layer: L) -> Self
+    where
+        L: Layer<Route> + Clone + Send + 'static,
+        L::Service: Service<Request> + Clone + Send + 'static,
+        <L::Service as Service<Request>>::Response: IntoResponse + 'static,
+        <L::Service as Service<Request>>::Error: Into<Infallible> + 'static,
+        <L::Service as Service<Request>>::Future: Send + 'static,
+    {
+        self.router = self.router.route_layer(layer);
+        self
+    }
+
+    /// Puts the routes so far under the rate limit `layer` applies.
+    #[must_use]
+    pub fn limit<L>(mut self, layer: L) -> Self
+    where
+        L: Layer<Route> + Clone + Send + 'static,
+        L::Service: Service<Request> + Clone + Send + 'static,
+        <L::Service as Service<Request>>::Response: IntoResponse + 'static,
+        <L::Service as Service<Request>>::Error: Into<Infallible> + 'static,
+        <L::Service as Service<Request>>::Future: Send + 'static,
+    {
+        self.router = self.router.route_layer(layer);
+        self.operations().for_each(limited);
+        self
+    }
+
+    /// Documents who may call the routes so far, as enforced by the layers
+    /// in front of them.
+    #[must_use]
+    pub fn access(mut self, access: Access) -> Self {
+        self.operations()
+            .for_each(|operation| access.describe(operation));
+        self
+    }
+
+    pub fn with_state<S2>(self, state: S) -> ApiRouter<S2> {
+        ApiRouter {
+            router: self.router.with_state(state),
+            paths: self.paths,
+            schemas: self.schemas,
+        }
+    }
+
+    fn operations(&mut self) -> impl Iterator<Item = &mut Operation> {
+        self.paths.paths.values_mut().flat_map(|item| {
+            [
+                &mut item.get,
+                &mut item.put,
+                &mut item.post,
+                &mut item.delete,
+                &mut item.options,
+                &mut item.head,
+                &mut item.patch,
+                &mut item.trace,
+            ]
+            .into_iter()
+            .flatten()
+        })
+    }
+
+    /// The router and the document of its routes, served under `/api/v1`.
+    pub fn split(self) -> (Router<S>, OpenApi) {
+        let components = ComponentsBuilder::new()
+            .schemas_from_iter(self.schemas)
+            .schema_from::<Failure>()
+            .security_scheme(
+                "bearer",
+                SecurityScheme::Http(
+                    HttpBuilder::new()
+                        .scheme(HttpAuthScheme::Bearer)
+                        .description(Some(
+                            "An access token, or a personal API token where \
+                             the operation allows it.",
+                        ))
+                        .build(),
+                ),
+            )
+            .build();
+        let openapi = OpenApiBuilder::new()
+            .info(
+                InfoBuilder::new()
+                    .title("Miner API")
+                    .version(env!("CARGO_PKG_VERSION")),
+            )
+            .servers(Some([Server::new("/api/v1")]))
+            .paths(self.paths)
+            .components(Some(components))
+            .security(Some([SecurityRequirement::new(
+                "bearer",
+                Vec::<String>::new(),
+            )]))
+            .build();
+        (self.router, openapi)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use axum::{
+        body::{to_bytes, Body},
+        http::{header::AUTHORIZATION, Method, StatusCode},
+    };
+    use serde_json::{json, Value};
+    use sqlx::PgPool;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::{
+        library::{
+            cacher::Cacher,
+            cfg,
+            storage::{MemoryStorage, Storage},
+            Dber,
+        },
+        miner::{
+            api::route,
+            bootstrap::AppState,
+            service::{
+                session_service::{self, ClientInfo},
+                Services,
+            },
+        },
+        models::account::BwAccount,
+    };
+
+    async fn serve(pool: PgPool) -> (Router, Arc<AppState>) {
+        cfg::init(&"./fixtures/config.toml".to_string());
+        let storage: Arc<dyn Storage> = Arc::new(MemoryStorage::new());
+        let state = Arc::new(AppState {
+            db: Dber { pool },
+            cacher: Cacher::init(storage.clone()),
+            storage,
+            services: Services::init().await,
+        });
+        (route::init(state.clone()), state)
+    }
+
+    fn request(method: &Method, path: &str, token: Option<&str>) -> Request {
+        let mut request = Request::builder()
+            .method(method)
+            .uri(format!("/api/v1{path}"));
+        if let Some(token) = token {
+            request = request.header(AUTHORIZATION, format!("Bearer {token}"));
+        }
+        request.body(Body::empty()).unwrap()
+    }
+
+    async fn status(
+        router: &Router,
+        method: &Method,
+        path: &str,
+        token: Option<&str>,
+    ) -> StatusCode {
+        let request = request(method, path, token);
+        router.clone().oneshot(request).await.unwrap().status()
+    }
+
+    /// The document as served.
+    async fn document(router: &Router) -> Value {
+        let request = request(&Method::GET, "/openapi.json", None);
+        let response = router.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[sqlx::test(fixtures(path = "../../../fixtures", scripts("users")))]
+    async fn test_documented_routes_are_served(pool: PgPool) {
+        sqlx::query("UPDATE bw_account SET status = 'active'")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let user = BwAccount::fetch_user_by_email(&pool, "vainjoker@tuta.io")
+            .await
+            .unwrap()
+            .unwrap();
+        let (router, state) = serve(pool).await;
+        let tokens =
+            session_service::issue(&state, &user, &ClientInfo::default())
+                .await
+                .unwrap();
+        let token = Some(tokens.access_token.as_str());
+        let document = document(&router).await;
+        assert_eq!(
+            status(&router, &Method::POST, "/nowhere", token).await,
+            StatusCode::NOT_FOUND
+        );
+
+        for (path, item) in document["paths"].as_object().unwrap() {
+            for method in [Method::GET, Method::POST] {
+                let operation = &item[method.as_str().to_lowercase()];
+                if operation.is_null() {
+                    // Past authentication, so the method is what is refused.
+                    assert_eq!(
+                        status(&router, &method, path, token).await,
+                        StatusCode::METHOD_NOT_ALLOWED,
+                        "{method} {path} is served but not documented"
+                    );
+                    continue;
+                }
+                // Without a token, so no handler does anything.
+                let status = status(&router, &method, path, None).await;
+                assert!(
+                    ![StatusCode::NOT_FOUND, StatusCode::METHOD_NOT_ALLOWED]
+                        .contains(&status),
+                    "{method} {path} is documented but not served"
+                );
+                assert_eq!(
+                    status != StatusCode::UNAUTHORIZED,
+                    operation["security"] == json!([]),
+                    "{method} {path} is not as open as documented"
+                );
+            }
+        }
+    }
+
+    #[sqlx::test]
+    async fn test_references_resolve(pool: PgPool) {
+        let document = document(&serve(pool).await.0).await;
+        let schemas = document["components"]["schemas"].as_object().unwrap();
+        let text = document.to_string();
+        for (i, _) in text.match_indices("#/components/schemas/") {
+            let name = text[i..]
+                .trim_start_matches("#/components/schemas/")
+                .split('"')
+                .next()
+                .unwrap();
+            assert!(schemas.contains_key(name), "unresolved {name}");
+        }
+    }
+
+    #[sqlx::test]
+    async fn test_secrets_are_not_documented(pool: PgPool) {
+        let document = document(&serve(pool).await.0).await;
+        let schemas = &document["components"]["schemas"];
+        let account = &schemas["BwAccount"]["properties"];
+        assert!(account["email"].is_object());
+        assert!(account.get("password").is_none());
+        let token = &schemas["BwApiToken"]["properties"];
+        assert!(token["prefix"].is_object());
+        assert!(token.get("token_hash").is_none());
+    }
+}
@@ -2,82 +2,150 @@ use std::{sync::Arc, time::Duration};
 
 use axum::{
     middleware::{from_fn, from_fn_with_state},
-    routing::post,
-    Router,
+    routing::get,
+    Extension, Router,
 };
 use tower_http::timeout::TimeoutLayer;
 
 use super::{
     controller::{
         handler_404,
+        health::{healthz_handler, metrics_handler, readyz_handler},
         v1::{
-            account::{
-                change_password_handler, refresh_token_handler,
-                send_reset_password_email_handler,
-                verify_active_account_code_handler,
-            },
-            operate::operate_handler,
+            account, api_token, coin_stat, enrollment_key, exchange_rate,
+            fleet_event, group, openapi, operate, organization, setting,
         },
     },
-    middleware::{auth, basic_auth, cors, log, req_id},
-};
-use crate::miner::{
-    api::controller::v1::{
-        account::{
-            get_me_handler, login_user_handler, register_user_handler,
-            send_active_account_email_handler,
-        },
-        group::{
-            create_group_handler, delete_group_handler,
-            get_groups_by_ids_handler, get_groups_handler,
-            update_group_handler,
-        },
+    middleware::{
+        auth, basic_auth, cors, log, metrics, rate_limit, req_id, scope, trace,
     },
-    bootstrap::AppState,
+    openapi::{api_route, Access, ApiRouter},
+};
+use crate::{
+    library::cfg,
+    miner::{bootstrap::AppState, service::rate_limit_service::Policy},
+    models::types::ApiScope,
 };
 
 pub fn init(miner_state: Arc<AppState>) -> Router {
-    let open = Router::new()
-        .route("/auth/login", post(login_user_handler))
-        .route("/auth/register", post(register_user_handler))
-        .route("/users/refresh_token", post(refresh_token_handler));
+    let limit = |policy| {
+        from_fn_with_state((miner_state.clone(), policy), rate_limit::handle)
+    };
 
-    let basic = Router::new()
+    let open = ApiRouter::new()
+        .route(api_route!(openapi::openapi_handler))
+        .route(
+            api_route!(account::login_user_handler).limit(limit(Policy::Login)),
+        )
+        .route(
+            api_route!(account::login_mfa_handler).limit(limit(Policy::Login)),
+        )
         .route(
-            "/users/send_active",
-            post(send_active_account_email_handler),
+            api_route!(account::register_user_handler)
+                .limit(limit(Policy::Register)),
         )
         .route(
-            "/users/verify_active",
-            post(verify_active_account_code_handler),
+            api_route!(account::forgot_password_handler)
+                .limit(limit(Policy::Email)),
         )
-        .layer(from_fn(basic_auth::handle));
+        .route(
+            api_route!(account::reset_password_handler)
+                .limit(limit(Policy::Code)),
+        )
+        .route(api_route!(account::refresh_token_handler))
+        .access(Access::Open);
 
-    let auth = Router::new()
-        .route("/users/get_me", post(get_me_handler))
+    let basic = ApiRouter::new()
         .route(
-            "/users/send_reset_password",
-            post(send_reset_password_email_handler),
+            api_route!(account::send_active_account_email_handler)
+                .limit(limit(Policy::Email)),
         )
         .route(
-            "/users/verify_reset_password",
-            post(change_password_handler),
+            api_route!(account::verify_active_account_code_handler)
+                .limit(limit(Policy::Code)),
         )
-        .route("/groups/list", post(get_groups_handler))
-        .route("/groups/create", post(create_group_handler))
-        .route("/groups/update", post(update_group_handler))
-        .route("/groups/delete", post(delete_group_handler))
-        .route("/groups/ids", post(get_groups_by_ids_handler))
-        .route("/operate/do", post(operate_handler))
+        .route(api_route!(account::logout_handler))
+        .route(api_route!(account::logout_all_handler))
+        .route(api_route!(account::get_sessions_handler))
+        .route(api_route!(account::revoke_session_handler))
+        .layer(from_fn_with_state(miner_state.clone(), basic_auth::handle));
+
+    // Routes managing the account itself, closed to personal API tokens.
+    let account = ApiRouter::new()
+        .route(api_route!(account::update_preferences_handler))
+        .route(api_route!(account::enroll_mfa_handler))
+        .route(api_route!(account::confirm_mfa_handler))
+        .route(api_route!(account::disable_mfa_handler))
+        .route(api_route!(account::regenerate_recovery_codes_handler))
+        .route(api_route!(enrollment_key::get_enrollment_keys_handler))
+        .route(api_route!(enrollment_key::create_enrollment_key_handler))
+        .route(api_route!(enrollment_key::revoke_enrollment_key_handler))
+        .route(api_route!(enrollment_key::rotate_enrollment_key_handler))
+        .route(api_route!(api_token::get_api_tokens_handler))
+        .route(api_route!(api_token::create_api_token_handler))
+        .route(api_route!(api_token::revoke_api_token_handler))
+        .route(api_route!(organization::create_organization_handler))
+        .route(api_route!(organization::get_organizations_handler))
+        .route(api_route!(organization::get_members_handler))
+        .route(api_route!(organization::add_member_handler))
+        .route(api_route!(organization::update_member_handler))
+        .route(api_route!(organization::remove_member_handler))
+        .route_layer(from_fn(scope::session_only))
+        .access(Access::Session);
+
+    let fleet_read = ApiRouter::new()
+        .route(api_route!(account::get_me_handler))
+        .route(api_route!(setting::get_currencies_handler))
+        .route(api_route!(setting::get_languages_handler))
+        .route(api_route!(group::get_groups_handler))
+        .route(api_route!(group::get_groups_by_ids_handler))
+        .route(api_route!(exchange_rate::get_current_rates_handler))
+        .route(api_route!(exchange_rate::get_history_rates_handler))
+        .route(api_route!(exchange_rate::convert_handler))
+        .route(api_route!(coin_stat::get_current_coin_stats_handler))
+        .route(api_route!(fleet_event::fleet_events_handler))
+        .route_layer(from_fn_with_state(ApiScope::FleetRead, scope::require))
+        .access(Access::Scope(ApiScope::FleetRead));
+
+    let fleet_write = ApiRouter::new()
+        .route(api_route!(group::create_group_handler))
+        .route(api_route!(group::update_group_handler))
+        .route(api_route!(group::delete_group_handler))
+        .route_layer(from_fn_with_state(ApiScope::FleetWrite, scope::require))
+        .access(Access::Scope(ApiScope::FleetWrite));
+
+    let operate = ApiRouter::new()
+        .route(api_route!(operate::operate_handler))
+        .route_layer(from_fn_with_state(ApiScope::Operate, scope::require))
+        .access(Access::Scope(ApiScope::Operate));
+
+    let auth = account
+        .merge(fleet_read)
+        .merge(fleet_write)
+        .merge(operate)
+        .limit(limit(Policy::Api))
         .route_layer(from_fn_with_state(miner_state.clone(), auth::handle))
         .with_state(miner_state.clone());
 
+    let (mut v1, document) = open.merge(basic).merge(auth).split();
+    let document = serde_json::to_value(document).unwrap_or_else(|e| {
+        panic!("💥 Failed to serialize the OpenAPI document: {e:?}")
+    });
+    if cfg::config().miner.api_docs {
+        v1 = v1.route("/docs", get(openapi::docs_handler));
+    }
+
     Router::new()
-        .nest("/api/v1", open.merge(basic).merge(auth))
+        .route("/healthz", get(healthz_handler))
+        .route("/readyz", get(readyz_handler))
+        .route("/metrics", get(metrics_handler))
+        .nest("/api/v1", v1.layer(Extension(Arc::new(document))))
+        .route_layer(from_fn(metrics::handle))
         .fallback(handler_404)
         .with_state(miner_state)
         .layer(TimeoutLayer::new(Duration::from_secs(30)))
         .layer(from_fn(log::handle))
         .layer(from_fn(cors::handle))
+        .layer(from_fn(trace::handle))
         .layer(from_fn(req_id::handle))
 }
@@ -2,8 +2,36 @@ pub const MQ_SEND_EMAIL_QUEUE: &str = "app.dev.send_email";
 
 pub const MQ_SEND_EMAIL_TAG: &str = "app.dev.send_email_tag";
 
-pub const REDIS_ACTIVE_ACCOUNT_KEY: &str = "active_code";
+pub const THIRTHEEN_DAYS_SECOND: usize = 259200;
 
-pub const REDIS_RESET_PASSWORD_KEY: &str = "reset_password_code";
+pub const REDIS_EXCHANGE_RATE_KEY: &str = "exchange_rate";
 
-pub const THIRTHEEN_DAYS_SECOND: usize = 259200;
+pub const REDIS_COIN_STAT_KEY: &str = "coin_stat";
+
+pub const REDIS_SESSION_KEY: &str = "session";
+
+pub const REDIS_SESSION_INDEX_KEY: &str = "sessions";
+
+pub const REDIS_LOGIN_FAILED_IP_KEY: &str = "login_failed_ip";
+
+pub const REDIS_MFA_ATTEMPT_KEY: &str = "mfa_attempt";
+
+pub const REDIS_MACHINE_USER_KEY: &str = "m_user";
+
+pub const REDIS_ACCOUNT_KEY: &str = "account";
+
+pub const REDIS_GROUP_KEY: &str = "groups";
+
+pub const REDIS_MACHINE_KEY: &str = "machine";
+
+pub const REDIS_API_TOKEN_RATE_KEY: &str = "api_token_rate";
+
+pub const REDIS_RATE_LIMIT_KEY: &str = "rate_limit";
+
+pub const MQ_SEND_EMAIL_RETRY_QUEUE: &str = "app.dev.send_email.retry";
+
+pub const MQ_SEND_EMAIL_DEAD_QUEUE: &str = "app.dev.send_email.dead";
+
+pub const MQ_FLEET_EVENT_EXCHANGE: &str = "app.dev.fleet_events";
+
+pub const MQ_FLEET_EVENT_TAG: &str = "app.dev.fleet_events_tag";
@@ -1,25 +1,39 @@
 pub mod constants;
 
-use std::sync::Arc;
+use std::{
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
 
-use tokio::signal;
+use tokio::{signal, sync::watch};
 
 use crate::{
-    library::{dber::DB, error::AppResult, Dber, Mqer, Redis, Redisor},
+    library::{
+        bus::MessageBus,
+        cacher::Cacher,
+        cfg,
+        dber::DB,
+        error::AppResult,
+        storage::{self, Storage},
+        Dber,
+    },
     miner::service::Services,
 };
 
 pub struct AppState {
     pub db: Dber,
-    pub redis: Redisor,
+    pub storage: Arc<dyn Storage>,
+    pub cacher: Cacher,
     pub services: Services,
 }
 
 impl AppState {
     pub async fn init() -> Self {
+        let storage = storage::init();
         Self {
             db: Dber::init().await,
-            redis: Redisor::init(),
+            cacher: Cacher::init(storage.clone()),
+            storage,
             services: Services::init().await,
         }
     }
@@ -32,15 +46,39 @@ impl AppState {
         &self.db.pool
     }
 
-    pub async fn get_redis(&self) -> AppResult<Redis> {
-        Ok(self.redis.get_redis().await?)
+    pub fn get_storage(&self) -> &dyn Storage {
+        self.storage.as_ref()
+    }
+
+    pub const fn get_cacher(&self) -> &Cacher {
+        &self.cacher
     }
 
-    pub fn get_mq(&self) -> AppResult<Arc<Mqer>> {
-        Ok(self.services.message_queue.mqer.clone())
+    pub fn get_mq(&self) -> AppResult<Arc<dyn MessageBus>> {
+        Ok(self.services.message_queue.bus.clone())
     }
 }
 
+fn shutting_down_sender() -> &'static watch::Sender<bool> {
+    static SHUTTING_DOWN: OnceLock<watch::Sender<bool>> = OnceLock::new();
+    SHUTTING_DOWN.get_or_init(|| watch::Sender::new(false))
+}
+
+/// Whether a shutdown signal was received.
+pub fn is_shutting_down() -> bool {
+    *shutting_down_sender().borrow()
+}
+
+/// Resolves once a shutdown signal is received, for long-lived responses
+/// that would otherwise hold up the graceful shutdown.
+pub async fn shutting_down() {
+    let mut receiver = shutting_down_sender().subscribe();
+    // The sender is static, so it is never dropped.
+    let _ = receiver.wait_for(|down| *down).await;
+}
+
+/// Resolves once the process is asked to stop and `miner.health.drain_secs`
+/// have passed since, [`is_shutting_down`] turning true right away.
 // pub async fn shutdown_signal(app_state: Arc<AppState>) {
 pub async fn shutdown_signal() {
     let ctrl_c = async {
@@ -68,5 +106,11 @@ pub async fn shutdown_signal() {
             tracing::info!("Terminate signal received.");
         },
     }
+    shutting_down_sender().send_replace(true);
+    let drain_secs = cfg::config().miner.health.drain_secs;
+    if drain_secs > 0 {
+        tracing::info!("Draining for {drain_secs}s before stopping.");
+        tokio::time::sleep(Duration::from_secs(drain_secs)).await;
+    }
     // app_state.services.shutdown().await;
 }
@@ -1,14 +1,15 @@
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
 use crate::{
-    miner::service::jwt_service::TokenSchema,
+    miner::service::{jwt_service::TokenSchema, session_service::Session},
     models::{
         account::BwAccount,
         types::{Currency, Language},
     },
 };
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct LoginResponse {
     pub tokens: TokenSchema,
     pub name: String,
@@ -29,32 +30,117 @@ impl LoginResponse {
     }
 }
 
-#[derive(Debug, Deserialize)]
+/// Returned by the password step of a login when a second factor is needed.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct MfaRequiredResponse {
+    pub mfa_required: bool,
+    pub mfa_token: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(untagged)]
+pub enum LoginResult {
+    Success(LoginResponse),
+    MfaRequired(MfaRequiredResponse),
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct LoginMfaRequest {
+    pub mfa_token: String,
+    pub code: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct MfaEnrollResponse {
+    pub secret: String,
+    pub otpauth_uri: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct MfaCodeRequest {
+    pub code: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RecoveryCodesResponse {
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct RegisterUserRequest {
     pub name: String,
     pub email: String,
     pub password: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct LoginUserRequest {
     pub email_or_name: String,
     pub password: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub enum CodeType {
-    ActiveAccount,
-    ResetPassword,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct ActiveAccountRequest {
     pub code: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ForgotPasswordRequest {
+    pub email: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct ResetPasswordRequest {
+    pub email: String,
     pub code: String,
     pub password: String,
 }
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct UpdatePreferencesRequest {
+    pub local_currency: Option<Currency>,
+    pub system_lang: Option<Language>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PreferencesResponse {
+    pub local_currency: Currency,
+    pub system_lang: Language,
+}
+
+impl From<BwAccount> for PreferencesResponse {
+    fn from(user: BwAccount) -> Self {
+        Self {
+            local_currency: user.local_currency,
+            system_lang: user.system_lang,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SessionResponse {
+    pub sid: String,
+    pub device: Option<String>,
+    pub ip: Option<String>,
+    pub created_at: i64,
+    pub last_used_at: i64,
+    pub current: bool,
+}
+
+impl SessionResponse {
+    pub fn new(session: Session, current_sid: Option<&str>) -> Self {
+        Self {
+            current: current_sid == Some(session.sid.as_str()),
+            sid: session.sid,
+            device: session.device,
+            ip: session.ip,
+            created_at: session.created_at,
+            last_used_at: session.last_used_at,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct RevokeSessionRequest {
+    pub sid: String,
+}
@@ -0,0 +1,27 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::models::{api_token::BwApiToken, types::ApiScope};
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateApiTokenRequest {
+    pub name: String,
+    pub scopes: Vec<ApiScope>,
+    /// Requests per minute, defaults to the configured limit.
+    pub rate_limit: Option<i32>,
+    pub expires_at: Option<NaiveDateTime>,
+}
+
+/// The token secret is only ever returned here.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CreateApiTokenResponse {
+    pub token: String,
+    #[serde(flatten)]
+    pub info: BwApiToken,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, ToSchema)]
+pub struct RevokeApiTokenRequest {
+    pub token_id: i64,
+}
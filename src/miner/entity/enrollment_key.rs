@@ -0,0 +1,14 @@
+use chrono::NaiveDateTime;
+use serde::Deserialize;
+use utoipa::ToSchema;
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateEnrollmentKeyRequest {
+    pub name: String,
+    pub expires_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, ToSchema)]
+pub struct EnrollmentKeyRequest {
+    pub key_id: i64,
+}
@@ -0,0 +1,26 @@
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone, Copy, Deserialize, ToSchema)]
+pub struct HistoryExchangeRateRequest {
+    pub date: NaiveDate,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ConvertRequest {
+    pub amount: Decimal,
+    pub from: String,
+    pub to: String,
+    pub date: Option<NaiveDate>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ConvertResponse {
+    pub amount: Decimal,
+    pub from: String,
+    pub to: String,
+    pub result: Decimal,
+    pub date: Option<NaiveDate>,
+}
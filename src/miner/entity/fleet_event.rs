@@ -0,0 +1,90 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::library::bus::Event;
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum FleetEventKind {
+    Status,
+    Presence,
+    CommandAck,
+    Alert,
+}
+
+impl FleetEventKind {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Status => "status",
+            Self::Presence => "presence",
+            Self::CommandAck => "command_ack",
+            Self::Alert => "alert",
+        }
+    }
+
+    /// The kind of event a device publishes on `topic`, if it is one
+    /// worth pushing to clients.
+    pub fn from_topic(topic: &str) -> Option<Self> {
+        if topic.ends_with("/heartbeat") {
+            Some(Self::Presence)
+        } else if topic.ends_with("/status/upload")
+            || topic.ends_with("/property/upload")
+        {
+            Some(Self::Status)
+        } else if topic.ends_with("/command/ack") {
+            Some(Self::CommandAck)
+        } else if topic.ends_with("/alert") {
+            Some(Self::Alert)
+        } else {
+            None
+        }
+    }
+}
+
+impl std::str::FromStr for FleetEventKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "status" => Ok(Self::Status),
+            "presence" => Ok(Self::Presence),
+            "command_ack" => Ok(Self::CommandAck),
+            "alert" => Ok(Self::Alert),
+            _ => Err(format!("Unknown fleet event kind `{s}`")),
+        }
+    }
+}
+
+/// Something a machine reported, tagged with the fleet it belongs to.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct FleetEvent {
+    pub kind: FleetEventKind,
+    /// Account owning the machine.
+    pub uid: i64,
+    pub group_id: Option<i64>,
+    pub mac: String,
+    /// What the machine sent, as it sent it.
+    pub payload: Value,
+    pub timestamp: DateTime<Utc>,
+}
+
+impl Event for FleetEvent {
+    const TYPE: &'static str = "fleet.event";
+    const VERSION: u32 = 1;
+}
+
+/// Narrows an event stream down, the whole fleet if nothing is given.
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct FleetEventQuery {
+    /// Only machines of this group.
+    pub group_id: Option<i64>,
+    /// Comma separated MAC addresses.
+    pub macs: Option<String>,
+    /// Comma separated kinds.
+    pub kinds: Option<String>,
+}
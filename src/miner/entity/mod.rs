@@ -1,11 +1,16 @@
 pub mod account;
+pub mod api_token;
 pub mod common;
+pub mod enrollment_key;
+pub mod exchange_rate;
+pub mod fleet_event;
 pub mod group;
 pub mod limit;
 pub mod machine;
 pub mod mqtt;
 pub mod news;
 pub mod operate;
+pub mod organization;
 pub mod policy;
 pub mod product;
 pub mod template;
@@ -4,7 +4,8 @@ use chrono::Utc;
 use serde::{Deserialize, Deserializer, Serialize};
 
 use crate::{
-    library::error::AppResult, miner::bootstrap::AppState,
+    library::{error::AppResult, storage::Pipeline},
+    miner::bootstrap::AppState,
     models::machine::Coin,
 };
 
@@ -92,7 +93,7 @@ impl Message {
         app_state: Arc<AppState>,
         mac: &str,
     ) -> AppResult<()> {
-        let mut redis = app_state.get_redis().await?;
+        let storage = app_state.get_storage();
         let r_key = format!("miner_status:{}", mac);
         let r_field = match self {
             Message::MessageMode(_) => "mode",
@@ -104,10 +105,13 @@ impl Message {
             tracing::error!("{}", format_err);
             anyhow::anyhow!(format_err)
         })?;
-        redis.hset(&r_key, r_field, &r_value).await?;
         let t_now = Utc::now().timestamp();
-        redis.hset(&r_key, "time", &t_now).await?;
-        redis.expire(&r_key, 259200).await?;
+        let mut pipeline = Pipeline::new();
+        pipeline
+            .hset(&r_key, r_field, &r_value)
+            .hset(&r_key, "time", &t_now.to_string())
+            .expire(&r_key, 259200);
+        storage.exec(&pipeline).await?;
         Ok(())
     }
 }
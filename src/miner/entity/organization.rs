@@ -0,0 +1,37 @@
+use serde::Deserialize;
+use utoipa::ToSchema;
+
+use crate::models::types::OrgRole;
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateOrganizationRequest {
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, ToSchema)]
+pub struct OrganizationRequest {
+    pub org_id: i64,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct AddMemberRequest {
+    pub org_id: i64,
+    pub email: String,
+    pub role: OrgRole,
+    /// Groups to limit the member to, all of them if omitted.
+    pub group_ids: Option<Vec<i64>>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct UpdateMemberRequest {
+    pub org_id: i64,
+    pub uid: i64,
+    pub role: OrgRole,
+    pub group_ids: Option<Vec<i64>>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, ToSchema)]
+pub struct RemoveMemberRequest {
+    pub org_id: i64,
+    pub uid: i64,
+}
@@ -6,7 +6,7 @@ pub mod service;
 
 use std::sync::Arc;
 
-use crate::miner::bootstrap::AppState;
+use crate::{admin, miner::bootstrap::AppState};
 
 pub async fn serve() {
     let miner_state = Arc::new(AppState::init().await);
@@ -23,7 +23,12 @@ pub async fn serve() {
         abi::Server::init(miner_state2).serve().await;
     });
 
-    let _ = tokio::join!(api_server, abi_server);
+    let miner_state3 = miner_state.clone();
+    let admin_server = tokio::spawn(async move {
+        admin::api::Server::init(miner_state3).serve().await;
+    });
+
+    let _ = tokio::join!(api_server, abi_server, admin_server);
 
     miner_state.services.shutdown().await;
 }
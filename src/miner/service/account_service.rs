@@ -0,0 +1,33 @@
+//! Accounts looked up by uid, as on every token refresh and API token
+//! request. They are cached under `account:{uid}`, so anything writing an
+//! account has to [`invalidate`] it afterwards. The cache leaves out the
+//! password hash, so the accounts returned here are not to check
+//! passwords against.
+
+use crate::{
+    library::error::AppResult,
+    miner::bootstrap::{constants::REDIS_ACCOUNT_KEY, AppState},
+    models::account::BwAccount,
+};
+
+fn cache_key(uid: i64) -> String {
+    format!("{REDIS_ACCOUNT_KEY}:{uid}")
+}
+
+pub async fn fetch(state: &AppState, uid: i64) -> AppResult<Option<BwAccount>> {
+    let db = state.get_db();
+    let account = state
+        .get_cacher()
+        .entry(&cache_key(uid))
+        .get_or_load(|| BwAccount::fetch_user_by_uid(db, uid))
+        .await?;
+    // Alike whether it was just loaded or read from the cache.
+    Ok(account.map(|account| BwAccount {
+        password: String::new(),
+        ..account
+    }))
+}
+
+pub async fn invalidate(state: &AppState, uid: i64) -> AppResult<()> {
+    Ok(state.get_cacher().invalidate(&cache_key(uid)).await?)
+}
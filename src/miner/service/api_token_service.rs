@@ -0,0 +1,141 @@
+//! Personal access tokens for scripts talking to the REST API.
+//!
+//! Tokens look like `pat_<40 random characters>` and are accepted in the
+//! `Authorization: Bearer` header wherever access tokens are, limited to
+//! the routes their scopes cover. Each token is rate limited with a fixed
+//! one minute window counted in Redis.
+
+use chrono::{NaiveDateTime, Utc};
+
+use crate::{
+    library::{
+        cfg, crypto,
+        error::{
+            ApiInnerError,
+            AppError::{ApiError, AuthError},
+            AppResult, AuthInnerError,
+        },
+    },
+    miner::{
+        bootstrap::{constants::REDIS_API_TOKEN_RATE_KEY, AppState},
+        service::{account_service, jwt_service::Claims, rate_limit_service},
+    },
+    models::{
+        api_token::{BwApiToken, CreateBwApiTokenSchema},
+        types::{AccountStatus, ApiScope},
+    },
+};
+
+pub const TOKEN_PREFIX: &str = "pat_";
+
+const TOKEN_LENGTH: usize = 40;
+
+/// Characters of the token kept in clear so users can tell tokens apart.
+const DISPLAY_PREFIX_LENGTH: usize = 12;
+
+const RATE_WINDOW_SECS: u64 = 60;
+
+pub fn is_api_token(token: &str) -> bool {
+    token.starts_with(TOKEN_PREFIX)
+}
+
+fn rate_key(token_id: i64) -> String {
+    format!("{REDIS_API_TOKEN_RATE_KEY}:{token_id}")
+}
+
+/// Creates a token, returning it along with the only copy of its secret.
+pub async fn create(
+    state: &AppState,
+    uid: i64,
+    name: String,
+    scopes: Vec<ApiScope>,
+    rate_limit: Option<i32>,
+    expires_at: Option<NaiveDateTime>,
+) -> AppResult<(BwApiToken, String)> {
+    let config = &cfg::config().miner.api_token;
+    if scopes.is_empty() {
+        return Err(ApiError(ApiInnerError::InvalidApiToken(
+            "at least one scope is required".to_string(),
+        )));
+    }
+    let rate_limit = rate_limit.unwrap_or(config.rate_limit);
+    if !(1..=config.max_rate_limit).contains(&rate_limit) {
+        return Err(ApiError(ApiInnerError::InvalidApiToken(format!(
+            "rate limit must be between 1 and {}",
+            config.max_rate_limit
+        ))));
+    }
+    if expires_at.is_some_and(|at| at <= Utc::now().naive_utc()) {
+        return Err(ApiError(ApiInnerError::InvalidApiToken(
+            "expiry must be in the future".to_string(),
+        )));
+    }
+    let count = BwApiToken::fetch_token_count(state.get_db(), uid).await?;
+    if count >= config.max_per_account {
+        return Err(ApiError(ApiInnerError::ApiTokenLimitExceeded));
+    }
+
+    let mut scopes = scopes;
+    scopes.sort_by_key(|scope| *scope as u8);
+    scopes.dedup();
+
+    let secret =
+        format!("{TOKEN_PREFIX}{}", crypto::random_words(TOKEN_LENGTH));
+    let item = CreateBwApiTokenSchema {
+        uid,
+        name,
+        token_hash: crypto::hash_token(&secret),
+        prefix: secret[..DISPLAY_PREFIX_LENGTH].to_string(),
+        scopes,
+        rate_limit,
+        expires_at,
+    };
+    let token = BwApiToken::create_bw_api_token(state.get_db(), &item).await?;
+    Ok((token, secret))
+}
+
+pub async fn revoke(
+    state: &AppState,
+    token_id: i64,
+    uid: i64,
+) -> AppResult<()> {
+    let affected =
+        BwApiToken::revoke_token(state.get_db(), token_id, uid).await?;
+    if affected == 0 {
+        return Err(ApiError(ApiInnerError::ApiTokenNotFound));
+    }
+    Ok(())
+}
+
+/// Resolves a personal access token to the claims of its owner, counting
+/// the request against the token's rate limit.
+pub async fn authenticate(
+    state: &AppState,
+    secret: &str,
+) -> AppResult<(Claims, BwApiToken)> {
+    let token = BwApiToken::fetch_token_by_hash(
+        state.get_db(),
+        &crypto::hash_token(secret),
+    )
+    .await?
+    .ok_or(AuthError(AuthInnerError::InvalidToken))?;
+
+    let limited = rate_limit_service::over_limit(
+        state.get_storage(),
+        &rate_key(token.token_id),
+        i64::from(token.rate_limit),
+        RATE_WINDOW_SECS,
+    )
+    .await?;
+    if let Some(retry) = limited {
+        return Err(AuthError(AuthInnerError::ApiTokenRateLimited(retry)));
+    }
+
+    let user = account_service::fetch(state, token.uid)
+        .await?
+        .filter(|user| user.status == AccountStatus::Active)
+        .ok_or(AuthError(AuthInnerError::InvalidToken))?;
+    BwApiToken::update_last_used_at(state.get_db(), token.token_id).await?;
+
+    Ok((Claims::for_api_token(&user, &token), token))
+}
@@ -0,0 +1,138 @@
+//! Device enrollment keys.
+//!
+//! A device presents one of its owner's keys when it signs in over gRPC.
+//! The key to account lookup is cached under `m_user:{key}`, so revoking a
+//! key has to drop that entry as well, and creating one the entry caching
+//! that it was unknown.
+
+use chrono::{NaiveDateTime, Utc};
+
+use crate::{
+    library::{
+        cfg, crypto,
+        error::{ApiInnerError, AppError, AppError::ApiError, AppResult},
+    },
+    miner::bootstrap::{constants::REDIS_MACHINE_USER_KEY, AppState},
+    models::{
+        account::BwAccount,
+        account_setting::{
+            BwAccountSetting, CreateBwAccountSettingSchema,
+            RevokeBwAccountSettingSchema,
+        },
+    },
+};
+
+const KEY_LENGTH: usize = 40;
+
+pub const DEFAULT_KEY_NAME: &str = "default";
+
+pub fn cache_key(key: &str) -> String {
+    format!("{REDIS_MACHINE_USER_KEY}:{key}")
+}
+
+pub async fn create(
+    state: &AppState,
+    uid: i64,
+    name: String,
+    expires_at: Option<NaiveDateTime>,
+) -> AppResult<BwAccountSetting> {
+    let item = CreateBwAccountSettingSchema {
+        uid,
+        name,
+        key: crypto::random_words(KEY_LENGTH),
+        expires_at,
+    };
+    let created =
+        BwAccountSetting::create_bw_account_setting(state.get_db(), &item)
+            .await?;
+    state
+        .get_cacher()
+        .invalidate(&cache_key(&created.key))
+        .await?;
+    Ok(created)
+}
+
+/// Gives the account a key on its first login so devices can be enrolled
+/// without visiting the key settings first. `user` is the account as it
+/// was before the login was recorded; later logins leave its keys alone,
+/// even if they were all revoked since.
+pub async fn ensure_default(
+    state: &AppState,
+    user: &BwAccount,
+) -> AppResult<()> {
+    if user.last_login.is_some() {
+        return Ok(());
+    }
+    let item = CreateBwAccountSettingSchema {
+        uid: user.uid,
+        name: DEFAULT_KEY_NAME.to_string(),
+        key: crypto::random_words(KEY_LENGTH),
+        expires_at: None,
+    };
+    if let Some(created) =
+        BwAccountSetting::create_first_key(state.get_db(), &item).await?
+    {
+        state
+            .get_cacher()
+            .invalidate(&cache_key(&created.key))
+            .await?;
+    }
+    Ok(())
+}
+
+pub async fn revoke(
+    state: &AppState,
+    item: &RevokeBwAccountSettingSchema,
+) -> AppResult<BwAccountSetting> {
+    let revoked = BwAccountSetting::revoke_key(state.get_db(), item)
+        .await?
+        .ok_or(ApiError(ApiInnerError::EnrollmentKeyNotFound))?;
+    state
+        .get_cacher()
+        .invalidate(&cache_key(&revoked.key))
+        .await?;
+    Ok(revoked)
+}
+
+/// Revokes a key and issues a new one with the same name and expiry.
+pub async fn rotate(
+    state: &AppState,
+    item: &RevokeBwAccountSettingSchema,
+) -> AppResult<BwAccountSetting> {
+    let (old, new) = BwAccountSetting::rotate_key(
+        state.get_db(),
+        item,
+        &crypto::random_words(KEY_LENGTH),
+    )
+    .await?
+    .ok_or(ApiError(ApiInnerError::EnrollmentKeyNotFound))?;
+    state.get_cacher().invalidate(&cache_key(&old.key)).await?;
+    state.get_cacher().invalidate(&cache_key(&new.key)).await?;
+    Ok(new)
+}
+
+/// Resolves the account owning `key`, or `None` if the key is unknown,
+/// revoked or expired. Records the use of a usable key, cached or not.
+pub async fn resolve(state: &AppState, key: &str) -> AppResult<Option<i64>> {
+    let db = state.get_db();
+    let owner = state
+        .get_cacher()
+        .entry(&cache_key(key))
+        .ttl(cfg::config().miner.cache.enrollment_key_ttl)
+        .get_or_load(|| async {
+            Ok::<_, AppError>(
+                BwAccountSetting::fetch_uid_by_key(db, key).await?,
+            )
+        })
+        .await?;
+
+    // The key may have expired since it was cached.
+    let now = Utc::now().naive_utc();
+    let uid = owner
+        .filter(|(_, expires_at)| expires_at.is_none_or(|at| at > now))
+        .map(|(uid, _)| uid);
+    if uid.is_some() {
+        BwAccountSetting::update_last_used_at(db, key).await?;
+    }
+    Ok(uid)
+}
@@ -1,5 +1,7 @@
-use std::{sync::Arc, time::Duration};
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
 
+use chrono::{DateTime, NaiveDate, Utc};
+use rust_decimal::Decimal;
 use serde_derive::{Deserialize, Serialize};
 use tokio::time::interval;
 
@@ -7,55 +9,93 @@ use super::Service;
 use crate::{
     library::{
         cfg,
-        error::{AppError, AppResult},
+        error::{ApiInnerError, AppError, AppResult},
+        metricer,
     },
-    miner::bootstrap::AppState,
+    miner::{
+        bootstrap::{constants::REDIS_EXCHANGE_RATE_KEY, AppState},
+        service::provider::{MarketData, ProviderChain},
+    },
+    models::exchange_rate::{BwExchangeRate, CreateBwExchangeRateSchema},
 };
 
 #[derive(Clone)]
 pub struct Server {
-    exchange_rate: Arc<ExchangeRate>,
+    providers: Arc<ProviderChain<RateTable>>,
+    duration: Duration,
 }
 
 impl Service for Server {
     async fn init() -> Server {
         let cfg = cfg::config();
-        let exchange_rate_host = &cfg.miner.exchange_rate.host;
-        let exchange_rate_key = &cfg.miner.exchange_rate.key;
-        let exchange_duration =
-            Duration::from_secs(cfg.miner.exchange_rate.frequency);
-        let exchange_rate = ExchangeRate::new(
-            exchange_rate_host,
-            exchange_rate_key,
-            exchange_duration,
+        let providers = ProviderChain::<RateTable>::from_config(
+            &cfg.miner.exchange_rate.providers,
+            cfg.miner.env == "dev",
         );
+        tracing::info!("Exchange rate providers: {:?}", providers.names());
         Server {
-            exchange_rate: Arc::new(exchange_rate),
+            providers: Arc::new(providers),
+            duration: Duration::from_secs(cfg.miner.exchange_rate.frequency),
         }
     }
     async fn serve(&mut self, app_state: Arc<AppState>) {
-        let exchange_rate = self.exchange_rate.clone();
+        let providers = self.providers.clone();
+        let duration = self.duration;
 
         tokio::spawn(async move {
-            let mut redis = app_state.get_redis().await.unwrap();
-            let mut interval = interval(exchange_rate.duration);
+            let storage = app_state.get_storage();
+            let mut interval = interval(duration);
 
             loop {
                 interval.tick().await;
 
-                match exchange_rate.get_rate().await {
+                let fetched = providers.fetch().await;
+                metricer::metrics()
+                    .market_fetches
+                    .with_label_values(&[
+                        "exchange_rate",
+                        metricer::result_label(&fetched),
+                    ])
+                    .inc();
+                match fetched {
                     Ok(res) => {
-                        redis
+                        storage
                             .set(
-                                "exchange_rate",
+                                REDIS_EXCHANGE_RATE_KEY,
                                 &serde_json::to_string(&res).unwrap(),
                             )
                             .await
                             .unwrap();
-                        tracing::trace!("Successfully fetched exchange rate")
+                        // Checked-in rates are no history of the market.
+                        if !res.is_fixture() {
+                            let item = CreateBwExchangeRateSchema {
+                                rate_date: res.data.date,
+                                base: &res.data.base,
+                                rates: &res.data.rates,
+                            };
+                            if let Err(e) = BwExchangeRate::upsert_rates(
+                                app_state.get_db(),
+                                &item,
+                            )
+                            .await
+                            {
+                                tracing::error!(
+                                    "Error storing exchange rate: {:?}",
+                                    e
+                                );
+                            }
+                        }
+                        tracing::trace!(
+                            "Successfully fetched exchange rate from {}",
+                            res.provider
+                        )
                     }
                     Err(e) => {
-                        tracing::error!("Error fetching exchange rate: {:?}", e)
+                        tracing::error!(
+                            "Error fetching exchange rate from every \
+                             provider: {:?}",
+                            e
+                        )
                     }
                 }
             }
@@ -65,416 +105,216 @@ impl Service for Server {
     async fn shutdown(&self) {}
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
-pub struct ExchangeRate {
-    pub host: String,
-    pub key: String,
-    pub duration: Duration,
+impl Server {
+    /// Returns the latest rate table, preferring the copy cached by the
+    /// background fetcher over the one persisted for today.
+    pub async fn current_rates(app_state: &AppState) -> AppResult<RateTable> {
+        Ok(Self::current_snapshot(app_state).await?.data)
+    }
+
+    /// Like [`Server::current_rates`], but also reports which provider the
+    /// rates came from, when they were fetched and whether they are stale.
+    pub async fn current_snapshot(
+        app_state: &AppState,
+    ) -> AppResult<MarketData<RateTable>> {
+        let max_age = cfg::config().miner.exchange_rate.max_age();
+        let storage = app_state.get_storage();
+        if let Some(cached) = storage.get(REDIS_EXCHANGE_RATE_KEY).await? {
+            match serde_json::from_str::<MarketData<RateTable>>(&cached) {
+                Ok(snapshot) => return Ok(snapshot.with_max_age(max_age)),
+                Err(e) => {
+                    tracing::error!("Error parsing cached exchange rate: {e}");
+                }
+            }
+        }
+        let table = Self::rates_on(app_state, Utc::now().date_naive()).await?;
+        Ok(MarketData {
+            provider: "database".to_string(),
+            fetched_at: table.updated_at,
+            stale: false,
+            data: table,
+        }
+        .with_max_age(max_age))
+    }
+
+    /// Returns the rate table that applied on `date`.
+    pub async fn rates_on(
+        app_state: &AppState,
+        date: NaiveDate,
+    ) -> AppResult<RateTable> {
+        let rows =
+            BwExchangeRate::fetch_rates_by_date(app_state.get_db(), date)
+                .await?;
+        RateTable::from_rows(rows)
+            .ok_or(AppError::ApiError(ApiInnerError::ExchangeRateNotFound))
+    }
+
+    pub async fn convert(
+        app_state: &AppState,
+        amount: Decimal,
+        from: &str,
+        to: &str,
+    ) -> AppResult<Decimal> {
+        Self::current_rates(app_state)
+            .await?
+            .convert(amount, from, to)
+    }
+
+    pub async fn convert_on(
+        app_state: &AppState,
+        amount: Decimal,
+        from: &str,
+        to: &str,
+        date: NaiveDate,
+    ) -> AppResult<Decimal> {
+        Self::rates_on(app_state, date)
+            .await?
+            .convert(amount, from, to)
+    }
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
 pub struct ApiResponseBody {
     pub result: String,
-    pub documentation: String,
-    #[serde(rename = "terms_of_use")]
-    pub terms_of_use: String,
-    #[serde(rename = "time_last_update_unix")]
     pub time_last_update_unix: i64,
-    #[serde(rename = "time_last_update_utc")]
-    pub time_last_update_utc: String,
-    #[serde(rename = "time_next_update_unix")]
-    pub time_next_update_unix: i64,
-    #[serde(rename = "time_next_update_utc")]
-    pub time_next_update_utc: String,
-    #[serde(rename = "base_code")]
     pub base_code: String,
-    #[serde(rename = "conversion_rates")]
-    pub conversion_rates: ConversionRates,
+    #[serde(alias = "rates")]
+    pub conversion_rates: BTreeMap<String, Decimal>,
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct ConversionRates {
-    #[serde(rename = "USD")]
-    pub usd: i64,
-    #[serde(rename = "AED")]
-    pub aed: f64,
-    #[serde(rename = "AFN")]
-    pub afn: f64,
-    #[serde(rename = "ALL")]
-    pub all: f64,
-    #[serde(rename = "AMD")]
-    pub amd: f64,
-    #[serde(rename = "ANG")]
-    pub ang: f64,
-    #[serde(rename = "AOA")]
-    pub aoa: f64,
-    #[serde(rename = "ARS")]
-    pub ars: f64,
-    #[serde(rename = "AUD")]
-    pub aud: f64,
-    #[serde(rename = "AWG")]
-    pub awg: f64,
-    #[serde(rename = "AZN")]
-    pub azn: f64,
-    #[serde(rename = "BAM")]
-    pub bam: f64,
-    #[serde(rename = "BBD")]
-    pub bbd: f64,
-    #[serde(rename = "BDT")]
-    pub bdt: f64,
-    #[serde(rename = "BGN")]
-    pub bgn: f64,
-    #[serde(rename = "BHD")]
-    pub bhd: f64,
-    #[serde(rename = "BIF")]
-    pub bif: f64,
-    #[serde(rename = "BMD")]
-    pub bmd: f64,
-    #[serde(rename = "BND")]
-    pub bnd: f64,
-    #[serde(rename = "BOB")]
-    pub bob: f64,
-    #[serde(rename = "BRL")]
-    pub brl: f64,
-    #[serde(rename = "BSD")]
-    pub bsd: f64,
-    #[serde(rename = "BTN")]
-    pub btn: f64,
-    #[serde(rename = "BWP")]
-    pub bwp: f64,
-    #[serde(rename = "BYN")]
-    pub byn: f64,
-    #[serde(rename = "BZD")]
-    pub bzd: f64,
-    #[serde(rename = "CAD")]
-    pub cad: f64,
-    #[serde(rename = "CDF")]
-    pub cdf: f64,
-    #[serde(rename = "CHF")]
-    pub chf: f64,
-    #[serde(rename = "CLP")]
-    pub clp: f64,
-    #[serde(rename = "CNY")]
-    pub cny: f64,
-    #[serde(rename = "COP")]
-    pub cop: f64,
-    #[serde(rename = "CRC")]
-    pub crc: f64,
-    #[serde(rename = "CUP")]
-    pub cup: f64,
-    #[serde(rename = "CVE")]
-    pub cve: f64,
-    #[serde(rename = "CZK")]
-    pub czk: f64,
-    #[serde(rename = "DJF")]
-    pub djf: f64,
-    #[serde(rename = "DKK")]
-    pub dkk: f64,
-    #[serde(rename = "DOP")]
-    pub dop: f64,
-    #[serde(rename = "DZD")]
-    pub dzd: f64,
-    #[serde(rename = "EGP")]
-    pub egp: f64,
-    #[serde(rename = "ERN")]
-    pub ern: f64,
-    #[serde(rename = "ETB")]
-    pub etb: f64,
-    #[serde(rename = "EUR")]
-    pub eur: f64,
-    #[serde(rename = "FJD")]
-    pub fjd: f64,
-    #[serde(rename = "FKP")]
-    pub fkp: f64,
-    #[serde(rename = "FOK")]
-    pub fok: f64,
-    #[serde(rename = "GBP")]
-    pub gbp: f64,
-    #[serde(rename = "GEL")]
-    pub gel: f64,
-    #[serde(rename = "GGP")]
-    pub ggp: f64,
-    #[serde(rename = "GHS")]
-    pub ghs: f64,
-    #[serde(rename = "GIP")]
-    pub gip: f64,
-    #[serde(rename = "GMD")]
-    pub gmd: f64,
-    #[serde(rename = "GNF")]
-    pub gnf: f64,
-    #[serde(rename = "GTQ")]
-    pub gtq: f64,
-    #[serde(rename = "GYD")]
-    pub gyd: f64,
-    #[serde(rename = "HKD")]
-    pub hkd: f64,
-    #[serde(rename = "HNL")]
-    pub hnl: f64,
-    #[serde(rename = "HRK")]
-    pub hrk: f64,
-    #[serde(rename = "HTG")]
-    pub htg: f64,
-    #[serde(rename = "HUF")]
-    pub huf: f64,
-    #[serde(rename = "IDR")]
-    pub idr: f64,
-    #[serde(rename = "ILS")]
-    pub ils: f64,
-    #[serde(rename = "IMP")]
-    pub imp: f64,
-    #[serde(rename = "INR")]
-    pub inr: f64,
-    #[serde(rename = "IQD")]
-    pub iqd: f64,
-    #[serde(rename = "IRR")]
-    pub irr: f64,
-    #[serde(rename = "ISK")]
-    pub isk: f64,
-    #[serde(rename = "JEP")]
-    pub jep: f64,
-    #[serde(rename = "JMD")]
-    pub jmd: f64,
-    #[serde(rename = "JOD")]
-    pub jod: f64,
-    #[serde(rename = "JPY")]
-    pub jpy: f64,
-    #[serde(rename = "KES")]
-    pub kes: f64,
-    #[serde(rename = "KGS")]
-    pub kgs: f64,
-    #[serde(rename = "KHR")]
-    pub khr: f64,
-    #[serde(rename = "KID")]
-    pub kid: f64,
-    #[serde(rename = "KMF")]
-    pub kmf: f64,
-    #[serde(rename = "KRW")]
-    pub krw: f64,
-    #[serde(rename = "KWD")]
-    pub kwd: f64,
-    #[serde(rename = "KYD")]
-    pub kyd: f64,
-    #[serde(rename = "KZT")]
-    pub kzt: f64,
-    #[serde(rename = "LAK")]
-    pub lak: f64,
-    #[serde(rename = "LBP")]
-    pub lbp: f64,
-    #[serde(rename = "LKR")]
-    pub lkr: f64,
-    #[serde(rename = "LRD")]
-    pub lrd: f64,
-    #[serde(rename = "LSL")]
-    pub lsl: f64,
-    #[serde(rename = "LYD")]
-    pub lyd: f64,
-    #[serde(rename = "MAD")]
-    pub mad: f64,
-    #[serde(rename = "MDL")]
-    pub mdl: f64,
-    #[serde(rename = "MGA")]
-    pub mga: f64,
-    #[serde(rename = "MKD")]
-    pub mkd: f64,
-    #[serde(rename = "MMK")]
-    pub mmk: f64,
-    #[serde(rename = "MNT")]
-    pub mnt: f64,
-    #[serde(rename = "MOP")]
-    pub mop: f64,
-    #[serde(rename = "MRU")]
-    pub mru: f64,
-    #[serde(rename = "MUR")]
-    pub mur: f64,
-    #[serde(rename = "MVR")]
-    pub mvr: f64,
-    #[serde(rename = "MWK")]
-    pub mwk: f64,
-    #[serde(rename = "MXN")]
-    pub mxn: f64,
-    #[serde(rename = "MYR")]
-    pub myr: f64,
-    #[serde(rename = "MZN")]
-    pub mzn: f64,
-    #[serde(rename = "NAD")]
-    pub nad: f64,
-    #[serde(rename = "NGN")]
-    pub ngn: f64,
-    #[serde(rename = "NIO")]
-    pub nio: f64,
-    #[serde(rename = "NOK")]
-    pub nok: f64,
-    #[serde(rename = "NPR")]
-    pub npr: f64,
-    #[serde(rename = "NZD")]
-    pub nzd: f64,
-    #[serde(rename = "OMR")]
-    pub omr: f64,
-    #[serde(rename = "PAB")]
-    pub pab: f64,
-    #[serde(rename = "PEN")]
-    pub pen: f64,
-    #[serde(rename = "PGK")]
-    pub pgk: f64,
-    #[serde(rename = "PHP")]
-    pub php: f64,
-    #[serde(rename = "PKR")]
-    pub pkr: f64,
-    #[serde(rename = "PLN")]
-    pub pln: f64,
-    #[serde(rename = "PYG")]
-    pub pyg: f64,
-    #[serde(rename = "QAR")]
-    pub qar: f64,
-    #[serde(rename = "RON")]
-    pub ron: f64,
-    #[serde(rename = "RSD")]
-    pub rsd: f64,
-    #[serde(rename = "RUB")]
-    pub rub: f64,
-    #[serde(rename = "RWF")]
-    pub rwf: f64,
-    #[serde(rename = "SAR")]
-    pub sar: f64,
-    #[serde(rename = "SBD")]
-    pub sbd: f64,
-    #[serde(rename = "SCR")]
-    pub scr: f64,
-    #[serde(rename = "SDG")]
-    pub sdg: f64,
-    #[serde(rename = "SEK")]
-    pub sek: f64,
-    #[serde(rename = "SGD")]
-    pub sgd: f64,
-    #[serde(rename = "SHP")]
-    pub shp: f64,
-    #[serde(rename = "SLE")]
-    pub sle: f64,
-    #[serde(rename = "SLL")]
-    pub sll: f64,
-    #[serde(rename = "SOS")]
-    pub sos: f64,
-    #[serde(rename = "SRD")]
-    pub srd: f64,
-    #[serde(rename = "SSP")]
-    pub ssp: f64,
-    #[serde(rename = "STN")]
-    pub stn: f64,
-    #[serde(rename = "SYP")]
-    pub syp: f64,
-    #[serde(rename = "SZL")]
-    pub szl: f64,
-    #[serde(rename = "THB")]
-    pub thb: f64,
-    #[serde(rename = "TJS")]
-    pub tjs: f64,
-    #[serde(rename = "TMT")]
-    pub tmt: f64,
-    #[serde(rename = "TND")]
-    pub tnd: f64,
-    #[serde(rename = "TOP")]
-    pub top: f64,
-    #[serde(rename = "TRY")]
-    pub try_field: f64,
-    #[serde(rename = "TTD")]
-    pub ttd: f64,
-    #[serde(rename = "TVD")]
-    pub tvd: f64,
-    #[serde(rename = "TWD")]
-    pub twd: f64,
-    #[serde(rename = "TZS")]
-    pub tzs: f64,
-    #[serde(rename = "UAH")]
-    pub uah: f64,
-    #[serde(rename = "UGX")]
-    pub ugx: f64,
-    #[serde(rename = "UYU")]
-    pub uyu: f64,
-    #[serde(rename = "UZS")]
-    pub uzs: f64,
-    #[serde(rename = "VES")]
-    pub ves: f64,
-    #[serde(rename = "VND")]
-    pub vnd: f64,
-    #[serde(rename = "VUV")]
-    pub vuv: f64,
-    #[serde(rename = "WST")]
-    pub wst: f64,
-    #[serde(rename = "XAF")]
-    pub xaf: f64,
-    #[serde(rename = "XCD")]
-    pub xcd: f64,
-    #[serde(rename = "XDR")]
-    pub xdr: f64,
-    #[serde(rename = "XOF")]
-    pub xof: f64,
-    #[serde(rename = "XPF")]
-    pub xpf: f64,
-    #[serde(rename = "YER")]
-    pub yer: f64,
-    #[serde(rename = "ZAR")]
-    pub zar: f64,
-    #[serde(rename = "ZMW")]
-    pub zmw: f64,
-    #[serde(rename = "ZWL")]
-    pub zwl: f64,
+/// Rates of every known currency against `base`, keyed by ISO 4217 symbol.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RateTable {
+    pub base: String,
+    pub date: NaiveDate,
+    pub updated_at: i64,
+    pub rates: BTreeMap<String, Decimal>,
 }
 
-impl ExchangeRate {
-    pub fn new<'a>(
-        host: &'a str,
-        key: &'a str,
-        duration: Duration,
-    ) -> ExchangeRate {
-        ExchangeRate {
-            host: host.to_string(),
-            key: key.to_string(),
-            duration,
-        }
+impl RateTable {
+    pub fn from_rows(rows: Vec<BwExchangeRate>) -> Option<Self> {
+        let first = rows.first()?;
+        let (base, date, updated_at) = (
+            first.base.clone(),
+            first.rate_date,
+            first
+                .updated_at
+                .unwrap_or(first.created_at)
+                .and_utc()
+                .timestamp(),
+        );
+        let rates = rows.into_iter().map(|r| (r.symbol, r.rate)).collect();
+        Some(Self {
+            base,
+            date,
+            updated_at,
+            rates,
+        })
     }
 
-    pub async fn get_rate(&self) -> AppResult<ConversionRates> {
-        let url = format!("{}/{}/{}", self.host, self.key, "latest/USD");
-        let client = reqwest::Client::new();
-        let response = client.get(url).send().await.map_err(|e| {
-            let es =
-                format!("Error occurred while getting exchange rate : {}", e);
-            tracing::error!(es);
-            anyhow::anyhow!(es)
-        })?;
+    pub fn rate(&self, symbol: &str) -> Option<Decimal> {
+        self.rates.get(&symbol.to_uppercase()).copied()
+    }
 
-        if !response.status().is_success() {
-            let es = format!(
-                "Error occurred while getting exchange rate : {}",
-                response.status()
-            );
-            tracing::error!(es);
-            return Err(AppError::Anyhow(anyhow::anyhow!(es)));
+    pub fn convert(
+        &self,
+        amount: Decimal,
+        from: &str,
+        to: &str,
+    ) -> AppResult<Decimal> {
+        let unsupported =
+            || AppError::ApiError(ApiInnerError::UnsupportedCurrency);
+        let from_rate = self.rate(from).ok_or_else(unsupported)?;
+        let to_rate = self.rate(to).ok_or_else(unsupported)?;
+        amount
+            .checked_mul(to_rate)
+            .and_then(|v| v.checked_div(from_rate))
+            .map(|v| v.normalize())
+            .ok_or_else(unsupported)
+    }
+}
+
+impl From<ApiResponseBody> for RateTable {
+    fn from(body: ApiResponseBody) -> Self {
+        let date = DateTime::from_timestamp(body.time_last_update_unix, 0)
+            .map_or_else(|| Utc::now().date_naive(), |t| t.date_naive());
+        Self {
+            base: body.base_code,
+            date,
+            updated_at: body.time_last_update_unix,
+            rates: body.conversion_rates,
         }
-        let body: ApiResponseBody = response.json().await.map_err(|e| {
-            let es =
-                format!("Error occurred while getting exchange rate : {}", e);
-            tracing::error!(es);
-            anyhow::anyhow!(es)
-        })?;
-        Ok(body.conversion_rates)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::str::FromStr;
+
     use super::*;
 
-    #[tokio::test]
-    async fn get_rate_works() {
-        let host = "https://v6.exchangerate-api.com/v6";
-        let key = "83b2f3250fcbb02d93d4e3bf";
-        let _rate = ExchangeRate::new(host, key, Duration::from_secs(60 * 60))
-            .get_rate()
-            .await
-            .unwrap();
-        // eprintln!("{:#?}",rate);
+    fn rate_table() -> RateTable {
+        let body: ApiResponseBody = serde_json::from_str(
+            r#"{
+                "result": "success",
+                "time_last_update_unix": 1718841601,
+                "base_code": "USD",
+                "conversion_rates": {
+                    "USD": 1,
+                    "EUR": 0.9306,
+                    "KZT": 462.1234,
+                    "BRL": 5.4321
+                }
+            }"#,
+        )
+        .unwrap();
+        body.into()
+    }
+
+    #[test]
+    fn deserialize_unknown_currency_works() {
+        let table = rate_table();
+        assert_eq!(table.date, NaiveDate::from_ymd_opt(2024, 6, 20).unwrap());
+        assert_eq!(table.rate("kzt"), Decimal::from_str("462.1234").ok());
+        assert_eq!(table.rate("USD"), Some(Decimal::ONE));
+    }
+
+    #[test]
+    fn convert_works() {
+        let table = rate_table();
+        let amount = Decimal::from(100);
+        assert_eq!(
+            table.convert(amount, "USD", "EUR").unwrap(),
+            Decimal::from_str("93.06").unwrap()
+        );
+        assert_eq!(
+            table
+                .convert(Decimal::from_str("93.06").unwrap(), "EUR", "USD")
+                .unwrap(),
+            amount
+        );
+        assert_eq!(table.convert(amount, "BRL", "BRL").unwrap(), amount);
+    }
+
+    #[test]
+    fn convert_unsupported_currency_fails() {
+        let table = rate_table();
+        assert!(table.convert(Decimal::ONE, "USD", "XXX").is_err());
+        assert!(table.convert(Decimal::ONE, "XXX", "USD").is_err());
+    }
+
+    #[test]
+    fn deserialize_open_er_api_works() {
+        let body: ApiResponseBody = serde_json::from_str(
+            r#"{
+                "result": "success",
+                "time_last_update_unix": 1718841601,
+                "base_code": "USD",
+                "rates": { "USD": 1, "EUR": 0.9306 }
+            }"#,
+        )
+        .unwrap();
+        let table: RateTable = body.into();
+        assert_eq!(table.rate("EUR"), Decimal::from_str("0.9306").ok());
     }
 }
@@ -0,0 +1,393 @@
+//! Fleet events pushed to the web UI as they happen.
+//!
+//! A device message reaches one instance only, over a shared MQTT
+//! subscription. That instance publishes it to [`MQ_FLEET_EVENT_EXCHANGE`],
+//! routed by `{uid}.{kind}`, and every instance forwards what it receives
+//! there to the streams its clients hold open. A stream is opened for one
+//! fleet and never sees events of machines owned by anyone else.
+//!
+//! A stream lasts no longer than the token it was opened with and ends as
+//! soon as a periodic check finds the session revoked or the access to the
+//! fleet changed, as well as when the server shuts down.
+
+use std::{collections::HashSet, sync::Arc, time::Duration};
+
+use chrono::Utc;
+use futures_util::{stream, Stream};
+use tokio::{
+    sync::broadcast::{self, error::RecvError, Receiver},
+    time::{self, Instant, Interval},
+};
+
+use super::{
+    jwt_service::Claims,
+    machine_service,
+    org_service::{self, FleetAccess},
+    Service,
+};
+use crate::{
+    library::{
+        bus::{Disposition, Envelope, Subscriber},
+        error::{ApiInnerError, AppError::ApiError, AppResult},
+    },
+    miner::{
+        bootstrap::{
+            self,
+            constants::{MQ_FLEET_EVENT_EXCHANGE, MQ_FLEET_EVENT_TAG},
+            AppState,
+        },
+        entity::fleet_event::{FleetEvent, FleetEventKind, FleetEventQuery},
+    },
+};
+
+/// Events buffered for a stream that is slow to read them.
+const STREAM_CAPACITY: usize = 1024;
+
+/// How often a stream checks its session and access still hold.
+const RECHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Clone)]
+pub struct Server {
+    pub hub: broadcast::Sender<Arc<FleetEvent>>,
+}
+
+impl Service for Server {
+    async fn init() -> Self {
+        let (hub, _) = broadcast::channel(STREAM_CAPACITY);
+        Self { hub }
+    }
+
+    async fn serve(&mut self, app_state: Arc<AppState>) {
+        let hub = self.hub.clone();
+        let subscriber =
+            Subscriber::typed(move |envelope: Envelope<FleetEvent>| {
+                // Nobody listening is fine, the event is just not needed.
+                let _ = hub.send(Arc::new(envelope.payload));
+                async { Disposition::Ack }
+            });
+        let subscribed = match app_state.get_mq() {
+            Ok(bus) => {
+                bus.topic_subscribe(
+                    MQ_FLEET_EVENT_EXCHANGE,
+                    &["#"],
+                    MQ_FLEET_EVENT_TAG,
+                    subscriber,
+                )
+                .await
+            }
+            Err(e) => Err(anyhow::anyhow!("{e}").into()),
+        };
+        if let Err(e) = subscribed {
+            tracing::error!(
+                "Error occurred while subscribing to fleet events: {}",
+                e
+            );
+        }
+    }
+
+    async fn shutdown(&self) {}
+}
+
+/// What a stream delivers.
+#[derive(Debug, Clone)]
+pub enum Delivery {
+    Event(Arc<FleetEvent>),
+    /// Events dropped because the stream fell behind, so the client knows
+    /// to reload.
+    Missed(u64),
+}
+
+/// Which events of a fleet a stream receives.
+#[derive(Debug, Clone)]
+pub struct FleetFilter {
+    access: FleetAccess,
+    group_id: Option<i64>,
+    macs: Option<HashSet<String>>,
+    kinds: Option<HashSet<FleetEventKind>>,
+}
+
+impl FleetFilter {
+    pub fn new(
+        access: FleetAccess,
+        query: &FleetEventQuery,
+    ) -> AppResult<Self> {
+        if let Some(group_id) = query.group_id {
+            access.ensure_group(group_id)?;
+        }
+        let macs = query.macs.as_deref().map(|macs| {
+            split(macs).map(machine_service::normalize_mac).collect()
+        });
+        let kinds = query
+            .kinds
+            .as_deref()
+            .map(|kinds| split(kinds).map(str::parse).collect())
+            .transpose()
+            .map_err(|_| ApiError(ApiInnerError::InvalidFleetEventKind))?;
+        Ok(Self {
+            access,
+            group_id: query.group_id,
+            macs,
+            kinds,
+        })
+    }
+
+    pub fn matches(&self, event: &FleetEvent) -> bool {
+        event.uid == self.access.uid
+            && (!self.access.is_scoped()
+                || event
+                    .group_id
+                    .is_some_and(|id| self.access.can_access_group(id)))
+            && self.group_id.is_none_or(|id| event.group_id == Some(id))
+            && self
+                .macs
+                .as_ref()
+                .is_none_or(|macs| macs.contains(&event.mac))
+            && self
+                .kinds
+                .as_ref()
+                .is_none_or(|kinds| kinds.contains(&event.kind))
+    }
+}
+
+fn split(list: &str) -> impl Iterator<Item = &str> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+}
+
+/// Publishes what the machine `mac` sent as an event of its fleet.
+/// Messages of machines nobody owns are dropped.
+pub async fn publish(
+    state: &AppState,
+    mac: &str,
+    kind: FleetEventKind,
+    payload: serde_json::Value,
+) -> AppResult<()> {
+    let Some((uid, group_id)) = machine_service::owner(state, mac).await?
+    else {
+        tracing::debug!(
+            "Dropping {} event of unknown machine {}",
+            kind.as_str(),
+            mac
+        );
+        return Ok(());
+    };
+    let event = FleetEvent {
+        kind,
+        uid,
+        group_id,
+        mac: machine_service::normalize_mac(mac),
+        payload,
+        timestamp: Utc::now(),
+    };
+    let routing_key = format!("{uid}.{}", kind.as_str());
+    state
+        .get_mq()?
+        .publish(MQ_FLEET_EVENT_EXCHANGE, &routing_key, &Envelope::new(event))
+        .await?;
+    Ok(())
+}
+
+/// When a token expiring at `exp` runs out, `None` if that is too far
+/// ahead to tell, as for API tokens without expiry.
+fn deadline(exp: usize) -> Option<Instant> {
+    let left = exp.saturating_sub(Utc::now().timestamp().max(0) as usize);
+    Instant::now().checked_add(Duration::from_secs(left as u64))
+}
+
+struct Subscription {
+    state: Arc<AppState>,
+    claims: Claims,
+    filter: FleetFilter,
+    rx: Receiver<Arc<FleetEvent>>,
+    deadline: Option<Instant>,
+    recheck: Interval,
+}
+
+impl Subscription {
+    /// Whether the session of the stream is still active and its access to
+    /// the fleet unchanged.
+    async fn still_allowed(&self) -> bool {
+        let checked = async {
+            self.claims.ensure_session(&self.state).await?;
+            org_service::resolve(
+                &self.state,
+                self.claims.uid,
+                self.filter.access.org_id,
+            )
+            .await
+        };
+        match checked.await {
+            Ok(access) => access == self.filter.access,
+            Err(e) => {
+                tracing::debug!(
+                    "Ending fleet event stream of {}: {}",
+                    self.claims.uid,
+                    e
+                );
+                false
+            }
+        }
+    }
+
+    async fn next(&mut self) -> Option<Delivery> {
+        let deadline = self.deadline;
+        let expired = async move {
+            match deadline {
+                Some(deadline) => time::sleep_until(deadline).await,
+                None => std::future::pending().await,
+            }
+        };
+        tokio::pin!(expired);
+        loop {
+            tokio::select! {
+                received = self.rx.recv() => match received {
+                    Ok(event) if self.filter.matches(&event) => {
+                        return Some(Delivery::Event(event));
+                    }
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(missed)) => {
+                        return Some(Delivery::Missed(missed));
+                    }
+                    Err(RecvError::Closed) => return None,
+                },
+                _ = self.recheck.tick() => {
+                    if !self.still_allowed().await {
+                        return None;
+                    }
+                }
+                () = &mut expired => return None,
+                () = bootstrap::shutting_down() => return None,
+            }
+        }
+    }
+}
+
+/// Events matching `filter` from now on, for as long as the stream is held
+/// and `claims` allow it.
+pub fn subscribe(
+    state: Arc<AppState>,
+    claims: Claims,
+    filter: FleetFilter,
+) -> impl Stream<Item = Delivery> {
+    let subscription = Subscription {
+        rx: state.services.fleet_event.hub.subscribe(),
+        deadline: deadline(claims.exp),
+        recheck: time::interval_at(
+            Instant::now() + RECHECK_INTERVAL,
+            RECHECK_INTERVAL,
+        ),
+        state,
+        claims,
+        filter,
+    };
+    stream::unfold(subscription, |mut subscription| async move {
+        let delivery = subscription.next().await?;
+        Some((delivery, subscription))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(
+        uid: i64,
+        group_id: Option<i64>,
+        kind: FleetEventKind,
+    ) -> FleetEvent {
+        FleetEvent {
+            kind,
+            uid,
+            group_id,
+            mac: "28:e2:97:3e:6f:06".to_string(),
+            payload: serde_json::json!({}),
+            timestamp: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_deadline() {
+        let now = Utc::now().timestamp() as usize;
+        let left = deadline(now + 60).unwrap() - Instant::now();
+        assert!((59..=60).contains(&left.as_secs()));
+        assert!(deadline(now - 60).unwrap() <= Instant::now());
+        assert!(deadline(usize::MAX).is_none());
+    }
+
+    #[test]
+    fn test_filter_isolates_accounts() {
+        let filter = FleetFilter::new(
+            FleetAccess::personal(1),
+            &FleetEventQuery::default(),
+        )
+        .unwrap();
+        assert!(filter.matches(&event(1, None, FleetEventKind::Status)));
+        assert!(!filter.matches(&event(2, None, FleetEventKind::Status)));
+    }
+
+    #[test]
+    fn test_filter_respects_group_scope() {
+        let access = FleetAccess {
+            org_id: Some(7),
+            group_ids: Some(vec![10]),
+            ..FleetAccess::personal(1)
+        };
+        let filter =
+            FleetFilter::new(access.clone(), &FleetEventQuery::default())
+                .unwrap();
+        assert!(filter.matches(&event(1, Some(10), FleetEventKind::Alert)));
+        assert!(!filter.matches(&event(1, Some(11), FleetEventKind::Alert)));
+        assert!(!filter.matches(&event(1, None, FleetEventKind::Alert)));
+
+        let query = FleetEventQuery {
+            group_id: Some(11),
+            ..Default::default()
+        };
+        assert!(FleetFilter::new(access, &query).is_err());
+    }
+
+    #[test]
+    fn test_filter_by_macs_and_kinds() {
+        let query = FleetEventQuery {
+            macs: Some("28-E2-97-3E-6F-06, 28:e2:97:3e:6f:07".to_string()),
+            kinds: Some("presence,command_ack".to_string()),
+            ..Default::default()
+        };
+        let filter =
+            FleetFilter::new(FleetAccess::personal(1), &query).unwrap();
+        assert!(filter.matches(&event(1, None, FleetEventKind::Presence)));
+        assert!(!filter.matches(&event(1, None, FleetEventKind::Status)));
+        let mut other = event(1, None, FleetEventKind::Presence);
+        other.mac = "28:e2:97:3e:6f:08".to_string();
+        assert!(!filter.matches(&other));
+
+        let query = FleetEventQuery {
+            kinds: Some("reboot".to_string()),
+            ..Default::default()
+        };
+        assert!(FleetFilter::new(FleetAccess::personal(1), &query).is_err());
+    }
+
+    #[test]
+    fn test_kind_from_topic() {
+        let kind = |topic| FleetEventKind::from_topic(topic);
+        assert_eq!(
+            kind("$share/routine//client/28:e2:97:3e:6f:06/heartbeat"),
+            Some(FleetEventKind::Presence)
+        );
+        assert_eq!(
+            kind("/client/28:e2:97:3e:6f:06/work/status/upload"),
+            Some(FleetEventKind::Status)
+        );
+        assert_eq!(
+            kind("/client/28:e2:97:3e:6f:06/command/ack"),
+            Some(FleetEventKind::CommandAck)
+        );
+        assert_eq!(
+            kind("/client/28:e2:97:3e:6f:06/alert"),
+            Some(FleetEventKind::Alert)
+        );
+        assert_eq!(kind("$SYS/brokers/emqx/clients/x/connected"), None);
+    }
+}
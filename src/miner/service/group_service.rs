@@ -0,0 +1,53 @@
+//! Groups of an account, read on every request scoped to some of them.
+//! Lookups are cached tagged `groups:{uid}`, so changing any group of an
+//! account has to [`invalidate`] all of them.
+
+use crate::{
+    library::error::AppResult,
+    miner::bootstrap::{constants::REDIS_GROUP_KEY, AppState},
+    models::group::{BwGroup, ReadBwGroupSchema},
+};
+
+fn tag(uid: i64) -> String {
+    format!("{REDIS_GROUP_KEY}:{uid}")
+}
+
+pub async fn fetch_by_uid(
+    state: &AppState,
+    uid: i64,
+) -> AppResult<Vec<BwGroup>> {
+    let db = state.get_db();
+    let groups = state
+        .get_cacher()
+        .entry(&tag(uid))
+        .tag(&tag(uid))
+        .get_or_load(|| async {
+            BwGroup::fetch_group_by_uid(db, uid).await.map(Some)
+        })
+        .await?;
+    Ok(groups.unwrap_or_default())
+}
+
+pub async fn fetch_by_ids(
+    state: &AppState,
+    item: &ReadBwGroupSchema,
+) -> AppResult<Vec<BwGroup>> {
+    let mut ids = item.group_ids.clone();
+    ids.sort_unstable();
+    ids.dedup();
+    let ids = ids.iter().map(i64::to_string).collect::<Vec<_>>().join(",");
+    let db = state.get_db();
+    let groups = state
+        .get_cacher()
+        .entry(&format!("{}:{ids}", tag(item.uid)))
+        .tag(&tag(item.uid))
+        .get_or_load(|| async {
+            BwGroup::fetch_group_info_by_ids(db, item).await.map(Some)
+        })
+        .await?;
+    Ok(groups.unwrap_or_default())
+}
+
+pub async fn invalidate(state: &AppState, uid: i64) -> AppResult<()> {
+    Ok(state.get_cacher().invalidate_tag(&tag(uid)).await?)
+}
@@ -0,0 +1,247 @@
+//! Readiness of the server and of what it depends on.
+//!
+//! Postgres, the storage, the message bus and the MQTT session must all
+//! answer for the server to be ready, and it stops being ready as soon as
+//! a shutdown signal is received. The market data caches are reported
+//! with their age but never make it unready, stale data being served with
+//! a flag rather than refused. Why a dependency failed is logged, and only
+//! told to callers presenting the metrics token, see [`Readiness::redact`].
+
+use std::{future::Future, time::Duration};
+
+use serde::{de::IgnoredAny, Serialize};
+use tokio::time::{timeout, Instant};
+
+use crate::{
+    library::{cfg, error::InnerResult},
+    miner::{
+        bootstrap::{
+            constants::{REDIS_COIN_STAT_KEY, REDIS_EXCHANGE_RATE_KEY},
+            is_shutting_down, AppState,
+        },
+        service::provider::MarketData,
+    },
+};
+
+#[derive(Debug, Serialize)]
+pub struct Check {
+    pub ok: bool,
+    pub latency_ms: u128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Checks {
+    pub postgres: Check,
+    pub redis: Check,
+    pub rabbitmq: Check,
+    pub mqtt: Check,
+}
+
+impl Checks {
+    fn all_ok(&self) -> bool {
+        [&self.postgres, &self.redis, &self.rabbitmq, &self.mqtt]
+            .iter()
+            .all(|check| check.ok)
+    }
+}
+
+/// Age of a market data cache, `None` fields when nothing is cached.
+#[derive(Debug, Serialize)]
+pub struct Freshness {
+    pub provider: Option<String>,
+    pub age_secs: Option<i64>,
+    pub max_age_secs: u64,
+    pub stale: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Caches {
+    pub exchange_rate: Freshness,
+    pub coin_stat: Freshness,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    Ready,
+    NotReady,
+    Draining,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Readiness {
+    pub status: Status,
+    pub checks: Checks,
+    pub caches: Caches,
+}
+
+impl Readiness {
+    pub fn is_ready(&self) -> bool {
+        self.status == Status::Ready
+    }
+
+    /// Drops the errors of the checks, which may name internal hosts.
+    pub fn redact(&mut self) {
+        let checks = &mut self.checks;
+        for check in [
+            &mut checks.postgres,
+            &mut checks.redis,
+            &mut checks.rabbitmq,
+            &mut checks.mqtt,
+        ] {
+            check.error = None;
+        }
+    }
+}
+
+/// Runs `probe` of the dependency `name` within the configured timeout.
+async fn check<F>(name: &str, probe: F) -> Check
+where
+    F: Future<Output = InnerResult<()>>,
+{
+    let limit =
+        Duration::from_millis(cfg::config().miner.health.check_timeout_ms);
+    let start = Instant::now();
+    let error = match timeout(limit, probe).await {
+        Ok(Ok(())) => None,
+        Ok(Err(e)) => Some(e.to_string()),
+        Err(_) => Some(format!("Timed out after {}ms", limit.as_millis())),
+    };
+    if let Some(e) = &error {
+        tracing::warn!("Readiness check of {name} failed: {e}");
+    }
+    Check {
+        ok: error.is_none(),
+        latency_ms: start.elapsed().as_millis(),
+        error,
+    }
+}
+
+pub async fn freshness(state: &AppState, key: &str, max_age: u64) -> Freshness {
+    let cached = match state.get_storage().get(key).await {
+        Ok(cached) => cached,
+        Err(e) => {
+            tracing::warn!("Failed to read {key} for readiness: {e}");
+            None
+        }
+    };
+    Freshness::of(cached.as_deref(), max_age)
+}
+
+impl Freshness {
+    /// Freshness of a cached [`MarketData`], unreadable counting as absent.
+    fn of(cached: Option<&str>, max_age: u64) -> Self {
+        let snapshot = cached.and_then(|cached| {
+            serde_json::from_str::<MarketData<IgnoredAny>>(cached).ok()
+        });
+        let age = snapshot.as_ref().map(MarketData::age);
+        Self {
+            provider: snapshot.map(|snapshot| snapshot.provider),
+            age_secs: age,
+            max_age_secs: max_age,
+            stale: age.is_none_or(|age| age > max_age as i64),
+        }
+    }
+}
+
+pub async fn readiness(state: &AppState) -> Readiness {
+    let config = &cfg::config().miner;
+    let (postgres, redis, rabbitmq, mqtt) = tokio::join!(
+        check("postgres", async {
+            sqlx::query("SELECT 1").execute(state.get_db()).await?;
+            Ok(())
+        }),
+        check("redis", state.get_storage().ping()),
+        check("rabbitmq", state.services.message_queue.bus.ping()),
+        check("mqtt", async {
+            if state.services.mqtt.is_connected() {
+                Ok(())
+            } else {
+                Err(anyhow::anyhow!("No MQTT session").into())
+            }
+        }),
+    );
+    let (exchange_rate, coin_stat) = tokio::join!(
+        freshness(
+            state,
+            REDIS_EXCHANGE_RATE_KEY,
+            config.exchange_rate.max_age()
+        ),
+        freshness(state, REDIS_COIN_STAT_KEY, config.coin_stat.max_age()),
+    );
+    let checks = Checks {
+        postgres,
+        redis,
+        rabbitmq,
+        mqtt,
+    };
+    let status = if is_shutting_down() {
+        Status::Draining
+    } else if checks.all_ok() {
+        Status::Ready
+    } else {
+        Status::NotReady
+    };
+    Readiness {
+        status,
+        checks,
+        caches: Caches {
+            exchange_rate,
+            coin_stat,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+
+    #[test]
+    fn test_freshness() {
+        let fetched_at = Utc::now().timestamp() - 90;
+        let cached = format!(
+            r#"{{"provider":"fixture","fetched_at":{fetched_at},"data":[1]}}"#
+        );
+        let fresh = Freshness::of(Some(&cached), 120);
+        assert_eq!(fresh.provider.as_deref(), Some("fixture"));
+        assert!(fresh.age_secs.is_some_and(|age| age >= 90));
+        assert!(!fresh.stale);
+        assert!(Freshness::of(Some(&cached), 60).stale);
+
+        for cached in [None, Some("not json")] {
+            let missing = Freshness::of(cached, 60);
+            assert_eq!(missing.age_secs, None);
+            assert!(missing.stale);
+        }
+    }
+
+    #[test]
+    fn test_redact() {
+        let failed = || Check {
+            ok: false,
+            latency_ms: 1,
+            error: Some("db.internal:5432 refused".to_string()),
+        };
+        let mut readiness = Readiness {
+            status: Status::NotReady,
+            checks: Checks {
+                postgres: failed(),
+                redis: failed(),
+                rabbitmq: failed(),
+                mqtt: failed(),
+            },
+            caches: Caches {
+                exchange_rate: Freshness::of(None, 60),
+                coin_stat: Freshness::of(None, 60),
+            },
+        };
+        readiness.redact();
+        let json = serde_json::to_string(&readiness).unwrap();
+        assert!(!json.contains("error"));
+        assert!(json.contains(r#""ok":false"#));
+    }
+}
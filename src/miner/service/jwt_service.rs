@@ -12,23 +12,40 @@ use jsonwebtoken::{
     decode, encode, DecodingKey, EncodingKey, Header, Validation,
 };
 use serde::{Deserialize, Serialize};
+use ulid::Ulid;
+use utoipa::ToSchema;
 
 use crate::{
     library::{
         cfg,
         error::{AppError, AppError::AuthError, AppResult, AuthInnerError},
     },
-    miner::bootstrap::AppState,
-    models::{account::BwAccount, types::AccountStatus},
+    miner::{
+        bootstrap::AppState,
+        service::{
+            account_service,
+            session_service::{self, ClientInfo},
+        },
+    },
+    models::{account::BwAccount, api_token::BwApiToken, types::AccountStatus},
 };
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Claims {
     pub uid: i64,
     pub email: String,
     pub status: AccountStatus,
     pub iat: usize,
     pub exp: usize,
+    /// Session (token family) the token belongs to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<String>,
+    /// Unique id of a refresh token, rotated on every refresh.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jti: Option<String>,
+    /// Kind of a token that is neither an access nor a refresh token.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub typ: Option<TokenType>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -38,13 +55,13 @@ pub struct UserInfo {
     pub status: AccountStatus,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct TokenSchema {
     pub refresh_token: String,
     pub access_token: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct RefreshTokenRequest {
     pub refresh_token: String,
 }
@@ -52,6 +69,7 @@ pub struct RefreshTokenRequest {
 pub struct TokenSecretInfo<'a> {
     secret: &'a [u8],
     expiration: i64,
+    token_type: TokenType,
 }
 
 impl<'a> TokenSecretInfo<'a> {
@@ -59,12 +77,13 @@ impl<'a> TokenSecretInfo<'a> {
         Self {
             secret: Self::get_secret(token_type),
             expiration: Self::get_secret_expiration(token_type),
+            token_type,
         }
     }
 
     fn get_secret(token_type: TokenType) -> &'a [u8] {
         match token_type {
-            TokenType::ACCESS => {
+            TokenType::ACCESS | TokenType::MFA => {
                 cfg::config().miner.access_token.secret.as_ref()
             }
             TokenType::REFRESH => {
@@ -81,26 +100,55 @@ impl<'a> TokenSecretInfo<'a> {
             TokenType::REFRESH => {
                 cfg::config().miner.refresh_token.secret_expiration.into()
             }
+            TokenType::MFA => cfg::config().miner.mfa.token_expiration.into(),
+        }
+    }
+
+    /// The `typ` claim tokens of this kind carry.
+    const fn typ(&self) -> Option<TokenType> {
+        match self.token_type {
+            TokenType::MFA => Some(TokenType::MFA),
+            TokenType::ACCESS | TokenType::REFRESH => None,
         }
     }
 }
 
 static ACCESS_INFO: OnceLock<Arc<TokenSecretInfo<'static>>> = OnceLock::new();
 static REFRESH_INFO: OnceLock<Arc<TokenSecretInfo<'static>>> = OnceLock::new();
+static MFA_INFO: OnceLock<Arc<TokenSecretInfo<'static>>> = OnceLock::new();
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum TokenType {
     ACCESS,
     REFRESH,
+    /// Proves the password was right while the second factor is pending.
+    MFA,
+}
+
+/// Identifies the session a pair of tokens is issued for.
+#[derive(Debug, Clone, Copy)]
+pub struct SessionIds<'a> {
+    pub sid: &'a str,
+    pub jti: &'a str,
 }
 
 pub trait TokenAuth {
-    fn generate_token(&self, credential: &UserInfo) -> AppResult<String>;
+    fn generate_token(
+        &self,
+        credential: &UserInfo,
+        sid: &str,
+        jti: Option<&str>,
+    ) -> AppResult<String>;
     fn parse_token(&self, token: &str) -> AppResult<Claims>;
 }
 
 impl TokenAuth for TokenSecretInfo<'_> {
-    fn generate_token(&self, credential: &UserInfo) -> AppResult<String> {
+    fn generate_token(
+        &self,
+        credential: &UserInfo,
+        sid: &str,
+        jti: Option<&str>,
+    ) -> AppResult<String> {
         let now = chrono::Utc::now();
         let duration = self.expiration;
         let claims = Claims {
@@ -110,6 +158,9 @@ impl TokenAuth for TokenSecretInfo<'_> {
             exp: (now + chrono::Duration::seconds(duration)).timestamp()
                 as usize,
             iat: now.timestamp() as usize,
+            sid: Some(sid.to_string()),
+            jti: jti.map(str::to_string),
+            typ: self.typ(),
         };
 
         let token = encode(
@@ -130,6 +181,9 @@ impl TokenAuth for TokenSecretInfo<'_> {
         )
         .map_err(|_| AuthError(AuthInnerError::InvalidToken))?;
 
+        if token_data.claims.typ != self.typ() {
+            return Err(AuthError(AuthInnerError::InvalidTokenType));
+        }
         Ok(token_data.claims)
     }
 }
@@ -145,6 +199,11 @@ where
         parts: &mut Parts,
         _state: &S,
     ) -> AppResult<Self> {
+        // Set by the `auth` middleware, which also accepts API tokens.
+        if let Some(claims) = parts.extensions.get::<Claims>() {
+            return Ok(claims.clone());
+        }
+
         let TypedHeader(Authorization(bearer)) = parts
             .extract::<TypedHeader<Authorization<Bearer>>>()
             .await
@@ -157,14 +216,19 @@ where
 }
 
 impl Claims {
-    pub fn generate_tokens(credential: &UserInfo) -> AppResult<TokenSchema> {
+    pub fn generate_tokens(
+        credential: &UserInfo,
+        ids: SessionIds<'_>,
+    ) -> AppResult<TokenSchema> {
         let access_info = ACCESS_INFO
             .get_or_init(|| Arc::new(TokenSecretInfo::new(TokenType::ACCESS)));
         let refresh_info = REFRESH_INFO
             .get_or_init(|| Arc::new(TokenSecretInfo::new(TokenType::REFRESH)));
 
-        let access_token = access_info.generate_token(credential)?;
-        let refresh_token = refresh_info.generate_token(credential)?;
+        let access_token =
+            access_info.generate_token(credential, ids.sid, None)?;
+        let refresh_token =
+            refresh_info.generate_token(credential, ids.sid, Some(ids.jti))?;
 
         Ok(TokenSchema {
             refresh_token,
@@ -182,6 +246,8 @@ impl Claims {
                 .get_or_init(|| Arc::new(TokenSecretInfo::new(token_type))),
             TokenType::REFRESH => REFRESH_INFO
                 .get_or_init(|| Arc::new(TokenSecretInfo::new(token_type))),
+            TokenType::MFA => MFA_INFO
+                .get_or_init(|| Arc::new(TokenSecretInfo::new(token_type))),
         };
         let claims = info.parse_token(token)?;
         if (flag && claims.status == AccountStatus::Active)
@@ -194,27 +260,157 @@ impl Claims {
 
     pub async fn generate_tokens_for_user(
         user: &BwAccount,
+        ids: SessionIds<'_>,
     ) -> AppResult<TokenSchema> {
         let user_info = UserInfo {
             uid: user.uid,
             email: user.email.clone(),
             status: user.status,
         };
-        let token = Claims::generate_tokens(&user_info)?;
+        let token = Claims::generate_tokens(&user_info, ids)?;
 
         Ok(token)
     }
 
+    /// A short-lived token standing in for the real ones until the second
+    /// factor is verified. Its `sid` identifies the pending login.
+    pub fn generate_mfa_token(user: &BwAccount) -> AppResult<String> {
+        let info = MFA_INFO
+            .get_or_init(|| Arc::new(TokenSecretInfo::new(TokenType::MFA)));
+        let user_info = UserInfo {
+            uid: user.uid,
+            email: user.email.clone(),
+            status: user.status,
+        };
+        info.generate_token(&user_info, &Ulid::new().to_string(), None)
+    }
+
+    /// Claims standing in for a personal API token. They carry no session
+    /// and expire with the token.
+    pub fn for_api_token(user: &BwAccount, token: &BwApiToken) -> Self {
+        Self {
+            uid: user.uid,
+            email: user.email.clone(),
+            status: user.status,
+            iat: token.created_at.and_utc().timestamp() as usize,
+            exp: token
+                .expires_at
+                .map_or(usize::MAX, |at| at.and_utc().timestamp() as usize),
+            sid: None,
+            jti: None,
+            typ: None,
+        }
+    }
+
+    /// Fails once the session the token was issued for has been logged out.
+    /// Tokens issued without a session are accepted until they expire.
+    pub async fn ensure_session(&self, state: &AppState) -> AppResult<()> {
+        match &self.sid {
+            Some(sid)
+                if !session_service::is_active(state, self.uid, sid)
+                    .await? =>
+            {
+                Err(AuthError(AuthInnerError::InvalidToken))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Exchanges a refresh token for a new pair, rotating the refresh token
+    /// of its session. See [`session_service::rotate`].
     pub async fn refresh_token(
         token: &str,
         state: Arc<AppState>,
+        client: &ClientInfo,
     ) -> AppResult<TokenSchema> {
         let claims = Claims::parse_token(token, TokenType::REFRESH, false)?;
 
-        let user = BwAccount::fetch_user_by_uid(state.get_db(), claims.uid)
+        let user = account_service::fetch(&state, claims.uid)
             .await?
             .ok_or(AuthError(AuthInnerError::WrongCredentials))?;
 
-        Claims::generate_tokens_for_user(&user).await
+        session_service::rotate(&state, &user, &claims, client).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_carry_session_ids() {
+        cfg::init(&"./fixtures/config.toml".to_string());
+        let user = UserInfo {
+            uid: 1,
+            email: "test@example.com".to_string(),
+            status: AccountStatus::Active,
+        };
+        let ids = SessionIds {
+            sid: "session",
+            jti: "token",
+        };
+        let tokens = Claims::generate_tokens(&user, ids).unwrap();
+
+        let access =
+            Claims::parse_token(&tokens.access_token, TokenType::ACCESS, true)
+                .unwrap();
+        assert_eq!(access.sid.as_deref(), Some("session"));
+        assert_eq!(access.jti, None);
+
+        let refresh = Claims::parse_token(
+            &tokens.refresh_token,
+            TokenType::REFRESH,
+            false,
+        )
+        .unwrap();
+        assert_eq!(refresh.sid.as_deref(), Some("session"));
+        assert_eq!(refresh.jti.as_deref(), Some("token"));
+    }
+
+    #[test]
+    fn mfa_token_is_not_an_access_token() {
+        cfg::init(&"./fixtures/config.toml".to_string());
+        let user = BwAccount {
+            uid: 1,
+            name: "test".to_string(),
+            email: "test@example.com".to_string(),
+            email_verified_at: None,
+            password: String::new(),
+            failed_attempt: 0,
+            status: AccountStatus::Active,
+            last_login: None,
+            locked_until: None,
+            local_currency: Default::default(),
+            system_lang: Default::default(),
+            created_at: chrono::Utc::now().naive_utc(),
+            updated_at: None,
+            deleted_at: None,
+        };
+        let token = Claims::generate_mfa_token(&user).unwrap();
+
+        let claims =
+            Claims::parse_token(&token, TokenType::MFA, false).unwrap();
+        assert_eq!(claims.typ, Some(TokenType::MFA));
+        assert!(claims.sid.is_some());
+        assert!(Claims::parse_token(&token, TokenType::ACCESS, false).is_err());
+
+        let tokens = Claims::generate_tokens(
+            &UserInfo {
+                uid: user.uid,
+                email: user.email,
+                status: user.status,
+            },
+            SessionIds {
+                sid: "session",
+                jti: "token",
+            },
+        )
+        .unwrap();
+        assert!(Claims::parse_token(
+            &tokens.access_token,
+            TokenType::MFA,
+            false
+        )
+        .is_err());
     }
 }
@@ -0,0 +1,146 @@
+//! Brute-force protection for password logins.
+//!
+//! Failures are counted per account in `bw_account.failed_attempt`, which
+//! locks the account for a growing period once the configured threshold is
+//! hit and starts over once failures stop for a while, and per client IP in
+//! a Redis counter with a fixed window.
+
+use chrono::Utc;
+
+use crate::{
+    library::{
+        cfg,
+        error::{AppError::AuthError, AppResult, AuthInnerError},
+    },
+    miner::{
+        bootstrap::{constants::REDIS_LOGIN_FAILED_IP_KEY, AppState},
+        service::{account_service, session_service::ClientInfo},
+    },
+    models::{
+        account::BwAccount,
+        account_lock::{BwAccountLock, CreateBwAccountLockSchema},
+        types::LockEvent,
+    },
+};
+
+fn ip_key(ip: &str) -> String {
+    format!("{REDIS_LOGIN_FAILED_IP_KEY}:{ip}")
+}
+
+/// Seconds until `user` is unlocked, if it is locked.
+pub fn locked_for(user: &BwAccount) -> Option<i64> {
+    let now = Utc::now().naive_utc();
+    user.locked_until
+        .filter(|until| *until > now)
+        .map(|until| (until - now).num_seconds().max(1))
+}
+
+/// Seconds until `user` is unlocked, if it is locked. A lock that ran out
+/// is cleared and recorded as an unlock event.
+pub async fn check_lock(
+    state: &AppState,
+    user: &BwAccount,
+) -> AppResult<Option<i64>> {
+    if user.locked_until.is_none() {
+        return Ok(None);
+    }
+    if let Some(secs) = locked_for(user) {
+        return Ok(Some(secs));
+    }
+    let released =
+        BwAccount::release_expired_lock(state.get_db(), user.uid).await?;
+    if let Some(failed_attempt) = released {
+        account_service::invalidate(state, user.uid).await?;
+        let item = CreateBwAccountLockSchema {
+            uid: user.uid,
+            event: LockEvent::Unlocked,
+            ip: None,
+            failed_attempt,
+            locked_until: None,
+        };
+        BwAccountLock::insert_event(state.get_db(), &item).await?;
+    }
+    Ok(None)
+}
+
+/// Rejects the login outright if its IP failed too often recently.
+pub async fn check_ip(state: &AppState, client: &ClientInfo) -> AppResult<()> {
+    let Some(ip) = &client.ip else {
+        return Ok(());
+    };
+    let policy = &cfg::config().miner.login_guard;
+    let storage = state.get_storage();
+    let key = ip_key(ip);
+    let count = storage.get_as::<i64>(&key).await?.unwrap_or(0);
+    if count >= policy.ip_max_attempts {
+        let retry = storage
+            .ttl(&key)
+            .await?
+            .unwrap_or(policy.ip_window_secs as i64);
+        return Err(AuthError(AuthInnerError::TooManyLoginAttempts(retry)));
+    }
+    Ok(())
+}
+
+/// Counts a failed login against the client IP and the unlocked `users`
+/// it could have been meant for. Returns how long the longest lock lasts
+/// if one of them just got locked.
+pub async fn record_failure(
+    state: &AppState,
+    users: &[&BwAccount],
+    client: &ClientInfo,
+) -> AppResult<Option<i64>> {
+    let policy = &cfg::config().miner.login_guard;
+    if let Some(ip) = &client.ip {
+        let storage = state.get_storage();
+        storage
+            .incr_ex(&ip_key(ip), policy.ip_window_secs as i64)
+            .await?;
+    }
+
+    let mut locked = None;
+    for user in users {
+        let Some((failed_attempt, locked_until)) =
+            BwAccount::record_failed_login(state.get_db(), user.uid, policy)
+                .await?
+        else {
+            continue;
+        };
+        account_service::invalidate(state, user.uid).await?;
+        if failed_attempt < policy.max_attempts {
+            continue;
+        }
+        tracing::warn!(
+            "Account {} locked after {} failed logins",
+            user.uid,
+            failed_attempt
+        );
+        let item = CreateBwAccountLockSchema {
+            uid: user.uid,
+            event: LockEvent::Locked,
+            ip: client.ip.as_deref(),
+            failed_attempt,
+            locked_until,
+        };
+        BwAccountLock::insert_event(state.get_db(), &item).await?;
+        let secs = locked_until
+            .map(|until| (until - Utc::now().naive_utc()).num_seconds())
+            .unwrap_or_default();
+        locked = locked.max(Some(secs.max(1)));
+    }
+    Ok(locked)
+}
+
+/// Clears the failure count of `user` after a successful login. Its lock,
+/// if any, already ran out and was recorded by [`check_lock`].
+pub async fn record_success(
+    state: &AppState,
+    user: &BwAccount,
+) -> AppResult<()> {
+    if user.failed_attempt == 0 && user.locked_until.is_none() {
+        return Ok(());
+    }
+    BwAccount::reset_failed_login(state.get_db(), user.uid).await?;
+    account_service::invalidate(state, user.uid).await?;
+    Ok(())
+}
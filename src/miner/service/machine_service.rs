@@ -0,0 +1,42 @@
+//! Owners of machines by MAC, looked up for every message a machine
+//! sends. They are cached under `machine:{mac}`, so enrolling, moving or
+//! transferring a machine has to [`invalidate`] it.
+
+use crate::{
+    library::error::{AppInnerError, AppResult},
+    miner::bootstrap::{constants::REDIS_MACHINE_KEY, AppState},
+    models::machine::BwMachine,
+};
+
+/// The form MACs are compared and cached in, as Postgres prints them.
+pub fn normalize_mac(mac: &str) -> String {
+    mac.trim().to_ascii_lowercase().replace('-', ":")
+}
+
+fn cache_key(mac: &str) -> String {
+    format!("{REDIS_MACHINE_KEY}:{}", normalize_mac(mac))
+}
+
+/// Account owning the machine `mac` and the group it is in.
+pub async fn owner(
+    state: &AppState,
+    mac: &str,
+) -> AppResult<Option<(i64, Option<i64>)>> {
+    let db = state.get_db();
+    Ok(state
+        .get_cacher()
+        .entry(&cache_key(mac))
+        .get_or_load(|| async {
+            let machines = BwMachine::fetch_machine_by_mac(db, mac).await?;
+            Ok::<_, AppInnerError>(
+                machines
+                    .first()
+                    .map(|machine| (machine.uid, machine.group_id)),
+            )
+        })
+        .await?)
+}
+
+pub async fn invalidate(state: &AppState, mac: &str) -> AppResult<()> {
+    Ok(state.get_cacher().invalidate(&cache_key(mac)).await?)
+}
@@ -1,37 +1,103 @@
-use std::sync::Arc;
+//! Outgoing email.
+//!
+//! Every email is first recorded in the outbox and then published to
+//! [`MQ_SEND_EMAIL_QUEUE`]. A failed send is published to a delay queue
+//! that dead-letters it back into the send queue after the next
+//! `retry_backoff` step, and to [`MQ_SEND_EMAIL_DEAD_QUEUE`] once the steps
+//! are used up. Emails whose publishing failed stay pending and are
+//! published again by a periodic sweep, which also deletes emails whose
+//! outcome is older than `retention`. One-time codes are dropped from the
+//! outbox once an email is sent or given up on.
+//!
+//! Messages carry what to send, never how: the consumer renders the
+//! template and sends it through the SMTP profile its own config routes
+//! the template to.
+
+use std::{sync::Arc, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use tokio::time::interval;
 
 use super::Service;
 use crate::{
-    library::{error::AppResult, mailor::Email, mqer::Subscriber, Mqer},
+    library::{
+        bus::{self, MessageBus, Subscriber},
+        cfg,
+        error::AppResult,
+        mailor::{Email, Mailer},
+        templator::{self, MailTemplate},
+    },
     miner::bootstrap::{
-        constants::{MQ_SEND_EMAIL_QUEUE, MQ_SEND_EMAIL_TAG},
+        constants::{
+            MQ_SEND_EMAIL_DEAD_QUEUE, MQ_SEND_EMAIL_QUEUE,
+            MQ_SEND_EMAIL_RETRY_QUEUE, MQ_SEND_EMAIL_TAG,
+        },
         AppState,
     },
+    models::{
+        email_outbox::{BwEmailOutbox, CreateBwEmailOutboxSchema},
+        types::{EmailStatus, Language},
+    },
 };
 
+/// Stale pending emails published per sweep.
+const SWEEP_BATCH: i64 = 100;
+
 #[derive(Clone)]
 pub struct Server {
-    pub mqer: Arc<Mqer>,
+    pub bus: Arc<dyn MessageBus>,
+    pub mailer: Arc<Mailer>,
+}
+
+/// What is published to the send queue: the outbox record tracking the
+/// email and what it is rendered from.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OutboxMessage {
+    pub email_id: i64,
+    pub to: String,
+    #[serde(flatten)]
+    pub template: MailTemplate,
+    pub lang: String,
 }
 
 impl Service for Server {
     async fn init() -> Server {
         Server {
-            mqer: Arc::new(Mqer::init()),
+            bus: bus::init(),
+            mailer: Arc::new(Mailer::init()),
         }
     }
 
-    async fn serve(&mut self, _app_state: Arc<AppState>) {
-        match self.email_sender().await {
+    async fn serve(&mut self, app_state: Arc<AppState>) {
+        match self.email_sender(app_state.clone()).await {
             Ok(()) => {}
             Err(e) => {
                 tracing::error!("Error occurred while sending email: {}", e)
             }
         };
+
+        tokio::spawn(async move {
+            let config = &cfg::config().miner.mail_outbox;
+            let mut interval =
+                interval(Duration::from_secs(config.sweep_interval));
+            loop {
+                interval.tick().await;
+                if let Err(e) = sweep(&app_state, config.stale_after).await {
+                    tracing::error!("Failed to publish pending emails: {}", e);
+                }
+                match BwEmailOutbox::purge(app_state.get_db(), config.retention)
+                    .await
+                {
+                    Ok(0) => {}
+                    Ok(purged) => tracing::info!("Purged {purged} old emails"),
+                    Err(e) => tracing::error!("Failed to purge emails: {}", e),
+                }
+            }
+        });
     }
 
     async fn shutdown(&self) {
-        match self.mqer.graceful_shutdown() {
+        match self.bus.graceful_shutdown() {
             Ok(()) => {}
             Err(e) => {
                 tracing::error!("Error occurred while closing MQ: {}", e)
@@ -41,28 +107,293 @@ impl Service for Server {
 }
 
 impl Server {
-    pub async fn email_sender(&self) -> AppResult<()> {
+    pub async fn email_sender(&self, state: Arc<AppState>) -> AppResult<()> {
         tracing::debug!("customer started");
-        let func = |message: String| {
-            let result = serde_json::from_str::<Email>(&message)
-                .map_err(|e| {
-                    tracing::error!("Failed to parse email from message: {}", e)
-                })
-                .and_then(|email| {
-                    let res = email.sync_send_text().map_err(|e| {
-                        tracing::error!("Failed to send email: {}", e)
-                    });
-                    tracing::debug!("received:{:#?}", email);
-                    res
-                });
-            if result.is_err() {
-                tracing::error!("Failed to send email")
-            }
+        let mailer = self.mailer.clone();
+        let func = move |message: String| {
+            let state = state.clone();
+            let mailer = mailer.clone();
+            async move { deliver(&state, &mailer, &message).await }
         };
-        let delegate = Subscriber::new(func, self.mqer.clone());
         Ok(self
-            .mqer
-            .basic_receive(MQ_SEND_EMAIL_QUEUE, MQ_SEND_EMAIL_TAG, delegate)
+            .bus
+            .basic_receive(
+                MQ_SEND_EMAIL_QUEUE,
+                MQ_SEND_EMAIL_TAG,
+                Subscriber::new(func),
+            )
             .await?)
     }
 }
+
+/// Sends one queued email. Returns whether the message is done with,
+/// which it is unless the outcome could not be recorded.
+async fn deliver(state: &AppState, mailer: &Mailer, message: &str) -> bool {
+    let queued = match serde_json::from_str::<OutboxMessage>(message) {
+        Ok(queued) => queued,
+        Err(e) => {
+            tracing::error!("Failed to parse email from message: {}", e);
+            return dead_letter(state, message).await;
+        }
+    };
+    let email =
+        match BwEmailOutbox::fetch_email_by_id(state.get_db(), queued.email_id)
+            .await
+        {
+            Ok(Some(email)) => email,
+            Ok(None) => {
+                tracing::error!(
+                    "Email {} is not in the outbox",
+                    queued.email_id
+                );
+                return dead_letter(state, message).await;
+            }
+            Err(e) => {
+                tracing::error!(
+                    "Failed to fetch email {}: {}",
+                    queued.email_id,
+                    e
+                );
+                return requeue().await;
+            }
+        };
+    if matches!(email.status, EmailStatus::Sent | EmailStatus::Failed) {
+        // Redelivered after its outcome was recorded.
+        return true;
+    }
+
+    let rendered = match queued.template.render(&Language(queued.lang)) {
+        Ok(rendered) => rendered,
+        Err(e) => {
+            tracing::error!("Failed to render email {}: {}", email.email_id, e);
+            return dead_letter(state, message).await;
+        }
+    };
+    let kind = queued.template.kind();
+    match mailer.send(kind, &Email::new(&queued.to, rendered)).await {
+        Ok(_) => {
+            if let Err(e) =
+                BwEmailOutbox::record_sent(state.get_db(), email.email_id).await
+            {
+                tracing::error!(
+                    "Failed to record email {} as sent: {}",
+                    email.email_id,
+                    e
+                );
+                // Sent again on redelivery rather than left queued with its
+                // code for good.
+                return requeue().await;
+            }
+            true
+        }
+        Err(e) => {
+            tracing::warn!("Failed to send email {}: {}", email.email_id, e);
+            retry(state, &email, message, &e.to_string()).await
+        }
+    }
+}
+
+/// Publishes a failed email to the delay queue for its next attempt, or to
+/// the dead letter queue if there is none.
+async fn retry(
+    state: &AppState,
+    email: &BwEmailOutbox,
+    message: &str,
+    error: &str,
+) -> bool {
+    let backoff = &cfg::config().miner.mail_outbox.retry_backoff;
+    let retry_in = usize::try_from(email.attempts)
+        .ok()
+        .and_then(|attempts| backoff.get(attempts))
+        .copied();
+    let published = match (state.get_mq(), retry_in) {
+        (Ok(mq), Some(secs)) => mq
+            .delay_send(
+                &format!("{MQ_SEND_EMAIL_RETRY_QUEUE}.{secs}s"),
+                MQ_SEND_EMAIL_QUEUE,
+                Duration::from_secs(secs),
+                message,
+            )
+            .await
+            .map_err(|e| e.to_string()),
+        (Ok(mq), None) => mq
+            .basic_send(MQ_SEND_EMAIL_DEAD_QUEUE, message)
+            .await
+            .map_err(|e| e.to_string()),
+        (Err(e), _) => Err(e.to_string()),
+    };
+    if let Err(e) = published {
+        tracing::error!(
+            "Failed to schedule email {} again: {}",
+            email.email_id,
+            e
+        );
+        return requeue().await;
+    }
+
+    if let Err(e) = BwEmailOutbox::record_failure(
+        state.get_db(),
+        email.email_id,
+        error,
+        retry_in,
+    )
+    .await
+    {
+        tracing::error!(
+            "Failed to record email {} as failed: {}",
+            email.email_id,
+            e
+        );
+    }
+    true
+}
+
+async fn dead_letter(state: &AppState, message: &str) -> bool {
+    let published = match state.get_mq() {
+        Ok(mq) => mq
+            .basic_send(MQ_SEND_EMAIL_DEAD_QUEUE, message)
+            .await
+            .map_err(|e| e.to_string()),
+        Err(e) => Err(e.to_string()),
+    };
+    match published {
+        Ok(()) => true,
+        Err(e) => {
+            tracing::error!("Failed to dead-letter message: {}", e);
+            requeue().await
+        }
+    }
+}
+
+/// Backs off briefly so a requeued message is not redelivered in a tight
+/// loop while the database or broker is unavailable.
+async fn requeue() -> bool {
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    false
+}
+
+async fn publish(state: &AppState, queued: &OutboxMessage) -> AppResult<()> {
+    let email_json = serde_json::to_string(&queued).map_err(|e| {
+        anyhow::anyhow!("Error occurred while sending email: {}", e)
+    })?;
+    state
+        .get_mq()?
+        .basic_send(MQ_SEND_EMAIL_QUEUE, &email_json)
+        .await?;
+    BwEmailOutbox::mark_queued(state.get_db(), queued.email_id).await?;
+    Ok(())
+}
+
+/// Records an email in the outbox and hands it to the sender, returning
+/// its id. Failing to reach the queue is not an error, the email is picked
+/// up by the next sweep.
+pub async fn queue_email(
+    state: &AppState,
+    uid: Option<i64>,
+    to: &str,
+    template: &MailTemplate,
+    lang: &Language,
+) -> AppResult<i64> {
+    // Rendered here too so a broken template fails the caller rather than
+    // the consumer, and for the subject recorded in the outbox.
+    let rendered = template.render(lang)?;
+    let lang = templator::resolve_language(lang);
+    let item = CreateBwEmailOutboxSchema {
+        uid,
+        recipient: to.to_string(),
+        subject: rendered.subject.clone(),
+        template: template.kind().name().to_string(),
+        params: template.params()?,
+        lang: lang.to_string(),
+    };
+    let email =
+        BwEmailOutbox::create_bw_email_outbox(state.get_db(), &item).await?;
+    let queued = OutboxMessage {
+        email_id: email.email_id,
+        to: to.to_string(),
+        template: template.clone(),
+        lang: lang.to_string(),
+    };
+    if let Err(e) = publish(state, &queued).await {
+        tracing::warn!("Email {} left pending: {}", email.email_id, e);
+    }
+    Ok(email.email_id)
+}
+
+/// Publishes emails left pending for more than `stale_after` seconds. An
+/// email that cannot be rebuilt is given up on, one that cannot be
+/// published is left for a later sweep; neither holds up the others.
+async fn sweep(state: &AppState, stale_after: i64) -> AppResult<()> {
+    let emails = BwEmailOutbox::claim_stale_pending(
+        state.get_db(),
+        stale_after,
+        SWEEP_BATCH,
+    )
+    .await?;
+    for email in emails {
+        let template =
+            match MailTemplate::from_parts(&email.template, email.params.0) {
+                Ok(template) => template,
+                Err(e) => {
+                    tracing::error!(
+                        "Failed to rebuild email {}: {}",
+                        email.email_id,
+                        e
+                    );
+                    BwEmailOutbox::record_failure(
+                        state.get_db(),
+                        email.email_id,
+                        &e.to_string(),
+                        None,
+                    )
+                    .await?;
+                    continue;
+                }
+            };
+        let queued = OutboxMessage {
+            email_id: email.email_id,
+            to: email.recipient,
+            template,
+            lang: email.lang,
+        };
+        if let Err(e) = publish(state, &queued).await {
+            tracing::warn!("Email {} left pending: {}", email.email_id, e);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library::templator::TemplateKind;
+
+    #[test]
+    fn test_outbox_message_round_trip() {
+        let queued = OutboxMessage {
+            email_id: 1,
+            to: "user@example.com".to_string(),
+            template: MailTemplate::sample(TemplateKind::Alert),
+            lang: "fr-FR".to_string(),
+        };
+        let json = serde_json::to_string(&queued).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["template"], "alert");
+        assert_eq!(value["params"]["machine"], "rig-07 (00:1A:2B:3C:4D:5E)");
+        for key in ["config", "password", "username", "host"] {
+            assert!(!json.contains(key), "{key} in {json}");
+        }
+
+        let parsed = serde_json::from_str::<OutboxMessage>(&json).unwrap();
+        assert_eq!(parsed.email_id, 1);
+        assert_eq!(parsed.to, queued.to);
+        assert_eq!(parsed.lang, queued.lang);
+        assert_eq!(
+            parsed
+                .template
+                .render(&Language(parsed.lang.clone()))
+                .unwrap(),
+            queued.template.render(&Language(queued.lang)).unwrap()
+        );
+    }
+}
@@ -0,0 +1,134 @@
+//! State gauges of [`metricer`], set right before a scrape.
+
+use std::{cmp::Reverse, collections::HashMap, time::Duration};
+
+use axum::http::{header::AUTHORIZATION, HeaderMap};
+
+use crate::{
+    library::{cfg, crypto, metricer},
+    miner::{
+        bootstrap::{
+            constants::{REDIS_COIN_STAT_KEY, REDIS_EXCHANGE_RATE_KEY},
+            AppState,
+        },
+        service::health_service,
+    },
+};
+
+/// Whether `headers` carry `token` as bearer token. Without a token
+/// configured nobody is.
+pub fn authorized(token: Option<&str>, headers: &HeaderMap) -> bool {
+    let Some(token) = token else {
+        return false;
+    };
+    headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        // Digests compared so the time taken tells nothing of the token.
+        .is_some_and(|given| {
+            crypto::hash_token(given) == crypto::hash_token(token)
+        })
+}
+
+/// The `top` accounts with the most machines online, ties going to the
+/// lowest uid so the series stay put between scrapes.
+fn busiest(online: HashMap<i64, i64>, top: usize) -> Vec<(i64, i64)> {
+    let mut online: Vec<_> = online.into_iter().collect();
+    online.sort_unstable_by_key(|&(uid, count)| (Reverse(count), uid));
+    online.truncate(top);
+    online
+}
+
+fn usize_gauge(value: usize) -> i64 {
+    i64::try_from(value).unwrap_or(i64::MAX)
+}
+
+/// Every metric in the Prometheus text format, gauges brought up to date.
+pub async fn render(state: &AppState) -> String {
+    let config = &cfg::config().miner;
+    let metrics = metricer::metrics();
+
+    let db = state.get_db();
+    let pools = &metrics.pool_connections;
+    let max_connections = db.options().get_max_connections();
+    pools
+        .with_label_values(&["postgres", "max"])
+        .set(i64::from(max_connections));
+    pools
+        .with_label_values(&["postgres", "size"])
+        .set(i64::from(db.size()));
+    pools
+        .with_label_values(&["postgres", "idle"])
+        .set(usize_gauge(db.num_idle()));
+    if let Some(redis) = state.get_storage().pool_state() {
+        for (name, value) in [
+            ("max", redis.max_size),
+            ("size", redis.size),
+            ("idle", redis.available),
+            ("waiting", redis.waiting),
+        ] {
+            pools
+                .with_label_values(&["redis", name])
+                .set(usize_gauge(value));
+        }
+    }
+
+    metrics
+        .mq_in_flight
+        .set(usize_gauge(state.services.message_queue.bus.in_flight()));
+
+    for (source, key, max_age) in [
+        (
+            "exchange_rate",
+            REDIS_EXCHANGE_RATE_KEY,
+            config.exchange_rate.max_age(),
+        ),
+        ("coin_stat", REDIS_COIN_STAT_KEY, config.coin_stat.max_age()),
+    ] {
+        let freshness = health_service::freshness(state, key, max_age).await;
+        metrics
+            .market_age
+            .with_label_values(&[source])
+            .set(freshness.age_secs.unwrap_or(-1));
+    }
+
+    let window = Duration::from_secs(config.metrics.online_window);
+    let online = state.services.mqtt.online_by_account(window);
+    metrics.miners_online.set(online.values().sum());
+    // Accounts that went quiet or fell out of the top disappear rather
+    // than stay at their last count.
+    metrics.miners_online_by_account.reset();
+    for (uid, count) in busiest(online, config.metrics.top_accounts) {
+        metrics
+            .miners_online_by_account
+            .with_label_values(&[&uid.to_string()])
+            .set(count);
+    }
+
+    metricer::render()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_busiest() {
+        let online = HashMap::from([(1, 2), (2, 5), (3, 2), (4, 1)]);
+        assert_eq!(busiest(online.clone(), 3), vec![(2, 5), (1, 2), (3, 2)]);
+        assert_eq!(busiest(online.clone(), 10).len(), 4);
+        assert!(busiest(online, 0).is_empty());
+    }
+
+    #[test]
+    fn test_authorized() {
+        let mut headers = HeaderMap::new();
+        assert!(!authorized(None, &headers));
+        assert!(!authorized(Some("secret"), &headers));
+        headers.insert(AUTHORIZATION, "Bearer secret".parse().unwrap());
+        assert!(authorized(Some("secret"), &headers));
+        assert!(!authorized(Some("other"), &headers));
+        assert!(!authorized(None, &headers));
+    }
+}
@@ -0,0 +1,237 @@
+//! TOTP based two-factor authentication.
+//!
+//! Enrolling stores a secret that only takes effect once a code generated
+//! from it is confirmed. From then on a correct password only yields an
+//! `MFA` token, which `login` exchanges for real tokens given a valid TOTP
+//! or recovery code.
+
+use chrono::Utc;
+
+use crate::{
+    library::{
+        cfg, crypto,
+        error::{AppError::AuthError, AppResult, AuthInnerError},
+        totp,
+    },
+    miner::{
+        bootstrap::{constants::REDIS_MFA_ATTEMPT_KEY, AppState},
+        service::{
+            account_service,
+            jwt_service::{Claims, TokenSchema, TokenType},
+            login_guard, session_service,
+            session_service::ClientInfo,
+        },
+    },
+    models::{
+        account::BwAccount,
+        mfa::{BwAccountMfa, BwRecoveryCode},
+    },
+};
+
+const RECOVERY_CODE_LEN: usize = 10;
+
+/// Secret and `otpauth://` URI of a pending enrollment.
+pub struct Enrollment {
+    pub secret: String,
+    pub otpauth_uri: String,
+}
+
+/// Lowercases and strips separators so `ABCDE-12345` matches `abcde12345`.
+fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+fn generate_recovery_codes() -> Vec<String> {
+    (0..cfg::config().miner.mfa.recovery_codes)
+        .map(|_| {
+            let code =
+                crypto::random_words(RECOVERY_CODE_LEN).to_ascii_lowercase();
+            let (head, tail) = code.split_at(RECOVERY_CODE_LEN / 2);
+            format!("{head}-{tail}")
+        })
+        .collect()
+}
+
+async fn store_recovery_codes(
+    state: &AppState,
+    uid: i64,
+) -> AppResult<Vec<String>> {
+    let codes = generate_recovery_codes();
+    let hashes = codes
+        .iter()
+        .map(|c| crypto::hash_token(&normalize_recovery_code(c)))
+        .collect::<Vec<_>>();
+    BwRecoveryCode::replace_codes(state.get_db(), uid, &hashes).await?;
+    Ok(codes)
+}
+
+async fn fetch_enabled(state: &AppState, uid: i64) -> AppResult<BwAccountMfa> {
+    BwAccountMfa::fetch_by_uid(state.get_db(), uid)
+        .await?
+        .filter(BwAccountMfa::is_enabled)
+        .ok_or(AuthError(AuthInnerError::MfaNotEnabled))
+}
+
+/// Checks a TOTP or recovery code, using it up so it cannot be replayed.
+async fn verify_code(
+    state: &AppState,
+    mfa: &BwAccountMfa,
+    code: &str,
+) -> AppResult<bool> {
+    if let Some(step) = totp::verify(&mfa.secret, code, Utc::now().timestamp())
+    {
+        return Ok(BwAccountMfa::consume_step(state.get_db(), mfa.uid, step)
+            .await?
+            == 1);
+    }
+    let hash = crypto::hash_token(&normalize_recovery_code(code));
+    Ok(
+        BwRecoveryCode::consume_code(state.get_db(), mfa.uid, &hash).await?
+            == 1,
+    )
+}
+
+pub async fn is_enabled(state: &AppState, uid: i64) -> AppResult<bool> {
+    Ok(BwAccountMfa::check_enabled_by_uid(state.get_db(), uid)
+        .await?
+        .unwrap_or(false))
+}
+
+/// Starts (or restarts) enrollment with a fresh secret.
+pub async fn enroll(
+    state: &AppState,
+    claims: &Claims,
+) -> AppResult<Enrollment> {
+    let secret = totp::generate_secret();
+    if BwAccountMfa::upsert_pending(state.get_db(), claims.uid, &secret).await?
+        == 0
+    {
+        return Err(AuthError(AuthInnerError::MfaAlreadyEnabled));
+    }
+    let otpauth_uri = totp::otpauth_uri(
+        &cfg::config().miner.mfa.issuer,
+        &claims.email,
+        &secret,
+    );
+    Ok(Enrollment {
+        secret,
+        otpauth_uri,
+    })
+}
+
+/// Enables two-factor authentication once the user proves their app
+/// produces valid codes, returning the one-time recovery codes.
+pub async fn confirm(
+    state: &AppState,
+    uid: i64,
+    code: &str,
+) -> AppResult<Vec<String>> {
+    let mfa = BwAccountMfa::fetch_by_uid(state.get_db(), uid)
+        .await?
+        .ok_or(AuthError(AuthInnerError::MfaNotEnabled))?;
+    if mfa.is_enabled() {
+        return Err(AuthError(AuthInnerError::MfaAlreadyEnabled));
+    }
+    let step = totp::verify(&mfa.secret, code, Utc::now().timestamp())
+        .ok_or(AuthError(AuthInnerError::WrongCode))?;
+    if BwAccountMfa::enable(state.get_db(), uid, step).await? == 0 {
+        return Err(AuthError(AuthInnerError::MfaAlreadyEnabled));
+    }
+    store_recovery_codes(state, uid).await
+}
+
+pub async fn disable(state: &AppState, uid: i64, code: &str) -> AppResult<()> {
+    let mfa = fetch_enabled(state, uid).await?;
+    if !verify_code(state, &mfa, code).await? {
+        return Err(AuthError(AuthInnerError::WrongCode));
+    }
+    BwAccountMfa::delete_by_uid(state.get_db(), uid).await?;
+    Ok(())
+}
+
+/// Replaces the recovery codes, invalidating the old ones.
+pub async fn regenerate_recovery_codes(
+    state: &AppState,
+    uid: i64,
+    code: &str,
+) -> AppResult<Vec<String>> {
+    let mfa = fetch_enabled(state, uid).await?;
+    if !verify_code(state, &mfa, code).await? {
+        return Err(AuthError(AuthInnerError::WrongCode));
+    }
+    store_recovery_codes(state, uid).await
+}
+
+/// Second login step: exchanges an `MFA` token and a code for a session.
+///
+/// Each `MFA` token accepts a limited number of codes, and wrong codes
+/// count towards the account lockout like wrong passwords do.
+pub async fn login(
+    state: &AppState,
+    mfa_token: &str,
+    code: &str,
+    client: &ClientInfo,
+) -> AppResult<(TokenSchema, BwAccount)> {
+    let claims = Claims::parse_token(mfa_token, TokenType::MFA, false)?;
+    let pending = claims
+        .sid
+        .as_deref()
+        .ok_or(AuthError(AuthInnerError::InvalidToken))?;
+    let policy = &cfg::config().miner.mfa;
+
+    let key = format!("{REDIS_MFA_ATTEMPT_KEY}:{pending}");
+    let storage = state.get_storage();
+    if storage
+        .incr_ex(&key, policy.token_expiration.into())
+        .await?
+        > policy.max_attempts
+    {
+        return Err(AuthError(AuthInnerError::InvalidToken));
+    }
+
+    let user = account_service::fetch(state, claims.uid)
+        .await?
+        .ok_or(AuthError(AuthInnerError::WrongCredentials))?;
+    if let Some(secs) = login_guard::check_lock(state, &user).await? {
+        return Err(AuthError(AuthInnerError::AccountLocked(secs)));
+    }
+
+    let mfa = fetch_enabled(state, user.uid).await?;
+    if !verify_code(state, &mfa, code).await? {
+        if let Some(secs) =
+            login_guard::record_failure(state, &[&user], client).await?
+        {
+            return Err(AuthError(AuthInnerError::AccountLocked(secs)));
+        }
+        return Err(AuthError(AuthInnerError::WrongCode));
+    }
+
+    // Burn the token so it cannot start a second session.
+    storage
+        .set_ex(
+            &key,
+            &policy.max_attempts.to_string(),
+            policy.token_expiration.into(),
+        )
+        .await?;
+    login_guard::record_success(state, &user).await?;
+    let tokens = session_service::issue(state, &user, client).await?;
+    Ok((tokens, user))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recovery_codes_are_normalized() {
+        assert_eq!(normalize_recovery_code(" AbCdE-12345 "), "abcde12345");
+        assert_eq!(
+            crypto::hash_token(&normalize_recovery_code("abcde-12345")),
+            crypto::hash_token(&normalize_recovery_code("ABCDE12345"))
+        );
+    }
+}
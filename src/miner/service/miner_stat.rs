@@ -2,57 +2,80 @@ use std::{sync::Arc, time::Duration};
 
 use serde::{Deserialize, Serialize};
 use tokio::time::interval;
+use utoipa::ToSchema;
 
 use super::Service;
 use crate::{
     library::{
         cfg,
-        error::{AppError, AppResult},
+        error::{ApiInnerError, AppError, AppResult},
+        metricer,
+    },
+    miner::{
+        bootstrap::{constants::REDIS_COIN_STAT_KEY, AppState},
+        service::provider::{MarketData, ProviderChain},
     },
-    miner::bootstrap::AppState,
 };
 
 #[derive(Clone)]
 pub struct Server {
-    coin_stat: Arc<CoinStat>,
+    providers: Arc<ProviderChain<Vec<CoinData>>>,
+    duration: Duration,
 }
 
 impl Service for Server {
     async fn init() -> Server {
         let cfg = cfg::config();
-        let coin_stat_host = &cfg.miner.coin_stat.host;
-        let coin_stat_duration =
-            Duration::from_secs(cfg.miner.coin_stat.frequency);
-        let coins = cfg.miner.coins.clone();
-        let coin_stat =
-            CoinStat::new(coin_stat_host, coins, coin_stat_duration);
+        let providers = ProviderChain::<Vec<CoinData>>::from_config(
+            &cfg.miner.coin_stat.providers,
+            &cfg.miner.coins,
+            cfg.miner.env == "dev",
+        );
+        tracing::info!("Coin stat providers: {:?}", providers.names());
         Server {
-            coin_stat: Arc::new(coin_stat),
+            providers: Arc::new(providers),
+            duration: Duration::from_secs(cfg.miner.coin_stat.frequency),
         }
     }
 
     async fn serve(&mut self, app_state: Arc<AppState>) {
-        let coin_stat = self.coin_stat.clone();
+        let providers = self.providers.clone();
+        let duration = self.duration;
 
         tokio::spawn(async move {
-            let mut interval = interval(coin_stat.duration);
-            let mut redis = app_state.get_redis().await.unwrap();
+            let mut interval = interval(duration);
+            let storage = app_state.get_storage();
             loop {
                 interval.tick().await;
 
-                match coin_stat.get_data().await {
+                let fetched = providers.fetch().await;
+                metricer::metrics()
+                    .market_fetches
+                    .with_label_values(&[
+                        "coin_stat",
+                        metricer::result_label(&fetched),
+                    ])
+                    .inc();
+                match fetched {
                     Ok(res) => {
-                        redis
+                        storage
                             .set(
-                                "coin_stat",
+                                REDIS_COIN_STAT_KEY,
                                 &serde_json::to_string(&res).unwrap(),
                             )
                             .await
                             .unwrap();
-                        tracing::trace!("Successfully fetched coin stats")
+                        tracing::trace!(
+                            "Successfully fetched coin stats from {}",
+                            res.provider
+                        )
                     }
                     Err(e) => {
-                        tracing::error!("Error fetching coin stats: {:?}", e)
+                        tracing::error!(
+                            "Error fetching coin stats from every provider: \
+                             {:?}",
+                            e
+                        )
                     }
                 }
             }
@@ -62,82 +85,44 @@ impl Service for Server {
     async fn shutdown(&self) {}
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct CoinData {
-    id: String,
-    coin: String,
-    name: String,
-    #[serde(rename = "type")]
-    type_: String,
-    algorithm: String,
-    network_hashrate: f64,
-    difficulty: f64,
-    reward: f64,
-    reward_unit: String,
-    reward_block: f64,
-    price: f64,
-    volume: f64,
-    updated: u64,
+impl Server {
+    /// Returns the coin stats cached by the background fetcher, flagged as
+    /// stale once they are older than the configured `max_age`.
+    pub async fn current_stats(
+        app_state: &AppState,
+    ) -> AppResult<MarketData<Vec<CoinData>>> {
+        let max_age = cfg::config().miner.coin_stat.max_age();
+        let storage = app_state.get_storage();
+        let cached = storage
+            .get(REDIS_COIN_STAT_KEY)
+            .await?
+            .ok_or(AppError::ApiError(ApiInnerError::CoinStatNotFound))?;
+        let snapshot: MarketData<Vec<CoinData>> = serde_json::from_str(&cached)
+            .map_err(|e| {
+                AppError::Anyhow(anyhow::anyhow!(
+                    "Error parsing cached coin stats: {e}"
+                ))
+            })?;
+        Ok(snapshot.with_max_age(max_age))
+    }
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
-pub struct CoinStat {
-    pub host: String,
-    pub coins: String,
-    pub duration: Duration,
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct CoinData {
+    pub id: String,
+    pub coin: String,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub algorithm: String,
+    pub network_hashrate: f64,
+    pub difficulty: f64,
+    pub reward: f64,
+    pub reward_unit: String,
+    pub reward_block: f64,
+    pub price: f64,
+    pub volume: f64,
+    pub updated: u64,
 }
 
 pub type CoinSymbol<'a> = &'a str;
-
-impl CoinStat {
-    pub fn new(host: &str, coins: Vec<String>, duration: Duration) -> CoinStat {
-        let coins = coins.join(",");
-        CoinStat {
-            host: host.to_string(),
-            coins,
-            duration,
-        }
-    }
-
-    pub async fn get_data(&self) -> AppResult<Vec<CoinData>> {
-        let url = format!("{}?list={}", self.host, self.coins);
-        let client = reqwest::Client::new();
-        let response = client.get(url).send().await.map_err(|e| {
-            let es = format!("Error occurred while getting coin stat : {}", e);
-            tracing::error!(es);
-            anyhow::anyhow!(es)
-        })?;
-        if !response.status().is_success() {
-            let es = format!(
-                "Error occurred while getting coin stat : {}",
-                response.status()
-            );
-            tracing::error!(es);
-            return Err(AppError::Anyhow(anyhow::anyhow!(es)));
-        }
-        Ok(response.json().await.map_err(|e| {
-            let es = format!("Error occurred while getting coin stat : {}", e);
-            tracing::error!(es);
-            anyhow::anyhow!(es)
-        })?)
-    }
-}
-
-#[cfg(test)]
-mod tests {
-    use std::time::Duration;
-
-    use crate::miner::service::miner_stat::CoinStat;
-
-    #[tokio::test]
-    async fn get_coin_stat_works() {
-        let host = "https://api.minerstat.com/v2/coins";
-        let coins =
-            vec!["BTC".to_string(), "BCH".to_string(), "BSV".to_string()];
-        let coin_stat =
-            CoinStat::new(host, coins, Duration::from_secs(60 * 60));
-        let list = ["BTC", "BCH", "BSV"];
-        let res = coin_stat.get_data().await.unwrap();
-        assert_eq!(res.len(), list.len());
-    }
-}
@@ -2,15 +2,31 @@ use std::sync::Arc;
 
 use crate::miner::bootstrap::AppState;
 
+pub mod account_service;
+pub mod api_token_service;
+pub mod enrollment_service;
 pub mod exchange_rate;
+pub mod fleet_event_service;
+pub mod group_service;
+pub mod health_service;
 pub mod jwt_service;
+pub mod login_guard;
+pub mod machine_service;
 pub mod message_queue;
+pub mod metrics_service;
+pub mod mfa_service;
 pub mod miner_stat;
 pub mod mqtt_service;
+pub mod org_service;
+pub mod provider;
+pub mod rate_limit_service;
+pub mod session_service;
+pub mod verification_service;
 
 #[derive(Clone)]
 pub struct Services {
     pub exchange_rate: exchange_rate::Server,
+    pub fleet_event: fleet_event_service::Server,
     pub miner_stat: miner_stat::Server,
     pub message_queue: message_queue::Server,
     pub mqtt: mqtt_service::Server,
@@ -20,6 +36,7 @@ impl Services {
     pub async fn init() -> Services {
         Services {
             exchange_rate: exchange_rate::Server::init().await,
+            fleet_event: fleet_event_service::Server::init().await,
             miner_stat: miner_stat::Server::init().await,
             message_queue: message_queue::Server::init().await,
             mqtt: mqtt_service::Server::init().await,
@@ -31,6 +48,7 @@ impl Services {
         self.miner_stat.clone().serve(app_state.clone()).await;
         self.mqtt.clone().serve(app_state.clone()).await;
         self.message_queue.clone().serve(app_state.clone()).await;
+        self.fleet_event.clone().serve(app_state.clone()).await;
     }
 
     pub async fn shutdown(&self) {
@@ -1,20 +1,36 @@
-use std::sync::Arc;
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering::SeqCst},
+        Arc, PoisonError,
+    },
+    time::{Duration, Instant},
+};
 
 use rumqttc::v5::{
     mqttbytes::QoS, AsyncClient, Event, EventLoop, Incoming, MqttOptions,
 };
 use tokio::sync::Mutex;
+use tracing::Instrument;
 
 use super::Service;
 use crate::{
-    library::cfg,
-    miner::{bootstrap::AppState, entity::mqtt::Message},
+    library::{cfg, metricer, tracer},
+    miner::{
+        bootstrap::AppState,
+        entity::{fleet_event::FleetEventKind, mqtt::Message},
+        service::{fleet_event_service, machine_service},
+    },
 };
 
 #[derive(Clone)]
 pub struct Server {
     pub client: AsyncClient,
     pub event_loop: Arc<Mutex<EventLoop>>,
+    /// Whether the broker acknowledged the current session.
+    connected: Arc<AtomicBool>,
+    /// Owner and time of the last message of each machine, by MAC.
+    seen: Arc<std::sync::Mutex<HashMap<String, (i64, Instant)>>>,
 }
 
 impl Service for Server {
@@ -48,12 +64,15 @@ impl Service for Server {
         Self {
             client,
             event_loop: Arc::new(Mutex::new(event_loop)),
+            connected: Arc::new(AtomicBool::new(false)),
+            seen: Arc::default(),
         }
     }
 
     async fn serve(&mut self, app_state: Arc<AppState>) {
         tracing::debug!("MQTT service started");
         let ep = self.event_loop.clone();
+        let connected = self.connected.clone();
         tokio::spawn(async move {
             loop {
                 let result = {
@@ -67,13 +86,37 @@ impl Service for Server {
                             &String::from_utf8_lossy(&p.topic),
                             p.payload
                         );
+                        let topic = String::from_utf8_lossy(&p.topic);
+                        let span = tracing::info_span!(
+                            "mqtt_message",
+                            otel.kind = "consumer",
+                            messaging.destination.name = %topic,
+                        );
+                        // MQTT 5 devices may pass the trace context on as
+                        // user properties.
+                        let properties: HashMap<String, String> = p
+                            .properties
+                            .map(|properties| properties.user_properties)
+                            .unwrap_or_default()
+                            .into_iter()
+                            .collect();
+                        tracer::continue_trace(&span, &properties);
                         Self::handle_message(
-                            &String::from_utf8_lossy(&p.topic),
+                            &topic,
                             p.payload.as_ref(),
                             app_state.clone(),
                         )
+                        .instrument(span)
                         .await;
                     }
+                    Ok(Event::Incoming(Incoming::ConnAck(_))) => {
+                        tracing::debug!("MQTT session established");
+                        connected.store(true, SeqCst);
+                    }
+                    Ok(Event::Incoming(Incoming::Disconnect(d))) => {
+                        tracing::warn!("MQTT broker disconnected: {:?}", d);
+                        connected.store(false, SeqCst);
+                    }
                     Ok(Event::Incoming(i)) => {
                         tracing::trace!("Incoming event: {:?}", i);
                     }
@@ -82,6 +125,7 @@ impl Service for Server {
                     }
                     Err(e) => {
                         tracing::error!("MQTT connection error: {:?}", e);
+                        connected.store(false, SeqCst);
                     }
                 }
             }
@@ -97,30 +141,76 @@ impl Service for Server {
 }
 
 impl Server {
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(SeqCst)
+    }
+
+    fn machine_seen(&self, mac: &str, uid: i64) {
+        self.seen
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(machine_service::normalize_mac(mac), (uid, Instant::now()));
+    }
+
+    /// Machines heard from within `window`, by owning account. Forgets the
+    /// others.
+    pub fn online_by_account(&self, window: Duration) -> HashMap<i64, i64> {
+        let mut seen = self.seen.lock().unwrap_or_else(PoisonError::into_inner);
+        seen.retain(|_, (_, at)| at.elapsed() <= window);
+        let mut online = HashMap::new();
+        for (uid, _) in seen.values() {
+            *online.entry(*uid).or_default() += 1;
+        }
+        online
+    }
+
     async fn handle_message(
         topic: &str,
         payload: &[u8],
         app_state: Arc<AppState>,
     ) {
+        let metrics = metricer::metrics();
+        let label = topic_label(topic);
+        metrics.mqtt_messages.with_label_values(&[label]).inc();
+        let parse_failed = || {
+            metrics
+                .mqtt_parse_failures
+                .with_label_values(&[label])
+                .inc()
+        };
+
+        let re =
+            regex_lite::Regex::new(r"([0-9A-Fa-f]{2}[:-]){5}([0-9A-Fa-f]{2})")
+                .unwrap();
+        let Some(mac) = re
+            .captures(topic)
+            .and_then(|m| m.get(0))
+            .map(|m| m.as_str())
+        else {
+            tracing::error!("Invalid topic: {}", topic);
+            return;
+        };
+        match machine_service::owner(&app_state, mac).await {
+            Ok(Some((uid, _))) => {
+                app_state.services.mqtt.machine_seen(mac, uid)
+            }
+            Ok(None) => {}
+            Err(e) => {
+                tracing::warn!("Failed to look up owner of {}: {}", mac, e);
+            }
+        }
+
+        let kind = FleetEventKind::from_topic(topic);
+        // Acks and alerts are only passed on, they are not machine state.
+        let stored = !matches!(
+            kind,
+            Some(FleetEventKind::CommandAck | FleetEventKind::Alert)
+        );
         match serde_json::from_slice::<Message>(payload) {
+            _ if !stored => {}
             Ok(message) => {
-                let re = regex_lite::Regex::new(
-                    r"([0-9A-Fa-f]{2}[:-]){5}([0-9A-Fa-f]{2})",
-                )
-                .unwrap();
-                let mac = match re
-                    .captures(topic)
-                    .and_then(|m| m.get(0))
-                    .map(|m| m.as_str())
-                {
-                    Some(mac) => mac,
-                    None => {
-                        tracing::error!("Invalid topic: {}", topic);
-                        return;
-                    }
-                };
                 tracing::trace!("MAC: {}, Message: {:#?}", mac, message);
-                if let Err(e) = message.store(app_state, mac).await {
+                if let Err(e) = message.store(app_state.clone(), mac).await {
                     tracing::error!(
                         "Error occurred while handling message: {}",
                         e
@@ -128,21 +218,92 @@ impl Server {
                 };
             }
             Err(e) => {
+                parse_failed();
                 tracing::error!(
                     "Error occurred while deserializing payload: {}",
                     e
                 );
             }
         }
+
+        let Some(kind) = kind else {
+            return;
+        };
+        let payload = match serde_json::from_slice(payload) {
+            Ok(payload) => payload,
+            Err(e) => {
+                // A stored message failing here was counted above already.
+                if !stored {
+                    parse_failed();
+                }
+                tracing::error!("Dropping event that is not JSON: {}", e);
+                return;
+            }
+        };
+        if let Err(e) =
+            fleet_event_service::publish(&app_state, mac, kind, payload).await
+        {
+            tracing::error!("Error occurred while publishing event: {}", e);
+        }
     }
 }
 
-#[cfg(test)]
+/// The subscribed filter `topic` matches, without its shared subscription
+/// prefix, to label metrics with rather than the topic and its MAC.
+fn topic_label(topic: &str) -> &'static str {
+    cfg::config()
+        .miner
+        .mqtt
+        .topics
+        .iter()
+        .map(|t| unshared(&t.topics))
+        .find(|filter| filter_matches(filter, topic))
+        .unwrap_or("other")
+}
 
+/// `$share/<group>/<filter>` without `$share/<group>/`.
+fn unshared(filter: &str) -> &str {
+    filter
+        .strip_prefix("$share/")
+        .and_then(|rest| rest.split_once('/'))
+        .map_or(filter, |(_, filter)| filter)
+}
+
+/// Whether the MQTT topic filter `filter`, with `+` and `#` wildcards,
+/// matches `topic`.
+fn filter_matches(filter: &str, topic: &str) -> bool {
+    let mut levels = topic.split('/');
+    for part in filter.split('/') {
+        match (part, levels.next()) {
+            ("#", _) => return true,
+            ("+", Some(_)) => {}
+            (part, Some(level)) if part == level => {}
+            _ => return false,
+        }
+    }
+    levels.next().is_none()
+}
+
+#[cfg(test)]
 mod tests {
     use super::*;
     use crate::miner::bootstrap::AppState;
 
+    #[test]
+    fn test_filter_matches() {
+        let filter = unshared("$share/routine//client/+/heartbeat");
+        assert_eq!(filter, "/client/+/heartbeat");
+        assert!(filter_matches(
+            filter,
+            "/client/00:1A:2B:3C:4D:5E/heartbeat"
+        ));
+        assert!(!filter_matches(filter, "/client/00:1A:2B:3C:4D:5E/alert"));
+        assert!(!filter_matches(filter, "/client/heartbeat"));
+        assert!(filter_matches("/client/#", "/client/a/b/c"));
+        assert!(!filter_matches("/client/+", "/client/a/b"));
+        assert_eq!(unshared("/client/+/alert"), "/client/+/alert");
+    }
+
     #[tokio::test]
     #[ignore]
     async fn test_mqtt_init() {
@@ -0,0 +1,334 @@
+//! Organizations share the fleet of their owner with member accounts.
+//!
+//! Requests act on the caller's own fleet unless they name an organization
+//! in the `X-Org-Id` header. They then act on the fleet of its owner with
+//! the caller's role there, limited to the member's groups if it has any.
+
+use std::collections::HashSet;
+
+use axum::{async_trait, extract::FromRequestParts, http::request::Parts};
+
+use crate::{
+    library::error::{
+        ApiInnerError, AppError,
+        AppError::{ApiError, AuthError},
+        AppResult, AuthInnerError,
+    },
+    miner::{bootstrap::AppState, service::group_service},
+    models::{
+        account::BwAccount,
+        group::ReadBwGroupSchema,
+        machine::BwMachine,
+        organization::{
+            BwOrganization, BwOrganizationMember, CreateBwOrganizationSchema,
+            Membership, SaveBwOrganizationMemberSchema,
+        },
+        types::OrgRole,
+    },
+};
+
+pub const ORG_HEADER: &str = "x-org-id";
+
+/// The fleet a request acts on and what the caller may do with it. Set by
+/// the `auth` middleware.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FleetAccess {
+    /// Organization the access comes from, `None` for the caller's own
+    /// fleet.
+    pub org_id: Option<i64>,
+    /// Account owning the fleet.
+    pub uid: i64,
+    pub role: OrgRole,
+    /// Groups the caller is limited to, `None` for the whole fleet.
+    pub group_ids: Option<Vec<i64>>,
+}
+
+impl FleetAccess {
+    /// Access of an account to its own fleet.
+    pub fn personal(uid: i64) -> Self {
+        Self {
+            org_id: None,
+            uid,
+            role: OrgRole::Owner,
+            group_ids: None,
+        }
+    }
+
+    pub fn is_scoped(&self) -> bool {
+        self.group_ids.is_some()
+    }
+
+    pub fn can_access_group(&self, group_id: i64) -> bool {
+        self.group_ids
+            .as_ref()
+            .is_none_or(|ids| ids.contains(&group_id))
+    }
+
+    /// Scoped members only see machines in one of their groups.
+    pub fn can_access_machine(&self, machine: &BwMachine) -> bool {
+        machine.uid == self.uid
+            && (!self.is_scoped()
+                || machine.group_id.is_some_and(|id| self.can_access_group(id)))
+    }
+
+    pub fn ensure_group(&self, group_id: i64) -> AppResult<()> {
+        if self.can_access_group(group_id) {
+            Ok(())
+        } else {
+            Err(AuthError(AuthInnerError::PermissionDenied))
+        }
+    }
+}
+
+impl From<Membership> for FleetAccess {
+    fn from(membership: Membership) -> Self {
+        Self {
+            org_id: Some(membership.org_id),
+            uid: membership.owner_uid,
+            role: membership.role,
+            group_ids: membership.group_ids,
+        }
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for FleetAccess
+where
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        _state: &S,
+    ) -> AppResult<Self> {
+        parts
+            .extensions
+            .get::<FleetAccess>()
+            .cloned()
+            .ok_or(AuthError(AuthInnerError::InvalidToken))
+    }
+}
+
+/// Resolves what `uid` may do with the fleet of `org_id`, or with its own
+/// fleet if no organization is given.
+pub async fn resolve(
+    state: &AppState,
+    uid: i64,
+    org_id: Option<i64>,
+) -> AppResult<FleetAccess> {
+    match org_id {
+        None => Ok(FleetAccess::personal(uid)),
+        Some(org_id) => Ok(membership(state, org_id, uid).await?.into()),
+    }
+}
+
+async fn membership(
+    state: &AppState,
+    org_id: i64,
+    uid: i64,
+) -> AppResult<Membership> {
+    BwOrganization::fetch_membership(state.get_db(), org_id, uid)
+        .await?
+        .ok_or(AuthError(AuthInnerError::NotOrganizationMember))
+}
+
+/// Checks `group_ids` belong to the fleet and, if the manager handing them
+/// out is itself limited to some groups, are among those.
+async fn check_groups(
+    state: &AppState,
+    manager: &Membership,
+    group_ids: Option<&Vec<i64>>,
+) -> AppResult<()> {
+    let Some(group_ids) = group_ids else {
+        return match manager.group_ids {
+            Some(_) => Err(ApiError(ApiInnerError::InvalidGroupScope)),
+            None => Ok(()),
+        };
+    };
+    let access = FleetAccess::from(manager.clone());
+    if !group_ids.iter().all(|id| access.can_access_group(*id)) {
+        return Err(ApiError(ApiInnerError::InvalidGroupScope));
+    }
+    let unique = group_ids.iter().collect::<HashSet<_>>();
+    let item = ReadBwGroupSchema {
+        group_ids: group_ids.clone(),
+        uid: manager.owner_uid,
+    };
+    let groups = group_service::fetch_by_ids(state, &item).await?;
+    if groups.len() != unique.len() {
+        return Err(ApiError(ApiInnerError::InvalidGroupScope));
+    }
+    Ok(())
+}
+
+pub async fn create(
+    state: &AppState,
+    uid: i64,
+    name: String,
+) -> AppResult<BwOrganization> {
+    if BwOrganization::fetch_organization_by_owner(state.get_db(), uid)
+        .await?
+        .is_some()
+    {
+        return Err(ApiError(ApiInnerError::OrganizationAlreadyExists));
+    }
+    let item = CreateBwOrganizationSchema {
+        owner_uid: uid,
+        name,
+    };
+    Ok(BwOrganization::create_bw_organization(state.get_db(), &item).await?)
+}
+
+pub async fn members(
+    state: &AppState,
+    uid: i64,
+    org_id: i64,
+) -> AppResult<Vec<BwOrganizationMember>> {
+    membership(state, org_id, uid).await?;
+    Ok(
+        BwOrganizationMember::fetch_members_by_org(state.get_db(), org_id)
+            .await?,
+    )
+}
+
+/// Adds the account registered with `email` to the organization.
+pub async fn add_member(
+    state: &AppState,
+    uid: i64,
+    org_id: i64,
+    email: &str,
+    role: OrgRole,
+    group_ids: Option<Vec<i64>>,
+) -> AppResult<()> {
+    let manager = membership(state, org_id, uid).await?;
+    if !manager.role.can_manage(role) {
+        return Err(AuthError(AuthInnerError::PermissionDenied));
+    }
+    check_groups(state, &manager, group_ids.as_ref()).await?;
+    let account = BwAccount::fetch_user_by_email(state.get_db(), email)
+        .await?
+        .ok_or(ApiError(ApiInnerError::AccountNotFound))?;
+
+    let item = SaveBwOrganizationMemberSchema {
+        org_id,
+        uid: account.uid,
+        role,
+        group_ids,
+    };
+    if BwOrganizationMember::add_member(state.get_db(), &item).await? == 0 {
+        return Err(ApiError(ApiInnerError::MemberAlreadyExists));
+    }
+    Ok(())
+}
+
+pub async fn update_member(
+    state: &AppState,
+    uid: i64,
+    item: &SaveBwOrganizationMemberSchema,
+) -> AppResult<()> {
+    let manager = membership(state, item.org_id, uid).await?;
+    let member =
+        BwOrganization::fetch_membership(state.get_db(), item.org_id, item.uid)
+            .await?
+            .ok_or(ApiError(ApiInnerError::MemberNotFound))?;
+    if !manager.role.can_manage(member.role)
+        || !manager.role.can_manage(item.role)
+    {
+        return Err(AuthError(AuthInnerError::PermissionDenied));
+    }
+    check_groups(state, &manager, item.group_ids.as_ref()).await?;
+    BwOrganizationMember::update_member(state.get_db(), item).await?;
+    Ok(())
+}
+
+/// Removes a member. Members other than the owner may also remove
+/// themselves to leave the organization.
+pub async fn remove_member(
+    state: &AppState,
+    uid: i64,
+    org_id: i64,
+    member_uid: i64,
+) -> AppResult<()> {
+    let manager = membership(state, org_id, uid).await?;
+    let member =
+        BwOrganization::fetch_membership(state.get_db(), org_id, member_uid)
+            .await?
+            .ok_or(ApiError(ApiInnerError::MemberNotFound))?;
+    let leaving = member_uid == uid && member.role != OrgRole::Owner;
+    if !leaving && !manager.role.can_manage(member.role) {
+        return Err(AuthError(AuthInnerError::PermissionDenied));
+    }
+    BwOrganizationMember::remove_member(state.get_db(), org_id, member_uid)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::types::ApiScope;
+
+    fn machine(uid: i64, group_id: Option<i64>) -> BwMachine {
+        serde_json::from_value(serde_json::json!({
+            "mac": "28:e2:97:3e:6f:06",
+            "uid": uid,
+            "device_type": "",
+            "device_name": "",
+            "device_ip": "",
+            "group_id": group_id,
+            "policy_id": null,
+            "pool_id": null,
+            "setting": {
+                "crypto_coin": [],
+                "power_modes": [],
+                "pool_maximal": 3,
+                "support_boot": true,
+                "support_reset": true,
+                "support_update": true,
+                "support_led": true
+            },
+            "hardware_version": "",
+            "software_version": "",
+            "exist": true,
+            "created_at": "2024-06-16T09:49:07",
+            "updated_at": null,
+            "deleted_at": null
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn roles_grant_scopes() {
+        assert!(OrgRole::Admin.grants(ApiScope::FleetWrite));
+        assert!(OrgRole::Operator.grants(ApiScope::Operate));
+        assert!(!OrgRole::Operator.grants(ApiScope::FleetWrite));
+        assert!(OrgRole::Viewer.grants(ApiScope::FleetRead));
+        assert!(!OrgRole::Viewer.grants(ApiScope::Operate));
+
+        assert!(OrgRole::Owner.can_manage(OrgRole::Admin));
+        assert!(!OrgRole::Owner.can_manage(OrgRole::Owner));
+        assert!(!OrgRole::Admin.can_manage(OrgRole::Admin));
+        assert!(OrgRole::Admin.can_manage(OrgRole::Viewer));
+    }
+
+    #[test]
+    fn scoped_access_is_limited_to_groups() {
+        let access = FleetAccess {
+            org_id: Some(7),
+            uid: 1,
+            role: OrgRole::Operator,
+            group_ids: Some(vec![10]),
+        };
+        assert!(access.can_access_group(10));
+        assert!(access.ensure_group(11).is_err());
+        assert!(access.can_access_machine(&machine(1, Some(10))));
+        assert!(!access.can_access_machine(&machine(1, Some(11))));
+        assert!(!access.can_access_machine(&machine(1, None)));
+        assert!(!access.can_access_machine(&machine(2, Some(10))));
+
+        let personal = FleetAccess::personal(1);
+        assert!(personal.can_access_machine(&machine(1, None)));
+        assert!(!personal.can_access_machine(&machine(2, None)));
+    }
+}
@@ -0,0 +1,136 @@
+use axum::async_trait;
+
+use super::{get_json, read_json, MarketProvider, ProviderChain, FIXTURE};
+use crate::{
+    library::{cfg::CoinStatProviderConfig, error::AppResult},
+    miner::service::miner_stat::CoinData,
+};
+
+impl ProviderChain<Vec<CoinData>> {
+    /// Fixture providers are skipped unless `fixtures` allows them.
+    pub fn from_config(
+        providers: &[CoinStatProviderConfig],
+        coins: &[String],
+        fixtures: bool,
+    ) -> Self {
+        Self::new(
+            providers
+                .iter()
+                .filter(|p| {
+                    let fixture =
+                        matches!(p, CoinStatProviderConfig::Fixture { .. });
+                    if fixture && !fixtures {
+                        tracing::warn!(
+                            "Skipping the coin stat fixture outside dev"
+                        );
+                    }
+                    fixtures || !fixture
+                })
+                .map(|p| -> Box<dyn MarketProvider<Output = Vec<CoinData>>> {
+                    match p {
+                        CoinStatProviderConfig::Minerstat { host } => {
+                            Box::new(MinerstatProvider::new(host, coins))
+                        }
+                        CoinStatProviderConfig::Fixture { path } => {
+                            Box::new(FixtureProvider::new(path, coins))
+                        }
+                    }
+                })
+                .collect(),
+        )
+    }
+}
+
+/// <https://minerstat.com>, keyless.
+#[derive(Debug, Clone)]
+pub struct MinerstatProvider {
+    pub host: String,
+    pub coins: String,
+}
+
+impl MinerstatProvider {
+    pub fn new(host: &str, coins: &[String]) -> Self {
+        Self {
+            host: host.to_string(),
+            coins: coins.join(","),
+        }
+    }
+}
+
+#[async_trait]
+impl MarketProvider for MinerstatProvider {
+    type Output = Vec<CoinData>;
+
+    fn name(&self) -> &'static str {
+        "minerstat"
+    }
+
+    async fn fetch(&self) -> AppResult<Vec<CoinData>> {
+        let url = format!("{}?list={}", self.host, self.coins);
+        get_json(&url, "coin stat").await
+    }
+}
+
+/// Reads coin stats from a local JSON file in the minerstat format.
+#[derive(Debug, Clone)]
+pub struct FixtureProvider {
+    pub path: String,
+    pub coins: Vec<String>,
+}
+
+impl FixtureProvider {
+    pub fn new(path: &str, coins: &[String]) -> Self {
+        Self {
+            path: path.to_string(),
+            coins: coins.to_vec(),
+        }
+    }
+}
+
+#[async_trait]
+impl MarketProvider for FixtureProvider {
+    type Output = Vec<CoinData>;
+
+    fn name(&self) -> &'static str {
+        FIXTURE
+    }
+
+    async fn fetch(&self) -> AppResult<Vec<CoinData>> {
+        let data: Vec<CoinData> = read_json(&self.path, "coin stat").await?;
+        Ok(data
+            .into_iter()
+            .filter(|c| self.coins.is_empty() || self.coins.contains(&c.coin))
+            .collect())
+    }
+
+    /// The latest update among the coins, as the file is not refreshed.
+    fn fetched_at(&self, data: &Vec<CoinData>) -> Option<i64> {
+        data.iter().map(|c| c.updated as i64).max()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn fixture_provider_filters_coins() {
+        let coins = vec!["BTC".to_string(), "LTC".to_string()];
+        let res =
+            FixtureProvider::new("./fixtures/market/coin_stat.json", &coins)
+                .fetch()
+                .await
+                .unwrap();
+        assert_eq!(res.len(), 2);
+        assert!(res.iter().all(|c| coins.contains(&c.coin)));
+    }
+
+    #[tokio::test]
+    async fn get_coin_stat_works() {
+        let host = "https://api.minerstat.com/v2/coins";
+        let coins =
+            vec!["BTC".to_string(), "BCH".to_string(), "BSV".to_string()];
+        let res = MinerstatProvider::new(host, &coins).fetch().await.unwrap();
+        assert_eq!(res.len(), coins.len());
+    }
+}
@@ -0,0 +1,207 @@
+use axum::async_trait;
+
+use super::{get_json, read_json, MarketProvider, ProviderChain, FIXTURE};
+use crate::{
+    library::{
+        cfg::ExchangeRateProviderConfig,
+        error::{AppError, AppResult},
+    },
+    miner::service::exchange_rate::{ApiResponseBody, RateTable},
+};
+
+impl ProviderChain<RateTable> {
+    /// Fixture providers are skipped unless `fixtures` allows them.
+    pub fn from_config(
+        providers: &[ExchangeRateProviderConfig],
+        fixtures: bool,
+    ) -> Self {
+        Self::new(
+            providers
+                .iter()
+                .filter(|p| {
+                    let fixture =
+                        matches!(p, ExchangeRateProviderConfig::Fixture { .. });
+                    if fixture && !fixtures {
+                        tracing::warn!(
+                            "Skipping the exchange rate fixture outside dev"
+                        );
+                    }
+                    fixtures || !fixture
+                })
+                .map(|p| -> Box<dyn MarketProvider<Output = RateTable>> {
+                    match p {
+                        ExchangeRateProviderConfig::ExchangerateApi {
+                            host,
+                            key,
+                        } => Box::new(ExchangerateApiProvider::new(host, key)),
+                        ExchangeRateProviderConfig::OpenErApi { host } => {
+                            Box::new(OpenErApiProvider::new(host))
+                        }
+                        ExchangeRateProviderConfig::Fixture { path } => {
+                            Box::new(FixtureProvider::new(path))
+                        }
+                    }
+                })
+                .collect(),
+        )
+    }
+}
+
+fn into_rate_table(body: ApiResponseBody) -> AppResult<RateTable> {
+    if body.result != "success" || body.conversion_rates.is_empty() {
+        let es = format!(
+            "Error occurred while getting exchange rate : result {}",
+            body.result
+        );
+        tracing::error!(es);
+        return Err(AppError::Anyhow(anyhow::anyhow!(es)));
+    }
+    Ok(body.into())
+}
+
+/// <https://www.exchangerate-api.com>, requires an API key.
+#[derive(Debug, Clone)]
+pub struct ExchangerateApiProvider {
+    pub host: String,
+    pub key: String,
+}
+
+impl ExchangerateApiProvider {
+    pub fn new(host: &str, key: &str) -> Self {
+        Self {
+            host: host.to_string(),
+            key: key.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl MarketProvider for ExchangerateApiProvider {
+    type Output = RateTable;
+
+    fn name(&self) -> &'static str {
+        "exchangerate_api"
+    }
+
+    async fn fetch(&self) -> AppResult<RateTable> {
+        let url = format!("{}/{}/{}", self.host, self.key, "latest/USD");
+        into_rate_table(get_json(&url, "exchange rate").await?)
+    }
+}
+
+/// The keyless open access endpoint of exchangerate-api.
+#[derive(Debug, Clone)]
+pub struct OpenErApiProvider {
+    pub host: String,
+}
+
+impl OpenErApiProvider {
+    pub fn new(host: &str) -> Self {
+        Self {
+            host: host.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl MarketProvider for OpenErApiProvider {
+    type Output = RateTable;
+
+    fn name(&self) -> &'static str {
+        "open_er_api"
+    }
+
+    async fn fetch(&self) -> AppResult<RateTable> {
+        let url = format!("{}/{}", self.host, "latest/USD");
+        into_rate_table(get_json(&url, "exchange rate").await?)
+    }
+}
+
+/// Reads rates from a local JSON file, for development and tests.
+#[derive(Debug, Clone)]
+pub struct FixtureProvider {
+    pub path: String,
+}
+
+impl FixtureProvider {
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: path.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl MarketProvider for FixtureProvider {
+    type Output = RateTable;
+
+    fn name(&self) -> &'static str {
+        FIXTURE
+    }
+
+    async fn fetch(&self) -> AppResult<RateTable> {
+        into_rate_table(read_json(&self.path, "exchange rate").await?)
+    }
+
+    fn fetched_at(&self, data: &RateTable) -> Option<i64> {
+        Some(data.updated_at)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rust_decimal::Decimal;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn fixture_provider_works() {
+        let table =
+            FixtureProvider::new("./fixtures/market/exchange_rate.json")
+                .fetch()
+                .await
+                .unwrap();
+        assert_eq!(table.base, "USD");
+        assert_eq!(table.rate("USD"), Some(Decimal::ONE));
+        assert!(table.rate("KZT").is_some());
+    }
+
+    #[tokio::test]
+    async fn chain_falls_back_to_fixture() {
+        let providers = [
+            ExchangeRateProviderConfig::Fixture {
+                path: "./fixtures/market/missing.json".to_string(),
+            },
+            ExchangeRateProviderConfig::Fixture {
+                path: "./fixtures/market/exchange_rate.json".to_string(),
+            },
+        ];
+        let chain = ProviderChain::<RateTable>::from_config(&providers, true);
+        let res = chain.fetch().await.unwrap();
+        assert!(res.is_fixture());
+        assert_eq!(res.data.base, "USD");
+        // Dated by the file, not by the fetch.
+        assert_eq!(res.fetched_at, res.data.updated_at);
+
+        let chain = ProviderChain::<RateTable>::from_config(&providers, false);
+        assert!(chain.names().is_empty());
+    }
+
+    #[tokio::test]
+    async fn get_rate_works() {
+        let host = "https://v6.exchangerate-api.com/v6";
+        let key = "83b2f3250fcbb02d93d4e3bf";
+        let _rate = ExchangerateApiProvider::new(host, key)
+            .fetch()
+            .await
+            .unwrap();
+        // eprintln!("{:#?}",rate);
+    }
+
+    #[tokio::test]
+    async fn get_open_rate_works() {
+        let host = "https://open.er-api.com/v6";
+        let rate = OpenErApiProvider::new(host).fetch().await.unwrap();
+        assert_eq!(rate.base, "USD");
+    }
+}
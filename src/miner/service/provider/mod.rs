@@ -0,0 +1,217 @@
+use axum::async_trait;
+use chrono::Utc;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::library::error::{AppError, AppResult};
+
+pub mod coin_stat;
+pub mod exchange_rate;
+
+/// Name of the providers reading checked-in files, whose data is never
+/// persisted and only configured in development.
+pub const FIXTURE: &str = "fixture";
+
+/// A source of market data, such as exchange rates or coin statistics.
+#[async_trait]
+pub trait MarketProvider: Send + Sync {
+    type Output: Send;
+
+    fn name(&self) -> &'static str;
+
+    async fn fetch(&self) -> AppResult<Self::Output>;
+
+    /// When `data` was current, for providers handing out data older than
+    /// the fetch. `None` stands for now.
+    fn fetched_at(&self, _data: &Self::Output) -> Option<i64> {
+        None
+    }
+}
+
+/// Market data as cached in Redis, tagged with where and when it was fetched.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct MarketData<T> {
+    pub provider: String,
+    pub fetched_at: i64,
+    #[serde(default)]
+    pub stale: bool,
+    pub data: T,
+}
+
+impl<T> MarketData<T> {
+    pub fn new(provider: &str, data: T) -> Self {
+        Self {
+            provider: provider.to_string(),
+            fetched_at: Utc::now().timestamp(),
+            stale: false,
+            data,
+        }
+    }
+
+    pub fn is_fixture(&self) -> bool {
+        self.provider == FIXTURE
+    }
+
+    pub fn age(&self) -> i64 {
+        Utc::now().timestamp() - self.fetched_at
+    }
+
+    #[must_use]
+    pub fn with_max_age(mut self, max_age: u64) -> Self {
+        self.stale = self.age() > max_age as i64;
+        self
+    }
+}
+
+/// Providers tried in priority order until one of them succeeds.
+pub struct ProviderChain<T> {
+    providers: Vec<Box<dyn MarketProvider<Output = T>>>,
+}
+
+impl<T: Send> ProviderChain<T> {
+    pub fn new(providers: Vec<Box<dyn MarketProvider<Output = T>>>) -> Self {
+        Self { providers }
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.providers.iter().map(|p| p.name()).collect()
+    }
+
+    pub async fn fetch(&self) -> AppResult<MarketData<T>> {
+        let mut last_error = None;
+        for provider in &self.providers {
+            match provider.fetch().await {
+                Ok(data) => {
+                    let fetched_at = provider.fetched_at(&data);
+                    let mut market = MarketData::new(provider.name(), data);
+                    if let Some(fetched_at) = fetched_at {
+                        market.fetched_at = fetched_at;
+                    }
+                    return Ok(market);
+                }
+                Err(e) => {
+                    tracing::warn!(
+                        "Market provider {} failed, trying next: {}",
+                        provider.name(),
+                        e
+                    );
+                    last_error = Some(e);
+                }
+            }
+        }
+        Err(last_error.unwrap_or_else(|| {
+            AppError::Anyhow(anyhow::anyhow!("No market provider configured"))
+        }))
+    }
+}
+
+pub(crate) async fn get_json<T: DeserializeOwned>(
+    url: &str,
+    what: &str,
+) -> AppResult<T> {
+    let client = reqwest::Client::new();
+    let response = client.get(url).send().await.map_err(|e| {
+        let es = format!("Error occurred while getting {what} : {e}");
+        tracing::error!(es);
+        anyhow::anyhow!(es)
+    })?;
+
+    if !response.status().is_success() {
+        let es = format!(
+            "Error occurred while getting {what} : {}",
+            response.status()
+        );
+        tracing::error!(es);
+        return Err(AppError::Anyhow(anyhow::anyhow!(es)));
+    }
+    Ok(response.json().await.map_err(|e| {
+        let es = format!("Error occurred while getting {what} : {e}");
+        tracing::error!(es);
+        anyhow::anyhow!(es)
+    })?)
+}
+
+pub(crate) async fn read_json<T: DeserializeOwned>(
+    path: &str,
+    what: &str,
+) -> AppResult<T> {
+    let content = tokio::fs::read_to_string(path).await.map_err(|e| {
+        anyhow::anyhow!(
+            "Error occurred while reading {what} fixture {path}: {e}"
+        )
+    })?;
+    Ok(serde_json::from_str(&content).map_err(|e| {
+        anyhow::anyhow!(
+            "Error occurred while parsing {what} fixture {path}: {e}"
+        )
+    })?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Failing;
+
+    #[async_trait]
+    impl MarketProvider for Failing {
+        type Output = u32;
+
+        fn name(&self) -> &'static str {
+            "failing"
+        }
+
+        async fn fetch(&self) -> AppResult<u32> {
+            Err(AppError::Unknown("down".to_string()))
+        }
+    }
+
+    struct Fixed(u32);
+
+    #[async_trait]
+    impl MarketProvider for Fixed {
+        type Output = u32;
+
+        fn name(&self) -> &'static str {
+            "fixed"
+        }
+
+        async fn fetch(&self) -> AppResult<u32> {
+            Ok(self.0)
+        }
+    }
+
+    #[tokio::test]
+    async fn chain_falls_back_to_next_provider() {
+        let chain: ProviderChain<u32> =
+            ProviderChain::new(vec![Box::new(Failing), Box::new(Fixed(7))]);
+        let res = chain.fetch().await.unwrap();
+        assert_eq!(res.provider, "fixed");
+        assert_eq!(res.data, 7);
+        assert!(!res.stale);
+    }
+
+    #[tokio::test]
+    async fn chain_uses_first_healthy_provider() {
+        let chain: ProviderChain<u32> =
+            ProviderChain::new(vec![Box::new(Fixed(1)), Box::new(Fixed(2))]);
+        assert_eq!(chain.fetch().await.unwrap().data, 1);
+    }
+
+    #[tokio::test]
+    async fn chain_fails_when_all_providers_fail() {
+        let chain: ProviderChain<u32> =
+            ProviderChain::new(vec![Box::new(Failing), Box::new(Failing)]);
+        assert!(chain.fetch().await.is_err());
+        let empty: ProviderChain<u32> = ProviderChain::new(vec![]);
+        assert!(empty.fetch().await.is_err());
+    }
+
+    #[test]
+    fn market_data_staleness() {
+        let mut data = MarketData::new("fixed", 1);
+        assert!(!data.clone().with_max_age(60).stale);
+        data.fetched_at -= 120;
+        assert!(data.with_max_age(60).stale);
+    }
+}
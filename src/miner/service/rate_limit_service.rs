@@ -0,0 +1,113 @@
+//! Fixed-window rate limits counted in the storage.
+//!
+//! A window opens with the first request of a subject and the subject is
+//! refused once it made more than the limit before the window closes,
+//! being told how long is left. Policies are configured in
+//! `miner.rate_limit` and picked by the route, see
+//! [`crate::miner::api::middleware::rate_limit`].
+
+use crate::{
+    library::{
+        cfg::{self, RateLimitPolicy},
+        error::AppResult,
+        storage::Storage,
+    },
+    miner::bootstrap::{constants::REDIS_RATE_LIMIT_KEY, AppState},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Policy {
+    Login,
+    Register,
+    Email,
+    Code,
+    Api,
+    Sign,
+}
+
+impl Policy {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Login => "login",
+            Self::Register => "register",
+            Self::Email => "email",
+            Self::Code => "code",
+            Self::Api => "api",
+            Self::Sign => "sign",
+        }
+    }
+
+    fn config(self) -> RateLimitPolicy {
+        let config = &cfg::config().miner.rate_limit;
+        match self {
+            Self::Login => config.login,
+            Self::Register => config.register,
+            Self::Email => config.email,
+            Self::Code => config.code,
+            Self::Api => config.api,
+            Self::Sign => config.sign,
+        }
+    }
+}
+
+/// Counts a request against `key`, returning the seconds until the window
+/// closes if it went over `limit`.
+pub async fn over_limit(
+    storage: &dyn Storage,
+    key: &str,
+    limit: i64,
+    window_secs: u64,
+) -> AppResult<Option<i64>> {
+    let window = window_secs as i64;
+    let count = storage.incr_ex(key, window).await?;
+    if count <= limit {
+        return Ok(None);
+    }
+    let retry = storage.ttl(key).await?.unwrap_or(window);
+    Ok(Some(retry.max(1)))
+}
+
+/// Counts a request of `subject` under `policy`, see [`over_limit`]. Never
+/// refuses while rate limiting is disabled.
+pub async fn hit(
+    state: &AppState,
+    policy: Policy,
+    subject: &str,
+) -> AppResult<Option<i64>> {
+    if !cfg::config().miner.rate_limit.enabled {
+        return Ok(None);
+    }
+    let RateLimitPolicy { limit, window_secs } = policy.config();
+    let key = format!("{REDIS_RATE_LIMIT_KEY}:{}:{subject}", policy.name());
+    over_limit(state.get_storage(), &key, limit, window_secs).await
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        http::{header::RETRY_AFTER, StatusCode},
+        response::IntoResponse,
+    };
+
+    use super::*;
+    use crate::library::{
+        error::{AppError::AuthError, AuthInnerError},
+        storage::MemoryStorage,
+    };
+
+    #[tokio::test]
+    async fn test_over_limit() {
+        let storage = MemoryStorage::new();
+        for _ in 0..3 {
+            assert_eq!(over_limit(&storage, "ip", 3, 60).await.unwrap(), None);
+        }
+        assert_eq!(over_limit(&storage, "ip", 3, 60).await.unwrap(), Some(60));
+        // Subjects are counted apart.
+        assert_eq!(over_limit(&storage, "uid", 3, 60).await.unwrap(), None);
+
+        let response =
+            AuthError(AuthInnerError::TooManyRequests(60)).into_response();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[RETRY_AFTER], "60");
+    }
+}
@@ -0,0 +1,444 @@
+//! Login sessions kept in [`Storage`].
+//!
+//! Every login starts a session (a refresh token family) identified by a
+//! `sid`. The session remembers the `jti` of the only refresh token that may
+//! currently be used; each refresh rotates it. Presenting an older refresh
+//! token means it leaked, so the whole session is revoked.
+
+use std::{
+    convert::Infallible,
+    net::{IpAddr, SocketAddr},
+};
+
+use axum::{
+    async_trait,
+    extract::{ConnectInfo, FromRequestParts},
+    http::{header::USER_AGENT, request::Parts, HeaderMap},
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use ulid::Ulid;
+
+use crate::{
+    library::{
+        cfg,
+        error::{AppError::AuthError, AppResult, AuthInnerError},
+        storage::{Pipeline, Storage},
+    },
+    miner::{
+        bootstrap::{
+            constants::{REDIS_SESSION_INDEX_KEY, REDIS_SESSION_KEY},
+            AppState,
+        },
+        service::jwt_service::{Claims, SessionIds, TokenSchema},
+    },
+    models::{account::BwAccount, types::AccountStatus},
+};
+
+const MAX_DEVICE_LEN: usize = 256;
+
+/// Where a request comes from, as far as we can tell.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ClientInfo {
+    pub ip: Option<String>,
+    pub device: Option<String>,
+}
+
+/// Whether `ip` is one of `proxies`, given as addresses or CIDR blocks.
+fn is_trusted(proxies: &[String], ip: IpAddr) -> bool {
+    let bits = |ip: IpAddr| match ip.to_canonical() {
+        IpAddr::V4(v4) => (u32::from(v4) as u128, 32),
+        IpAddr::V6(v6) => (u128::from(v6), 128),
+    };
+    let (ip, len) = bits(ip);
+    proxies.iter().any(|proxy| {
+        let (addr, prefix) = proxy.split_once('/').unwrap_or((proxy, ""));
+        let Ok(addr) = addr.trim().parse::<IpAddr>() else {
+            return false;
+        };
+        let (addr, addr_len) = bits(addr);
+        let prefix = match prefix.trim() {
+            "" => addr_len,
+            prefix => match prefix.parse::<u32>() {
+                Ok(prefix) if prefix <= addr_len => prefix,
+                _ => return false,
+            },
+        };
+        let shift = addr_len - prefix;
+        addr_len == len && (shift == addr_len || ip >> shift == addr >> shift)
+    })
+}
+
+impl ClientInfo {
+    /// Takes the client IP from `X-Forwarded-For` or `X-Real-IP` only when
+    /// they were set by one of `trusted_proxies`, the socket `peer` being
+    /// the client otherwise. Forwarded hops are walked from the nearest,
+    /// stopping at the first one that is not a trusted proxy itself.
+    pub fn from_parts(
+        headers: &HeaderMap,
+        peer: Option<SocketAddr>,
+        trusted_proxies: &[String],
+    ) -> Self {
+        let header = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+        };
+        let peer = peer.map(|addr| addr.ip().to_canonical());
+        let ip = match peer {
+            Some(peer) if is_trusted(trusted_proxies, peer) => {
+                let forwarded = header("x-forwarded-for")
+                    .or_else(|| header("x-real-ip"))
+                    .unwrap_or_default();
+                let mut ip = peer;
+                for hop in forwarded.rsplit(',') {
+                    let Ok(hop) = hop.trim().parse::<IpAddr>() else {
+                        break;
+                    };
+                    ip = hop.to_canonical();
+                    if !is_trusted(trusted_proxies, ip) {
+                        break;
+                    }
+                }
+                Some(ip)
+            }
+            peer => peer,
+        };
+        let device = header(USER_AGENT.as_str())
+            .map(|ua| ua.chars().take(MAX_DEVICE_LEN).collect());
+        Self {
+            ip: ip.map(|ip| ip.to_string()),
+            device,
+        }
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for ClientInfo
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        _state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        let peer = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| *addr);
+        Ok(Self::from_parts(
+            &parts.headers,
+            peer,
+            &cfg::config().miner.trusted_proxies,
+        ))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Session {
+    pub sid: String,
+    pub uid: i64,
+    pub jti: String,
+    pub device: Option<String>,
+    pub ip: Option<String>,
+    pub created_at: i64,
+    pub last_used_at: i64,
+}
+
+impl Session {
+    fn new(uid: i64, client: &ClientInfo) -> Self {
+        let now = Utc::now().timestamp();
+        Self {
+            sid: Ulid::new().to_string(),
+            uid,
+            jti: Ulid::new().to_string(),
+            device: client.device.clone(),
+            ip: client.ip.clone(),
+            created_at: now,
+            last_used_at: now,
+        }
+    }
+
+    /// The same session with a fresh refresh token id.
+    fn rotated(&self, client: &ClientInfo) -> Self {
+        Self {
+            jti: Ulid::new().to_string(),
+            device: client.device.clone().or_else(|| self.device.clone()),
+            ip: client.ip.clone().or_else(|| self.ip.clone()),
+            last_used_at: Utc::now().timestamp(),
+            ..self.clone()
+        }
+    }
+
+    fn ids(&self) -> SessionIds<'_> {
+        SessionIds {
+            sid: &self.sid,
+            jti: &self.jti,
+        }
+    }
+}
+
+fn session_key(uid: i64, sid: &str) -> String {
+    format!("{REDIS_SESSION_KEY}:{uid}:{sid}")
+}
+
+fn index_key(uid: i64) -> String {
+    format!("{REDIS_SESSION_INDEX_KEY}:{uid}")
+}
+
+fn session_ttl() -> u64 {
+    cfg::config().miner.refresh_token.secret_expiration.into()
+}
+
+async fn store(storage: &dyn Storage, session: &Session) -> AppResult<()> {
+    let json = serde_json::to_string(session)
+        .map_err(|e| anyhow::anyhow!("Error serializing session: {e}"))?;
+    let mut pipeline = Pipeline::new();
+    pipeline.set_ex(
+        &session_key(session.uid, &session.sid),
+        &json,
+        session_ttl(),
+    );
+    index(&mut pipeline, session);
+    Ok(storage.exec(&pipeline).await?)
+}
+
+async fn touch_index(
+    storage: &dyn Storage,
+    session: &Session,
+) -> AppResult<()> {
+    let mut pipeline = Pipeline::new();
+    index(&mut pipeline, session);
+    Ok(storage.exec(&pipeline).await?)
+}
+
+/// Queues listing `session` in the index of its user.
+fn index(pipeline: &mut Pipeline, session: &Session) {
+    let index = index_key(session.uid);
+    pipeline
+        .hset(&index, &session.sid, &session.last_used_at.to_string())
+        .expire(&index, session_ttl() as i64);
+}
+
+async fn fetch_raw(
+    storage: &dyn Storage,
+    uid: i64,
+    sid: &str,
+) -> AppResult<Option<(String, Session)>> {
+    let Some(raw) = storage.get(&session_key(uid, sid)).await? else {
+        return Ok(None);
+    };
+    match serde_json::from_str(&raw) {
+        Ok(session) => Ok(Some((raw, session))),
+        Err(e) => {
+            tracing::error!("Error parsing session {sid} of user {uid}: {e}");
+            Ok(None)
+        }
+    }
+}
+
+/// Starts a new session for `user` and issues its first pair of tokens.
+pub async fn issue(
+    state: &AppState,
+    user: &BwAccount,
+    client: &ClientInfo,
+) -> AppResult<TokenSchema> {
+    let session = Session::new(user.uid, client);
+    let storage = state.get_storage();
+    store(storage, &session).await?;
+    Claims::generate_tokens_for_user(user, session.ids()).await
+}
+
+/// Issues new tokens for an existing session, e.g. after the account
+/// status changed, starting a new session if `sid` is gone.
+pub async fn reissue(
+    state: &AppState,
+    user: &BwAccount,
+    sid: Option<&str>,
+    client: &ClientInfo,
+) -> AppResult<TokenSchema> {
+    let storage = state.get_storage();
+    if let Some(sid) = sid {
+        if let Some((_, session)) = fetch_raw(storage, user.uid, sid).await? {
+            let session = session.rotated(client);
+            store(storage, &session).await?;
+            return Claims::generate_tokens_for_user(user, session.ids()).await;
+        }
+    }
+    issue(state, user, client).await
+}
+
+/// Rotates the refresh token of the session `claims` belongs to.
+///
+/// A refresh token that is not the latest one of its session has either
+/// been used already or raced another refresh, so the session is revoked.
+pub async fn rotate(
+    state: &AppState,
+    user: &BwAccount,
+    claims: &Claims,
+    client: &ClientInfo,
+) -> AppResult<TokenSchema> {
+    if user.status == AccountStatus::Suspend {
+        return Err(AuthError(AuthInnerError::AccountSuspended));
+    }
+    let (Some(sid), Some(jti)) = (claims.sid.as_deref(), claims.jti.as_deref())
+    else {
+        return Err(AuthError(AuthInnerError::InvalidToken));
+    };
+
+    let storage = state.get_storage();
+    let (raw, session) = fetch_raw(storage, user.uid, sid)
+        .await?
+        .ok_or(AuthError(AuthInnerError::InvalidToken))?;
+
+    let next = session.rotated(client);
+    let json = serde_json::to_string(&next)
+        .map_err(|e| anyhow::anyhow!("Error serializing session: {e}"))?;
+    if session.jti != jti
+        || !storage
+            .compare_and_swap(
+                &session_key(user.uid, sid),
+                &raw,
+                &json,
+                session_ttl(),
+            )
+            .await?
+    {
+        tracing::warn!(
+            "Refresh token reuse detected for user {}, revoking session {}",
+            user.uid,
+            sid
+        );
+        revoke(state, user.uid, sid).await?;
+        return Err(AuthError(AuthInnerError::RefreshTokenReused));
+    }
+    touch_index(storage, &next).await?;
+
+    Claims::generate_tokens_for_user(user, next.ids()).await
+}
+
+/// Whether the session `sid` of `uid` is still alive.
+pub async fn is_active(
+    state: &AppState,
+    uid: i64,
+    sid: &str,
+) -> AppResult<bool> {
+    let storage = state.get_storage();
+    Ok(storage.get(&session_key(uid, sid)).await?.is_some())
+}
+
+pub async fn list(state: &AppState, uid: i64) -> AppResult<Vec<Session>> {
+    let storage = state.get_storage();
+    let index = index_key(uid);
+    let sids = storage.hkeys(&index).await?;
+    let mut sessions = Vec::with_capacity(sids.len());
+    for sid in sids {
+        match fetch_raw(storage, uid, &sid).await? {
+            Some((_, session)) => sessions.push(session),
+            // Expired on its own, drop it from the index.
+            None => storage.hdel(&index, &sid).await?,
+        }
+    }
+    sessions.sort_by_key(|s| std::cmp::Reverse(s.last_used_at));
+    Ok(sessions)
+}
+
+pub async fn revoke(state: &AppState, uid: i64, sid: &str) -> AppResult<()> {
+    let storage = state.get_storage();
+    storage.del(&session_key(uid, sid)).await?;
+    storage.hdel(&index_key(uid), sid).await?;
+    Ok(())
+}
+
+pub async fn revoke_all(state: &AppState, uid: i64) -> AppResult<()> {
+    let storage = state.get_storage();
+    let index = index_key(uid);
+    let sids = storage.hkeys(&index).await?;
+    for sid in sids {
+        storage.del(&session_key(uid, &sid)).await?;
+    }
+    storage.del(&index).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::HeaderValue;
+
+    use super::*;
+
+    #[test]
+    fn client_info_trusts_forwarded_only_from_proxies() {
+        let proxies = ["10.0.0.0/8".to_string(), "::1".to_string()];
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "x-forwarded-for",
+            HeaderValue::from_static("198.51.100.1, 203.0.113.7, 10.0.0.2"),
+        );
+        headers.insert(USER_AGENT, HeaderValue::from_static("miner-app/1.0"));
+
+        // The nearest hop that is not a proxy, not the spoofable first one.
+        let proxy = "10.1.2.3:5000".parse().ok();
+        let client = ClientInfo::from_parts(&headers, proxy, &proxies);
+        assert_eq!(client.ip.as_deref(), Some("203.0.113.7"));
+        assert_eq!(client.device.as_deref(), Some("miner-app/1.0"));
+        let proxy = "[::1]:5000".parse().ok();
+        let client = ClientInfo::from_parts(&headers, proxy, &proxies);
+        assert_eq!(client.ip.as_deref(), Some("203.0.113.7"));
+
+        // Anyone else may not pick their own address.
+        let peer = "192.0.2.9:5000".parse().ok();
+        let client = ClientInfo::from_parts(&headers, peer, &proxies);
+        assert_eq!(client.ip.as_deref(), Some("192.0.2.9"));
+        let client = ClientInfo::from_parts(&headers, peer, &[]);
+        assert_eq!(client.ip.as_deref(), Some("192.0.2.9"));
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-real-ip", HeaderValue::from_static("203.0.113.8"));
+        let client = ClientInfo::from_parts(&headers, proxy, &proxies);
+        assert_eq!(client.ip.as_deref(), Some("203.0.113.8"));
+        let client = ClientInfo::from_parts(&HeaderMap::new(), proxy, &proxies);
+        assert_eq!(client.ip.as_deref(), Some("::1"));
+        assert_eq!(client.device, None);
+    }
+
+    #[test]
+    fn trusted_proxies_match_blocks() {
+        let proxies = [
+            "10.0.0.0/8".to_string(),
+            "2001:db8::/32".to_string(),
+            "192.0.2.1".to_string(),
+            "not an ip".to_string(),
+        ];
+        let trusted = |ip: &str| is_trusted(&proxies, ip.parse().unwrap());
+        assert!(trusted("10.255.0.1"));
+        assert!(!trusted("11.0.0.1"));
+        assert!(trusted("2001:db8:1::1"));
+        assert!(!trusted("2001:db9::1"));
+        assert!(trusted("192.0.2.1"));
+        assert!(trusted("::ffff:192.0.2.1"));
+        assert!(!trusted("192.0.2.2"));
+        assert!(is_trusted(
+            &["0.0.0.0/0".to_string()],
+            "8.8.8.8".parse().unwrap()
+        ));
+    }
+
+    #[test]
+    fn rotated_session_keeps_identity() {
+        let client = ClientInfo {
+            ip: Some("203.0.113.7".to_string()),
+            device: None,
+        };
+        let session = Session::new(1, &client);
+        let rotated = session.rotated(&ClientInfo::default());
+        assert_eq!(rotated.sid, session.sid);
+        assert_eq!(rotated.created_at, session.created_at);
+        assert_eq!(rotated.ip, session.ip);
+        assert_ne!(rotated.jti, session.jti);
+    }
+}
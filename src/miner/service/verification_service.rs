@@ -0,0 +1,190 @@
+//! One-time codes sent by email to verify an address or reset a forgotten
+//! password.
+//!
+//! Only a hash of each code is stored. A code is used up by the first
+//! correct guess, replaced by the next one issued for the same purpose and
+//! rejected for good after `max_attempts` wrong guesses.
+
+use crate::{
+    library::{
+        cfg, crypto,
+        error::{
+            ApiInnerError,
+            AppError::{ApiError, AuthError},
+            AppResult, AuthInnerError,
+        },
+        templator::{CodeParams, MailTemplate},
+        DB,
+    },
+    miner::{bootstrap::AppState, service::message_queue},
+    models::{
+        account::{BwAccount, ResetPasswordSchema},
+        account_code::{BwAccountCode, CodeCheck, CreateBwAccountCodeSchema},
+        types::{AccountStatus, CodePurpose},
+    },
+};
+
+/// Uppercases and strips whitespace so a code copied from an email still
+/// matches.
+fn normalize_code(code: &str) -> String {
+    code.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+fn generate_code() -> String {
+    crypto::random_words(cfg::config().miner.verification.code_length)
+        .to_ascii_uppercase()
+}
+
+fn ttl(purpose: CodePurpose) -> i64 {
+    let config = &cfg::config().miner.verification;
+    match purpose {
+        CodePurpose::VerifyEmail => config.verify_email_ttl,
+        CodePurpose::ResetPassword => config.reset_password_ttl,
+    }
+}
+
+/// Issues a code for `purpose`, returning it in plain text to be sent.
+/// Fails with `CodeIntervalRejection` if one was issued too recently.
+pub async fn issue(
+    db: &DB,
+    uid: i64,
+    purpose: CodePurpose,
+) -> AppResult<String> {
+    let interval = cfg::config().miner.verification.resend_interval;
+    if BwAccountCode::fetch_seconds_since_issued(db, uid, purpose)
+        .await?
+        .is_some_and(|since| since < interval)
+    {
+        return Err(ApiError(ApiInnerError::CodeIntervalRejection));
+    }
+
+    let code = generate_code();
+    let item = CreateBwAccountCodeSchema {
+        uid,
+        purpose,
+        code_hash: crypto::hash_token(&code),
+        ttl: ttl(purpose),
+    };
+    BwAccountCode::issue(db, &item).await?;
+    Ok(code)
+}
+
+/// Checks `code`, using it up if it is correct.
+pub async fn check(
+    db: &DB,
+    uid: i64,
+    purpose: CodePurpose,
+    code: &str,
+) -> AppResult<()> {
+    let code_hash = crypto::hash_token(&normalize_code(code));
+    let max_attempts = cfg::config().miner.verification.max_attempts;
+    match BwAccountCode::consume(db, uid, purpose, &code_hash, max_attempts)
+        .await?
+    {
+        CodeCheck::Valid => Ok(()),
+        CodeCheck::Wrong => Err(AuthError(AuthInnerError::WrongCode)),
+        CodeCheck::Expired => Err(AuthError(AuthInnerError::CodeExpired)),
+        CodeCheck::TooManyAttempts => {
+            Err(AuthError(AuthInnerError::TooManyCodeAttempts))
+        }
+    }
+}
+
+/// Verifies the email of account `uid`, activating it.
+pub async fn verify_email(db: &DB, uid: i64, code: &str) -> AppResult<()> {
+    check(db, uid, CodePurpose::VerifyEmail, code).await?;
+    BwAccount::update_email_verified_at(db, uid).await?;
+    Ok(())
+}
+
+/// Issues a password reset code for the account registered with `email`.
+/// Returns `None`, rather than an error, when there is no such account or
+/// it is suspended, so callers cannot tell which addresses are registered.
+pub async fn forgot_password(
+    db: &DB,
+    email: &str,
+) -> AppResult<Option<(BwAccount, String)>> {
+    let Some(user) = BwAccount::fetch_user_by_email(db, email).await? else {
+        return Ok(None);
+    };
+    if user.status == AccountStatus::Suspend {
+        return Ok(None);
+    }
+    let code = issue(db, user.uid, CodePurpose::ResetPassword).await?;
+    Ok(Some((user, code)))
+}
+
+/// Sets a new password given a reset code, clearing any lockout. Returns
+/// the uid of the account so its sessions and tokens can be revoked.
+///
+/// A missing or expired code fails like a wrong one, as does an unknown
+/// email, so the answer does not tell whether an address is registered.
+pub async fn reset_password(
+    db: &DB,
+    email: &str,
+    code: &str,
+    password: &str,
+) -> AppResult<i64> {
+    let user = BwAccount::fetch_user_by_email(db, email)
+        .await?
+        .ok_or(AuthError(AuthInnerError::WrongCode))?;
+    check(db, user.uid, CodePurpose::ResetPassword, code)
+        .await
+        .map_err(|e| match e {
+            AuthError(AuthInnerError::CodeExpired) => {
+                AuthError(AuthInnerError::WrongCode)
+            }
+            e => e,
+        })?;
+    if user.status == AccountStatus::Suspend {
+        return Err(AuthError(AuthInnerError::AccountSuspended));
+    }
+
+    let item = ResetPasswordSchema {
+        uid: user.uid,
+        password: crypto::hash_password(password.as_bytes())?,
+    };
+    BwAccount::update_password_by_uid(db, &item).await?;
+    BwAccount::reset_failed_login(db, user.uid).await?;
+    Ok(user.uid)
+}
+
+/// Emails `code` to `user` in their language.
+pub async fn send_code(
+    state: &AppState,
+    user: &BwAccount,
+    purpose: CodePurpose,
+    code: &str,
+) -> AppResult<()> {
+    let params = CodeParams {
+        name: user.name.clone(),
+        code: code.to_string(),
+        expires_in: ttl(purpose) / 60,
+    };
+    let template = match purpose {
+        CodePurpose::VerifyEmail => MailTemplate::Activation(params),
+        CodePurpose::ResetPassword => MailTemplate::ResetPassword(params),
+    };
+    message_queue::queue_email(
+        state,
+        Some(user.uid),
+        &user.email,
+        &template,
+        &user.system_lang,
+    )
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_code() {
+        assert_eq!(normalize_code(" ab1 2c\n"), "AB12C");
+    }
+}
@@ -1,22 +1,28 @@
 use serde::{Deserialize, Serialize};
-use sqlx::types::chrono::NaiveDateTime;
+use sqlx::{types::chrono::NaiveDateTime, PgExecutor};
+use utoipa::ToSchema;
 
 use crate::{
-    library::{error::InnerResult, DB},
+    library::{cfg::LoginGuardConfig, error::InnerResult, DB},
     models::types::{AccountStatus, Currency, Language},
 };
 
-#[derive(sqlx::FromRow, Debug, Serialize, Deserialize, Clone)]
+#[derive(sqlx::FromRow, Debug, Serialize, Deserialize, Clone, ToSchema)]
 #[sqlx(rename_all = "lowercase")]
 pub struct BwAccount {
     pub uid: i64,
     pub name: String,
     pub email: String,
     pub email_verified_at: Option<NaiveDateTime>,
+    /// The hash, never sent or cached, so accounts read back from the cache
+    /// have none. Passwords are checked against accounts read from the
+    /// database.
+    #[serde(skip_serializing, default)]
     pub password: String,
     pub failed_attempt: i32,
     pub status: AccountStatus,
     pub last_login: Option<NaiveDateTime>,
+    pub locked_until: Option<NaiveDateTime>,
 
     pub local_currency: Currency,
     pub system_lang: Language,
@@ -32,6 +38,22 @@ pub struct ResetPasswordSchema {
     pub password: String,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct UpdatePreferencesSchema {
+    pub uid: i64,
+    pub local_currency: Option<Currency>,
+    pub system_lang: Option<Language>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SearchBwAccountSchema {
+    /// Matched against the name and email, case-insensitively.
+    pub keyword: Option<String>,
+    pub status: Option<AccountStatus>,
+    pub offset: i64,
+    pub limit: i64,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct CreateBwAccountSchema {
     pub name: String,
@@ -48,7 +70,7 @@ impl BwAccount {
             INSERT INTO bw_account (name, email, password) VALUES ($1, $2, $3)
             RETURNING uid,name,email,email_verified_at,password,
             local_currency,system_lang ,
-            status, failed_attempt, last_login,
+            status, failed_attempt, last_login, locked_until,
             created_at,updated_at,deleted_at
             "#;
         let map = sqlx::query_as(sql)
@@ -83,7 +105,7 @@ impl BwAccount {
     ) -> InnerResult<Vec<Self>> {
         let sql = r#"SELECT uid,name,email,email_verified_at,password,
             local_currency,system_lang ,
-            status, failed_attempt, last_login,
+            status, failed_attempt, last_login, locked_until,
             created_at,updated_at,deleted_at
             FROM bw_account WHERE name = $1 or email = $1"#;
         let map = sqlx::query_as(sql).bind(email_or_name);
@@ -96,7 +118,7 @@ impl BwAccount {
         uid: i64,
     ) -> InnerResult<Option<Self>> {
         let sql = r#"SELECT uid,name,email,email_verified_at,password,
-            local_currency, system_lang, status, failed_attempt, last_login,
+            local_currency, system_lang, status, failed_attempt, last_login, locked_until,
             created_at,updated_at,deleted_at
             FROM bw_account WHERE uid = $1"#;
 
@@ -110,7 +132,7 @@ impl BwAccount {
     ) -> InnerResult<Option<Self>> {
         let sql = r#"SELECT uid,name,email,email_verified_at,password,
             local_currency,system_lang ,
-            status , failed_attempt, last_login,
+            status , failed_attempt, last_login, locked_until,
             created_at,updated_at,deleted_at
             FROM bw_account WHERE email = $1"#;
         let map = sqlx::query_as(sql).bind(email);
@@ -126,20 +148,85 @@ impl BwAccount {
         Ok(map.execute(db).await?.rows_affected())
     }
 
+    /// Counts a failed login, starting over when the previous failure is
+    /// older than `policy.failure_window_secs`. Once `policy.max_attempts`
+    /// is reached the account is locked, for twice as long after every
+    /// further failure.
+    pub async fn record_failed_login(
+        db: &DB,
+        uid: i64,
+        policy: &LoginGuardConfig,
+    ) -> InnerResult<Option<(i32, Option<NaiveDateTime>)>> {
+        let sql = r#"
+            WITH failed AS (
+                SELECT uid, CASE WHEN last_failed_at IS NULL
+                    OR last_failed_at < (now() AT TIME ZONE 'UTC')
+                        - make_interval(secs => $5::FLOAT8)
+                    THEN 1 ELSE failed_attempt + 1 END AS attempt
+                FROM bw_account WHERE uid = $1 FOR UPDATE
+            )
+            UPDATE bw_account a SET failed_attempt = f.attempt,
+            last_failed_at = now() AT TIME ZONE 'UTC',
+            locked_until = CASE WHEN f.attempt >= $2 THEN
+                (now() AT TIME ZONE 'UTC') + make_interval(secs => LEAST($4::FLOAT8,
+                    $3 * POWER(2, LEAST(f.attempt - $2, 20))))
+                ELSE NULL END
+            FROM failed f
+            WHERE a.uid = f.uid
+            RETURNING a.failed_attempt, a.locked_until
+            "#;
+        let map = sqlx::query_as(sql)
+            .bind(uid)
+            .bind(policy.max_attempts)
+            .bind(policy.lock_secs as f64)
+            .bind(policy.max_lock_secs as f64)
+            .bind(policy.failure_window_secs as f64);
+        Ok(map.fetch_optional(db).await?)
+    }
+
+    pub async fn reset_failed_login(db: &DB, uid: i64) -> InnerResult<u64> {
+        let map = sqlx::query(
+            r#"UPDATE bw_account SET failed_attempt = 0, locked_until = NULL,
+        last_failed_at = NULL
+        WHERE uid = $1 AND (failed_attempt <> 0 OR locked_until IS NOT NULL)"#,
+        )
+        .bind(uid);
+        Ok(map.execute(db).await?.rows_affected())
+    }
+
+    /// Clears the lock of `uid` if it has run out, keeping the failure
+    /// count so that the next lock lasts longer. Returns that count when a
+    /// lock was cleared.
+    pub async fn release_expired_lock(
+        db: &DB,
+        uid: i64,
+    ) -> InnerResult<Option<i32>> {
+        let map = sqlx::query_scalar(
+            r#"UPDATE bw_account SET locked_until = NULL
+        WHERE uid = $1 AND locked_until <= (now() AT TIME ZONE 'UTC')
+        RETURNING failed_attempt"#,
+        )
+        .bind(uid);
+        Ok(map.fetch_optional(db).await?)
+    }
+
+    /// Marks the email as verified, activating the account unless it was
+    /// suspended in the meantime.
     pub async fn update_email_verified_at(
         db: &DB,
         uid: i64,
     ) -> InnerResult<u64> {
         let map = sqlx::query(
-            r#"UPDATE bw_account set email_verified_at = now(), status = 'active'
+            r#"UPDATE bw_account set email_verified_at = now(),
+        status = CASE WHEN status = 'inactive' THEN 'active' ELSE status END
         WHERE uid = $1"#,
         )
-            .bind(uid);
+        .bind(uid);
         Ok(map.execute(db).await?.rows_affected())
     }
 
     pub async fn update_password_by_uid(
-        db: &DB,
+        db: impl PgExecutor<'_>,
         item: &ResetPasswordSchema,
     ) -> InnerResult<u64> {
         let map = sqlx::query(
@@ -151,6 +238,85 @@ impl BwAccount {
         Ok(map.execute(db).await?.rows_affected())
     }
 
+    pub async fn update_preferences(
+        db: &DB,
+        item: &UpdatePreferencesSchema,
+    ) -> InnerResult<u64> {
+        let map = sqlx::query(
+            r#"UPDATE bw_account SET local_currency = COALESCE($1, local_currency),
+        system_lang = COALESCE($2, system_lang)
+        WHERE uid = $3"#,
+        )
+        .bind(&item.local_currency)
+        .bind(&item.system_lang)
+        .bind(item.uid);
+        Ok(map.execute(db).await?.rows_affected())
+    }
+
+    /// Pages through accounts matching `item`, newest first, along with the
+    /// total number of matches.
+    pub async fn search_accounts(
+        db: &DB,
+        item: &SearchBwAccountSchema,
+    ) -> InnerResult<(Vec<Self>, i64)> {
+        let filter = r#"
+            WHERE ($1::TEXT IS NULL OR name ILIKE '%' || $1 || '%'
+                OR email ILIKE '%' || $1 || '%')
+            AND ($2::account_status IS NULL OR status = $2)
+            "#;
+        let sql = format!(
+            r#"SELECT uid,name,email,email_verified_at,password,
+            local_currency, system_lang, status, failed_attempt, last_login, locked_until,
+            created_at,updated_at,deleted_at
+            FROM bw_account {filter}
+            ORDER BY created_at DESC, uid DESC
+            OFFSET $3 LIMIT $4"#
+        );
+        let accounts = sqlx::query_as(&sql)
+            .bind(&item.keyword)
+            .bind(item.status)
+            .bind(item.offset)
+            .bind(item.limit)
+            .fetch_all(db)
+            .await?;
+        let total = sqlx::query_scalar(&format!(
+            "SELECT COUNT(*) FROM bw_account {filter}"
+        ))
+        .bind(&item.keyword)
+        .bind(item.status)
+        .fetch_one(db)
+        .await?;
+        Ok((accounts, total))
+    }
+
+    pub async fn suspend(
+        db: impl PgExecutor<'_>,
+        uid: i64,
+    ) -> InnerResult<u64> {
+        let map = sqlx::query(
+            r#"UPDATE bw_account SET status = 'suspended'
+        WHERE uid = $1 AND status <> 'suspended'"#,
+        )
+        .bind(uid);
+        Ok(map.execute(db).await?.rows_affected())
+    }
+
+    /// Lifts a suspension, back to `active` if the email was verified and
+    /// `inactive` otherwise.
+    pub async fn reactivate(
+        db: impl PgExecutor<'_>,
+        uid: i64,
+    ) -> InnerResult<u64> {
+        let map = sqlx::query(
+            r#"UPDATE bw_account SET status = CASE
+            WHEN email_verified_at IS NULL THEN 'inactive'::account_status
+            ELSE 'active'::account_status END
+        WHERE uid = $1 AND status = 'suspended'"#,
+        )
+        .bind(uid);
+        Ok(map.execute(db).await?.rows_affected())
+    }
+
     pub async fn check_user_active_by_uid(
         db: &DB,
         uid: i64,
@@ -211,6 +377,24 @@ mod tests {
         Ok(())
     }
 
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("users")))]
+    async fn test_password_is_not_serialized(pool: PgPool) -> sqlx::Result<()> {
+        let account = BwAccount::fetch_user_by_uid(&pool, ACCOUNT_ID)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(!account.password.is_empty());
+        let json = serde_json::to_string(&account).unwrap();
+        assert!(!json.contains("password"), "{json}");
+        assert!(!json.contains(&account.password));
+
+        let cached: BwAccount = serde_json::from_str(&json).unwrap();
+        assert_eq!(cached.uid, ACCOUNT_ID);
+        assert!(cached.password.is_empty());
+
+        Ok(())
+    }
+
     #[sqlx::test(fixtures(path = "../../fixtures", scripts("users")))]
     async fn test_check_user_exists_by_email(pool: PgPool) -> sqlx::Result<()> {
         let exists = BwAccount::check_user_exists_by_email(&pool, MY_EMAIL)
@@ -262,6 +446,29 @@ mod tests {
             .await
             .unwrap();
         assert!(is_active.unwrap()); // Assuming the account is active
+        let user = BwAccount::fetch_user_by_uid(&pool, ACCOUNT_ID)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(user.status, AccountStatus::Active);
+        assert!(user.email_verified_at.is_some());
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("users")))]
+    async fn test_update_email_verified_at_keeps_suspension(
+        pool: PgPool,
+    ) -> sqlx::Result<()> {
+        BwAccount::suspend(&pool, ACCOUNT_ID).await.unwrap();
+        BwAccount::update_email_verified_at(&pool, ACCOUNT_ID)
+            .await
+            .unwrap();
+        let user = BwAccount::fetch_user_by_uid(&pool, ACCOUNT_ID)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(user.status, AccountStatus::Suspend);
 
         Ok(())
     }
@@ -280,6 +487,41 @@ mod tests {
         Ok(())
     }
 
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("users")))]
+    async fn test_update_preferences(pool: PgPool) -> sqlx::Result<()> {
+        let item = UpdatePreferencesSchema {
+            uid: ACCOUNT_ID,
+            local_currency: Some(Currency("KZT".to_string())),
+            system_lang: None,
+        };
+        let rows_affected =
+            BwAccount::update_preferences(&pool, &item).await.unwrap();
+        assert_eq!(rows_affected, 1);
+        let account = BwAccount::fetch_user_by_uid(&pool, ACCOUNT_ID)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(account.local_currency.as_str(), "KZT");
+        assert_eq!(account.system_lang, Language::default());
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("users")))]
+    async fn test_update_preferences_with_unknown_currency(
+        pool: PgPool,
+    ) -> sqlx::Result<()> {
+        let item = UpdatePreferencesSchema {
+            uid: ACCOUNT_ID,
+            local_currency: Some(Currency("XXX".to_string())),
+            system_lang: None,
+        };
+        let result = BwAccount::update_preferences(&pool, &item).await;
+        assert!(result.is_err());
+
+        Ok(())
+    }
+
     #[sqlx::test(fixtures(path = "../../fixtures", scripts("users")))]
     async fn test_register_account_with_existing_email(
         pool: PgPool,
@@ -400,4 +642,199 @@ mod tests {
 
         Ok(())
     }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("users")))]
+    async fn test_record_failed_login_locks_progressively(
+        pool: PgPool,
+    ) -> sqlx::Result<()> {
+        let policy = LoginGuardConfig {
+            max_attempts: 3,
+            lock_secs: 60,
+            max_lock_secs: 150,
+            ..Default::default()
+        };
+        for attempt in 1..3 {
+            let (failed, locked_until) =
+                BwAccount::record_failed_login(&pool, ACCOUNT_ID, &policy)
+                    .await
+                    .unwrap()
+                    .unwrap();
+            assert_eq!(failed, attempt);
+            assert!(locked_until.is_none());
+        }
+
+        let now = chrono::Utc::now().naive_utc();
+        let lock_secs =
+            |until: Option<NaiveDateTime>| (until.unwrap() - now).num_seconds();
+        let (_, first) =
+            BwAccount::record_failed_login(&pool, ACCOUNT_ID, &policy)
+                .await
+                .unwrap()
+                .unwrap();
+        let (_, second) =
+            BwAccount::record_failed_login(&pool, ACCOUNT_ID, &policy)
+                .await
+                .unwrap()
+                .unwrap();
+        let (_, third) =
+            BwAccount::record_failed_login(&pool, ACCOUNT_ID, &policy)
+                .await
+                .unwrap()
+                .unwrap();
+        assert!((55..=65).contains(&lock_secs(first)));
+        assert!((115..=125).contains(&lock_secs(second)));
+        assert!((145..=155).contains(&lock_secs(third)));
+
+        let account = BwAccount::fetch_user_by_uid(&pool, ACCOUNT_ID)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(account.locked_until, third);
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("users")))]
+    async fn test_record_failed_login_forgets_old_failures(
+        pool: PgPool,
+    ) -> sqlx::Result<()> {
+        let policy = LoginGuardConfig {
+            max_attempts: 2,
+            ..Default::default()
+        };
+        BwAccount::record_failed_login(&pool, ACCOUNT_ID, &policy)
+            .await
+            .unwrap();
+        sqlx::query(
+            "UPDATE bw_account SET last_failed_at = last_failed_at - \
+             INTERVAL '2 days' WHERE uid = $1",
+        )
+        .bind(ACCOUNT_ID)
+        .execute(&pool)
+        .await?;
+        let (failed, locked_until) =
+            BwAccount::record_failed_login(&pool, ACCOUNT_ID, &policy)
+                .await
+                .unwrap()
+                .unwrap();
+        assert_eq!(failed, 1);
+        assert!(locked_until.is_none());
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("users")))]
+    async fn test_release_expired_lock(pool: PgPool) -> sqlx::Result<()> {
+        let policy = LoginGuardConfig {
+            max_attempts: 1,
+            ..Default::default()
+        };
+        BwAccount::record_failed_login(&pool, ACCOUNT_ID, &policy)
+            .await
+            .unwrap();
+        // Still running.
+        let released = BwAccount::release_expired_lock(&pool, ACCOUNT_ID)
+            .await
+            .unwrap();
+        assert_eq!(released, None);
+
+        sqlx::query(
+            "UPDATE bw_account SET locked_until = locked_until - \
+             INTERVAL '1 day' WHERE uid = $1",
+        )
+        .bind(ACCOUNT_ID)
+        .execute(&pool)
+        .await?;
+        let released = BwAccount::release_expired_lock(&pool, ACCOUNT_ID)
+            .await
+            .unwrap();
+        assert_eq!(released, Some(1));
+        let account = BwAccount::fetch_user_by_uid(&pool, ACCOUNT_ID)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(account.locked_until.is_none());
+        assert_eq!(account.failed_attempt, 1);
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("users")))]
+    async fn test_reset_failed_login(pool: PgPool) -> sqlx::Result<()> {
+        let policy = LoginGuardConfig {
+            max_attempts: 1,
+            ..Default::default()
+        };
+        BwAccount::record_failed_login(&pool, ACCOUNT_ID, &policy)
+            .await
+            .unwrap();
+        let rows_affected = BwAccount::reset_failed_login(&pool, ACCOUNT_ID)
+            .await
+            .unwrap();
+        assert_eq!(rows_affected, 1);
+
+        let account = BwAccount::fetch_user_by_uid(&pool, ACCOUNT_ID)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(account.failed_attempt, 0);
+        assert!(account.locked_until.is_none());
+
+        // Nothing to reset the second time.
+        let rows_affected = BwAccount::reset_failed_login(&pool, ACCOUNT_ID)
+            .await
+            .unwrap();
+        assert_eq!(rows_affected, 0);
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("users")))]
+    async fn test_search_accounts(pool: PgPool) -> sqlx::Result<()> {
+        let item = SearchBwAccountSchema {
+            keyword: Some("VAINJOKER@TUTA".to_string()),
+            status: None,
+            offset: 0,
+            limit: 10,
+        };
+        let (accounts, total) =
+            BwAccount::search_accounts(&pool, &item).await.unwrap();
+        assert_eq!(total, 1);
+        assert_eq!(accounts[0].email, MY_EMAIL);
+
+        let item = SearchBwAccountSchema {
+            keyword: None,
+            status: Some(AccountStatus::Suspend),
+            offset: 0,
+            limit: 10,
+        };
+        let (accounts, total) =
+            BwAccount::search_accounts(&pool, &item).await.unwrap();
+        assert_eq!(total, 0);
+        assert!(accounts.is_empty());
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("users")))]
+    async fn test_suspend_and_reactivate(pool: PgPool) -> sqlx::Result<()> {
+        assert_eq!(BwAccount::suspend(&pool, ACCOUNT_ID).await.unwrap(), 1);
+        assert_eq!(BwAccount::suspend(&pool, ACCOUNT_ID).await.unwrap(), 0);
+        let account = BwAccount::fetch_user_by_uid(&pool, ACCOUNT_ID)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(account.status, AccountStatus::Suspend);
+
+        assert_eq!(BwAccount::reactivate(&pool, ACCOUNT_ID).await.unwrap(), 1);
+        assert_eq!(BwAccount::reactivate(&pool, ACCOUNT_ID).await.unwrap(), 0);
+        let account = BwAccount::fetch_user_by_uid(&pool, ACCOUNT_ID)
+            .await
+            .unwrap()
+            .unwrap();
+        // The fixture account never verified its email.
+        assert_eq!(account.status, AccountStatus::Inactive);
+
+        Ok(())
+    }
 }
@@ -0,0 +1,233 @@
+use serde::{Deserialize, Serialize};
+use sqlx::types::chrono::NaiveDateTime;
+
+use crate::{
+    library::{error::InnerResult, DB},
+    models::types::CodePurpose,
+};
+
+#[derive(sqlx::FromRow, Debug, Serialize, Deserialize, Clone)]
+#[sqlx(rename_all = "lowercase")]
+pub struct BwAccountCode {
+    pub id: i64,
+    pub uid: i64,
+    pub purpose: CodePurpose,
+    #[serde(skip_serializing)]
+    pub code_hash: String,
+    pub attempts: i32,
+    pub expires_at: NaiveDateTime,
+    pub used_at: Option<NaiveDateTime>,
+
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateBwAccountCodeSchema {
+    pub uid: i64,
+    pub purpose: CodePurpose,
+    pub code_hash: String,
+    /// Seconds until the code expires.
+    pub ttl: i64,
+}
+
+/// Outcome of checking a code against the latest one issued.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodeCheck {
+    /// The code matched and is now used up.
+    Valid,
+    /// The code did not match. The attempt has been counted.
+    Wrong,
+    /// No unused code was issued or it has expired.
+    Expired,
+    /// The code was guessed at too often and no longer accepted.
+    TooManyAttempts,
+}
+
+impl BwAccountCode {
+    /// Issues a code, discarding the unused ones issued before it for the
+    /// same purpose so only the latest email is valid.
+    pub async fn issue(
+        db: &DB,
+        item: &CreateBwAccountCodeSchema,
+    ) -> InnerResult<Self> {
+        let mut tx = db.begin().await?;
+        sqlx::query(
+            r#"DELETE FROM bw_account_code
+        WHERE uid = $1 AND purpose = $2 AND used_at IS NULL"#,
+        )
+        .bind(item.uid)
+        .bind(item.purpose)
+        .execute(&mut *tx)
+        .await?;
+        let sql = r#"
+            INSERT INTO bw_account_code (uid, purpose, code_hash, expires_at)
+            VALUES ($1, $2, $3, now() + make_interval(secs => $4))
+            RETURNING id, uid, purpose, code_hash, attempts, expires_at,
+            used_at, created_at
+            "#;
+        let code = sqlx::query_as(sql)
+            .bind(item.uid)
+            .bind(item.purpose)
+            .bind(&item.code_hash)
+            .bind(item.ttl as f64)
+            .fetch_one(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(code)
+    }
+
+    /// Seconds since the latest code for `purpose` was issued, used or not.
+    pub async fn fetch_seconds_since_issued(
+        db: &DB,
+        uid: i64,
+        purpose: CodePurpose,
+    ) -> InnerResult<Option<i64>> {
+        let sql = r#"
+            SELECT EXTRACT(EPOCH FROM now() - MAX(created_at))::BIGINT
+            FROM bw_account_code WHERE uid = $1 AND purpose = $2
+            "#;
+        let map = sqlx::query_scalar(sql).bind(uid).bind(purpose);
+        Ok(map.fetch_one(db).await?)
+    }
+
+    /// Checks `code_hash` against the latest unused code, using it up on a
+    /// match and counting the attempt otherwise.
+    pub async fn consume(
+        db: &DB,
+        uid: i64,
+        purpose: CodePurpose,
+        code_hash: &str,
+        max_attempts: i32,
+    ) -> InnerResult<CodeCheck> {
+        let mut tx = db.begin().await?;
+        let sql = r#"
+            SELECT id, uid, purpose, code_hash, attempts, expires_at,
+            used_at, created_at
+            FROM bw_account_code
+            WHERE uid = $1 AND purpose = $2 AND used_at IS NULL
+            AND expires_at > now()
+            ORDER BY created_at DESC, id DESC LIMIT 1
+            FOR UPDATE
+            "#;
+        let code: Option<Self> = sqlx::query_as(sql)
+            .bind(uid)
+            .bind(purpose)
+            .fetch_optional(&mut *tx)
+            .await?;
+        let Some(code) = code else {
+            return Ok(CodeCheck::Expired);
+        };
+        if code.attempts >= max_attempts {
+            return Ok(CodeCheck::TooManyAttempts);
+        }
+
+        let check = if code.code_hash == code_hash {
+            sqlx::query(
+                "UPDATE bw_account_code SET used_at = now() WHERE id = $1",
+            )
+            .bind(code.id)
+            .execute(&mut *tx)
+            .await?;
+            CodeCheck::Valid
+        } else {
+            sqlx::query(
+                "UPDATE bw_account_code SET attempts = attempts + 1 WHERE id = $1",
+            )
+            .bind(code.id)
+            .execute(&mut *tx)
+            .await?;
+            CodeCheck::Wrong
+        };
+        tx.commit().await?;
+        Ok(check)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::PgPool;
+
+    use super::*;
+
+    const ACCOUNT_ID: i64 = 6192889942050345985;
+
+    async fn issue(pool: &PgPool, code_hash: &str, ttl: i64) -> BwAccountCode {
+        let item = CreateBwAccountCodeSchema {
+            uid: ACCOUNT_ID,
+            purpose: CodePurpose::ResetPassword,
+            code_hash: code_hash.to_string(),
+            ttl,
+        };
+        BwAccountCode::issue(pool, &item).await.unwrap()
+    }
+
+    async fn consume(pool: &PgPool, code_hash: &str) -> CodeCheck {
+        BwAccountCode::consume(
+            pool,
+            ACCOUNT_ID,
+            CodePurpose::ResetPassword,
+            code_hash,
+            3,
+        )
+        .await
+        .unwrap()
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("users")))]
+    async fn test_consume_is_single_use(pool: PgPool) -> sqlx::Result<()> {
+        assert_eq!(consume(&pool, "hash").await, CodeCheck::Expired);
+
+        issue(&pool, "hash", 600).await;
+        assert_eq!(consume(&pool, "hash").await, CodeCheck::Valid);
+        assert_eq!(consume(&pool, "hash").await, CodeCheck::Expired);
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("users")))]
+    async fn test_consume_counts_attempts(pool: PgPool) -> sqlx::Result<()> {
+        issue(&pool, "hash", 600).await;
+        for _ in 0..3 {
+            assert_eq!(consume(&pool, "wrong").await, CodeCheck::Wrong);
+        }
+        // Locked even for the right code once the attempts are used up.
+        assert_eq!(consume(&pool, "hash").await, CodeCheck::TooManyAttempts);
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("users")))]
+    async fn test_consume_expired(pool: PgPool) -> sqlx::Result<()> {
+        issue(&pool, "hash", 0).await;
+        assert_eq!(consume(&pool, "hash").await, CodeCheck::Expired);
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("users")))]
+    async fn test_issue_replaces_unused(pool: PgPool) -> sqlx::Result<()> {
+        issue(&pool, "first", 600).await;
+        issue(&pool, "second", 600).await;
+        assert_eq!(consume(&pool, "first").await, CodeCheck::Wrong);
+        assert_eq!(consume(&pool, "second").await, CodeCheck::Valid);
+
+        let since = BwAccountCode::fetch_seconds_since_issued(
+            &pool,
+            ACCOUNT_ID,
+            CodePurpose::ResetPassword,
+        )
+        .await
+        .unwrap();
+        assert!(since.is_some_and(|s| s < 60));
+        let since = BwAccountCode::fetch_seconds_since_issued(
+            &pool,
+            ACCOUNT_ID,
+            CodePurpose::VerifyEmail,
+        )
+        .await
+        .unwrap();
+        assert!(since.is_none());
+
+        Ok(())
+    }
+}
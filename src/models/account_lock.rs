@@ -0,0 +1,102 @@
+use serde::{Deserialize, Serialize};
+use sqlx::types::chrono::NaiveDateTime;
+
+use crate::{
+    library::{error::InnerResult, DB},
+    models::types::LockEvent,
+};
+
+#[derive(sqlx::FromRow, Debug, Serialize, Deserialize, Clone)]
+#[sqlx(rename_all = "lowercase")]
+pub struct BwAccountLock {
+    pub id: i64,
+    pub uid: i64,
+    pub event: LockEvent,
+    pub ip: Option<String>,
+    pub failed_attempt: i32,
+    pub locked_until: Option<NaiveDateTime>,
+
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Clone)]
+pub struct CreateBwAccountLockSchema<'a> {
+    pub uid: i64,
+    pub event: LockEvent,
+    pub ip: Option<&'a str>,
+    pub failed_attempt: i32,
+    pub locked_until: Option<NaiveDateTime>,
+}
+
+impl BwAccountLock {
+    pub async fn insert_event(
+        db: &DB,
+        item: &CreateBwAccountLockSchema<'_>,
+    ) -> InnerResult<Self> {
+        let sql = r#"
+            INSERT INTO bw_account_lock
+            (uid, event, ip, failed_attempt, locked_until)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING id, uid, event, ip, failed_attempt, locked_until,
+            created_at
+            "#;
+        let map = sqlx::query_as(sql)
+            .bind(item.uid)
+            .bind(item.event)
+            .bind(item.ip)
+            .bind(item.failed_attempt)
+            .bind(item.locked_until);
+        Ok(map.fetch_one(db).await?)
+    }
+
+    pub async fn fetch_events_by_uid(
+        db: &DB,
+        uid: i64,
+    ) -> InnerResult<Vec<Self>> {
+        let sql = r#"
+            SELECT id, uid, event, ip, failed_attempt, locked_until, created_at
+            FROM bw_account_lock WHERE uid = $1
+            ORDER BY created_at DESC, id DESC
+            "#;
+        let map = sqlx::query_as(sql).bind(uid);
+        Ok(map.fetch_all(db).await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::PgPool;
+
+    use super::*;
+
+    const ACCOUNT_ID: i64 = 6192889942050345985;
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("users")))]
+    async fn test_insert_and_fetch_events(pool: PgPool) -> sqlx::Result<()> {
+        let item = CreateBwAccountLockSchema {
+            uid: ACCOUNT_ID,
+            event: LockEvent::Locked,
+            ip: Some("203.0.113.7"),
+            failed_attempt: 5,
+            locked_until: Some(chrono::Utc::now().naive_utc()),
+        };
+        let event = BwAccountLock::insert_event(&pool, &item).await.unwrap();
+        assert_eq!(event.event, LockEvent::Locked);
+
+        let item = CreateBwAccountLockSchema {
+            event: LockEvent::Unlocked,
+            ip: None,
+            locked_until: None,
+            ..item
+        };
+        BwAccountLock::insert_event(&pool, &item).await.unwrap();
+
+        let events = BwAccountLock::fetch_events_by_uid(&pool, ACCOUNT_ID)
+            .await
+            .unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].event, LockEvent::Unlocked);
+
+        Ok(())
+    }
+}
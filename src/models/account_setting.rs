@@ -1,42 +1,403 @@
+use chrono::NaiveDateTime;
 use serde_derive::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
 use crate::library::{error::InnerResult, DB};
 
-#[derive(sqlx::FromRow, Debug, Serialize, Deserialize, Clone)]
+/// A device enrollment key. Devices present it when they sign in over gRPC
+/// to be attached to the account owning it.
+#[derive(sqlx::FromRow, Debug, Serialize, Deserialize, Clone, ToSchema)]
 #[sqlx(rename_all = "lowercase")]
+#[schema(as = EnrollmentKey)]
 pub struct BwAccountSetting {
+    pub key_id: i64,
     pub uid: i64,
+    pub name: String,
     pub key: String,
+    pub expires_at: Option<NaiveDateTime>,
+    pub last_used_at: Option<NaiveDateTime>,
+
+    pub created_at: NaiveDateTime,
+    pub updated_at: Option<NaiveDateTime>,
+    pub deleted_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateBwAccountSettingSchema {
+    pub uid: i64,
+    pub name: String,
+    pub key: String,
+    pub expires_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct RevokeBwAccountSettingSchema {
+    pub key_id: i64,
+    pub uid: i64,
 }
 
 impl BwAccountSetting {
     pub async fn create_bw_account_setting(
         db: &DB,
-        item: &BwAccountSetting,
+        item: &CreateBwAccountSettingSchema,
     ) -> InnerResult<Self> {
         let sql = r#"
-            INSERT INTO bw_account_setting (uid, key) VALUES ($1, $2)
-            RETURNING uid, key
+            INSERT INTO bw_account_setting (uid, name, key, expires_at)
+            VALUES ($1, $2, $3, $4)
+            RETURNING key_id, uid, name, key, expires_at, last_used_at,
+            created_at, updated_at, deleted_at
             "#;
-        let map = sqlx::query_as(sql).bind(item.uid).bind(&item.key);
+        let map = sqlx::query_as(sql)
+            .bind(item.uid)
+            .bind(&item.name)
+            .bind(&item.key)
+            .bind(item.expires_at);
         Ok(map.fetch_one(db).await?)
     }
 
-    pub async fn fetch_key_by_uid(db: &DB, uid: i64) -> InnerResult<String> {
+    /// Lists the keys of `uid` that are neither revoked nor expired.
+    pub async fn fetch_keys_by_uid(
+        db: &DB,
+        uid: i64,
+    ) -> InnerResult<Vec<Self>> {
         let sql = r#"
-        SELECT key
-        FROM bw_account_setting WHERE uid = $1
+        SELECT key_id, uid, name, key, expires_at, last_used_at,
+        created_at, updated_at, deleted_at
+        FROM bw_account_setting
+        WHERE uid = $1 AND deleted_at IS NULL
+        AND (expires_at IS NULL OR expires_at > (now() AT TIME ZONE 'UTC'))
+        ORDER BY created_at, key_id
         "#;
-        let map = sqlx::query_scalar(sql).bind(uid);
-        Ok(map.fetch_one(db).await?)
+        let map = sqlx::query_as(sql).bind(uid);
+        Ok(map.fetch_all(db).await?)
+    }
+
+    /// Creates the key only if the account has no usable key yet. Calls
+    /// for the same account are serialized on its row, so concurrent logins
+    /// create at most one key.
+    pub async fn create_first_key(
+        db: &DB,
+        item: &CreateBwAccountSettingSchema,
+    ) -> InnerResult<Option<Self>> {
+        let mut tx = db.begin().await?;
+        sqlx::query("SELECT uid FROM bw_account WHERE uid = $1 FOR UPDATE")
+            .bind(item.uid)
+            .fetch_optional(&mut *tx)
+            .await?;
+        let created = sqlx::query_as(
+            r#"
+            INSERT INTO bw_account_setting (uid, name, key, expires_at)
+            SELECT $1, $2, $3, $4
+            WHERE NOT EXISTS (
+                SELECT 1 FROM bw_account_setting
+                WHERE uid = $1 AND deleted_at IS NULL
+                AND (expires_at IS NULL OR expires_at > (now() AT TIME ZONE 'UTC'))
+            )
+            RETURNING key_id, uid, name, key, expires_at, last_used_at,
+            created_at, updated_at, deleted_at
+            "#,
+        )
+        .bind(item.uid)
+        .bind(&item.name)
+        .bind(&item.key)
+        .bind(item.expires_at)
+        .fetch_optional(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(created)
+    }
+
+    /// Resolves a key to its account, if the key is still usable.
+    pub async fn fetch_uid_by_key(
+        db: &DB,
+        key: &str,
+    ) -> InnerResult<Option<(i64, Option<NaiveDateTime>)>> {
+        let sql = r#"
+        SELECT uid, expires_at
+        FROM bw_account_setting WHERE key = $1 AND deleted_at IS NULL
+        AND (expires_at IS NULL OR expires_at > (now() AT TIME ZONE 'UTC'))
+        "#;
+        let map = sqlx::query_as(sql).bind(key);
+        Ok(map.fetch_optional(db).await?)
     }
 
-    pub async fn fetch_uid_by_key(db: &DB, key: &str) -> InnerResult<i64> {
+    /// Records a use of the key. Updates at most once a minute so devices
+    /// signing in often do not write on every request.
+    pub async fn update_last_used_at(db: &DB, key: &str) -> InnerResult<u64> {
+        let map = sqlx::query(
+            r#"UPDATE bw_account_setting
+        SET last_used_at = now() AT TIME ZONE 'UTC'
+        WHERE key = $1 AND (last_used_at IS NULL
+            OR last_used_at < (now() AT TIME ZONE 'UTC') - INTERVAL '1 minute')"#,
+        )
+        .bind(key);
+        Ok(map.execute(db).await?.rows_affected())
+    }
+
+    /// Revokes a key, returning it so its cache entry can be dropped.
+    pub async fn revoke_key(
+        db: &DB,
+        item: &RevokeBwAccountSettingSchema,
+    ) -> InnerResult<Option<Self>> {
         let sql = r#"
-        SELECT uid
-        FROM bw_account_setting WHERE key = $1
+        UPDATE bw_account_setting SET deleted_at = now()
+        WHERE key_id = $1 AND uid = $2 AND deleted_at IS NULL
+        RETURNING key_id, uid, name, key, expires_at, last_used_at,
+        created_at, updated_at, deleted_at
         "#;
-        let map = sqlx::query_scalar(sql).bind(key);
-        Ok(map.fetch_one(db).await?)
+        let map = sqlx::query_as(sql).bind(item.key_id).bind(item.uid);
+        Ok(map.fetch_optional(db).await?)
+    }
+
+    /// Replaces a key with a new one of the same name and expiry, returning
+    /// the revoked and the new key.
+    pub async fn rotate_key(
+        db: &DB,
+        item: &RevokeBwAccountSettingSchema,
+        new_key: &str,
+    ) -> InnerResult<Option<(Self, Self)>> {
+        let mut tx = db.begin().await?;
+        let old: Option<Self> = sqlx::query_as(
+            r#"
+        UPDATE bw_account_setting SET deleted_at = now()
+        WHERE key_id = $1 AND uid = $2 AND deleted_at IS NULL
+        RETURNING key_id, uid, name, key, expires_at, last_used_at,
+        created_at, updated_at, deleted_at
+        "#,
+        )
+        .bind(item.key_id)
+        .bind(item.uid)
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(old) = old else {
+            return Ok(None);
+        };
+        let new = sqlx::query_as(
+            r#"
+            INSERT INTO bw_account_setting (uid, name, key, expires_at)
+            VALUES ($1, $2, $3, $4)
+            RETURNING key_id, uid, name, key, expires_at, last_used_at,
+            created_at, updated_at, deleted_at
+            "#,
+        )
+        .bind(old.uid)
+        .bind(&old.name)
+        .bind(new_key)
+        .bind(old.expires_at)
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(Some((old, new)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::{
+        postgres::{PgConnectOptions, PgPoolOptions},
+        Executor, PgPool,
+    };
+
+    use super::*;
+
+    const ACCOUNT_ID: i64 = 6192889942050345985;
+    const KEY: &str = "dd99c54c08bb2752f5f8ad6e526243cbea722";
+
+    fn schema(name: &str, key: &str) -> CreateBwAccountSettingSchema {
+        CreateBwAccountSettingSchema {
+            uid: ACCOUNT_ID,
+            name: name.to_string(),
+            key: key.to_string(),
+            expires_at: None,
+        }
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("machine")))]
+    async fn test_multiple_keys_per_account(pool: PgPool) -> sqlx::Result<()> {
+        BwAccountSetting::create_bw_account_setting(
+            &pool,
+            &schema("farm 2", "second-key"),
+        )
+        .await
+        .unwrap();
+        let keys = BwAccountSetting::fetch_keys_by_uid(&pool, ACCOUNT_ID)
+            .await
+            .unwrap();
+        assert_eq!(keys.len(), 2);
+        assert_eq!(keys[1].name, "farm 2");
+
+        let (uid, _) = BwAccountSetting::fetch_uid_by_key(&pool, "second-key")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(uid, ACCOUNT_ID);
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("machine")))]
+    async fn test_expired_key_is_unusable(pool: PgPool) -> sqlx::Result<()> {
+        let item = CreateBwAccountSettingSchema {
+            expires_at: Some(
+                chrono::Utc::now().naive_utc() - chrono::Duration::days(1),
+            ),
+            ..schema("expired", "expired-key")
+        };
+        BwAccountSetting::create_bw_account_setting(&pool, &item)
+            .await
+            .unwrap();
+        assert!(BwAccountSetting::fetch_uid_by_key(&pool, "expired-key")
+            .await
+            .unwrap()
+            .is_none());
+        let keys = BwAccountSetting::fetch_keys_by_uid(&pool, ACCOUNT_ID)
+            .await
+            .unwrap();
+        assert_eq!(keys.len(), 1);
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("machine")))]
+    async fn test_expiry_is_utc(
+        pool_options: PgPoolOptions,
+        connect_options: PgConnectOptions,
+    ) -> sqlx::Result<()> {
+        // Far ahead of UTC, so a local `now()` would be past the expiry.
+        let pool = pool_options
+            .after_connect(|conn, _| {
+                Box::pin(async move {
+                    conn.execute("SET TIME ZONE 'Pacific/Kiritimati'").await?;
+                    Ok(())
+                })
+            })
+            .connect_with(connect_options)
+            .await?;
+        let item = CreateBwAccountSettingSchema {
+            expires_at: Some(
+                chrono::Utc::now().naive_utc() + chrono::Duration::hours(1),
+            ),
+            ..schema("soon", "soon-key")
+        };
+        BwAccountSetting::create_bw_account_setting(&pool, &item)
+            .await
+            .unwrap();
+        assert!(BwAccountSetting::fetch_uid_by_key(&pool, "soon-key")
+            .await
+            .unwrap()
+            .is_some());
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("machine")))]
+    async fn test_update_last_used_at(pool: PgPool) -> sqlx::Result<()> {
+        assert_eq!(
+            BwAccountSetting::update_last_used_at(&pool, KEY)
+                .await
+                .unwrap(),
+            1
+        );
+        // At most once a minute.
+        assert_eq!(
+            BwAccountSetting::update_last_used_at(&pool, KEY)
+                .await
+                .unwrap(),
+            0
+        );
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("machine")))]
+    async fn test_revoke_key(pool: PgPool) -> sqlx::Result<()> {
+        let keys = BwAccountSetting::fetch_keys_by_uid(&pool, ACCOUNT_ID)
+            .await
+            .unwrap();
+        let item = RevokeBwAccountSettingSchema {
+            key_id: keys[0].key_id,
+            uid: ACCOUNT_ID,
+        };
+        let revoked = BwAccountSetting::revoke_key(&pool, &item)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(revoked.key, KEY);
+        assert!(BwAccountSetting::fetch_uid_by_key(&pool, KEY)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(BwAccountSetting::revoke_key(&pool, &item)
+            .await
+            .unwrap()
+            .is_none());
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("machine")))]
+    async fn test_rotate_key(pool: PgPool) -> sqlx::Result<()> {
+        let keys = BwAccountSetting::fetch_keys_by_uid(&pool, ACCOUNT_ID)
+            .await
+            .unwrap();
+        let item = RevokeBwAccountSettingSchema {
+            key_id: keys[0].key_id,
+            uid: ACCOUNT_ID,
+        };
+        let (old, new) =
+            BwAccountSetting::rotate_key(&pool, &item, "rotated-key")
+                .await
+                .unwrap()
+                .unwrap();
+        assert_eq!(old.key, KEY);
+        assert_eq!(new.name, old.name);
+        assert_eq!(new.key, "rotated-key");
+
+        let keys = BwAccountSetting::fetch_keys_by_uid(&pool, ACCOUNT_ID)
+            .await
+            .unwrap();
+        assert_eq!(keys.len(), 1);
+        assert_eq!(keys[0].key, "rotated-key");
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("machine")))]
+    async fn test_create_first_key(pool: PgPool) -> sqlx::Result<()> {
+        // The account already has a key.
+        let first = BwAccountSetting::create_first_key(
+            &pool,
+            &schema("default", "first-key"),
+        )
+        .await
+        .unwrap();
+        assert!(first.is_none());
+
+        let keys = BwAccountSetting::fetch_keys_by_uid(&pool, ACCOUNT_ID)
+            .await
+            .unwrap();
+        let item = RevokeBwAccountSettingSchema {
+            key_id: keys[0].key_id,
+            uid: ACCOUNT_ID,
+        };
+        BwAccountSetting::revoke_key(&pool, &item).await.unwrap();
+        let (first, second) = (
+            schema("default", "first-key"),
+            schema("default", "second-key"),
+        );
+        let (first, second) = tokio::join!(
+            BwAccountSetting::create_first_key(&pool, &first),
+            BwAccountSetting::create_first_key(&pool, &second),
+        );
+        assert_eq!(
+            first.unwrap().is_some() as u8 + second.unwrap().is_some() as u8,
+            1
+        );
+        let keys = BwAccountSetting::fetch_keys_by_uid(&pool, ACCOUNT_ID)
+            .await
+            .unwrap();
+        assert_eq!(keys.len(), 1);
+
+        Ok(())
     }
 }
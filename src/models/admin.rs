@@ -0,0 +1,297 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{
+    types::{chrono::NaiveDateTime, Json},
+    PgExecutor,
+};
+
+use crate::{
+    library::{error::InnerResult, DB},
+    models::types::AdminAction,
+};
+
+/// An operator of the platform, signing in to the admin console.
+#[derive(sqlx::FromRow, Debug, Serialize, Deserialize, Clone)]
+#[sqlx(rename_all = "lowercase")]
+pub struct BwAdmin {
+    pub admin_id: i64,
+    pub name: String,
+    pub email: String,
+    #[serde(skip_serializing)]
+    pub password: String,
+    pub last_login: Option<NaiveDateTime>,
+
+    pub created_at: NaiveDateTime,
+    pub updated_at: Option<NaiveDateTime>,
+    pub deleted_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateBwAdminSchema {
+    pub name: String,
+    pub email: String,
+    pub password: String,
+}
+
+#[derive(sqlx::FromRow, Debug, Serialize, Deserialize, Clone)]
+#[sqlx(rename_all = "lowercase")]
+pub struct BwAdminAudit {
+    pub id: i64,
+    pub admin_id: i64,
+    pub action: AdminAction,
+    pub target_uid: Option<i64>,
+    pub detail: Json<serde_json::Value>,
+    pub ip: Option<String>,
+
+    pub created_at: NaiveDateTime,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateBwAdminAuditSchema {
+    pub admin_id: i64,
+    pub action: AdminAction,
+    pub target_uid: Option<i64>,
+    pub detail: serde_json::Value,
+    pub ip: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReadBwAdminAuditSchema {
+    pub admin_id: Option<i64>,
+    pub target_uid: Option<i64>,
+    pub offset: i64,
+    pub limit: i64,
+}
+
+/// Platform wide counters shown on the admin dashboard.
+#[derive(sqlx::FromRow, Debug, Serialize, Deserialize, Clone)]
+pub struct PlatformStats {
+    pub accounts: i64,
+    pub active_accounts: i64,
+    pub inactive_accounts: i64,
+    pub suspended_accounts: i64,
+    pub machines: i64,
+    pub groups: i64,
+    pub organizations: i64,
+}
+
+impl BwAdmin {
+    /// Creates an admin, returning `None` if the email is taken.
+    pub async fn create_bw_admin(
+        db: impl PgExecutor<'_>,
+        item: &CreateBwAdminSchema,
+    ) -> InnerResult<Option<Self>> {
+        let sql = r#"
+            INSERT INTO bw_admin (name, email, password) VALUES ($1, $2, $3)
+            ON CONFLICT (email) DO NOTHING
+            RETURNING admin_id, name, email, password, last_login,
+            created_at, updated_at, deleted_at
+            "#;
+        let map = sqlx::query_as(sql)
+            .bind(&item.name)
+            .bind(&item.email)
+            .bind(&item.password);
+        Ok(map.fetch_optional(db).await?)
+    }
+
+    pub async fn fetch_admin_by_email(
+        db: &DB,
+        email: &str,
+    ) -> InnerResult<Option<Self>> {
+        let sql = r#"
+            SELECT admin_id, name, email, password, last_login,
+            created_at, updated_at, deleted_at
+            FROM bw_admin WHERE email = $1 AND deleted_at IS NULL
+            "#;
+        let map = sqlx::query_as(sql).bind(email);
+        Ok(map.fetch_optional(db).await?)
+    }
+
+    pub async fn check_admin_exists_by_id(
+        db: &DB,
+        admin_id: i64,
+    ) -> InnerResult<Option<bool>> {
+        let map = sqlx::query_scalar(
+            "SELECT EXISTS(SELECT 1 FROM bw_admin WHERE admin_id = $1 AND deleted_at IS NULL)",
+        )
+        .bind(admin_id);
+        Ok(map.fetch_one(db).await?)
+    }
+
+    pub async fn update_last_login(db: &DB, admin_id: i64) -> InnerResult<u64> {
+        let map = sqlx::query(
+            r#"UPDATE bw_admin SET last_login = now() WHERE admin_id = $1"#,
+        )
+        .bind(admin_id);
+        Ok(map.execute(db).await?.rows_affected())
+    }
+}
+
+impl BwAdminAudit {
+    pub async fn insert_audit(
+        db: impl PgExecutor<'_>,
+        item: &CreateBwAdminAuditSchema,
+    ) -> InnerResult<Self> {
+        let sql = r#"
+            INSERT INTO bw_admin_audit (admin_id, action, target_uid, detail, ip)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING id, admin_id, action, target_uid, detail, ip, created_at
+            "#;
+        let map = sqlx::query_as(sql)
+            .bind(item.admin_id)
+            .bind(item.action)
+            .bind(item.target_uid)
+            .bind(Json(&item.detail))
+            .bind(&item.ip);
+        Ok(map.fetch_one(db).await?)
+    }
+
+    /// Pages through the audit log, newest first, along with the total
+    /// number of matching entries.
+    pub async fn fetch_audits(
+        db: &DB,
+        item: &ReadBwAdminAuditSchema,
+    ) -> InnerResult<(Vec<Self>, i64)> {
+        let filter = r#"
+            WHERE ($1::BIGINT IS NULL OR admin_id = $1)
+            AND ($2::BIGINT IS NULL OR target_uid = $2)
+            "#;
+        let audits = sqlx::query_as(&format!(
+            r#"SELECT id, admin_id, action, target_uid, detail, ip, created_at
+            FROM bw_admin_audit {filter}
+            ORDER BY created_at DESC, id DESC
+            OFFSET $3 LIMIT $4"#
+        ))
+        .bind(item.admin_id)
+        .bind(item.target_uid)
+        .bind(item.offset)
+        .bind(item.limit)
+        .fetch_all(db)
+        .await?;
+        let total = sqlx::query_scalar(&format!(
+            "SELECT COUNT(*) FROM bw_admin_audit {filter}"
+        ))
+        .bind(item.admin_id)
+        .bind(item.target_uid)
+        .fetch_one(db)
+        .await?;
+        Ok((audits, total))
+    }
+}
+
+impl PlatformStats {
+    pub async fn fetch(db: &DB) -> InnerResult<Self> {
+        let sql = r#"
+        SELECT
+            (SELECT COUNT(*) FROM bw_account) AS accounts,
+            (SELECT COUNT(*) FROM bw_account WHERE status = 'active') AS active_accounts,
+            (SELECT COUNT(*) FROM bw_account WHERE status = 'inactive') AS inactive_accounts,
+            (SELECT COUNT(*) FROM bw_account WHERE status = 'suspended') AS suspended_accounts,
+            (SELECT COUNT(*) FROM bw_machine WHERE exist = true AND deleted_at IS NULL) AS machines,
+            (SELECT COUNT(*) FROM bw_group WHERE deleted_at IS NULL) AS groups,
+            (SELECT COUNT(*) FROM bw_organization) AS organizations
+        "#;
+        Ok(sqlx::query_as(sql).fetch_one(db).await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::PgPool;
+
+    use super::*;
+
+    const ACCOUNT_ID: i64 = 6192889942050345985;
+
+    async fn create_admin(pool: &PgPool) -> BwAdmin {
+        let item = CreateBwAdminSchema {
+            name: "root".to_string(),
+            email: "root@example.com".to_string(),
+            password: "hash".to_string(),
+        };
+        BwAdmin::create_bw_admin(pool, &item)
+            .await
+            .unwrap()
+            .unwrap()
+    }
+
+    #[sqlx::test]
+    async fn test_create_admin(pool: PgPool) -> sqlx::Result<()> {
+        let admin = create_admin(&pool).await;
+        let found = BwAdmin::fetch_admin_by_email(&pool, "root@example.com")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(found.admin_id, admin.admin_id);
+        assert_eq!(
+            BwAdmin::check_admin_exists_by_id(&pool, admin.admin_id)
+                .await
+                .unwrap(),
+            Some(true)
+        );
+
+        let item = CreateBwAdminSchema {
+            name: "other".to_string(),
+            email: "root@example.com".to_string(),
+            password: "hash".to_string(),
+        };
+        assert!(BwAdmin::create_bw_admin(&pool, &item)
+            .await
+            .unwrap()
+            .is_none());
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("machine")))]
+    async fn test_audit_log(pool: PgPool) -> sqlx::Result<()> {
+        let admin = create_admin(&pool).await;
+        for action in [AdminAction::SuspendAccount, AdminAction::Login] {
+            let item = CreateBwAdminAuditSchema {
+                admin_id: admin.admin_id,
+                action,
+                target_uid: (action != AdminAction::Login)
+                    .then_some(ACCOUNT_ID),
+                detail: serde_json::json!({"reason": "test"}),
+                ip: Some("127.0.0.1".to_string()),
+            };
+            BwAdminAudit::insert_audit(&pool, &item).await.unwrap();
+        }
+
+        let item = ReadBwAdminAuditSchema {
+            admin_id: Some(admin.admin_id),
+            target_uid: None,
+            offset: 0,
+            limit: 1,
+        };
+        let (audits, total) =
+            BwAdminAudit::fetch_audits(&pool, &item).await.unwrap();
+        assert_eq!(total, 2);
+        assert_eq!(audits.len(), 1);
+
+        let item = ReadBwAdminAuditSchema {
+            admin_id: None,
+            target_uid: Some(ACCOUNT_ID),
+            offset: 0,
+            limit: 10,
+        };
+        let (audits, total) =
+            BwAdminAudit::fetch_audits(&pool, &item).await.unwrap();
+        assert_eq!(total, 1);
+        assert_eq!(audits[0].action, AdminAction::SuspendAccount);
+        assert_eq!(audits[0].detail.0["reason"], "test");
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("machine")))]
+    async fn test_platform_stats(pool: PgPool) -> sqlx::Result<()> {
+        let stats = PlatformStats::fetch(&pool).await.unwrap();
+        assert_eq!(stats.accounts, 2);
+        assert_eq!(stats.inactive_accounts, 2);
+        assert_eq!(stats.machines, 3);
+        assert_eq!(stats.groups, 2);
+        assert_eq!(stats.organizations, 0);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,288 @@
+use serde::{Deserialize, Serialize};
+use sqlx::types::chrono::NaiveDateTime;
+use utoipa::ToSchema;
+
+use crate::{
+    library::{error::InnerResult, DB},
+    models::types::ApiScope,
+};
+
+/// A personal access token. Only the SHA-256 of the token is stored, the
+/// token itself is shown once when it is created.
+#[derive(sqlx::FromRow, Debug, Serialize, Deserialize, Clone, ToSchema)]
+#[sqlx(rename_all = "lowercase")]
+pub struct BwApiToken {
+    pub token_id: i64,
+    pub uid: i64,
+    pub name: String,
+    /// Never serialized, so left empty when read back.
+    #[serde(skip_serializing, default)]
+    pub token_hash: String,
+    pub prefix: String,
+    pub scopes: Vec<ApiScope>,
+    pub rate_limit: i32,
+    pub expires_at: Option<NaiveDateTime>,
+    pub last_used_at: Option<NaiveDateTime>,
+
+    pub created_at: NaiveDateTime,
+    pub updated_at: Option<NaiveDateTime>,
+    pub deleted_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateBwApiTokenSchema {
+    pub uid: i64,
+    pub name: String,
+    pub token_hash: String,
+    pub prefix: String,
+    pub scopes: Vec<ApiScope>,
+    pub rate_limit: i32,
+    pub expires_at: Option<NaiveDateTime>,
+}
+
+impl BwApiToken {
+    pub fn has_scope(&self, required: ApiScope) -> bool {
+        self.scopes.iter().any(|scope| scope.grants(required))
+    }
+
+    pub async fn create_bw_api_token(
+        db: &DB,
+        item: &CreateBwApiTokenSchema,
+    ) -> InnerResult<Self> {
+        let sql = r#"
+            INSERT INTO bw_api_token
+            (uid, name, token_hash, prefix, scopes, rate_limit, expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING token_id, uid, name, token_hash, prefix, scopes,
+            rate_limit, expires_at, last_used_at,
+            created_at, updated_at, deleted_at
+            "#;
+        let map = sqlx::query_as(sql)
+            .bind(item.uid)
+            .bind(&item.name)
+            .bind(&item.token_hash)
+            .bind(&item.prefix)
+            .bind(&item.scopes)
+            .bind(item.rate_limit)
+            .bind(item.expires_at);
+        Ok(map.fetch_one(db).await?)
+    }
+
+    /// Lists the tokens of `uid` that are neither revoked nor expired.
+    pub async fn fetch_tokens_by_uid(
+        db: &DB,
+        uid: i64,
+    ) -> InnerResult<Vec<Self>> {
+        let sql = r#"
+        SELECT token_id, uid, name, token_hash, prefix, scopes,
+        rate_limit, expires_at, last_used_at,
+        created_at, updated_at, deleted_at
+        FROM bw_api_token
+        WHERE uid = $1 AND deleted_at IS NULL
+        AND (expires_at IS NULL OR expires_at > (now() AT TIME ZONE 'UTC'))
+        ORDER BY created_at DESC
+        "#;
+        let map = sqlx::query_as(sql).bind(uid);
+        Ok(map.fetch_all(db).await?)
+    }
+
+    pub async fn fetch_token_count(db: &DB, uid: i64) -> InnerResult<i64> {
+        let map = sqlx::query_scalar(
+            r#"SELECT COUNT(*) FROM bw_api_token
+        WHERE uid = $1 AND deleted_at IS NULL
+        AND (expires_at IS NULL OR expires_at > (now() AT TIME ZONE 'UTC'))"#,
+        )
+        .bind(uid);
+        Ok(map.fetch_one(db).await?)
+    }
+
+    /// Looks up a usable token by its hash.
+    pub async fn fetch_token_by_hash(
+        db: &DB,
+        token_hash: &str,
+    ) -> InnerResult<Option<Self>> {
+        let sql = r#"
+        SELECT token_id, uid, name, token_hash, prefix, scopes,
+        rate_limit, expires_at, last_used_at,
+        created_at, updated_at, deleted_at
+        FROM bw_api_token
+        WHERE token_hash = $1 AND deleted_at IS NULL
+        AND (expires_at IS NULL OR expires_at > (now() AT TIME ZONE 'UTC'))
+        "#;
+        let map = sqlx::query_as(sql).bind(token_hash);
+        Ok(map.fetch_optional(db).await?)
+    }
+
+    /// Records a use of the token. Updates at most once a minute so busy
+    /// tokens do not write on every request.
+    pub async fn update_last_used_at(
+        db: &DB,
+        token_id: i64,
+    ) -> InnerResult<u64> {
+        let map = sqlx::query(
+            r#"UPDATE bw_api_token SET last_used_at = now() AT TIME ZONE 'UTC'
+        WHERE token_id = $1 AND (last_used_at IS NULL
+            OR last_used_at < (now() AT TIME ZONE 'UTC') - INTERVAL '1 minute')"#,
+        )
+        .bind(token_id);
+        Ok(map.execute(db).await?.rows_affected())
+    }
+
+    pub async fn revoke_token(
+        db: &DB,
+        token_id: i64,
+        uid: i64,
+    ) -> InnerResult<u64> {
+        let map = sqlx::query(
+            r#"UPDATE bw_api_token SET deleted_at = now()
+        WHERE token_id = $1 AND uid = $2 AND deleted_at IS NULL"#,
+        )
+        .bind(token_id)
+        .bind(uid);
+        Ok(map.execute(db).await?.rows_affected())
+    }
+
+    pub async fn revoke_tokens_by_uid(db: &DB, uid: i64) -> InnerResult<u64> {
+        let map = sqlx::query(
+            r#"UPDATE bw_api_token SET deleted_at = now()
+        WHERE uid = $1 AND deleted_at IS NULL"#,
+        )
+        .bind(uid);
+        Ok(map.execute(db).await?.rows_affected())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::{
+        postgres::{PgConnectOptions, PgPoolOptions},
+        Executor, PgPool,
+    };
+
+    use super::*;
+
+    const ACCOUNT_ID: i64 = 6192889942050345985;
+
+    fn schema(token_hash: &str) -> CreateBwApiTokenSchema {
+        CreateBwApiTokenSchema {
+            uid: ACCOUNT_ID,
+            name: "ci".to_string(),
+            token_hash: token_hash.to_string(),
+            prefix: "pat_abcd".to_string(),
+            scopes: vec![ApiScope::FleetRead, ApiScope::Operate],
+            rate_limit: 60,
+            expires_at: None,
+        }
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("users")))]
+    async fn test_create_and_fetch_token(pool: PgPool) -> sqlx::Result<()> {
+        let token = BwApiToken::create_bw_api_token(&pool, &schema("hash"))
+            .await
+            .unwrap();
+        assert_eq!(token.scopes, vec![ApiScope::FleetRead, ApiScope::Operate]);
+        assert!(token.has_scope(ApiScope::Operate));
+        assert!(!token.has_scope(ApiScope::FleetWrite));
+
+        let found = BwApiToken::fetch_token_by_hash(&pool, "hash")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(found.token_id, token.token_id);
+        assert_eq!(
+            BwApiToken::fetch_token_count(&pool, ACCOUNT_ID)
+                .await
+                .unwrap(),
+            1
+        );
+
+        assert_eq!(
+            BwApiToken::update_last_used_at(&pool, token.token_id)
+                .await
+                .unwrap(),
+            1
+        );
+        // Throttled within the same minute.
+        assert_eq!(
+            BwApiToken::update_last_used_at(&pool, token.token_id)
+                .await
+                .unwrap(),
+            0
+        );
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("users")))]
+    async fn test_revoked_and_expired_tokens(pool: PgPool) -> sqlx::Result<()> {
+        let token = BwApiToken::create_bw_api_token(&pool, &schema("revoked"))
+            .await
+            .unwrap();
+        let expired = CreateBwApiTokenSchema {
+            expires_at: Some(
+                chrono::Utc::now().naive_utc() - chrono::Duration::hours(1),
+            ),
+            ..schema("expired")
+        };
+        BwApiToken::create_bw_api_token(&pool, &expired)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            BwApiToken::revoke_token(&pool, token.token_id, ACCOUNT_ID)
+                .await
+                .unwrap(),
+            1
+        );
+        assert!(BwApiToken::fetch_token_by_hash(&pool, "revoked")
+            .await
+            .unwrap()
+            .is_none());
+        assert!(BwApiToken::fetch_token_by_hash(&pool, "expired")
+            .await
+            .unwrap()
+            .is_none());
+        assert!(BwApiToken::fetch_tokens_by_uid(&pool, ACCOUNT_ID)
+            .await
+            .unwrap()
+            .is_empty());
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("users")))]
+    async fn test_expiry_is_utc(
+        pool_options: PgPoolOptions,
+        connect_options: PgConnectOptions,
+    ) -> sqlx::Result<()> {
+        // Far ahead of UTC, so a local `now()` would be past the expiry.
+        let pool = pool_options
+            .after_connect(|conn, _| {
+                Box::pin(async move {
+                    conn.execute("SET TIME ZONE 'Pacific/Kiritimati'").await?;
+                    Ok(())
+                })
+            })
+            .connect_with(connect_options)
+            .await?;
+        let item = CreateBwApiTokenSchema {
+            expires_at: Some(
+                chrono::Utc::now().naive_utc() + chrono::Duration::hours(1),
+            ),
+            ..schema("soon")
+        };
+        BwApiToken::create_bw_api_token(&pool, &item).await.unwrap();
+        assert!(BwApiToken::fetch_token_by_hash(&pool, "soon")
+            .await
+            .unwrap()
+            .is_some());
+        assert_eq!(
+            BwApiToken::fetch_token_count(&pool, ACCOUNT_ID)
+                .await
+                .unwrap(),
+            1
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,104 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::library::{error::InnerResult, DB};
+
+#[derive(sqlx::FromRow, Debug, Serialize, Deserialize, Clone, ToSchema)]
+#[sqlx(rename_all = "lowercase")]
+pub struct BwCurrency {
+    pub symbol: String,
+    pub name: String,
+    pub enabled: bool,
+
+    pub created_at: NaiveDateTime,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+impl BwCurrency {
+    /// Fetches the enabled currencies that the latest exchange-rate snapshot
+    /// can actually convert.
+    pub async fn fetch_supported_currencies(db: &DB) -> InnerResult<Vec<Self>> {
+        let sql = r#"
+        SELECT c.symbol, c.name, c.enabled, c.created_at, c.updated_at
+        FROM bw_currency c
+        WHERE c.enabled AND EXISTS (
+            SELECT 1 FROM bw_exchange_rate r
+            WHERE r.symbol = c.symbol
+            AND r.rate_date = (SELECT MAX(rate_date) FROM bw_exchange_rate)
+        )
+        ORDER BY c.symbol
+        "#;
+        let map = sqlx::query_as(sql);
+        Ok(map.fetch_all(db).await?)
+    }
+
+    pub async fn check_currency_supported(
+        db: &DB,
+        symbol: &str,
+    ) -> InnerResult<Option<bool>> {
+        let sql = r#"
+        SELECT EXISTS(
+            SELECT 1 FROM bw_currency c
+            WHERE c.symbol = $1 AND c.enabled AND EXISTS (
+                SELECT 1 FROM bw_exchange_rate r
+                WHERE r.symbol = c.symbol
+                AND r.rate_date = (SELECT MAX(rate_date) FROM bw_exchange_rate)
+            )
+        )
+        "#;
+        let map = sqlx::query_scalar(sql).bind(symbol);
+        Ok(map.fetch_one(db).await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::PgPool;
+
+    use super::*;
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("exchange_rate")))]
+    async fn test_fetch_supported_currencies(pool: PgPool) -> sqlx::Result<()> {
+        let currencies =
+            BwCurrency::fetch_supported_currencies(&pool).await.unwrap();
+        let symbols: Vec<&str> =
+            currencies.iter().map(|c| c.symbol.as_str()).collect();
+        assert_eq!(symbols, vec!["CNY", "EUR", "USD"]);
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("exchange_rate")))]
+    async fn test_check_currency_supported(pool: PgPool) -> sqlx::Result<()> {
+        let supported = BwCurrency::check_currency_supported(&pool, "EUR")
+            .await
+            .unwrap();
+        assert!(supported.unwrap());
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("exchange_rate")))]
+    async fn test_check_currency_without_rate(
+        pool: PgPool,
+    ) -> sqlx::Result<()> {
+        // KZT is reference data, but no rate has been fetched for it yet.
+        let supported = BwCurrency::check_currency_supported(&pool, "KZT")
+            .await
+            .unwrap();
+        assert!(!supported.unwrap());
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("exchange_rate")))]
+    async fn test_check_unknown_currency(pool: PgPool) -> sqlx::Result<()> {
+        let supported = BwCurrency::check_currency_supported(&pool, "XXX")
+            .await
+            .unwrap();
+        assert!(!supported.unwrap());
+
+        Ok(())
+    }
+}
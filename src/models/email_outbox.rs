@@ -0,0 +1,391 @@
+use serde::{Deserialize, Serialize};
+use sqlx::types::{chrono::NaiveDateTime, Json};
+
+use crate::{
+    library::{error::InnerResult, DB},
+    models::types::EmailStatus,
+};
+
+#[derive(sqlx::FromRow, Debug, Serialize, Deserialize, Clone)]
+#[sqlx(rename_all = "lowercase")]
+pub struct BwEmailOutbox {
+    pub email_id: i64,
+    pub uid: Option<i64>,
+    pub recipient: String,
+    pub subject: String,
+    pub template: String,
+    #[serde(skip_serializing)]
+    pub params: Json<serde_json::Value>,
+    pub lang: String,
+    pub status: EmailStatus,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub next_attempt_at: Option<NaiveDateTime>,
+    pub sent_at: Option<NaiveDateTime>,
+
+    pub created_at: NaiveDateTime,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateBwEmailOutboxSchema {
+    pub uid: Option<i64>,
+    pub recipient: String,
+    pub subject: String,
+    pub template: String,
+    pub params: serde_json::Value,
+    pub lang: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReadBwEmailOutboxSchema {
+    pub uid: Option<i64>,
+    pub status: Option<EmailStatus>,
+    /// Matched exactly, case-insensitively.
+    pub recipient: Option<String>,
+    pub offset: i64,
+    pub limit: i64,
+}
+
+impl BwEmailOutbox {
+    pub async fn create_bw_email_outbox(
+        db: &DB,
+        item: &CreateBwEmailOutboxSchema,
+    ) -> InnerResult<Self> {
+        let sql = r#"
+            INSERT INTO bw_email_outbox (uid, recipient, subject, template, params, lang)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING email_id, uid, recipient, subject, template, params, lang,
+            status, attempts, last_error, next_attempt_at, sent_at,
+            created_at, updated_at
+            "#;
+        let map = sqlx::query_as(sql)
+            .bind(item.uid)
+            .bind(&item.recipient)
+            .bind(&item.subject)
+            .bind(&item.template)
+            .bind(Json(&item.params))
+            .bind(&item.lang);
+        Ok(map.fetch_one(db).await?)
+    }
+
+    pub async fn fetch_email_by_id(
+        db: &DB,
+        email_id: i64,
+    ) -> InnerResult<Option<Self>> {
+        let sql = r#"
+            SELECT email_id, uid, recipient, subject, template, params, lang,
+            status, attempts, last_error, next_attempt_at, sent_at,
+            created_at, updated_at
+            FROM bw_email_outbox WHERE email_id = $1
+            "#;
+        let map = sqlx::query_as(sql).bind(email_id);
+        Ok(map.fetch_optional(db).await?)
+    }
+
+    /// Claims pending emails recorded more than `older_than` seconds ago,
+    /// whose hand-off to the queue must have failed. A claimed email is
+    /// not claimed again for another `older_than` seconds, so concurrent
+    /// sweeps publish each email once.
+    pub async fn claim_stale_pending(
+        db: &DB,
+        older_than: i64,
+        limit: i64,
+    ) -> InnerResult<Vec<Self>> {
+        let sql = r#"
+            UPDATE bw_email_outbox
+            SET next_attempt_at = now() + make_interval(secs => $1)
+            WHERE email_id IN (
+                SELECT email_id FROM bw_email_outbox
+                WHERE status = 'pending'
+                AND created_at < now() - make_interval(secs => $1)
+                AND (next_attempt_at IS NULL OR next_attempt_at <= now())
+                ORDER BY created_at LIMIT $2
+                FOR UPDATE SKIP LOCKED
+            )
+            RETURNING email_id, uid, recipient, subject, template, params, lang,
+            status, attempts, last_error, next_attempt_at, sent_at,
+            created_at, updated_at
+            "#;
+        let map = sqlx::query_as(sql).bind(older_than as f64).bind(limit);
+        Ok(map.fetch_all(db).await?)
+    }
+
+    pub async fn fetch_emails(
+        db: &DB,
+        item: &ReadBwEmailOutboxSchema,
+    ) -> InnerResult<(Vec<Self>, i64)> {
+        let filter = r#"
+            WHERE ($1::BIGINT IS NULL OR uid = $1)
+            AND ($2::email_status IS NULL OR status = $2)
+            AND ($3::VARCHAR IS NULL OR LOWER(recipient) = LOWER($3))
+            "#;
+        let emails = sqlx::query_as(&format!(
+            r#"SELECT email_id, uid, recipient, subject, template, params, lang,
+            status, attempts, last_error, next_attempt_at, sent_at,
+            created_at, updated_at
+            FROM bw_email_outbox {filter}
+            ORDER BY created_at DESC, email_id DESC
+            OFFSET $4 LIMIT $5"#
+        ))
+        .bind(item.uid)
+        .bind(item.status)
+        .bind(&item.recipient)
+        .bind(item.offset)
+        .bind(item.limit)
+        .fetch_all(db)
+        .await?;
+        let total = sqlx::query_scalar(&format!(
+            "SELECT COUNT(*) FROM bw_email_outbox {filter}"
+        ))
+        .bind(item.uid)
+        .bind(item.status)
+        .bind(&item.recipient)
+        .fetch_one(db)
+        .await?;
+        Ok((emails, total))
+    }
+
+    /// Records that the email was handed to the queue.
+    pub async fn mark_queued(db: &DB, email_id: i64) -> InnerResult<u64> {
+        let map = sqlx::query(
+            r#"UPDATE bw_email_outbox SET status = 'queued',
+        next_attempt_at = NULL
+        WHERE email_id = $1 AND status = 'pending'"#,
+        )
+        .bind(email_id);
+        Ok(map.execute(db).await?.rows_affected())
+    }
+
+    /// Records that the email was sent, dropping the one-time code it
+    /// carried as nothing needs it any more.
+    pub async fn record_sent(db: &DB, email_id: i64) -> InnerResult<u64> {
+        let map = sqlx::query(
+            r#"UPDATE bw_email_outbox SET status = 'sent',
+        attempts = attempts + 1, sent_at = now(), next_attempt_at = NULL,
+        params = params - 'code'
+        WHERE email_id = $1 AND status <> 'sent'"#,
+        )
+        .bind(email_id);
+        Ok(map.execute(db).await?.rows_affected())
+    }
+
+    /// Records a failed attempt. The email is retried in `retry_in` seconds
+    /// if given and given up on otherwise, dropping its one-time code.
+    pub async fn record_failure(
+        db: &DB,
+        email_id: i64,
+        error: &str,
+        retry_in: Option<u64>,
+    ) -> InnerResult<u64> {
+        let map = sqlx::query(
+            r#"UPDATE bw_email_outbox SET attempts = attempts + 1,
+        last_error = $2,
+        status = CASE WHEN $3::FLOAT8 IS NULL THEN 'failed' ELSE 'retrying' END::email_status,
+        next_attempt_at = now() + make_interval(secs => $3),
+        params = CASE WHEN $3::FLOAT8 IS NULL THEN params - 'code'
+            ELSE params END
+        WHERE email_id = $1 AND status <> 'sent'"#,
+        )
+        .bind(email_id)
+        .bind(error)
+        .bind(retry_in.map(|secs| secs as f64));
+        Ok(map.execute(db).await?.rows_affected())
+    }
+
+    /// Deletes sent and failed emails recorded more than `older_than`
+    /// seconds ago.
+    pub async fn purge(db: &DB, older_than: i64) -> InnerResult<u64> {
+        let map = sqlx::query(
+            r#"DELETE FROM bw_email_outbox
+        WHERE status IN ('sent', 'failed')
+        AND created_at < now() - make_interval(secs => $1)"#,
+        )
+        .bind(older_than as f64);
+        Ok(map.execute(db).await?.rows_affected())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::PgPool;
+
+    use super::*;
+
+    const ACCOUNT_ID: i64 = 6192889942050345985;
+    const EMAIL: &str = "vainjoker@tuta.io";
+
+    async fn create(pool: &PgPool) -> BwEmailOutbox {
+        let item = CreateBwEmailOutboxSchema {
+            uid: Some(ACCOUNT_ID),
+            recipient: EMAIL.to_string(),
+            subject: "Activate your Miner account".to_string(),
+            template: "activation".to_string(),
+            params: serde_json::json!({"name": "VJ", "code": "ABCDEF"}),
+            lang: "en-US".to_string(),
+        };
+        BwEmailOutbox::create_bw_email_outbox(pool, &item)
+            .await
+            .unwrap()
+    }
+
+    async fn fetch(pool: &PgPool, email_id: i64) -> BwEmailOutbox {
+        BwEmailOutbox::fetch_email_by_id(pool, email_id)
+            .await
+            .unwrap()
+            .unwrap()
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("users")))]
+    async fn test_delivery_lifecycle(pool: PgPool) -> sqlx::Result<()> {
+        let email = create(&pool).await;
+        assert_eq!(email.status, EmailStatus::Pending);
+        assert_eq!(email.params.0["code"], "ABCDEF");
+
+        assert_eq!(
+            BwEmailOutbox::mark_queued(&pool, email.email_id)
+                .await
+                .unwrap(),
+            1
+        );
+        BwEmailOutbox::record_failure(
+            &pool,
+            email.email_id,
+            "timeout",
+            Some(30),
+        )
+        .await
+        .unwrap();
+        let email = fetch(&pool, email.email_id).await;
+        assert_eq!(email.status, EmailStatus::Retrying);
+        assert_eq!(email.attempts, 1);
+        assert_eq!(email.last_error.as_deref(), Some("timeout"));
+        assert!(email.next_attempt_at.is_some());
+
+        BwEmailOutbox::record_sent(&pool, email.email_id)
+            .await
+            .unwrap();
+        let email = fetch(&pool, email.email_id).await;
+        assert_eq!(email.status, EmailStatus::Sent);
+        assert_eq!(email.attempts, 2);
+        assert!(email.sent_at.is_some());
+        assert!(email.next_attempt_at.is_none());
+        assert_eq!(email.params.0, serde_json::json!({"name": "VJ"}));
+
+        // A redelivered message does not count again.
+        assert_eq!(
+            BwEmailOutbox::record_sent(&pool, email.email_id)
+                .await
+                .unwrap(),
+            0
+        );
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("users")))]
+    async fn test_record_failure_gives_up(pool: PgPool) -> sqlx::Result<()> {
+        let email = create(&pool).await;
+        BwEmailOutbox::record_failure(&pool, email.email_id, "rejected", None)
+            .await
+            .unwrap();
+        let email = fetch(&pool, email.email_id).await;
+        assert_eq!(email.status, EmailStatus::Failed);
+        assert!(email.next_attempt_at.is_none());
+        assert!(email.params.0.get("code").is_none());
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("users")))]
+    async fn test_claim_stale_pending(pool: PgPool) -> sqlx::Result<()> {
+        let pending = create(&pool).await;
+        let queued = create(&pool).await;
+        BwEmailOutbox::mark_queued(&pool, queued.email_id)
+            .await
+            .unwrap();
+
+        let stale = BwEmailOutbox::claim_stale_pending(&pool, 60, 10)
+            .await
+            .unwrap();
+        assert!(stale.is_empty());
+        sqlx::query(
+            "UPDATE bw_email_outbox SET created_at = created_at - \
+             INTERVAL '2 minutes'",
+        )
+        .execute(&pool)
+        .await?;
+        let stale = BwEmailOutbox::claim_stale_pending(&pool, 60, 10)
+            .await
+            .unwrap();
+        assert_eq!(stale.len(), 1);
+        assert_eq!(stale[0].email_id, pending.email_id);
+        // Claimed for the next minute.
+        let stale = BwEmailOutbox::claim_stale_pending(&pool, 60, 10)
+            .await
+            .unwrap();
+        assert!(stale.is_empty());
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("users")))]
+    async fn test_purge(pool: PgPool) -> sqlx::Result<()> {
+        let pending = create(&pool).await;
+        let sent = create(&pool).await;
+        BwEmailOutbox::record_sent(&pool, sent.email_id)
+            .await
+            .unwrap();
+
+        assert_eq!(BwEmailOutbox::purge(&pool, 60).await.unwrap(), 0);
+        sqlx::query(
+            "UPDATE bw_email_outbox SET created_at = created_at - \
+             INTERVAL '2 minutes'",
+        )
+        .execute(&pool)
+        .await?;
+        assert_eq!(BwEmailOutbox::purge(&pool, 60).await.unwrap(), 1);
+        assert!(BwEmailOutbox::fetch_email_by_id(&pool, sent.email_id)
+            .await
+            .unwrap()
+            .is_none());
+        fetch(&pool, pending.email_id).await;
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("users")))]
+    async fn test_fetch_emails(pool: PgPool) -> sqlx::Result<()> {
+        let first = create(&pool).await;
+        create(&pool).await;
+        BwEmailOutbox::mark_queued(&pool, first.email_id)
+            .await
+            .unwrap();
+
+        let item = ReadBwEmailOutboxSchema {
+            uid: Some(ACCOUNT_ID),
+            status: None,
+            recipient: None,
+            offset: 0,
+            limit: 1,
+        };
+        let (emails, total) =
+            BwEmailOutbox::fetch_emails(&pool, &item).await.unwrap();
+        assert_eq!(total, 2);
+        assert_eq!(emails.len(), 1);
+
+        let item = ReadBwEmailOutboxSchema {
+            uid: None,
+            status: Some(EmailStatus::Queued),
+            recipient: Some(EMAIL.to_uppercase()),
+            offset: 0,
+            limit: 10,
+        };
+        let (emails, total) =
+            BwEmailOutbox::fetch_emails(&pool, &item).await.unwrap();
+        assert_eq!(total, 1);
+        assert_eq!(emails[0].email_id, first.email_id);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,166 @@
+use std::collections::BTreeMap;
+
+use chrono::{NaiveDate, NaiveDateTime};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::library::{error::InnerResult, DB};
+
+#[derive(sqlx::FromRow, Debug, Serialize, Deserialize, Clone, ToSchema)]
+#[sqlx(rename_all = "lowercase")]
+pub struct BwExchangeRate {
+    pub rate_date: NaiveDate,
+    pub symbol: String,
+    pub base: String,
+    pub rate: Decimal,
+
+    pub created_at: NaiveDateTime,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CreateBwExchangeRateSchema<'a> {
+    pub rate_date: NaiveDate,
+    pub base: &'a str,
+    pub rates: &'a BTreeMap<String, Decimal>,
+}
+
+impl BwExchangeRate {
+    pub async fn upsert_rates(
+        db: &DB,
+        item: &CreateBwExchangeRateSchema<'_>,
+    ) -> InnerResult<u64> {
+        let sql = r#"
+            INSERT INTO bw_exchange_rate (rate_date, symbol, base, rate)
+            SELECT $1, symbol, $2, rate
+            FROM UNNEST($3::VARCHAR[], $4::NUMERIC[]) AS t(symbol, rate)
+            ON CONFLICT (rate_date, symbol) DO UPDATE
+                SET base = EXCLUDED.base, rate = EXCLUDED.rate
+            "#;
+        let (symbols, rates): (Vec<&str>, Vec<Decimal>) = item
+            .rates
+            .iter()
+            .map(|(symbol, rate)| (symbol.as_str(), *rate))
+            .unzip();
+        let map = sqlx::query(sql)
+            .bind(item.rate_date)
+            .bind(item.base)
+            .bind(symbols)
+            .bind(rates);
+        Ok(map.execute(db).await?.rows_affected())
+    }
+
+    /// Fetches the rates that applied on `date`, i.e. the most recent
+    /// snapshot stored on or before that day.
+    pub async fn fetch_rates_by_date(
+        db: &DB,
+        date: NaiveDate,
+    ) -> InnerResult<Vec<Self>> {
+        let sql = r#"
+        SELECT rate_date, symbol, base, rate, created_at, updated_at
+        FROM bw_exchange_rate
+        WHERE rate_date = (
+            SELECT MAX(rate_date) FROM bw_exchange_rate WHERE rate_date <= $1
+        )
+        ORDER BY symbol
+        "#;
+        let map = sqlx::query_as(sql).bind(date);
+        Ok(map.fetch_all(db).await?)
+    }
+
+    pub async fn fetch_rates_by_symbol(
+        db: &DB,
+        symbol: &str,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> InnerResult<Vec<Self>> {
+        let sql = r#"
+        SELECT rate_date, symbol, base, rate, created_at, updated_at
+        FROM bw_exchange_rate
+        WHERE symbol = $1 AND rate_date BETWEEN $2 AND $3
+        ORDER BY rate_date
+        "#;
+        let map = sqlx::query_as(sql).bind(symbol).bind(from).bind(to);
+        Ok(map.fetch_all(db).await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use sqlx::PgPool;
+
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::from_str(s).unwrap()
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("exchange_rate")))]
+    async fn test_upsert_rates(pool: PgPool) -> sqlx::Result<()> {
+        let mut rates = BTreeMap::new();
+        rates.insert("USD".to_string(), Decimal::ONE);
+        rates.insert("KZT".to_string(), Decimal::from_str("470.5").unwrap());
+        let item = CreateBwExchangeRateSchema {
+            rate_date: date("2024-06-20"),
+            base: "USD",
+            rates: &rates,
+        };
+        let rows_affected =
+            BwExchangeRate::upsert_rates(&pool, &item).await.unwrap();
+        assert_eq!(rows_affected, 2);
+
+        let stored =
+            BwExchangeRate::fetch_rates_by_date(&pool, date("2024-06-20"))
+                .await
+                .unwrap();
+        let kzt = stored.iter().find(|r| r.symbol == "KZT").unwrap();
+        assert_eq!(kzt.rate, Decimal::from_str("470.5").unwrap());
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("exchange_rate")))]
+    async fn test_fetch_rates_by_date_falls_back_to_previous_day(
+        pool: PgPool,
+    ) -> sqlx::Result<()> {
+        let rates =
+            BwExchangeRate::fetch_rates_by_date(&pool, date("2024-06-03"))
+                .await
+                .unwrap();
+        assert_eq!(rates.len(), 3);
+        assert!(rates.iter().all(|r| r.rate_date == date("2024-06-02")));
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("exchange_rate")))]
+    async fn test_fetch_rates_before_first_snapshot(
+        pool: PgPool,
+    ) -> sqlx::Result<()> {
+        let rates =
+            BwExchangeRate::fetch_rates_by_date(&pool, date("2024-01-01"))
+                .await
+                .unwrap();
+        assert!(rates.is_empty());
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("exchange_rate")))]
+    async fn test_fetch_rates_by_symbol(pool: PgPool) -> sqlx::Result<()> {
+        let rates = BwExchangeRate::fetch_rates_by_symbol(
+            &pool,
+            "EUR",
+            date("2024-06-01"),
+            date("2024-06-30"),
+        )
+        .await
+        .unwrap();
+        assert_eq!(rates.len(), 2);
+
+        Ok(())
+    }
+}
@@ -1,9 +1,10 @@
 use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
 use crate::library::{error::InnerResult, DB};
 
-#[derive(sqlx::FromRow, Debug, Serialize, Deserialize, Clone)]
+#[derive(sqlx::FromRow, Debug, Serialize, Deserialize, Clone, ToSchema)]
 #[sqlx(rename_all = "lowercase")]
 pub struct BwGroup {
     pub group_id: i64,
@@ -0,0 +1,67 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::library::{error::InnerResult, DB};
+
+#[derive(sqlx::FromRow, Debug, Serialize, Deserialize, Clone, ToSchema)]
+#[sqlx(rename_all = "lowercase")]
+pub struct BwLanguage {
+    pub code: String,
+    pub name: String,
+    pub enabled: bool,
+
+    pub created_at: NaiveDateTime,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+impl BwLanguage {
+    pub async fn fetch_enabled_languages(db: &DB) -> InnerResult<Vec<Self>> {
+        let sql = r#"
+        SELECT code, name, enabled, created_at, updated_at
+        FROM bw_language WHERE enabled
+        ORDER BY code
+        "#;
+        let map = sqlx::query_as(sql);
+        Ok(map.fetch_all(db).await?)
+    }
+
+    pub async fn check_language_supported(
+        db: &DB,
+        code: &str,
+    ) -> InnerResult<Option<bool>> {
+        let sql = r#"SELECT EXISTS(SELECT 1 FROM bw_language WHERE code = $1 AND enabled)"#;
+        let map = sqlx::query_scalar(sql).bind(code);
+        Ok(map.fetch_one(db).await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::PgPool;
+
+    use super::*;
+
+    #[sqlx::test]
+    async fn test_fetch_enabled_languages(pool: PgPool) -> sqlx::Result<()> {
+        let languages =
+            BwLanguage::fetch_enabled_languages(&pool).await.unwrap();
+        assert!(languages.iter().any(|l| l.code == "kk-KZ"));
+
+        Ok(())
+    }
+
+    #[sqlx::test]
+    async fn test_check_language_supported(pool: PgPool) -> sqlx::Result<()> {
+        let supported = BwLanguage::check_language_supported(&pool, "pt-BR")
+            .await
+            .unwrap();
+        assert!(supported.unwrap());
+        let supported = BwLanguage::check_language_supported(&pool, "xx-XX")
+            .await
+            .unwrap();
+        assert!(!supported.unwrap());
+
+        Ok(())
+    }
+}
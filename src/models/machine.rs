@@ -1,6 +1,6 @@
 use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
-use sqlx::types::Json;
+use sqlx::{types::Json, PgConnection};
 
 use crate::{
     library::{error::InnerResult, DB},
@@ -237,6 +237,66 @@ impl BwMachine {
         Ok(map.execute(db).await?.rows_affected())
     }
 
+    /// Hands the machine over to `to_uid`, as if it had signed in with one
+    /// of their enrollment keys. Its group, policy and pool belonged to the
+    /// previous owner and are dropped. Returns the previous owner and the
+    /// machine, or `None` if no account currently holds it.
+    /// Moves the machine to `to_uid`, returning its previous owner. Runs in
+    /// the transaction of the caller, which holds the machine locked until
+    /// it ends.
+    pub async fn transfer_bw_machine(
+        tx: &mut PgConnection,
+        mac: &str,
+        to_uid: i64,
+    ) -> InnerResult<Option<(i64, Self)>> {
+        let from_uid: Option<i64> = sqlx::query_scalar(
+            r#"SELECT uid FROM bw_machine
+            WHERE mac = MACADDR($1) AND exist = true AND deleted_at IS NULL
+            FOR UPDATE"#,
+        )
+        .bind(mac)
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(from_uid) = from_uid else {
+            return Ok(None);
+        };
+
+        let machine = sqlx::query_as(
+            r#"
+            INSERT INTO bw_machine
+                (mac, uid, device_type, device_name, device_ip, setting, hardware_version, software_version)
+            SELECT mac, $2, device_type, device_name, device_ip, setting, hardware_version, software_version
+            FROM bw_machine WHERE mac = MACADDR($1) AND uid = $3
+            ON CONFLICT (mac,uid) DO UPDATE
+                SET exist = true, deleted_at = NULL,
+                group_id = NULL, policy_id = NULL, pool_id = NULL,
+                device_type = EXCLUDED.device_type,
+                device_name = EXCLUDED.device_name,
+                device_ip = EXCLUDED.device_ip,
+                setting = EXCLUDED.setting,
+                hardware_version = EXCLUDED.hardware_version,
+                software_version = EXCLUDED.software_version
+            RETURNING mac::VARCHAR, uid, device_type, device_name, device_ip::VARCHAR, group_id,
+                policy_id, pool_id, setting, hardware_version, software_version, exist, created_at, updated_at, deleted_at
+            "#,
+        )
+        .bind(mac)
+        .bind(to_uid)
+        .bind(from_uid)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        sqlx::query(
+            r#"UPDATE bw_machine SET exist = false
+            WHERE uid != $1 AND mac = MACADDR($2)"#,
+        )
+        .bind(to_uid)
+        .bind(mac)
+        .execute(&mut *tx)
+        .await?;
+        Ok(Some((from_uid, machine)))
+    }
+
     pub async fn fetch_machines_by_uid(
         db: &DB,
         uid: i64,
@@ -369,4 +429,33 @@ mod tests {
             .unwrap();
         assert_eq!(res.len(), 2);
     }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("machine")))]
+    async fn test_transfer_bw_machine(pool: PgPool) {
+        let mut tx = pool.begin().await.unwrap();
+        let (from_uid, machine) =
+            BwMachine::transfer_bw_machine(&mut tx, MAC1, ACCOUNT_ID)
+                .await
+                .unwrap()
+                .unwrap();
+        tx.commit().await.unwrap();
+        assert_eq!(from_uid, 6192889942050345986);
+        assert_eq!(machine.uid, ACCOUNT_ID);
+        assert!(machine.exist);
+        assert_eq!(machine.policy_id, None);
+
+        let machines =
+            BwMachine::fetch_machine_by_mac(&pool, MAC1).await.unwrap();
+        assert_eq!(machines.len(), 1);
+        assert_eq!(machines[0].uid, ACCOUNT_ID);
+
+        assert!(BwMachine::transfer_bw_machine(
+            &mut pool.acquire().await.unwrap(),
+            "28:e2:97:3e:6f:ff",
+            ACCOUNT_ID
+        )
+        .await
+        .unwrap()
+        .is_none());
+    }
 }
@@ -0,0 +1,285 @@
+use serde::{Deserialize, Serialize};
+use sqlx::types::chrono::NaiveDateTime;
+
+use crate::library::{error::InnerResult, DB};
+
+#[derive(sqlx::FromRow, Debug, Serialize, Deserialize, Clone)]
+#[sqlx(rename_all = "lowercase")]
+pub struct BwAccountMfa {
+    pub uid: i64,
+    #[serde(skip_serializing)]
+    pub secret: String,
+    pub enabled_at: Option<NaiveDateTime>,
+    pub last_used_step: i64,
+
+    pub created_at: NaiveDateTime,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+#[derive(sqlx::FromRow, Debug, Serialize, Deserialize, Clone)]
+#[sqlx(rename_all = "lowercase")]
+pub struct BwRecoveryCode {
+    pub id: i64,
+    pub uid: i64,
+    #[serde(skip_serializing)]
+    pub code_hash: String,
+    pub used_at: Option<NaiveDateTime>,
+
+    pub created_at: NaiveDateTime,
+}
+
+impl BwAccountMfa {
+    pub fn is_enabled(&self) -> bool {
+        self.enabled_at.is_some()
+    }
+
+    pub async fn fetch_by_uid(db: &DB, uid: i64) -> InnerResult<Option<Self>> {
+        let sql = r#"
+            SELECT uid, secret, enabled_at, last_used_step,
+            created_at, updated_at
+            FROM bw_account_mfa WHERE uid = $1
+            "#;
+        let map = sqlx::query_as(sql).bind(uid);
+        Ok(map.fetch_optional(db).await?)
+    }
+
+    pub async fn check_enabled_by_uid(
+        db: &DB,
+        uid: i64,
+    ) -> InnerResult<Option<bool>> {
+        let map = sqlx::query_scalar(
+            "SELECT EXISTS(SELECT 1 FROM bw_account_mfa WHERE uid = $1 AND enabled_at IS NOT NULL)",
+        )
+        .bind(uid);
+        Ok(map.fetch_one(db).await?)
+    }
+
+    /// Stores a new secret awaiting confirmation. Does nothing, returning 0,
+    /// if two-factor authentication is already enabled.
+    pub async fn upsert_pending(
+        db: &DB,
+        uid: i64,
+        secret: &str,
+    ) -> InnerResult<u64> {
+        let sql = r#"
+            INSERT INTO bw_account_mfa (uid, secret) VALUES ($1, $2)
+            ON CONFLICT (uid) DO UPDATE
+                SET secret = EXCLUDED.secret, last_used_step = 0
+                WHERE bw_account_mfa.enabled_at IS NULL
+            "#;
+        let map = sqlx::query(sql).bind(uid).bind(secret);
+        Ok(map.execute(db).await?.rows_affected())
+    }
+
+    pub async fn enable(db: &DB, uid: i64, step: i64) -> InnerResult<u64> {
+        let map = sqlx::query(
+            r#"UPDATE bw_account_mfa SET enabled_at = now(), last_used_step = $2
+        WHERE uid = $1 AND enabled_at IS NULL"#,
+        )
+        .bind(uid)
+        .bind(step);
+        Ok(map.execute(db).await?.rows_affected())
+    }
+
+    /// Marks the time step of an accepted code as used. Returns 0 if that
+    /// step, or a later one, was used already, i.e. the code is replayed.
+    pub async fn consume_step(
+        db: &DB,
+        uid: i64,
+        step: i64,
+    ) -> InnerResult<u64> {
+        let map = sqlx::query(
+            r#"UPDATE bw_account_mfa SET last_used_step = $2
+        WHERE uid = $1 AND last_used_step < $2"#,
+        )
+        .bind(uid)
+        .bind(step);
+        Ok(map.execute(db).await?.rows_affected())
+    }
+
+    /// Disables two-factor authentication and drops the recovery codes.
+    pub async fn delete_by_uid(db: &DB, uid: i64) -> InnerResult<u64> {
+        let mut tx = db.begin().await?;
+        sqlx::query("DELETE FROM bw_account_recovery_code WHERE uid = $1")
+            .bind(uid)
+            .execute(&mut *tx)
+            .await?;
+        let rows_affected =
+            sqlx::query("DELETE FROM bw_account_mfa WHERE uid = $1")
+                .bind(uid)
+                .execute(&mut *tx)
+                .await?
+                .rows_affected();
+        tx.commit().await?;
+        Ok(rows_affected)
+    }
+}
+
+impl BwRecoveryCode {
+    /// Replaces every recovery code of `uid` with `code_hashes`.
+    pub async fn replace_codes(
+        db: &DB,
+        uid: i64,
+        code_hashes: &[String],
+    ) -> InnerResult<u64> {
+        let mut tx = db.begin().await?;
+        sqlx::query("DELETE FROM bw_account_recovery_code WHERE uid = $1")
+            .bind(uid)
+            .execute(&mut *tx)
+            .await?;
+        let rows_affected = sqlx::query(
+            r#"INSERT INTO bw_account_recovery_code (uid, code_hash)
+        SELECT $1, code_hash FROM UNNEST($2::VARCHAR[]) AS t(code_hash)"#,
+        )
+        .bind(uid)
+        .bind(code_hashes)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        tx.commit().await?;
+        Ok(rows_affected)
+    }
+
+    /// Uses up the recovery code with `code_hash`, returning 0 if there is
+    /// no such unused code.
+    pub async fn consume_code(
+        db: &DB,
+        uid: i64,
+        code_hash: &str,
+    ) -> InnerResult<u64> {
+        let map = sqlx::query(
+            r#"UPDATE bw_account_recovery_code SET used_at = now()
+        WHERE uid = $1 AND code_hash = $2 AND used_at IS NULL"#,
+        )
+        .bind(uid)
+        .bind(code_hash);
+        Ok(map.execute(db).await?.rows_affected())
+    }
+
+    pub async fn count_unused_by_uid(db: &DB, uid: i64) -> InnerResult<i64> {
+        let map = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM bw_account_recovery_code WHERE uid = $1 AND used_at IS NULL",
+        )
+        .bind(uid);
+        Ok(map.fetch_one(db).await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::PgPool;
+
+    use super::*;
+
+    const ACCOUNT_ID: i64 = 6192889942050345985;
+    const SECRET: &str = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("users")))]
+    async fn test_enroll_and_enable(pool: PgPool) -> sqlx::Result<()> {
+        let rows_affected =
+            BwAccountMfa::upsert_pending(&pool, ACCOUNT_ID, SECRET)
+                .await
+                .unwrap();
+        assert_eq!(rows_affected, 1);
+        assert!(!BwAccountMfa::check_enabled_by_uid(&pool, ACCOUNT_ID)
+            .await
+            .unwrap()
+            .unwrap());
+
+        // Enrolling again before confirming replaces the secret.
+        BwAccountMfa::upsert_pending(&pool, ACCOUNT_ID, "ABCDEFGH")
+            .await
+            .unwrap();
+        let mfa = BwAccountMfa::fetch_by_uid(&pool, ACCOUNT_ID)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(mfa.secret, "ABCDEFGH");
+
+        let rows_affected =
+            BwAccountMfa::enable(&pool, ACCOUNT_ID, 100).await.unwrap();
+        assert_eq!(rows_affected, 1);
+        assert!(BwAccountMfa::check_enabled_by_uid(&pool, ACCOUNT_ID)
+            .await
+            .unwrap()
+            .unwrap());
+
+        // Once enabled the secret can no longer be swapped.
+        let rows_affected =
+            BwAccountMfa::upsert_pending(&pool, ACCOUNT_ID, SECRET)
+                .await
+                .unwrap();
+        assert_eq!(rows_affected, 0);
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("users")))]
+    async fn test_consume_step_rejects_replay(
+        pool: PgPool,
+    ) -> sqlx::Result<()> {
+        BwAccountMfa::upsert_pending(&pool, ACCOUNT_ID, SECRET)
+            .await
+            .unwrap();
+        BwAccountMfa::enable(&pool, ACCOUNT_ID, 100).await.unwrap();
+
+        assert_eq!(
+            BwAccountMfa::consume_step(&pool, ACCOUNT_ID, 100)
+                .await
+                .unwrap(),
+            0
+        );
+        assert_eq!(
+            BwAccountMfa::consume_step(&pool, ACCOUNT_ID, 101)
+                .await
+                .unwrap(),
+            1
+        );
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("users")))]
+    async fn test_recovery_codes(pool: PgPool) -> sqlx::Result<()> {
+        BwAccountMfa::upsert_pending(&pool, ACCOUNT_ID, SECRET)
+            .await
+            .unwrap();
+        let hashes = vec!["a".to_string(), "b".to_string()];
+        let rows_affected =
+            BwRecoveryCode::replace_codes(&pool, ACCOUNT_ID, &hashes)
+                .await
+                .unwrap();
+        assert_eq!(rows_affected, 2);
+
+        assert_eq!(
+            BwRecoveryCode::consume_code(&pool, ACCOUNT_ID, "a")
+                .await
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            BwRecoveryCode::consume_code(&pool, ACCOUNT_ID, "a")
+                .await
+                .unwrap(),
+            0
+        );
+        assert_eq!(
+            BwRecoveryCode::count_unused_by_uid(&pool, ACCOUNT_ID)
+                .await
+                .unwrap(),
+            1
+        );
+
+        BwAccountMfa::delete_by_uid(&pool, ACCOUNT_ID)
+            .await
+            .unwrap();
+        assert_eq!(
+            BwRecoveryCode::count_unused_by_uid(&pool, ACCOUNT_ID)
+                .await
+                .unwrap(),
+            0
+        );
+
+        Ok(())
+    }
+}
@@ -1,8 +1,18 @@
 pub mod account;
+pub mod account_code;
+pub mod account_lock;
 pub mod account_setting;
 pub mod action;
+pub mod admin;
+pub mod api_token;
+pub mod currency;
+pub mod email_outbox;
+pub mod exchange_rate;
 pub mod group;
+pub mod language;
 pub mod machine;
+pub mod mfa;
+pub mod organization;
 pub mod policy;
 pub mod pool;
 pub mod types;
@@ -0,0 +1,323 @@
+use serde::{Deserialize, Serialize};
+use sqlx::types::chrono::NaiveDateTime;
+use utoipa::ToSchema;
+
+use crate::{
+    library::{error::InnerResult, DB},
+    models::types::OrgRole,
+};
+
+/// An organization shares the fleet of its owner with its members.
+#[derive(sqlx::FromRow, Debug, Serialize, Deserialize, Clone, ToSchema)]
+#[sqlx(rename_all = "lowercase")]
+pub struct BwOrganization {
+    pub org_id: i64,
+    pub owner_uid: i64,
+    pub name: String,
+
+    pub created_at: NaiveDateTime,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+/// An account's membership of an organization, as seen by that account.
+#[derive(sqlx::FromRow, Debug, Serialize, Deserialize, Clone, ToSchema)]
+#[sqlx(rename_all = "lowercase")]
+pub struct Membership {
+    pub org_id: i64,
+    pub owner_uid: i64,
+    pub name: String,
+    pub role: OrgRole,
+    /// Groups the member is limited to, `None` for the whole fleet.
+    pub group_ids: Option<Vec<i64>>,
+}
+
+/// A member of an organization, as seen by the other members.
+#[derive(sqlx::FromRow, Debug, Serialize, Deserialize, Clone, ToSchema)]
+#[sqlx(rename_all = "lowercase")]
+pub struct BwOrganizationMember {
+    pub org_id: i64,
+    pub uid: i64,
+    pub name: String,
+    pub email: String,
+    pub role: OrgRole,
+    pub group_ids: Option<Vec<i64>>,
+
+    pub created_at: NaiveDateTime,
+    pub updated_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateBwOrganizationSchema {
+    pub owner_uid: i64,
+    pub name: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SaveBwOrganizationMemberSchema {
+    pub org_id: i64,
+    pub uid: i64,
+    pub role: OrgRole,
+    pub group_ids: Option<Vec<i64>>,
+}
+
+impl BwOrganization {
+    /// Creates the organization along with the membership of its owner.
+    pub async fn create_bw_organization(
+        db: &DB,
+        item: &CreateBwOrganizationSchema,
+    ) -> InnerResult<Self> {
+        let mut tx = db.begin().await?;
+        let org: Self = sqlx::query_as(
+            r#"
+            INSERT INTO bw_organization (owner_uid, name) VALUES ($1, $2)
+            RETURNING org_id, owner_uid, name, created_at, updated_at
+            "#,
+        )
+        .bind(item.owner_uid)
+        .bind(&item.name)
+        .fetch_one(&mut *tx)
+        .await?;
+        sqlx::query(
+            r#"INSERT INTO bw_organization_member (org_id, uid, role)
+            VALUES ($1, $2, 'owner')"#,
+        )
+        .bind(org.org_id)
+        .bind(org.owner_uid)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(org)
+    }
+
+    pub async fn fetch_organization_by_owner(
+        db: &DB,
+        owner_uid: i64,
+    ) -> InnerResult<Option<Self>> {
+        let sql = r#"
+        SELECT org_id, owner_uid, name, created_at, updated_at
+        FROM bw_organization WHERE owner_uid = $1
+        "#;
+        let map = sqlx::query_as(sql).bind(owner_uid);
+        Ok(map.fetch_optional(db).await?)
+    }
+
+    pub async fn fetch_memberships_by_uid(
+        db: &DB,
+        uid: i64,
+    ) -> InnerResult<Vec<Membership>> {
+        let sql = r#"
+        SELECT o.org_id, o.owner_uid, o.name, m.role, m.group_ids
+        FROM bw_organization_member m
+        JOIN bw_organization o ON o.org_id = m.org_id
+        WHERE m.uid = $1
+        ORDER BY m.created_at
+        "#;
+        let map = sqlx::query_as(sql).bind(uid);
+        Ok(map.fetch_all(db).await?)
+    }
+
+    pub async fn fetch_membership(
+        db: &DB,
+        org_id: i64,
+        uid: i64,
+    ) -> InnerResult<Option<Membership>> {
+        let sql = r#"
+        SELECT o.org_id, o.owner_uid, o.name, m.role, m.group_ids
+        FROM bw_organization_member m
+        JOIN bw_organization o ON o.org_id = m.org_id
+        WHERE m.org_id = $1 AND m.uid = $2
+        "#;
+        let map = sqlx::query_as(sql).bind(org_id).bind(uid);
+        Ok(map.fetch_optional(db).await?)
+    }
+}
+
+impl BwOrganizationMember {
+    pub async fn fetch_members_by_org(
+        db: &DB,
+        org_id: i64,
+    ) -> InnerResult<Vec<Self>> {
+        let sql = r#"
+        SELECT m.org_id, m.uid, a.name, a.email, m.role, m.group_ids,
+        m.created_at, m.updated_at
+        FROM bw_organization_member m
+        JOIN bw_account a ON a.uid = m.uid
+        WHERE m.org_id = $1
+        ORDER BY m.created_at
+        "#;
+        let map = sqlx::query_as(sql).bind(org_id);
+        Ok(map.fetch_all(db).await?)
+    }
+
+    /// Adds a member, returning 0 if the account already is one.
+    pub async fn add_member(
+        db: &DB,
+        item: &SaveBwOrganizationMemberSchema,
+    ) -> InnerResult<u64> {
+        let sql = r#"
+            INSERT INTO bw_organization_member (org_id, uid, role, group_ids)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (org_id, uid) DO NOTHING
+            "#;
+        let map = sqlx::query(sql)
+            .bind(item.org_id)
+            .bind(item.uid)
+            .bind(item.role)
+            .bind(&item.group_ids);
+        Ok(map.execute(db).await?.rows_affected())
+    }
+
+    /// Changes the role and groups of a member other than the owner.
+    pub async fn update_member(
+        db: &DB,
+        item: &SaveBwOrganizationMemberSchema,
+    ) -> InnerResult<u64> {
+        let sql = r#"
+            UPDATE bw_organization_member SET role = $3, group_ids = $4
+            WHERE org_id = $1 AND uid = $2 AND role <> 'owner'
+            "#;
+        let map = sqlx::query(sql)
+            .bind(item.org_id)
+            .bind(item.uid)
+            .bind(item.role)
+            .bind(&item.group_ids);
+        Ok(map.execute(db).await?.rows_affected())
+    }
+
+    /// Removes a member other than the owner.
+    pub async fn remove_member(
+        db: &DB,
+        org_id: i64,
+        uid: i64,
+    ) -> InnerResult<u64> {
+        let sql = r#"
+            DELETE FROM bw_organization_member
+            WHERE org_id = $1 AND uid = $2 AND role <> 'owner'
+            "#;
+        let map = sqlx::query(sql).bind(org_id).bind(uid);
+        Ok(map.execute(db).await?.rows_affected())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::PgPool;
+
+    use super::*;
+
+    const OWNER_ID: i64 = 6192889942050345985;
+    const MEMBER_ID: i64 = 6192889942050345986;
+    const GROUP_ID_1: i64 = 6193003777960711169;
+
+    async fn create(pool: &PgPool) -> BwOrganization {
+        let item = CreateBwOrganizationSchema {
+            owner_uid: OWNER_ID,
+            name: "Farm".to_string(),
+        };
+        BwOrganization::create_bw_organization(pool, &item)
+            .await
+            .unwrap()
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("machine")))]
+    async fn test_create_organization(pool: PgPool) -> sqlx::Result<()> {
+        let org = create(&pool).await;
+        let owner =
+            BwOrganization::fetch_membership(&pool, org.org_id, OWNER_ID)
+                .await
+                .unwrap()
+                .unwrap();
+        assert_eq!(owner.role, OrgRole::Owner);
+        assert_eq!(owner.owner_uid, OWNER_ID);
+        assert_eq!(owner.group_ids, None);
+
+        let found =
+            BwOrganization::fetch_organization_by_owner(&pool, OWNER_ID)
+                .await
+                .unwrap()
+                .unwrap();
+        assert_eq!(found.org_id, org.org_id);
+
+        // One organization per owner.
+        let item = CreateBwOrganizationSchema {
+            owner_uid: OWNER_ID,
+            name: "Farm 2".to_string(),
+        };
+        assert!(BwOrganization::create_bw_organization(&pool, &item)
+            .await
+            .is_err());
+
+        Ok(())
+    }
+
+    #[sqlx::test(fixtures(path = "../../fixtures", scripts("machine")))]
+    async fn test_manage_members(pool: PgPool) -> sqlx::Result<()> {
+        let org = create(&pool).await;
+        let mut item = SaveBwOrganizationMemberSchema {
+            org_id: org.org_id,
+            uid: MEMBER_ID,
+            role: OrgRole::Viewer,
+            group_ids: None,
+        };
+        assert_eq!(
+            BwOrganizationMember::add_member(&pool, &item)
+                .await
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            BwOrganizationMember::add_member(&pool, &item)
+                .await
+                .unwrap(),
+            0
+        );
+
+        item.role = OrgRole::Operator;
+        item.group_ids = Some(vec![GROUP_ID_1]);
+        assert_eq!(
+            BwOrganizationMember::update_member(&pool, &item)
+                .await
+                .unwrap(),
+            1
+        );
+        let memberships =
+            BwOrganization::fetch_memberships_by_uid(&pool, MEMBER_ID)
+                .await
+                .unwrap();
+        assert_eq!(memberships.len(), 1);
+        assert_eq!(memberships[0].role, OrgRole::Operator);
+        assert_eq!(memberships[0].group_ids, Some(vec![GROUP_ID_1]));
+
+        let members =
+            BwOrganizationMember::fetch_members_by_org(&pool, org.org_id)
+                .await
+                .unwrap();
+        assert_eq!(members.len(), 2);
+
+        // The owner can be neither changed nor removed.
+        let owner = SaveBwOrganizationMemberSchema {
+            uid: OWNER_ID,
+            ..item
+        };
+        assert_eq!(
+            BwOrganizationMember::update_member(&pool, &owner)
+                .await
+                .unwrap(),
+            0
+        );
+        assert_eq!(
+            BwOrganizationMember::remove_member(&pool, org.org_id, OWNER_ID)
+                .await
+                .unwrap(),
+            0
+        );
+        assert_eq!(
+            BwOrganizationMember::remove_member(&pool, org.org_id, MEMBER_ID)
+                .await
+                .unwrap(),
+            1
+        );
+
+        Ok(())
+    }
+}
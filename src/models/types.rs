@@ -1,25 +1,62 @@
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
-#[derive(sqlx::Type, Debug, Clone, Copy, Serialize, Deserialize)]
-#[sqlx(type_name = "currency")]
-pub enum Currency {
-    USD,
-    EUR,
-    GBP,
-    CNY,
+/// ISO 4217 currency code, validated against `bw_currency`.
+#[derive(
+    sqlx::Type,
+    Debug,
+    Clone,
+    PartialEq,
+    Eq,
+    Hash,
+    Serialize,
+    Deserialize,
+    ToSchema,
+)]
+#[sqlx(transparent)]
+#[serde(transparent)]
+#[schema(example = "USD")]
+pub struct Currency(pub String);
+
+impl Currency {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
 }
 
-#[derive(sqlx::Type, Debug, Clone, Copy, Serialize, Deserialize)]
-#[sqlx(type_name = "language")]
-pub enum Language {
-    #[sqlx(rename = "en-US")]
-    EnUs,
-    #[sqlx(rename = "zh-CN")]
-    ZhCn,
-    #[sqlx(rename = "fr-FR")]
-    FrFr,
-    #[sqlx(rename = "es-ES")]
-    EsEs,
+impl Default for Currency {
+    fn default() -> Self {
+        Self("USD".to_string())
+    }
+}
+
+/// BCP 47 language tag, validated against `bw_language`.
+#[derive(
+    sqlx::Type,
+    Debug,
+    Clone,
+    PartialEq,
+    Eq,
+    Hash,
+    Serialize,
+    Deserialize,
+    ToSchema,
+)]
+#[sqlx(transparent)]
+#[serde(transparent)]
+#[schema(example = "en-US")]
+pub struct Language(pub String);
+
+impl Language {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Default for Language {
+    fn default() -> Self {
+        Self("en-US".to_string())
+    }
 }
 
 #[derive(
@@ -31,6 +68,7 @@ pub enum Language {
     Deserialize,
     PartialOrd,
     PartialEq,
+    ToSchema,
 )]
 #[sqlx(type_name = "account_status")]
 #[sqlx(rename_all = "lowercase")]
@@ -43,6 +81,23 @@ pub enum AccountStatus {
     Suspend,
 }
 
+#[derive(
+    sqlx::Type,
+    Debug,
+    Clone,
+    Copy,
+    Serialize,
+    Deserialize,
+    PartialOrd,
+    PartialEq,
+)]
+#[sqlx(type_name = "lock_event")]
+#[sqlx(rename_all = "lowercase")]
+pub enum LockEvent {
+    Locked,
+    Unlocked,
+}
+
 #[derive(
     sqlx::Type,
     Debug,
@@ -84,3 +139,138 @@ pub enum EnergyMode {
     Balance,
     Economize,
 }
+
+/// What a personal API token may do. Tokens without a scope a route
+/// requires are rejected by it.
+#[derive(
+    sqlx::Type,
+    Debug,
+    Clone,
+    Copy,
+    Serialize,
+    Deserialize,
+    PartialEq,
+    Eq,
+    Hash,
+    ToSchema,
+)]
+#[sqlx(type_name = "api_scope")]
+#[sqlx(rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum ApiScope {
+    /// Read machines, groups, policies, pools and market data.
+    FleetRead,
+    /// Create, update and delete groups, policies and pools.
+    FleetWrite,
+    /// Send commands to machines.
+    Operate,
+}
+
+impl ApiScope {
+    /// Whether holding `self` is enough for a route requiring `required`.
+    /// Write access implies read access.
+    pub fn grants(self, required: Self) -> bool {
+        self == required
+            || (self == Self::FleetWrite && required == Self::FleetRead)
+    }
+}
+
+impl sqlx::postgres::PgHasArrayType for ApiScope {
+    fn array_type_info() -> sqlx::postgres::PgTypeInfo {
+        sqlx::postgres::PgTypeInfo::with_name("_api_scope")
+    }
+}
+
+/// Role of an account within an organization.
+#[derive(
+    sqlx::Type,
+    Debug,
+    Clone,
+    Copy,
+    Serialize,
+    Deserialize,
+    PartialEq,
+    Eq,
+    Hash,
+    ToSchema,
+)]
+#[sqlx(type_name = "org_role")]
+#[sqlx(rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum OrgRole {
+    /// Owns the shared fleet. Every organization has exactly one.
+    Owner,
+    /// Manages the fleet and the operators and viewers.
+    Admin,
+    /// Reads the fleet and sends commands to machines.
+    Operator,
+    /// Reads the fleet.
+    Viewer,
+}
+
+impl OrgRole {
+    /// Whether the role is allowed what `scope` stands for.
+    pub fn grants(self, scope: ApiScope) -> bool {
+        match self {
+            Self::Owner | Self::Admin => true,
+            Self::Operator => scope != ApiScope::FleetWrite,
+            Self::Viewer => scope == ApiScope::FleetRead,
+        }
+    }
+
+    /// Whether a member with this role may add, change or remove members
+    /// with role `other`. Ownership cannot be handed out.
+    pub fn can_manage(self, other: Self) -> bool {
+        match self {
+            Self::Owner => other != Self::Owner,
+            Self::Admin => matches!(other, Self::Operator | Self::Viewer),
+            Self::Operator | Self::Viewer => false,
+        }
+    }
+}
+
+#[derive(
+    sqlx::Type, Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq,
+)]
+#[sqlx(type_name = "admin_action")]
+#[sqlx(rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum AdminAction {
+    Login,
+    CreateAdmin,
+    SuspendAccount,
+    ReactivateAccount,
+    ForcePasswordReset,
+    TransferMachine,
+}
+
+/// What an emailed one-time code may be used for.
+#[derive(
+    sqlx::Type, Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq,
+)]
+#[sqlx(type_name = "code_purpose")]
+#[sqlx(rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum CodePurpose {
+    VerifyEmail,
+    ResetPassword,
+}
+
+/// Delivery state of an email in the outbox.
+#[derive(
+    sqlx::Type, Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq,
+)]
+#[sqlx(type_name = "email_status")]
+#[sqlx(rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum EmailStatus {
+    /// Recorded but not yet handed to the queue.
+    Pending,
+    /// Waiting in the queue for its first attempt.
+    Queued,
+    /// Failed at least once and waiting for another attempt.
+    Retrying,
+    Sent,
+    /// Gave up on after the last attempt.
+    Failed,
+}
@@ -0,0 +1,318 @@
+//! Email verification and forgotten password flows, driven through the
+//! service layer against a real database.
+
+use miner_server::{
+    library::{
+        cfg, crypto,
+        error::{
+            ApiInnerError,
+            AppError::{self, ApiError, AuthError},
+            AuthInnerError,
+        },
+    },
+    miner::service::{login_guard, verification_service},
+    models::{
+        account::BwAccount,
+        account_code::{BwAccountCode, CreateBwAccountCodeSchema},
+        types::{AccountStatus, CodePurpose},
+    },
+};
+use sqlx::PgPool;
+
+const ACCOUNT_ID: i64 = 6192889942050345985;
+const EMAIL: &str = "vainjoker@tuta.io";
+const NEW_PASSWORD: &str = "correct horse battery staple";
+
+fn init() {
+    cfg::init(&"./fixtures/config.toml".to_string());
+}
+
+async fn fetch_account(pool: &PgPool) -> BwAccount {
+    BwAccount::fetch_user_by_uid(pool, ACCOUNT_ID)
+        .await
+        .unwrap()
+        .unwrap()
+}
+
+/// Stores a known code, as if it had been emailed.
+async fn plant_code(pool: &PgPool, purpose: CodePurpose, code: &str, ttl: i64) {
+    let item = CreateBwAccountCodeSchema {
+        uid: ACCOUNT_ID,
+        purpose,
+        code_hash: crypto::hash_token(code),
+        ttl,
+    };
+    BwAccountCode::issue(pool, &item).await.unwrap();
+}
+
+fn assert_auth_error(
+    result: Result<impl std::fmt::Debug, AppError>,
+    expected: AuthInnerError,
+) {
+    match result {
+        Err(AuthError(e)) => {
+            assert_eq!(e.to_string(), expected.to_string())
+        }
+        other => panic!("expected {expected:?}, got {other:?}"),
+    }
+}
+
+#[sqlx::test(fixtures(path = "../fixtures", scripts("users")))]
+async fn verify_email_activates_account(pool: PgPool) -> sqlx::Result<()> {
+    init();
+    let code = verification_service::issue(
+        &pool,
+        ACCOUNT_ID,
+        CodePurpose::VerifyEmail,
+    )
+    .await
+    .unwrap();
+    assert_eq!(fetch_account(&pool).await.status, AccountStatus::Inactive);
+
+    // Case and stray whitespace do not matter.
+    let typed = format!(" {} ", code.to_ascii_lowercase());
+    verification_service::verify_email(&pool, ACCOUNT_ID, &typed)
+        .await
+        .unwrap();
+
+    let user = fetch_account(&pool).await;
+    assert_eq!(user.status, AccountStatus::Active);
+    assert!(user.email_verified_at.is_some());
+
+    Ok(())
+}
+
+#[sqlx::test(fixtures(path = "../fixtures", scripts("users")))]
+async fn verify_email_without_code_fails(pool: PgPool) -> sqlx::Result<()> {
+    init();
+    assert_auth_error(
+        verification_service::verify_email(&pool, ACCOUNT_ID, "ABCDEF").await,
+        AuthInnerError::CodeExpired,
+    );
+    assert_eq!(fetch_account(&pool).await.status, AccountStatus::Inactive);
+
+    Ok(())
+}
+
+#[sqlx::test(fixtures(path = "../fixtures", scripts("users")))]
+async fn verify_email_code_is_single_use(pool: PgPool) -> sqlx::Result<()> {
+    init();
+    plant_code(&pool, CodePurpose::VerifyEmail, "ABCDEF", 600).await;
+    verification_service::verify_email(&pool, ACCOUNT_ID, "ABCDEF")
+        .await
+        .unwrap();
+    assert_auth_error(
+        verification_service::verify_email(&pool, ACCOUNT_ID, "ABCDEF").await,
+        AuthInnerError::CodeExpired,
+    );
+
+    Ok(())
+}
+
+#[sqlx::test(fixtures(path = "../fixtures", scripts("users")))]
+async fn verify_email_with_wrong_code_fails(pool: PgPool) -> sqlx::Result<()> {
+    init();
+    plant_code(&pool, CodePurpose::VerifyEmail, "ABCDEF", 600).await;
+    assert_auth_error(
+        verification_service::verify_email(&pool, ACCOUNT_ID, "FEDCBA").await,
+        AuthInnerError::WrongCode,
+    );
+    assert_eq!(fetch_account(&pool).await.status, AccountStatus::Inactive);
+
+    Ok(())
+}
+
+#[sqlx::test(fixtures(path = "../fixtures", scripts("users")))]
+async fn resend_is_rate_limited(pool: PgPool) -> sqlx::Result<()> {
+    init();
+    verification_service::issue(&pool, ACCOUNT_ID, CodePurpose::VerifyEmail)
+        .await
+        .unwrap();
+    let result = verification_service::issue(
+        &pool,
+        ACCOUNT_ID,
+        CodePurpose::VerifyEmail,
+    )
+    .await;
+    assert!(matches!(
+        result,
+        Err(ApiError(ApiInnerError::CodeIntervalRejection))
+    ));
+    // Other purposes have their own interval.
+    verification_service::issue(&pool, ACCOUNT_ID, CodePurpose::ResetPassword)
+        .await
+        .unwrap();
+
+    Ok(())
+}
+
+#[sqlx::test(fixtures(path = "../fixtures", scripts("users")))]
+async fn forgot_password_hides_unknown_email(pool: PgPool) -> sqlx::Result<()> {
+    init();
+    let issued =
+        verification_service::forgot_password(&pool, "nobody@example.com")
+            .await
+            .unwrap();
+    assert!(issued.is_none());
+
+    Ok(())
+}
+
+#[sqlx::test(fixtures(path = "../fixtures", scripts("users")))]
+async fn forgot_password_skips_suspended(pool: PgPool) -> sqlx::Result<()> {
+    init();
+    BwAccount::suspend(&pool, ACCOUNT_ID).await.unwrap();
+    let issued = verification_service::forgot_password(&pool, EMAIL)
+        .await
+        .unwrap();
+    assert!(issued.is_none());
+
+    Ok(())
+}
+
+#[sqlx::test(fixtures(path = "../fixtures", scripts("users")))]
+async fn reset_password_sets_password(pool: PgPool) -> sqlx::Result<()> {
+    init();
+    let policy = &cfg::config().miner.login_guard;
+    for _ in 0..policy.max_attempts {
+        BwAccount::record_failed_login(&pool, ACCOUNT_ID, policy)
+            .await
+            .unwrap();
+    }
+    assert!(login_guard::locked_for(&fetch_account(&pool).await).is_some());
+    let (user, code) = verification_service::forgot_password(&pool, EMAIL)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(user.uid, ACCOUNT_ID);
+
+    let uid =
+        verification_service::reset_password(&pool, EMAIL, &code, NEW_PASSWORD)
+            .await
+            .unwrap();
+    assert_eq!(uid, ACCOUNT_ID);
+
+    let user = fetch_account(&pool).await;
+    assert!(crypto::verify_password(&user.password, NEW_PASSWORD).unwrap());
+    assert!(login_guard::locked_for(&user).is_none());
+    assert_eq!(user.failed_attempt, 0);
+
+    // The code cannot be used a second time.
+    assert_auth_error(
+        verification_service::reset_password(&pool, EMAIL, &code, "another")
+            .await,
+        AuthInnerError::WrongCode,
+    );
+
+    Ok(())
+}
+
+#[sqlx::test(fixtures(path = "../fixtures", scripts("users")))]
+async fn reset_password_with_expired_code_fails(
+    pool: PgPool,
+) -> sqlx::Result<()> {
+    init();
+    plant_code(&pool, CodePurpose::ResetPassword, "ABCDEF", 0).await;
+    let before = fetch_account(&pool).await.password;
+    assert_auth_error(
+        verification_service::reset_password(
+            &pool,
+            EMAIL,
+            "ABCDEF",
+            NEW_PASSWORD,
+        )
+        .await,
+        AuthInnerError::WrongCode,
+    );
+    assert_eq!(fetch_account(&pool).await.password, before);
+
+    Ok(())
+}
+
+#[sqlx::test(fixtures(path = "../fixtures", scripts("users")))]
+async fn reset_password_attempts_are_limited(pool: PgPool) -> sqlx::Result<()> {
+    init();
+    plant_code(&pool, CodePurpose::ResetPassword, "ABCDEF", 600).await;
+    let max_attempts = cfg::config().miner.verification.max_attempts;
+    for _ in 0..max_attempts {
+        assert_auth_error(
+            verification_service::reset_password(
+                &pool,
+                EMAIL,
+                "FEDCBA",
+                NEW_PASSWORD,
+            )
+            .await,
+            AuthInnerError::WrongCode,
+        );
+    }
+    // Even the right code is refused once the attempts are used up.
+    assert_auth_error(
+        verification_service::reset_password(
+            &pool,
+            EMAIL,
+            "ABCDEF",
+            NEW_PASSWORD,
+        )
+        .await,
+        AuthInnerError::TooManyCodeAttempts,
+    );
+
+    Ok(())
+}
+
+#[sqlx::test(fixtures(path = "../fixtures", scripts("users")))]
+async fn reset_password_ignores_verify_codes(pool: PgPool) -> sqlx::Result<()> {
+    init();
+    plant_code(&pool, CodePurpose::VerifyEmail, "ABCDEF", 600).await;
+    assert_auth_error(
+        verification_service::reset_password(
+            &pool,
+            EMAIL,
+            "ABCDEF",
+            NEW_PASSWORD,
+        )
+        .await,
+        AuthInnerError::WrongCode,
+    );
+
+    Ok(())
+}
+
+#[sqlx::test(fixtures(path = "../fixtures", scripts("users")))]
+async fn reset_password_unknown_email_fails(pool: PgPool) -> sqlx::Result<()> {
+    init();
+    assert_auth_error(
+        verification_service::reset_password(
+            &pool,
+            "nobody@example.com",
+            "ABCDEF",
+            NEW_PASSWORD,
+        )
+        .await,
+        AuthInnerError::WrongCode,
+    );
+
+    Ok(())
+}
+
+#[sqlx::test(fixtures(path = "../fixtures", scripts("users")))]
+async fn reset_password_without_code_fails_like_unknown_email(
+    pool: PgPool,
+) -> sqlx::Result<()> {
+    init();
+    for email in [EMAIL, "nobody@example.com"] {
+        assert_auth_error(
+            verification_service::reset_password(
+                &pool,
+                email,
+                "ABCDEF",
+                NEW_PASSWORD,
+            )
+            .await,
+            AuthInnerError::WrongCode,
+        );
+    }
+
+    Ok(())
+}